@@ -0,0 +1,84 @@
+/*
+ * Compares evaluating the source tree with dynamic checks (interpret, as with --no-typecheck)
+ * and evaluating the typed tree (eval). Run with `cargo bench`.
+ */
+use calc::trace::NoTracer;
+use calc::{eval, interpret, parse, typing};
+use std::time::{Duration, Instant};
+
+const PROGRAMS: &[(&str, &str)] = &[
+    (
+        "fib",
+        "let function fib(n: int): int = if n < 2 then n else fib(n - 1) + fib(n - 2) in fib(20) end",
+    ),
+    (
+        "queens",
+        "let
+  var N := 8
+  var count := 0
+  type intArray = array of int
+  var row := intArray [N] of 0
+  var col := intArray [N] of 0
+  var diag1 := intArray [N + N - 1] of 0
+  var diag2 := intArray [N + N - 1] of 0
+  function try(c: int) =
+    if c = N then count := count + 1
+    else (for r := 0 to N - 1 do
+      if row[r] = 0 & diag1[r + c] = 0 & diag2[r + 7 - c] = 0 then
+        (row[r] := 1; diag1[r + c] := 1; diag2[r + 7 - c] := 1;
+         col[c] := r;
+         try(c + 1);
+         row[r] := 0; diag1[r + c] := 0; diag2[r + 7 - c] := 0))
+in
+  try(0); count
+end",
+    ),
+    (
+        "list",
+        "let
+  type list = {hd: int, tl: list}
+  var l: list := nil
+  var s := 0
+in
+  for i := 1 to 3000 do l := list {hd = i, tl = l};
+  while l <> nil do (s := s + l.hd; l := l.tl);
+  s
+end",
+    ),
+];
+
+const RUNS: u32 = 5;
+
+fn time<F: FnMut()>(mut f: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        f();
+    }
+    start.elapsed() / RUNS
+}
+
+fn main() {
+    println!(
+        "{:<8} {:>12} {:>12} {:>8}",
+        "program", "interpret", "eval", "speedup"
+    );
+    for &(name, src) in PROGRAMS {
+        let ast = parse::parse_located(src);
+        let (_, tast) = typing::f(&ast).unwrap();
+        let expected = interpret::f(&ast, &mut NoTracer);
+        assert_eq!(eval::f(&tast), expected);
+        let untyped = time(|| {
+            interpret::f(&ast, &mut NoTracer);
+        });
+        let typed = time(|| {
+            eval::f(&tast);
+        });
+        println!(
+            "{:<8} {:>10.2}ms {:>10.2}ms {:>7.1}x",
+            name,
+            untyped.as_secs_f64() * 1e3,
+            typed.as_secs_f64() * 1e3,
+            untyped.as_secs_f64() / typed.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use crate::tree::{Label, Temp};
+use std::slice;
+
+/*
+ * Instructions of the target machine with their registers left abstract, after Appel's
+ * Assem: assem is the text of the instruction, where 'd0, 'd1, ... stand for the
+ * temporaries in dst and 's0, 's1, ... for those in src.
+ */
+#[derive(PartialEq, Clone, Debug)]
+pub enum Instr {
+    Oper {
+        assem: String,
+        dst: Vec<Temp>,
+        src: Vec<Temp>,
+        jump: Option<Vec<Label>>, // where control may go, if not only to the next instruction
+    },
+    Label {
+        assem: String,
+        label: Label,
+    },
+    /* A move from one temporary to another, which register allocation may remove. */
+    Move {
+        assem: String,
+        dst: Temp,
+        src: Temp,
+    },
+}
+
+impl Instr {
+    pub fn oper(assem: impl Into<String>, dst: Vec<Temp>, src: Vec<Temp>) -> Self {
+        Instr::Oper {
+            assem: assem.into(),
+            dst,
+            src,
+            jump: None,
+        }
+    }
+
+    /* The temporaries the instruction writes. */
+    pub fn defs(&self) -> &[Temp] {
+        match *self {
+            Instr::Oper { ref dst, .. } => dst,
+            Instr::Label { .. } => &[],
+            Instr::Move { ref dst, .. } => slice::from_ref(dst),
+        }
+    }
+
+    /* The temporaries the instruction reads. */
+    pub fn uses(&self) -> &[Temp] {
+        match *self {
+            Instr::Oper { ref src, .. } => src,
+            Instr::Label { .. } => &[],
+            Instr::Move { ref src, .. } => slice::from_ref(src),
+        }
+    }
+
+    /* The instruction with every temporary t replaced by f(t). */
+    pub fn rename(self, f: &dyn Fn(Temp) -> Temp) -> Instr {
+        match self {
+            Instr::Oper {
+                assem,
+                dst,
+                src,
+                jump,
+            } => Instr::Oper {
+                assem,
+                dst: dst.into_iter().map(f).collect(),
+                src: src.into_iter().map(f).collect(),
+                jump,
+            },
+            Instr::Label { .. } => self,
+            Instr::Move { assem, dst, src } => Instr::Move {
+                assem,
+                dst: f(dst),
+                src: f(src),
+            },
+        }
+    }
+
+    /* The instruction as assembly, with temporaries named by temp_name. */
+    pub fn format(&self, temp_name: &dyn Fn(Temp) -> String) -> String {
+        let (assem, dst, src) = match *self {
+            Instr::Oper {
+                ref assem,
+                ref dst,
+                ref src,
+                ..
+            } => (assem, &dst[..], &src[..]),
+            Instr::Label { ref assem, .. } => return assem.clone(),
+            Instr::Move {
+                ref assem,
+                ref dst,
+                ref src,
+            } => (assem, slice::from_ref(dst), slice::from_ref(src)),
+        };
+        let mut out = String::with_capacity(assem.len());
+        let mut chars = assem.chars().peekable();
+        while let Some(c) = chars.next() {
+            let temps = match (c, chars.peek()) {
+                ('\'', Some('d')) => dst,
+                ('\'', Some('s')) => src,
+                _ => {
+                    out.push(c);
+                    continue;
+                }
+            };
+            chars.next();
+            let mut i = 0;
+            while let Some(d) = chars.peek().and_then(|c| c.to_digit(10)) {
+                i = i * 10 + d as usize;
+                chars.next();
+            }
+            out += &temp_name(temps[i]);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_test() {
+        let instr = Instr::oper("add 'd0, 's1", vec![Temp(100)], vec![Temp(100), Temp(12)]);
+        let name = |t: Temp| format!("r{}", t.0);
+        assert_eq!(instr.format(&name), "add r100, r12");
+        let mov = Instr::Move {
+            assem: "mov 'd0, 's0".to_string(),
+            dst: Temp(1),
+            src: Temp(2),
+        };
+        assert_eq!(mov.format(&name), "mov r1, r2");
+        assert_eq!(mov.defs(), &[Temp(1)]);
+        assert_eq!(mov.uses(), &[Temp(2)]);
+    }
+}
@@ -1,4 +1,7 @@
+use crate::resolve::BindingId;
+use std::cell::RefCell;
 use std::fmt;
+use std::rc::Rc;
 
 // Expr
 #[derive(PartialEq, Clone, Debug)]
@@ -19,6 +22,98 @@ pub enum Expr {
     NewStruct(String, Vec<Field>),
     NewArray(String, Box<Expr>, Box<Expr>),
     Break,
+    Spanned(Span, Box<Expr>), // Transparent wrapper that records where the inner expression came from.
+}
+
+/// A half-open range `[lo, hi)` of byte offsets into the source text.
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, PartialOrd, Ord)]
+pub struct Span {
+    pub lo: usize,
+    pub hi: usize,
+}
+
+impl Span {
+    pub fn new(lo: usize, hi: usize) -> Span {
+        Span { lo, hi }
+    }
+    /// The smallest span covering both `self` and `other`.
+    pub fn join(self, other: Span) -> Span {
+        Span::new(self.lo.min(other.lo), self.hi.max(other.hi))
+    }
+}
+
+/// Converts byte offsets into 1-origin (line, column) pairs.
+#[derive(Clone, Debug)]
+pub struct LineIndex {
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(src: &str) -> LineIndex {
+        let mut line_starts = vec![0];
+        for (i, c) in src.char_indices() {
+            if c == '\n' {
+                line_starts.push(i + 1);
+            }
+        }
+        LineIndex { line_starts }
+    }
+    pub fn line_col(&self, pos: usize) -> (usize, usize) {
+        let line = match self.line_starts.binary_search(&pos) {
+            Ok(i) => i,
+            Err(i) => i - 1,
+        };
+        (line + 1, pos - self.line_starts[line] + 1)
+    }
+}
+
+impl Expr {
+    /// Removes every `Expr::Spanned` wrapper, leaving the bare tree.
+    pub fn without_spans(self) -> Expr {
+        fn b(e: Expr) -> Box<Expr> {
+            Box::new(e.without_spans())
+        }
+        fn lval(l: LValue) -> LValue {
+            match l {
+                LValue::Id(x) => LValue::Id(x),
+                LValue::Mem(l, name) => LValue::Mem(Box::new(lval(*l)), name),
+                LValue::Idx(l, e) => LValue::Idx(Box::new(lval(*l)), b(*e)),
+            }
+        }
+        match self {
+            Expr::Spanned(_, e) => e.without_spans(),
+            Expr::LVal(l) => Expr::LVal(lval(l)),
+            Expr::Neg(e) => Expr::Neg(b(*e)),
+            Expr::OpNode(op, e1, e2) => Expr::OpNode(op, b(*e1), b(*e2)),
+            Expr::IfNode(c, e1, e2) => Expr::IfNode(b(*c), b(*e1), b(*e2)),
+            Expr::LAsgn(l, e) => Expr::LAsgn(lval(l), b(*e)),
+            Expr::Seq(es) => Expr::Seq(es.into_iter().map(Expr::without_spans).collect()),
+            Expr::Let(decs, e) => Expr::Let(
+                decs.into_iter()
+                    .map(|dec| match dec {
+                        Dec::Var(x, ty, e, _) => Dec::Var(x, ty, e.without_spans(), None),
+                        Dec::Fun(f, params, ty, e) => Dec::Fun(f, params, ty, e.without_spans()),
+                        Dec::Type(x, ty, _) => Dec::Type(x, ty, None),
+                    })
+                    .collect(),
+                b(*e),
+            ),
+            Expr::For(x, st, en, e) => Expr::For(x, b(*st), b(*en), b(*e)),
+            Expr::Do(c, e) => Expr::Do(b(*c), b(*e)),
+            Expr::FunApp(f, es) => {
+                Expr::FunApp(f, es.into_iter().map(Expr::without_spans).collect())
+            }
+            Expr::NewStruct(ty, fields) => Expr::NewStruct(
+                ty,
+                fields
+                    .into_iter()
+                    .map(|(name, e)| (name, e.without_spans()))
+                    .collect(),
+            ),
+            Expr::NewArray(ty, n, e) => Expr::NewArray(ty, b(*n), b(*e)),
+            e @ (Expr::Num(_) | Expr::Str(_) | Expr::Nil | Expr::Break) => e,
+        }
+    }
 }
 
 #[derive(PartialEq, Clone, Debug)]
@@ -48,33 +143,77 @@ pub enum Op {
 
 #[derive(PartialEq, Clone, Debug)]
 pub enum Dec {
-    Type(String, Type),
-    Var(String, Option<String>, Expr), // second is type-id
+    Type(String, Type, Option<Span>), // third is the span of the name
+    Var(String, Option<String>, Expr, Option<Span>), // second is type-id, fourth the span of the name
     Fun(String, Vec<TypeField>, Option<String>, Expr), // third is type-id
 }
 
 pub type Field = (String, Expr);
 pub type TypeField = (String, String);
 
-#[derive(PartialEq, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub enum Value {
     VNum(i64),
     VStr(String),
     VNil,
-    VNoResult, // indicates that an expression does't return a value
+    VUnit, // the value of expressions of type unit, which produce no value
+    VRecord(Rc<RefCell<Vec<(String, Value)>>>),
+    VArray(Rc<RefCell<Vec<Value>>>),
 }
 
-impl fmt::Display for Value {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+/* Records and arrays are compared by identity, as in Tiger. */
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::VNum(a), Value::VNum(b)) => a == b,
+            (Value::VStr(a), Value::VStr(b)) => a == b,
+            (Value::VNil, Value::VNil) => true,
+            (Value::VUnit, Value::VUnit) => true,
+            (Value::VRecord(a), Value::VRecord(b)) => Rc::ptr_eq(a, b),
+            (Value::VArray(a), Value::VArray(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+/* Nesting deeper than this is elided, which also keeps cyclic records printable. */
+const DISPLAY_DEPTH: usize = 4;
+
+impl Value {
+    fn fmt_depth(&self, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
         match self {
             &Value::VNum(v) => write!(f, "{}", v),
             Value::VStr(ref v) => write!(f, "\"{}\"", v),
             &Value::VNil => write!(f, "nil"),
-            &Value::VNoResult => write!(f, "***No Result--This should not be printed***"),
+            &Value::VUnit => write!(f, "()"),
+            Value::VRecord(_) if depth == 0 => write!(f, "{{...}}"),
+            Value::VArray(_) if depth == 0 => write!(f, "[...]"),
+            Value::VRecord(ref fields) => {
+                write!(f, "{{")?;
+                for (i, (name, val)) in fields.borrow().iter().enumerate() {
+                    write!(f, "{}{} = ", if i == 0 { "" } else { ", " }, name)?;
+                    val.fmt_depth(f, depth - 1)?;
+                }
+                write!(f, "}}")
+            }
+            Value::VArray(ref elems) => {
+                write!(f, "[")?;
+                for (i, val) in elems.borrow().iter().enumerate() {
+                    write!(f, "{}", if i == 0 { "" } else { ", " })?;
+                    val.fmt_depth(f, depth - 1)?;
+                }
+                write!(f, "]")
+            }
         }
     }
 }
 
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_depth(f, DISPLAY_DEPTH)
+    }
+}
+
 pub type TypeId = String;
 #[derive(PartialEq, Clone, Debug)]
 pub enum Type {
@@ -83,27 +222,104 @@ pub enum Type {
     Array(String),
 }
 
+/*
+ * Semantic types, as computed by typing.
+ * Every record or array type declaration creates a new type, identified by
+ * its index into the TypeTable; two such types are equal only if the indices are.
+ */
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum Ty {
+    Int,
+    Str,
+    Nil,
+    Unit, // the type of expressions that produce no value
+    Record(usize),
+    Array(usize),
+    Unknown, // the type of an erroneous expression, compatible with anything so that one mistake is reported once
+    Var(usize), // the result type of a function while typing infers it; never in a checked tree
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum TyDef {
+    Record(String, Vec<(String, Ty)>),
+    Array(String, Ty),
+}
+
+pub type TypeTable = Vec<TyDef>;
+
+impl Ty {
+    pub fn show(self, types: &TypeTable) -> String {
+        match self {
+            Ty::Int => "int".to_string(),
+            Ty::Str => "string".to_string(),
+            Ty::Nil => "nil".to_string(),
+            Ty::Unit => "unit".to_string(),
+            Ty::Record(i) | Ty::Array(i) => match types[i] {
+                TyDef::Record(ref name, _) | TyDef::Array(ref name, _) => name.clone(),
+            },
+            Ty::Unknown | Ty::Var(_) => "?".to_string(),
+        }
+    }
+}
+
+/*
+ * The result of typing: Expr with every name resolved and every node's type known.
+ * Names carry the BindingId resolve gives them in the tree typed, with the same host names.
+ */
 #[derive(PartialEq, Clone, Debug)]
 pub enum TypedExpr {
     Num(i64),
     Str(String),
-    Var(String, Type),
-    OpNode(Op, Type, Box<TypedExpr>, Box<TypedExpr>),
-    IfNode(Box<TypedExpr>, Type, Box<TypedExpr>, Box<TypedExpr>),
-    LetEx(String, Type, Box<TypedExpr>, Box<TypedExpr>),
-    FunApp(String, Vec<Type>, Type, Vec<TypedExpr>), // name, argtype, rettype, arg
+    Nil,
+    Var(String, BindingId, Ty),
+    Field(Box<TypedExpr>, String, Ty), // record, field name, field type
+    Index(Box<TypedExpr>, Box<TypedExpr>, Ty), // array, index, element type
+    Neg(Box<TypedExpr>),
+    OpNode(Op, Ty, Box<TypedExpr>, Box<TypedExpr>), // Ty is the type of the operands
+    IfNode(Box<TypedExpr>, Ty, Box<TypedExpr>, Box<TypedExpr>),
+    Assign(Box<TypedExpr>, Box<TypedExpr>),
+    Seq(Vec<TypedExpr>, Ty),
+    Let(Vec<TypedDec>, Box<TypedExpr>, Ty),
+    For(
+        String,
+        BindingId,
+        Box<TypedExpr>,
+        Box<TypedExpr>,
+        Box<TypedExpr>,
+    ),
+    While(Box<TypedExpr>, Box<TypedExpr>),
+    FunApp(String, BindingId, Vec<Ty>, Ty, Vec<TypedExpr>), // name, binding, argtype, rettype, arg
+    NewStruct(Ty, Vec<(String, TypedExpr)>),
+    NewArray(Ty, Box<TypedExpr>, Box<TypedExpr>), // type, size, initial value
+    Break,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum TypedDec {
+    Var(String, BindingId, Ty, TypedExpr),
+    Fun(TypedFunDec),
 }
 
-pub type TypedFunDec = (String, Vec<(String, Type)>, Type, TypedExpr);
+/* name, binding, parameters, result type, body */
+pub type TypedFunDec = (
+    String,
+    BindingId,
+    Vec<(String, BindingId, Ty)>,
+    Ty,
+    TypedExpr,
+);
 
-pub fn ty_of_ast(tast: &TypedExpr) -> Type {
+pub fn ty_of_ast(tast: &TypedExpr) -> Ty {
     match *tast {
-        TypedExpr::Num(_) => Type::Id("int".to_string()),
-        TypedExpr::Str(_) => Type::Id("str".to_string()),
-        TypedExpr::Var(_, ref ty) => ty.clone(),
-        TypedExpr::OpNode(_, ref ty, _, _) => ty.clone(),
-        TypedExpr::IfNode(_, ref ty, _, _) => ty.clone(),
-        TypedExpr::LetEx(_, ref ty, _, _) => ty.clone(),
-        TypedExpr::FunApp(_, _, ref ty, _) => ty.clone(),
+        TypedExpr::Num(_) | TypedExpr::Neg(_) | TypedExpr::OpNode(..) => Ty::Int,
+        TypedExpr::Str(_) => Ty::Str,
+        TypedExpr::Nil => Ty::Nil,
+        TypedExpr::Var(_, _, ty) | TypedExpr::Field(_, _, ty) | TypedExpr::Index(_, _, ty) => ty,
+        TypedExpr::IfNode(_, ty, _, _) | TypedExpr::Seq(_, ty) | TypedExpr::Let(_, _, ty) => ty,
+        TypedExpr::FunApp(_, _, _, ty, _) => ty,
+        TypedExpr::NewStruct(ty, _) | TypedExpr::NewArray(ty, _, _) => ty,
+        TypedExpr::Assign(..) | TypedExpr::For(..) | TypedExpr::While(..) | TypedExpr::Break => {
+            Ty::Unit
+        }
     }
 }
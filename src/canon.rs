@@ -0,0 +1,533 @@
+use crate::tree::{jump, mov, Exp, Gen, Label, Stm};
+use std::collections::HashMap;
+use std::mem;
+
+/*
+ * Canonicalization of Tree IR, after Appel's canon module:
+ * linearize lifts every ESEQ out and flattens SEQs, leaving calls only directly under
+ * MOVE(TEMP, ...) or EXP; basic_blocks splits the result into blocks that start with a
+ * label and end with a jump; trace_schedule orders the blocks so that the false label of
+ * every CJUMP follows it.
+ */
+
+fn nop() -> Stm {
+    Stm::Exp(Box::new(Exp::Const(0)))
+}
+
+fn is_nop(s: &Stm) -> bool {
+    matches!(*s, Stm::Exp(ref e) if matches!(**e, Exp::Const(_)))
+}
+
+fn join(s1: Stm, s2: Stm) -> Stm {
+    match (is_nop(&s1), is_nop(&s2)) {
+        (true, _) => s2,
+        (_, true) => s1,
+        _ => Stm::Seq(Box::new(s1), Box::new(s2)),
+    }
+}
+
+/* Whether s can run before e without changing the value of e (conservatively). */
+fn commute(s: &Stm, e: &Exp) -> bool {
+    is_nop(s) || matches!(*e, Exp::Const(_) | Exp::Name(_))
+}
+
+struct Canon<'g> {
+    gen: &'g mut Gen,
+}
+
+impl Canon<'_> {
+    /*
+     * Pulls the side effects out of exps, keeping their order: the statement to run first
+     * and the pure expressions left. Calls are saved in temporaries so that no call is an
+     * operand of another expression.
+     */
+    fn reorder(&mut self, exps: Vec<Exp>) -> (Stm, Vec<Exp>) {
+        let mut exps = exps.into_iter();
+        let Some(e) = exps.next() else {
+            return (nop(), Vec::new());
+        };
+        let e = match e {
+            call @ Exp::Call(..) => {
+                let t = Exp::Temp(self.gen.temp());
+                Exp::Eseq(Box::new(mov(t.clone(), call)), Box::new(t))
+            }
+            e => e,
+        };
+        let (s, e) = self.exp(e);
+        let (rest_s, mut rest) = self.reorder(exps.collect());
+        if commute(&rest_s, &e) {
+            rest.insert(0, e);
+            (join(s, rest_s), rest)
+        } else {
+            let t = Exp::Temp(self.gen.temp());
+            rest.insert(0, t.clone());
+            (join(join(s, mov(t, e)), rest_s), rest)
+        }
+    }
+
+    fn exp(&mut self, e: Exp) -> (Stm, Exp) {
+        match e {
+            Exp::Binop(op, a, b) => {
+                let (s, mut es) = self.reorder(vec![*a, *b]);
+                let b = es.pop().unwrap();
+                let a = es.pop().unwrap();
+                (s, Exp::Binop(op, Box::new(a), Box::new(b)))
+            }
+            Exp::Mem(a) => {
+                let (s, mut es) = self.reorder(vec![*a]);
+                (s, Exp::Mem(Box::new(es.pop().unwrap())))
+            }
+            Exp::Eseq(s, e) => {
+                let s = self.stm(*s);
+                let (s2, e) = self.exp(*e);
+                (join(s, s2), e)
+            }
+            Exp::Call(f, args) => self.reorder_call(*f, args),
+            e => (nop(), e),
+        }
+    }
+
+    fn reorder_call(&mut self, f: Exp, args: Vec<Exp>) -> (Stm, Exp) {
+        let mut exps = Vec::with_capacity(args.len() + 1);
+        exps.push(f);
+        exps.extend(args);
+        let (s, mut es) = self.reorder(exps);
+        let f = es.remove(0);
+        (s, Exp::Call(Box::new(f), es))
+    }
+
+    fn stm(&mut self, s: Stm) -> Stm {
+        match s {
+            Stm::Seq(a, b) => {
+                let a = self.stm(*a);
+                let b = self.stm(*b);
+                join(a, b)
+            }
+            Stm::Jump(e, labels) => {
+                let (s, mut es) = self.reorder(vec![*e]);
+                join(s, Stm::Jump(Box::new(es.pop().unwrap()), labels))
+            }
+            Stm::Cjump(op, a, b, t, f) => {
+                let (s, mut es) = self.reorder(vec![*a, *b]);
+                let b = es.pop().unwrap();
+                let a = es.pop().unwrap();
+                join(s, Stm::Cjump(op, Box::new(a), Box::new(b), t, f))
+            }
+            Stm::Move(dst, src) => match (*dst, *src) {
+                (Exp::Temp(t), Exp::Call(f, args)) => {
+                    let (s, call) = self.reorder_call(*f, args);
+                    join(s, mov(Exp::Temp(t), call))
+                }
+                (Exp::Temp(t), src) => {
+                    let (s, mut es) = self.reorder(vec![src]);
+                    join(s, mov(Exp::Temp(t), es.pop().unwrap()))
+                }
+                (Exp::Mem(at), src) => {
+                    let (s, mut es) = self.reorder(vec![*at, src]);
+                    let src = es.pop().unwrap();
+                    let at = es.pop().unwrap();
+                    join(s, mov(Exp::Mem(Box::new(at)), src))
+                }
+                (Exp::Eseq(s, dst), src) => self.stm(Stm::Seq(s, Box::new(mov(*dst, src)))),
+                (dst, src) => panic!("internal error: move to {:?} from {:?}", dst, src),
+            },
+            Stm::Exp(e) => match *e {
+                Exp::Call(f, args) => {
+                    let (s, call) = self.reorder_call(*f, args);
+                    join(s, Stm::Exp(Box::new(call)))
+                }
+                e => {
+                    let (s, mut es) = self.reorder(vec![e]);
+                    join(s, Stm::Exp(Box::new(es.pop().unwrap())))
+                }
+            },
+            s @ Stm::Label(_) => s,
+        }
+    }
+}
+
+fn flatten(stm: Stm, out: &mut Vec<Stm>) {
+    match stm {
+        Stm::Seq(s1, s2) => {
+            flatten(*s1, out);
+            flatten(*s2, out);
+        }
+        s if is_nop(&s) => {}
+        s => out.push(s),
+    }
+}
+
+/* The statements of stm, in order, with neither SEQ nor ESEQ. */
+pub fn linearize(stm: Stm, gen: &mut Gen) -> Vec<Stm> {
+    let stm = Canon { gen }.stm(stm);
+    let mut out = Vec::new();
+    flatten(stm, &mut out);
+    out
+}
+
+/*
+ * Splits linearized statements into basic blocks, each starting with a label and ending
+ * with a JUMP or a CJUMP, and returns them with the label the last block jumps to.
+ */
+pub fn basic_blocks(stms: Vec<Stm>, gen: &mut Gen) -> (Vec<Vec<Stm>>, Label) {
+    let done = gen.label();
+    let mut blocks = Vec::new();
+    let mut block = Vec::new();
+    for stm in stms {
+        if let Stm::Label(ref label) = stm {
+            if !block.is_empty() {
+                block.push(jump(label.clone()));
+                blocks.push(mem::take(&mut block));
+            }
+            block.push(stm);
+            continue;
+        }
+        if block.is_empty() {
+            block.push(Stm::Label(gen.label()));
+        }
+        let ends = matches!(stm, Stm::Jump(..) | Stm::Cjump(..));
+        block.push(stm);
+        if ends {
+            blocks.push(mem::take(&mut block));
+        }
+    }
+    if !block.is_empty() {
+        block.push(jump(done.clone()));
+        blocks.push(block);
+    }
+    (blocks, done)
+}
+
+/*
+ * Orders blocks into traces, each following unconditional jumps and the false labels of
+ * CJUMPs while it can: every CJUMP ends up followed by its false label, and a JUMP
+ * right before its target is dropped. The statements end with the label done.
+ */
+pub fn trace_schedule(blocks: Vec<Vec<Stm>>, done: Label, gen: &mut Gen) -> Vec<Stm> {
+    let index: HashMap<Label, usize> = blocks
+        .iter()
+        .enumerate()
+        .map(|(i, block)| match block[0] {
+            Stm::Label(ref label) => (label.clone(), i),
+            _ => panic!("internal error: block without a label"),
+        })
+        .collect();
+    let mut blocks: Vec<Option<Vec<Stm>>> = blocks.into_iter().map(Some).collect();
+    // a block is unmarked until a trace takes it
+    let take = |label: &Label, blocks: &mut [Option<Vec<Stm>>]| {
+        index.get(label).and_then(|&i| blocks[i].take())
+    };
+    let mut out = Vec::new();
+    for i in 0..blocks.len() {
+        let mut next = blocks[i].take();
+        while let Some(mut block) = next {
+            next = None;
+            match block.pop().unwrap() {
+                Stm::Cjump(op, a, b, t, f) => {
+                    out.extend(block);
+                    if let Some(b_f) = take(&f, &mut blocks) {
+                        out.push(Stm::Cjump(op, a, b, t, f));
+                        next = Some(b_f);
+                    } else if let Some(b_t) = take(&t, &mut blocks) {
+                        out.push(Stm::Cjump(!op, a, b, f, t));
+                        next = Some(b_t);
+                    } else {
+                        let f2 = gen.label();
+                        out.push(Stm::Cjump(op, a, b, t, f2.clone()));
+                        out.push(Stm::Label(f2));
+                        out.push(jump(f));
+                    }
+                }
+                Stm::Jump(e, labels) => {
+                    out.extend(block);
+                    let target = match *e {
+                        Exp::Name(ref label) => take(label, &mut blocks),
+                        _ => None,
+                    };
+                    match target {
+                        Some(target) => next = Some(target),
+                        None => out.push(Stm::Jump(e, labels)),
+                    }
+                }
+                _ => panic!("internal error: block without a jump at its end"),
+            }
+        }
+    }
+    // the trace that reaches done may end with a jump to it
+    if let Some(Stm::Jump(e, _)) = out.last() {
+        if **e == Exp::Name(done.clone()) {
+            out.pop();
+        }
+    }
+    out.push(Stm::Label(done));
+    out
+}
+
+/* linearize, basic_blocks and trace_schedule in turn. */
+pub fn f(stm: Stm, gen: &mut Gen) -> Vec<Stm> {
+    let stms = linearize(stm, gen);
+    let (blocks, done) = basic_blocks(stms, gen);
+    trace_schedule(blocks, done, gen)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::{Fragment, Frame, X64Frame};
+    use crate::ir_interpret::Machine;
+    use crate::translate::MAIN;
+    use crate::tree::{binop, mem, seq, BinOp, RelOp, Temp};
+    use crate::{escape, library, resolve, translate, typing};
+
+    fn is_canonical(stms: &[Stm]) -> bool {
+        fn pure(e: &Exp) -> bool {
+            match *e {
+                Exp::Binop(_, ref a, ref b) => pure(a) && pure(b),
+                Exp::Mem(ref a) => pure(a),
+                Exp::Call(..) | Exp::Eseq(..) => false,
+                _ => true,
+            }
+        }
+        let call = |e: &Exp| match *e {
+            Exp::Call(ref f, ref args) => pure(f) && args.iter().all(pure),
+            _ => pure(e),
+        };
+        stms.iter().enumerate().all(|(i, s)| match *s {
+            Stm::Seq(..) => false,
+            Stm::Move(ref dst, ref src) => match **dst {
+                Exp::Temp(_) => call(src),
+                _ => pure(dst) && pure(src),
+            },
+            Stm::Exp(ref e) => call(e),
+            Stm::Jump(ref e, _) => pure(e),
+            Stm::Cjump(_, ref a, ref b, _, ref f) => {
+                pure(a) && pure(b) && stms.get(i + 1) == Some(&Stm::Label(f.clone()))
+            }
+            Stm::Label(_) => true,
+        })
+    }
+
+    /* Runs body as the main function, giving its result and output. */
+    fn run(body: Stm, gen: &mut Gen) -> (Result<i64, String>, Vec<u8>) {
+        let frame = X64Frame::new(Label(MAIN.to_string()), &[], gen);
+        let fragments = vec![Fragment::Proc { body, frame }];
+        let mut out = Vec::new();
+        let result = Machine::new(&fragments, &mut out, &mut &b""[..]).run();
+        (result, out)
+    }
+
+    /* Random statements over a few temporaries and a record, with calls that print. */
+    struct Random<'g> {
+        state: u64,
+        gen: &'g mut Gen,
+        temps: Vec<Temp>,
+        record: Temp,
+    }
+
+    impl Random<'_> {
+        fn below(&mut self, n: u64) -> u64 {
+            // xorshift64
+            self.state ^= self.state << 13;
+            self.state ^= self.state >> 7;
+            self.state ^= self.state << 17;
+            self.state % n
+        }
+        fn temp(&mut self) -> Exp {
+            let i = self.below(self.temps.len() as u64) as usize;
+            Exp::Temp(self.temps[i])
+        }
+        fn field(&mut self, depth: u32) -> Exp {
+            let k = Exp::Const(8 * self.below(4) as i64);
+            let record = match self.below(2) {
+                0 if depth > 0 => {
+                    let s = self.stm(depth - 1);
+                    Exp::Eseq(Box::new(s), Box::new(Exp::Temp(self.record)))
+                }
+                _ => Exp::Temp(self.record),
+            };
+            mem(binop(BinOp::Plus, record, k))
+        }
+        fn exp(&mut self, depth: u32) -> Exp {
+            match self.below(if depth == 0 { 3 } else { 7 }) {
+                0 => Exp::Const(self.below(10) as i64 - 3),
+                1 => self.temp(),
+                2 => self.field(0),
+                3 => {
+                    let ops = [
+                        BinOp::Plus,
+                        BinOp::Minus,
+                        BinOp::Mul,
+                        BinOp::And,
+                        BinOp::Xor,
+                    ];
+                    let op = ops[self.below(ops.len() as u64) as usize];
+                    binop(op, self.exp(depth - 1), self.exp(depth - 1))
+                }
+                4 => {
+                    let s = self.stm(depth - 1);
+                    Exp::Eseq(Box::new(s), Box::new(self.exp(depth - 1)))
+                }
+                5 => {
+                    let printi = Box::new(Exp::Name(Label("printi".to_string())));
+                    let e = Exp::Call(printi, vec![self.exp(depth - 1)]);
+                    binop(BinOp::Plus, e, self.exp(depth - 1))
+                }
+                _ => self.field(depth),
+            }
+        }
+        fn stm(&mut self, depth: u32) -> Stm {
+            match self.below(if depth == 0 { 3 } else { 5 }) {
+                0 => mov(self.temp(), self.exp(depth.saturating_sub(1))),
+                1 => {
+                    let printi = Box::new(Exp::Name(Label("printi".to_string())));
+                    Stm::Exp(Box::new(Exp::Call(printi, vec![self.exp(depth)])))
+                }
+                2 => {
+                    let field = self.field(depth);
+                    mov(field, self.exp(depth.saturating_sub(1)))
+                }
+                3 => Stm::Seq(Box::new(self.stm(depth - 1)), Box::new(self.stm(depth - 1))),
+                _ => {
+                    let ops = [RelOp::Eq, RelOp::Ne, RelOp::Lt, RelOp::Ge, RelOp::Ult];
+                    let op = ops[self.below(ops.len() as u64) as usize];
+                    let (t, f, join) = (self.gen.label(), self.gen.label(), self.gen.label());
+                    seq(vec![
+                        Stm::Cjump(
+                            op,
+                            Box::new(self.exp(depth - 1)),
+                            Box::new(self.exp(depth - 1)),
+                            t.clone(),
+                            f.clone(),
+                        ),
+                        Stm::Label(t),
+                        self.stm(depth - 1),
+                        jump(join.clone()),
+                        Stm::Label(f),
+                        self.stm(depth - 1),
+                        Stm::Label(join),
+                    ])
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn random_test() {
+        let mut gen = Gen::new();
+        for seed in 1..300u64 {
+            let temps = (0..3).map(|_| gen.temp()).collect::<Vec<_>>();
+            let record = gen.temp();
+            let mut random = Random {
+                state: seed.wrapping_mul(0x9e37_79b9_7f4a_7c15),
+                gen: &mut gen,
+                temps: temps.clone(),
+                record,
+            };
+            let mut stms = vec![mov(
+                Exp::Temp(record),
+                Exp::Call(
+                    Box::new(Exp::Name(Label("allocRecord".to_string()))),
+                    vec![Exp::Const(32)],
+                ),
+            )];
+            for (k, &t) in temps.iter().enumerate() {
+                stms.push(mov(Exp::Temp(t), Exp::Const(k as i64)));
+            }
+            stms.push(random.stm(4));
+            stms.push(mov(Exp::Temp(X64Frame::rv()), random.exp(3)));
+            let body = seq(stms);
+            let canonical = f(body.clone(), &mut gen);
+            assert!(is_canonical(&canonical), "{}", seq(canonical));
+            let expected = run(body, &mut gen);
+            assert!(expected.0.is_ok());
+            assert_eq!(run(seq(canonical), &mut gen), expected, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn samples_test() {
+        let mut paths: Vec<_> = std::fs::read_dir("sample")
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        paths.sort();
+        for path in paths {
+            let src = std::fs::read_to_string(&path).unwrap();
+            let host = library::functions(
+                std::rc::Rc::new(std::cell::RefCell::new(Vec::new())),
+                std::rc::Rc::new(std::cell::RefCell::new(&b""[..])),
+            );
+            let (ast, types, tast) = typing::check(&src, &host).unwrap();
+            let (res, _) = resolve::f(&ast, library::NAMES);
+            let mut gen = Gen::new();
+            let fragments: Vec<Fragment<X64Frame>> =
+                translate::f(&tast, &types, &escape::f(&res), &mut gen);
+            let canonical: Vec<Fragment<X64Frame>> = fragments
+                .iter()
+                .cloned()
+                .map(|fragment| match fragment {
+                    Fragment::Proc { body, frame } => {
+                        let stms = f(body, &mut gen);
+                        assert!(is_canonical(&stms));
+                        Fragment::Proc {
+                            body: seq(stms),
+                            frame,
+                        }
+                    }
+                    fragment => fragment,
+                })
+                .collect();
+            let run = |fragments: &[Fragment<X64Frame>]| {
+                let mut out = Vec::new();
+                let result = Machine::new(fragments, &mut out, &mut &b""[..]).run();
+                (result, out)
+            };
+            assert_eq!(run(&canonical), run(&fragments), "{}", path.display());
+        }
+    }
+
+    #[test]
+    fn trace_test() {
+        // L0: CJUMP to L1 or L2; L1: JUMP L3; L2: JUMP L3; L3:
+        let mut gen = Gen::new();
+        let l: Vec<Label> = (0..4).map(|_| gen.label()).collect();
+        let stms = vec![
+            Stm::Label(l[0].clone()),
+            Stm::Cjump(
+                RelOp::Lt,
+                Box::new(Exp::Const(1)),
+                Box::new(Exp::Const(2)),
+                l[1].clone(),
+                l[2].clone(),
+            ),
+            Stm::Label(l[2].clone()),
+            jump(l[3].clone()),
+            Stm::Label(l[1].clone()),
+            jump(l[3].clone()),
+            Stm::Label(l[3].clone()),
+        ];
+        let (blocks, done) = basic_blocks(stms, &mut gen);
+        assert_eq!(blocks.len(), 4);
+        let trace = trace_schedule(blocks, done.clone(), &mut gen);
+        // the false branch falls through to L2, and L2 to L3
+        assert_eq!(
+            trace,
+            vec![
+                Stm::Label(l[0].clone()),
+                Stm::Cjump(
+                    RelOp::Lt,
+                    Box::new(Exp::Const(1)),
+                    Box::new(Exp::Const(2)),
+                    l[1].clone(),
+                    l[2].clone(),
+                ),
+                Stm::Label(l[2].clone()),
+                Stm::Label(l[3].clone()),
+                jump(done.clone()),
+                Stm::Label(l[1].clone()),
+                jump(l[3].clone()),
+                Stm::Label(done),
+            ]
+        );
+    }
+}
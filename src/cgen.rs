@@ -0,0 +1,730 @@
+use crate::ast::{ty_of_ast, Op, Ty, TyDef, TypeTable, TypedDec, TypedExpr};
+use crate::compile::RUNTIME;
+use crate::escape::Escapes;
+use crate::library;
+use crate::resolve::BindingId;
+use crate::translate::{self, MAIN};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+
+/*
+ * The C backend: typed trees become one standalone C99 file, with the runtime of the
+ * native backend (runtime.c) at its top. Every Tiger function becomes a C function, and
+ * a nested one takes a pointer to the environment of the function it is nested in: a
+ * struct holding that function's escaping variables and, in turn, its own static link.
+ * Records are pointers to structs and arrays pointers to their elements, both on the heap.
+ * C leaves the order of evaluating operands open, so calls and operands that come before
+ * ones with effects are put in temporaries, keeping Tiger's left-to-right order.
+ */
+
+const C_KEYWORDS: &[&str] = &[
+    "auto", "case", "char", "const", "continue", "default", "double", "enum", "extern", "float",
+    "goto", "inline", "int", "long", "register", "restrict", "return", "short", "signed", "sizeof",
+    "static", "struct", "switch", "typedef", "union", "unsigned", "void", "volatile",
+];
+
+/* A function being generated, the one it is nested in, and its environment's members. */
+struct Level {
+    name: String,
+    parent: Option<usize>,
+    env: Vec<String>,
+    uses_env: bool,
+}
+
+#[derive(Clone)]
+enum Entry {
+    Var(usize, String, bool), // level, C name, whether it lives in the environment
+    Fun(usize, String),       // level of the function itself, C name
+}
+
+type Env = HashMap<BindingId, Entry>;
+
+/* Statements of a function body, indented by depth. */
+#[derive(Default)]
+struct Body {
+    code: String,
+    depth: usize,
+}
+
+impl Body {
+    fn line(&mut self, s: &str) {
+        for _ in 0..self.depth {
+            self.code += "    ";
+        }
+        self.code += s;
+        self.code.push('\n');
+    }
+
+    fn open(&mut self, s: &str) {
+        self.line(&format!("{} {{", s));
+        self.depth += 1;
+    }
+
+    fn close(&mut self) {
+        self.depth -= 1;
+        self.line("}");
+    }
+}
+
+/* name declared with the C type ty, as in "int64_t x" or "struct string *s". */
+fn decl(ty: &str, name: &str) -> String {
+    if ty.ends_with('*') {
+        format!("{}{}", ty, name)
+    } else {
+        format!("{} {}", ty, name)
+    }
+}
+
+fn field(name: &str) -> String {
+    if C_KEYWORDS.contains(&name) {
+        format!("{}_", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/* s as a C string literal, without trigraphs. */
+fn c_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for b in s.bytes() {
+        match b {
+            b'"' | b'\\' | b'?' => {
+                out.push('\\');
+                out.push(b as char);
+            }
+            0x20..=0x7e => out.push(b as char),
+            _ => out += &format!("\\{:03o}", b),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/* Whether a C expression is a constant, so that evaluating it later changes nothing. */
+fn is_constant(e: &str) -> bool {
+    e == "NULL" || e.starts_with("(struct string *)&") || e.parse::<i64>().is_ok()
+}
+
+struct Ctx<'a> {
+    types: &'a TypeTable,
+    escapes: &'a Escapes,
+    levels: Vec<Level>,
+    labels: HashSet<String>, // function names taken
+    names: usize,            // locals and temporaries made so far
+    strings: Vec<String>,
+    protos: Vec<String>,
+    funs: Vec<String>,
+}
+
+impl Ctx<'_> {
+    fn fun_name(&mut self, name: &str) -> String {
+        let mut label = name.to_string();
+        let mut k = 1;
+        while self.labels.contains(&label) {
+            label = format!("{}_{}", name, k);
+            k += 1;
+        }
+        self.labels.insert(label.clone());
+        format!("tiger_{}", label)
+    }
+
+    /* A fresh name for a local, which stays apart from other locals and from temporaries. */
+    fn local(&mut self, name: &str) -> String {
+        self.names += 1;
+        format!("{}_{}", name, self.names)
+    }
+
+    fn temp(&mut self, ty: Ty, value: &str, body: &mut Body) -> String {
+        self.names += 1;
+        let t = format!("t{}", self.names);
+        body.line(&format!("{} = {};", decl(&self.ctype(ty), &t), value));
+        t
+    }
+
+    fn ctype(&self, ty: Ty) -> String {
+        match ty {
+            Ty::Int | Ty::Unit | Ty::Unknown => "int64_t".to_string(),
+            Ty::Var(_) => unreachable!(),
+            Ty::Str => "struct string *".to_string(),
+            Ty::Nil => "void *".to_string(),
+            Ty::Record(i) => match self.types[i] {
+                TyDef::Record(ref name, _) => format!("struct {}_{} *", name, i),
+                TyDef::Array(..) => unreachable!(),
+            },
+            Ty::Array(i) => match self.types[i] {
+                TyDef::Array(_, elem) => decl(&self.ctype(elem), "*"),
+                TyDef::Record(..) => unreachable!(),
+            },
+        }
+    }
+
+    /* How many static links lead from level from to level to, whose environments are all needed. */
+    fn hops(&mut self, mut from: usize, to: usize) -> usize {
+        let mut n = 0;
+        self.levels[from].uses_env = true;
+        while from != to {
+            from = self.levels[from]
+                .parent
+                .expect("environment above the main program");
+            self.levels[from].uses_env = true;
+            n += 1;
+        }
+        n
+    }
+
+    /* A pointer to the environment of level to, as seen from level from, which it encloses. */
+    fn env_ptr(&mut self, from: usize, to: usize) -> String {
+        match self.hops(from, to) {
+            0 => "&env".to_string(),
+            n => format!("env.link{}", "->link".repeat(n - 1)),
+        }
+    }
+
+    /* What the members of the environment of level to are accessed after, from level from. */
+    fn env_path(&mut self, from: usize, to: usize) -> String {
+        match self.hops(from, to) {
+            0 => "env.".to_string(),
+            n => format!("env.link{}->", "->link".repeat(n - 1)),
+        }
+    }
+
+    fn sub(&mut self, e: &TypedExpr, env: &Env, level: usize, depth: usize) -> (Body, String) {
+        let mut body = Body {
+            code: String::new(),
+            depth,
+        };
+        let v = self.expr(e, env, level, &mut body);
+        (body, v)
+    }
+
+    /* Generates es in order, keeping in temporaries those that later ones could change. */
+    fn operands(
+        &mut self,
+        es: &[&TypedExpr],
+        env: &Env,
+        level: usize,
+        body: &mut Body,
+    ) -> Vec<String> {
+        let parts: Vec<(Body, String)> = es
+            .iter()
+            .map(|e| self.sub(e, env, level, body.depth))
+            .collect();
+        let mut out = Vec::with_capacity(es.len());
+        for (i, (b, v)) in parts.iter().enumerate() {
+            body.code += &b.code;
+            if parts[i + 1..].iter().any(|(b, _)| !b.code.is_empty()) && !is_constant(v) {
+                out.push(self.temp(ty_of_ast(es[i]), v, body));
+            } else {
+                out.push(v.clone());
+            }
+        }
+        out
+    }
+
+    fn compare(&self, op: Op, ty: Ty, a: &str, b: &str) -> String {
+        let op = match op {
+            Op::Eq => "==",
+            Op::Ne => "!=",
+            Op::Lt => "<",
+            Op::Gt => ">",
+            Op::Le => "<=",
+            Op::Ge => ">=",
+            _ => unreachable!(),
+        };
+        match (ty, op) {
+            (Ty::Str, "==") => format!("tiger_stringEqual({}, {})", a, b),
+            (Ty::Str, "!=") => format!("!tiger_stringEqual({}, {})", a, b),
+            (Ty::Str, _) => format!("(tiger_stringCompare({}, {}) {} 0)", a, b, op),
+            _ => format!("({} {} {})", a, op, b),
+        }
+    }
+
+    /* Generates the statements of e into body, giving a C expression without effects for its value. */
+    fn expr(&mut self, e: &TypedExpr, env: &Env, level: usize, body: &mut Body) -> String {
+        match *e {
+            TypedExpr::Num(i64::MIN) => "INT64_MIN".to_string(),
+            TypedExpr::Num(i) if i < 0 => format!("({})", i),
+            TypedExpr::Num(i) => i.to_string(),
+            TypedExpr::Str(ref s) => {
+                self.strings.push(s.clone());
+                format!("(struct string *)&str{}", self.strings.len() - 1)
+            }
+            TypedExpr::Nil => "NULL".to_string(),
+            TypedExpr::Var(ref name, id, _) => match env.get(&id) {
+                Some(Entry::Var(var_level, cname, true)) => {
+                    let (var_level, cname) = (*var_level, cname.clone());
+                    self.env_path(level, var_level) + &cname
+                }
+                Some(Entry::Var(_, cname, false)) => cname.clone(),
+                _ => panic!("internal error: unbound variable {}", name),
+            },
+            TypedExpr::Field(ref r, ref name, _) => {
+                let record = self.ctype(ty_of_ast(r));
+                let r = self.expr(r, env, level, body);
+                format!("(({})tiger_checkNil({}))->{}", record, r, field(name))
+            }
+            TypedExpr::Index(ref a, ref i, ty) => {
+                let elem = self.ctype(ty);
+                let ops = self.operands(&[a, i], env, level, body);
+                format!("(*({} *)tiger_checkIndex({}, {}))", elem, ops[0], ops[1])
+            }
+            TypedExpr::Neg(ref e) => format!(
+                "(int64_t)(0 - (uint64_t){})",
+                self.expr(e, env, level, body)
+            ),
+            TypedExpr::OpNode(Op::And, _, ref e1, ref e2) => {
+                // e1 & e2 is if e1 then e2 else 0
+                let a = self.expr(e1, env, level, body);
+                let (b_body, b) = self.sub(e2, env, level, body.depth + 1);
+                if b_body.code.is_empty() {
+                    return format!("({} != 0 ? {} : 0)", a, b);
+                }
+                let t = self.temp(Ty::Int, "0", body);
+                body.open(&format!("if ({} != 0)", a));
+                body.code += &b_body.code;
+                body.line(&format!("{} = {};", t, b));
+                body.close();
+                t
+            }
+            TypedExpr::OpNode(Op::Or, _, ref e1, ref e2) => {
+                // e1 | e2 is e1 if it is not 0, else e2
+                let a = self.expr(e1, env, level, body);
+                let (b_body, b) = self.sub(e2, env, level, body.depth + 1);
+                if b_body.code.is_empty() {
+                    return format!("({0} != 0 ? {0} : {1})", a, b);
+                }
+                let t = self.temp(Ty::Int, &a, body);
+                body.open(&format!("if ({} == 0)", t));
+                body.code += &b_body.code;
+                body.line(&format!("{} = {};", t, b));
+                body.close();
+                t
+            }
+            TypedExpr::OpNode(op, ty, ref e1, ref e2) => {
+                let ops = self.operands(&[e1, e2], env, level, body);
+                let (a, b) = (&ops[0], &ops[1]);
+                match op {
+                    // through uint64_t, as signed overflow is undefined in C
+                    Op::Add => format!("(int64_t)((uint64_t){} + (uint64_t){})", a, b),
+                    Op::Sub => format!("(int64_t)((uint64_t){} - (uint64_t){})", a, b),
+                    Op::Mul => format!("(int64_t)((uint64_t){} * (uint64_t){})", a, b),
+                    Op::Div => format!("tiger_divide({}, {})", a, b),
+                    _ => self.compare(op, ty, a, b),
+                }
+            }
+            TypedExpr::IfNode(ref c, ty, ref e1, ref e2) => {
+                let c = self.expr(c, env, level, body);
+                let (b1, v1) = self.sub(e1, env, level, body.depth + 1);
+                let (b2, v2) = self.sub(e2, env, level, body.depth + 1);
+                if ty == Ty::Unit {
+                    body.line(&format!("if ({}) {{", c));
+                    body.code += &b1.code;
+                    if !b2.code.is_empty() {
+                        body.line("} else {");
+                        body.code += &b2.code;
+                    }
+                    body.line("}");
+                    return "0".to_string();
+                }
+                if b1.code.is_empty() && b2.code.is_empty() {
+                    return format!("({} ? {} : {})", c, v1, v2);
+                }
+                self.names += 1;
+                let t = format!("t{}", self.names);
+                body.line(&format!("{};", decl(&self.ctype(ty), &t)));
+                body.line(&format!("if ({}) {{", c));
+                body.code += &b1.code;
+                body.line(&format!("    {} = {};", t, v1));
+                body.line("} else {");
+                body.code += &b2.code;
+                body.line(&format!("    {} = {};", t, v2));
+                body.line("}");
+                t
+            }
+            TypedExpr::Assign(ref lval, ref e) => {
+                match **lval {
+                    TypedExpr::Field(ref r, ref name, _) => {
+                        let record = self.ctype(ty_of_ast(r));
+                        let ops = self.operands(&[r, e], env, level, body);
+                        body.line(&format!(
+                            "(({})tiger_checkNil({}))->{} = {};",
+                            record,
+                            ops[0],
+                            field(name),
+                            ops[1]
+                        ));
+                    }
+                    TypedExpr::Index(ref a, ref i, ty) => {
+                        let elem = self.ctype(ty);
+                        let ops = self.operands(&[a, i, e], env, level, body);
+                        body.line(&format!(
+                            "*({} *)tiger_checkIndex({}, {}) = {};",
+                            elem, ops[0], ops[1], ops[2]
+                        ));
+                    }
+                    _ => {
+                        let lval = self.expr(lval, env, level, body);
+                        let v = self.expr(e, env, level, body);
+                        body.line(&format!("{} = {};", lval, v));
+                    }
+                }
+                "0".to_string()
+            }
+            TypedExpr::Seq(ref es, ty) => {
+                let mut v = "0".to_string();
+                for e in es {
+                    v = self.expr(e, env, level, body);
+                }
+                if ty == Ty::Unit {
+                    "0".to_string()
+                } else {
+                    v
+                }
+            }
+            TypedExpr::Let(ref decs, ref e, ty) => {
+                let mut env = env.clone();
+                let mut i = 0;
+                while i < decs.len() {
+                    match decs[i] {
+                        TypedDec::Var(ref name, id, var_ty, ref init) => {
+                            let init = self.expr(init, &env, level, body);
+                            let escape = self.escapes.escapes(id);
+                            let cname = self.local(name);
+                            let ctype = self.ctype(var_ty);
+                            if escape {
+                                self.levels[level].env.push(decl(&ctype, &cname));
+                                let path = self.env_path(level, level);
+                                body.line(&format!("{}{} = {};", path, cname, init));
+                            } else {
+                                body.line(&format!("{} = {};", decl(&ctype, &cname), init));
+                            }
+                            env.insert(id, Entry::Var(level, cname, escape));
+                            i += 1;
+                        }
+                        TypedDec::Fun(_) => {
+                            let n = decs[i..]
+                                .iter()
+                                .take_while(|dec| matches!(dec, TypedDec::Fun(_)))
+                                .count();
+                            self.fun_group(&decs[i..i + n], &mut env, level);
+                            i += n;
+                        }
+                    }
+                }
+                let v = self.expr(e, &env, level, body);
+                if ty == Ty::Unit {
+                    "0".to_string()
+                } else {
+                    v
+                }
+            }
+            TypedExpr::For(ref var, id, ref lo, ref hi, ref e) => {
+                let ops = self.operands(&[lo, hi], env, level, body);
+                let limit = self.temp(Ty::Int, &ops[1], body);
+                let escape = self.escapes.escapes(id);
+                let cname = self.local(var);
+                let i = if escape {
+                    self.levels[level].env.push(decl("int64_t", &cname));
+                    let i = self.env_path(level, level) + &cname;
+                    body.line(&format!("{} = {};", i, ops[0]));
+                    i
+                } else {
+                    body.line(&format!("int64_t {} = {};", cname, ops[0]));
+                    cname.clone()
+                };
+                let mut env = env.clone();
+                env.insert(id, Entry::Var(level, cname, escape));
+                // compares before incrementing, so that hi may be the largest int
+                body.open(&format!("if ({} <= {})", i, limit));
+                body.open("for (;;)");
+                self.expr(e, &env, level, body);
+                body.line(&format!("if ({} >= {}) break;", i, limit));
+                body.line(&format!("{}++;", i));
+                body.close();
+                body.close();
+                "0".to_string()
+            }
+            TypedExpr::While(ref c, ref e) => {
+                let (c_body, c) = self.sub(c, env, level, body.depth + 1);
+                if c_body.code.is_empty() {
+                    body.open(&format!("while ({})", c));
+                } else {
+                    body.open("for (;;)");
+                    body.code += &c_body.code;
+                    body.line(&format!("if (!{}) break;", c));
+                }
+                self.expr(e, env, level, body);
+                body.close();
+                "0".to_string()
+            }
+            TypedExpr::FunApp(ref name, id, _, ret, ref args) => {
+                let args: Vec<&TypedExpr> = args.iter().collect();
+                let mut vals = self.operands(&args, env, level, body);
+                let fun = match env.get(&id) {
+                    Some(Entry::Fun(fun_level, cname)) => {
+                        let (fun_level, cname) = (*fun_level, cname.clone());
+                        let parent = self.levels[fun_level].parent.unwrap();
+                        vals.insert(0, self.env_ptr(level, parent));
+                        cname
+                    }
+                    _ => format!("tiger_{}", name),
+                };
+                let call = format!("{}({})", fun, vals.join(", "));
+                if ret == Ty::Unit {
+                    body.line(&format!("{};", call));
+                    "0".to_string()
+                } else {
+                    self.temp(ret, &call, body)
+                }
+            }
+            TypedExpr::NewStruct(ty, ref fields) => {
+                let record = self.ctype(ty);
+                let size = format!("alloc(sizeof({}))", record.trim_end_matches(" *"));
+                let r = self.temp(ty, &size, body);
+                for (name, e) in fields {
+                    let v = self.expr(e, env, level, body);
+                    body.line(&format!("{}->{} = {};", r, field(name), v));
+                }
+                r
+            }
+            TypedExpr::NewArray(ty, ref n, ref init) => {
+                let ops = self.operands(&[n, init], env, level, body);
+                let n = self.temp(Ty::Int, &ops[0], body);
+                body.line(&format!("if ({} < 0) fail(\"array of negative size\");", n));
+                // the length is the word before the elements, for tiger_checkIndex
+                let words = self.local("words");
+                body.line(&format!("int64_t *{} = alloc(({} + 1) * 8);", words, n));
+                body.line(&format!("*{}++ = {};", words, n));
+                let a = self.temp(ty, &format!("(void *){}", words), body);
+                let i = self.local("i");
+                body.line(&format!(
+                    "for (int64_t {0} = 0; {0} < {1}; {0}++) {2}[{0}] = {3};",
+                    i, n, a, ops[1]
+                ));
+                a
+            }
+            TypedExpr::Break => {
+                body.line("break;");
+                "0".to_string()
+            }
+        }
+    }
+
+    /* Declares every function of a group first, so that members can call each other. */
+    fn fun_group(&mut self, decs: &[TypedDec], env: &mut Env, level: usize) {
+        let mut levels = Vec::with_capacity(decs.len());
+        for dec in decs {
+            if let TypedDec::Fun((ref name, id, ..)) = *dec {
+                let cname = self.fun_name(name);
+                self.levels.push(Level {
+                    name: cname.clone(),
+                    parent: Some(level),
+                    env: Vec::new(),
+                    uses_env: false,
+                });
+                let fun_level = self.levels.len() - 1;
+                env.insert(id, Entry::Fun(fun_level, cname));
+                levels.push(fun_level);
+            }
+        }
+        for (dec, fun_level) in decs.iter().zip(levels) {
+            if let TypedDec::Fun((_, _, ref params, ret, ref e)) = *dec {
+                let mut body = Body {
+                    code: String::new(),
+                    depth: 1,
+                };
+                let mut formals = vec![format!("struct {}_env *link", self.levels[level].name)];
+                let mut body_env = env.clone();
+                for &(ref param, id, ty) in params {
+                    let escape = self.escapes.escapes(id);
+                    let cname = self.local(param);
+                    let formal = decl(&self.ctype(ty), &cname);
+                    if escape {
+                        self.levels[fun_level].env.push(formal.clone());
+                        let path = self.env_path(fun_level, fun_level);
+                        body.line(&format!("{}{} = {};", path, cname, cname));
+                    }
+                    formals.push(formal);
+                    body_env.insert(id, Entry::Var(fun_level, cname, escape));
+                }
+                let v = self.expr(e, &body_env, fun_level, &mut body);
+                let ret = if ret == Ty::Unit {
+                    "void".to_string()
+                } else {
+                    body.line(&format!("return {};", v));
+                    self.ctype(ret)
+                };
+                let name = &self.levels[fun_level].name;
+                let head = decl(&ret, &format!("{}({})", name, formals.join(", ")));
+                self.function(fun_level, format!("static {}", head), "link", body);
+            }
+        }
+    }
+
+    /* Adds the function at level, with its environment if it uses one. */
+    fn function(&mut self, level: usize, head: String, link: &str, body: Body) {
+        let name = &self.levels[level].name;
+        let mut text = format!("{} {{\n", head);
+        if self.levels[level].uses_env {
+            text += &format!("    struct {}_env env = {{{}}};\n", name, link);
+        }
+        text += &body.code;
+        text += "}\n";
+        self.protos.push(format!("{};", head));
+        self.funs.push(text);
+    }
+
+    fn write<W: Write>(&self, mut out: W) -> io::Result<()> {
+        writeln!(
+            out,
+            "/* Generated by rust-tigress; compile with any C99 compiler. */"
+        )?;
+        out.write_all(RUNTIME.as_bytes())?;
+        writeln!(out)?;
+        for (i, def) in self.types.iter().enumerate() {
+            if let TyDef::Record(ref name, _) = *def {
+                writeln!(out, "struct {}_{};", name, i)?;
+            }
+        }
+        for level in &self.levels {
+            writeln!(out, "struct {}_env;", level.name)?;
+        }
+        for (i, def) in self.types.iter().enumerate() {
+            if let TyDef::Record(ref name, ref fields) = *def {
+                writeln!(out, "\nstruct {}_{} {{", name, i)?;
+                for &(ref f, ty) in fields {
+                    writeln!(out, "    {};", decl(&self.ctype(ty), &field(f)))?;
+                }
+                if fields.is_empty() {
+                    // C has no empty structs
+                    writeln!(out, "    char unused;")?;
+                }
+                writeln!(out, "}};")?;
+            }
+        }
+        if !self.strings.is_empty() {
+            writeln!(out)?;
+        }
+        for (i, s) in self.strings.iter().enumerate() {
+            writeln!(
+                out,
+                "static struct {{ int64_t length; unsigned char chars[{}]; }} str{} = {{{}, {}}};",
+                s.len() + 1,
+                i,
+                s.len(),
+                c_string(s)
+            )?;
+        }
+        for level in self.levels.iter().filter(|l| l.uses_env) {
+            writeln!(out, "\nstruct {}_env {{", level.name)?;
+            match level.parent {
+                Some(parent) => {
+                    writeln!(out, "    struct {}_env *link;", self.levels[parent].name)?
+                }
+                None => writeln!(out, "    void *link;")?,
+            }
+            for member in &level.env {
+                writeln!(out, "    {};", member)?;
+            }
+            writeln!(out, "}};")?;
+        }
+        writeln!(out)?;
+        for proto in &self.protos {
+            writeln!(out, "{}", proto)?;
+        }
+        for fun in &self.funs {
+            writeln!(out, "\n{}", fun.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+/*
+ * Writes tast, typed with the given types, as C. escapes come from the resolution of the
+ * located tree tast was typed from, as for translate. The program gives its value if it is an
+ * int, 0 otherwise, which the runtime makes the exit status.
+ */
+pub fn write<W: Write>(
+    out: W,
+    tast: &TypedExpr,
+    types: &TypeTable,
+    escapes: &Escapes,
+) -> io::Result<()> {
+    let mut labels: HashSet<String> = translate::RUNTIME
+        .iter()
+        .chain(library::NAMES)
+        .map(|s| s.to_string())
+        .collect();
+    labels.insert(MAIN.to_string());
+    let mut ctx = Ctx {
+        types,
+        escapes,
+        levels: vec![Level {
+            name: format!("tiger_{}", MAIN),
+            parent: None,
+            env: Vec::new(),
+            uses_env: false,
+        }],
+        labels,
+        names: 0,
+        strings: Vec::new(),
+        protos: Vec::new(),
+        funs: Vec::new(),
+    };
+    let mut body = Body {
+        code: String::new(),
+        depth: 1,
+    };
+    let v = ctx.expr(tast, &Env::new(), 0, &mut body);
+    if ty_of_ast(tast) == Ty::Int {
+        body.line(&format!("return {};", v));
+    } else {
+        body.line("return 0;");
+    }
+    let head = format!("int64_t tiger_{}(void)", MAIN);
+    ctx.function(0, head, "NULL", body);
+    ctx.write(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{escape, resolve, typing};
+
+    fn generate(src: &str) -> String {
+        let (ast, types, tast) = typing::check(src, &[]).unwrap();
+        let (res, _) = resolve::f(&ast, &[]);
+        let mut out = Vec::new();
+        write(&mut out, &tast, &types, &escape::f(&res)).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn env_test() {
+        let out = generate(
+            "let function f(x: int, y: int): int =
+  let function g(): int = x + 1 in g() + y end
+in f(1, 2) end",
+        );
+        // x escapes to g, so it is copied into the environment of f; y stays a parameter
+        assert!(out.contains(
+            "struct tiger_f_env {\n    struct tiger_tigermain_env *link;\n    int64_t x_1;\n};"
+        ));
+        assert!(out.contains("static int64_t tiger_g(struct tiger_f_env *link) {"));
+        assert!(out.contains("    env.x_1 = x_1;"));
+        assert!(out.contains("(int64_t)((uint64_t)env.link->x_1 + (uint64_t)1)"));
+        assert!(out.contains("tiger_g(&env)"));
+        assert!(out.contains("(int64_t)((uint64_t)t3 + (uint64_t)y_2)"));
+    }
+
+    #[test]
+    fn order_test() {
+        // the first operand is read before the second assigns to it
+        let out = generate("let var x := 1 in x + (x := 2; x) end");
+        assert!(out.contains(
+            "int64_t t2 = x_1;\n    x_1 = 2;\n    return (int64_t)((uint64_t)t2 + (uint64_t)x_1);"
+        ));
+        assert_eq!(c_string("a\"?\n"), "\"a\\\"\\?\\012\"");
+    }
+}
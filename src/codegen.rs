@@ -0,0 +1,407 @@
+use crate::assem::Instr;
+use crate::frame::{x64, Frame, X64Frame};
+use crate::tree::{BinOp, Exp, Gen, Label, RelOp, Stm, Temp};
+
+/*
+ * Instruction selection for x86-64 by maximal munch over canonical statements, in the
+ * Intel syntax of the GNU assembler. Each tree pattern covers as large a tile as one
+ * instruction can: constants become immediates and additions of a constant to an address
+ * become displacements.
+ */
+
+/*
+ * The assembler symbol of a label. Labels made by Gen stay local to the file; the others
+ * name functions and get a prefix, so that neither Tiger functions nor the runtime's
+ * clash with the C library.
+ */
+pub fn symbol(label: &Label) -> String {
+    let l = &label.0;
+    if l.starts_with('L') && l.len() > 1 && l[1..].bytes().all(|b| b.is_ascii_digit()) {
+        format!(".{}", l)
+    } else {
+        format!("tiger_{}", l)
+    }
+}
+
+pub(crate) fn is_precolored(t: Temp) -> bool {
+    t.0 < x64::REGISTERS.len()
+}
+
+/* k as an immediate operand, if it fits in one. */
+fn imm(e: &Exp) -> Option<i64> {
+    match *e {
+        Exp::Const(k) if i32::try_from(k).is_ok() => Some(k),
+        _ => None,
+    }
+}
+
+/* The displacement k in an address, as in [rbp - 8]. */
+pub(crate) fn disp(k: i64) -> String {
+    match k {
+        0 => String::new(),
+        k if k < 0 => format!(" - {}", -k),
+        k => format!(" + {}", k),
+    }
+}
+
+struct Codegen<'g> {
+    instrs: Vec<Instr>,
+    gen: &'g mut Gen,
+}
+
+impl Codegen<'_> {
+    fn emit(&mut self, instr: Instr) {
+        self.instrs.push(instr);
+    }
+
+    fn mov(&mut self, dst: Temp, src: Temp) {
+        self.emit(Instr::Move {
+            assem: "mov 'd0, 's0".to_string(),
+            dst,
+            src,
+        });
+    }
+
+    /* The base and displacement of the address a. */
+    fn address(&mut self, a: Exp) -> (Temp, i64) {
+        match a {
+            Exp::Binop(BinOp::Plus, e, k) | Exp::Binop(BinOp::Plus, k, e) if imm(&k).is_some() => {
+                (self.exp(*e), imm(&k).unwrap())
+            }
+            Exp::Binop(BinOp::Minus, e, k) if imm(&k).is_some() => {
+                (self.exp(*e), -imm(&k).unwrap())
+            }
+            a => (self.exp(a), 0),
+        }
+    }
+
+    fn stm(&mut self, s: Stm) {
+        match s {
+            Stm::Move(dst, src) => match (*dst, *src) {
+                (Exp::Temp(t), Exp::Call(f, args)) => {
+                    self.call(*f, args);
+                    self.mov(t, x64::RAX);
+                }
+                (Exp::Temp(t), Exp::Const(k)) => {
+                    self.emit(Instr::oper(format!("mov 'd0, {}", k), vec![t], vec![]))
+                }
+                (Exp::Temp(t), Exp::Mem(a)) => {
+                    let (base, k) = self.address(*a);
+                    let assem = format!("mov 'd0, QWORD PTR ['s0{}]", disp(k));
+                    self.emit(Instr::oper(assem, vec![t], vec![base]));
+                }
+                (Exp::Temp(t), src) => {
+                    let src = self.exp(src);
+                    self.mov(t, src);
+                }
+                (Exp::Mem(a), src) => {
+                    let (base, k) = self.address(*a);
+                    match imm(&src) {
+                        Some(v) => {
+                            let assem = format!("mov QWORD PTR ['s0{}], {}", disp(k), v);
+                            self.emit(Instr::oper(assem, vec![], vec![base]));
+                        }
+                        None => {
+                            let src = self.exp(src);
+                            let assem = format!("mov QWORD PTR ['s0{}], 's1", disp(k));
+                            self.emit(Instr::oper(assem, vec![], vec![base, src]));
+                        }
+                    }
+                }
+                (dst, _) => panic!("internal error: move to {:?}", dst),
+            },
+            Stm::Exp(e) => match *e {
+                Exp::Call(f, args) => self.call(*f, args),
+                e => {
+                    self.exp(e);
+                }
+            },
+            Stm::Jump(e, labels) => match *e {
+                Exp::Name(ref l) => self.emit(Instr::Oper {
+                    assem: format!("jmp {}", symbol(l)),
+                    dst: vec![],
+                    src: vec![],
+                    jump: Some(labels),
+                }),
+                e => {
+                    let t = self.exp(e);
+                    self.emit(Instr::Oper {
+                        assem: "jmp 's0".to_string(),
+                        dst: vec![],
+                        src: vec![t],
+                        jump: Some(labels),
+                    });
+                }
+            },
+            Stm::Cjump(op, a, b, t, f) => {
+                let a = self.exp(*a);
+                match imm(&b) {
+                    Some(k) => self.emit(Instr::oper(format!("cmp 's0, {}", k), vec![], vec![a])),
+                    None => {
+                        let b = self.exp(*b);
+                        self.emit(Instr::oper("cmp 's0, 's1", vec![], vec![a, b]));
+                    }
+                }
+                let jcc = match op {
+                    RelOp::Eq => "je",
+                    RelOp::Ne => "jne",
+                    RelOp::Lt => "jl",
+                    RelOp::Gt => "jg",
+                    RelOp::Le => "jle",
+                    RelOp::Ge => "jge",
+                    RelOp::Ult => "jb",
+                    RelOp::Ule => "jbe",
+                    RelOp::Ugt => "ja",
+                    RelOp::Uge => "jae",
+                };
+                self.emit(Instr::Oper {
+                    assem: format!("{} {}", jcc, symbol(&t)),
+                    dst: vec![],
+                    src: vec![],
+                    jump: Some(vec![t, f]),
+                });
+            }
+            Stm::Label(label) => self.emit(Instr::Label {
+                assem: format!("{}:", symbol(&label)),
+                label,
+            }),
+            Stm::Seq(..) => panic!("internal error: SEQ in canonical statements"),
+        }
+    }
+
+    /* Evaluates e into a temporary. */
+    fn exp(&mut self, e: Exp) -> Temp {
+        match e {
+            Exp::Temp(t) => t,
+            Exp::Const(k) => {
+                let r = self.gen.temp();
+                self.emit(Instr::oper(format!("mov 'd0, {}", k), vec![r], vec![]));
+                r
+            }
+            Exp::Name(ref l) => {
+                let r = self.gen.temp();
+                let assem = format!("lea 'd0, [rip + {}]", symbol(l));
+                self.emit(Instr::oper(assem, vec![r], vec![]));
+                r
+            }
+            Exp::Mem(a) => {
+                let (base, k) = self.address(*a);
+                let r = self.gen.temp();
+                let assem = format!("mov 'd0, QWORD PTR ['s0{}]", disp(k));
+                self.emit(Instr::oper(assem, vec![r], vec![base]));
+                r
+            }
+            Exp::Binop(BinOp::Div, a, b) => {
+                let (a, b) = (self.exp(*a), self.exp(*b));
+                let r = self.gen.temp();
+                self.mov(x64::RAX, a);
+                self.emit(Instr::oper("cqo", vec![x64::RDX], vec![x64::RAX]));
+                self.emit(Instr::oper(
+                    "idiv 's0",
+                    vec![x64::RAX, x64::RDX],
+                    vec![b, x64::RAX, x64::RDX],
+                ));
+                self.mov(r, x64::RAX);
+                r
+            }
+            Exp::Binop(op @ (BinOp::Lshift | BinOp::Rshift | BinOp::Arshift), a, b) => {
+                let (a, b) = (self.exp(*a), self.exp(*b));
+                let r = self.gen.temp();
+                self.mov(r, a);
+                self.mov(x64::RCX, b);
+                let shift = match op {
+                    BinOp::Lshift => "shl",
+                    BinOp::Rshift => "shr",
+                    _ => "sar",
+                };
+                let assem = format!("{} 'd0, cl", shift);
+                self.emit(Instr::oper(assem, vec![r], vec![r, x64::RCX]));
+                r
+            }
+            Exp::Binop(op, a, b) => {
+                let ins = match op {
+                    BinOp::Plus => "add",
+                    BinOp::Minus => "sub",
+                    BinOp::Mul => "imul",
+                    BinOp::And => "and",
+                    BinOp::Or => "or",
+                    BinOp::Xor => "xor",
+                    _ => unreachable!(),
+                };
+                let a = self.exp(*a);
+                let r = self.gen.temp();
+                self.mov(r, a);
+                match imm(&b) {
+                    Some(k) => {
+                        self.emit(Instr::oper(format!("{} 'd0, {}", ins, k), vec![r], vec![r]))
+                    }
+                    None => {
+                        let b = self.exp(*b);
+                        let assem = format!("{} 'd0, 's1", ins);
+                        self.emit(Instr::oper(assem, vec![r], vec![r, b]));
+                    }
+                }
+                r
+            }
+            Exp::Call(f, args) => {
+                self.call(*f, args);
+                let r = self.gen.temp();
+                self.mov(r, x64::RAX);
+                r
+            }
+            Exp::Eseq(..) => panic!("internal error: ESEQ in canonical statements"),
+        }
+    }
+
+    /*
+     * A call following the System V convention: arguments after the sixth are pushed
+     * right to left, with padding that keeps rsp 16-byte aligned, and popped afterwards.
+     */
+    fn call(&mut self, f: Exp, args: Vec<Exp>) {
+        let Exp::Name(f) = f else {
+            panic!("internal error: computed call");
+        };
+        let args: Vec<Temp> = args.into_iter().map(|arg| self.exp(arg)).collect();
+        let stacked = args.len().saturating_sub(x64::ARGS.len());
+        if stacked % 2 == 1 {
+            self.emit(Instr::oper("sub rsp, 8", vec![], vec![]));
+        }
+        for &arg in args[x64::ARGS.len().min(args.len())..].iter().rev() {
+            self.emit(Instr::oper("push 's0", vec![], vec![arg]));
+        }
+        for (&arg, &reg) in args.iter().zip(&x64::ARGS) {
+            self.mov(reg, arg);
+        }
+        let in_regs = x64::ARGS[..args.len().min(x64::ARGS.len())].to_vec();
+        let assem = format!("call {}", symbol(&f));
+        self.emit(Instr::oper(assem, x64::CALLER_SAVES.to_vec(), in_regs));
+        if stacked > 0 {
+            let size = (stacked + stacked % 2) as i64 * X64Frame::WORD_SIZE;
+            self.emit(Instr::oper(format!("add rsp, {}", size), vec![], vec![]));
+        }
+    }
+}
+
+/*
+ * The instructions of a function body given as canonical statements. Callee-saved
+ * registers are moved to temporaries on entry and back on exit, and a final empty
+ * instruction marks them and the result as live at the end, as Appel's procEntryExit2.
+ */
+pub fn f(stms: Vec<Stm>, gen: &mut Gen) -> Vec<Instr> {
+    let mut codegen = Codegen {
+        instrs: Vec::new(),
+        gen,
+    };
+    let saves: Vec<(Temp, Temp)> = x64::CALLEE_SAVES
+        .iter()
+        .map(|&reg| (codegen.gen.temp(), reg))
+        .collect();
+    for &(t, reg) in &saves {
+        codegen.mov(t, reg);
+    }
+    for stm in stms {
+        codegen.stm(stm);
+    }
+    for &(t, reg) in &saves {
+        codegen.mov(reg, t);
+    }
+    let mut live = vec![x64::RAX, x64::RSP, x64::RBP];
+    live.extend(x64::CALLEE_SAVES);
+    codegen.emit(Instr::oper("", vec![], live));
+    codegen.instrs
+}
+
+/* The whole function in assembly: body between a prologue and an epilogue, as procEntryExit3. */
+pub fn proc_entry_exit3(frame: &X64Frame, body: &[Instr]) -> String {
+    let name = symbol(frame.name());
+    // rsp is 16-byte aligned at calls: the return address and rbp take 16 bytes
+    let size = (frame.frame_size() + 15) / 16 * 16;
+    let mut out = format!("\t.globl {0}\n\t.type {0}, @function\n{0}:\n", name);
+    out += "\tpush rbp\n\tmov rbp, rsp\n";
+    if size > 0 {
+        out += &format!("\tsub rsp, {}\n", size);
+    }
+    for instr in body {
+        let text = instr.format(&X64Frame::temp_name);
+        match *instr {
+            Instr::Label { .. } => out += &format!("{}\n", text),
+            _ if text.is_empty() => {}
+            // moves of a register to itself are left by allocation
+            Instr::Move { dst, src, .. } if dst == src => {}
+            _ => out += &format!("\t{}\n", text),
+        }
+    }
+    out += "\tmov rsp, rbp\n\tpop rbp\n\tret\n";
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::{binop, mem, mov};
+
+    #[test]
+    fn munch_test() {
+        let mut gen = Gen::new();
+        let t = Exp::Temp(gen.temp());
+        let stms = vec![
+            // a displacement and an immediate
+            mov(
+                mem(binop(BinOp::Plus, Exp::Temp(x64::RBP), Exp::Const(-8))),
+                Exp::Const(3),
+            ),
+            mov(
+                t.clone(),
+                binop(BinOp::Mul, t.clone(), mem(Exp::Temp(x64::RBP))),
+            ),
+        ];
+        let instrs = f(stms, &mut gen);
+        // without the saves and restores of the five callee-saved registers, and the sink
+        let text: Vec<String> = instrs[5..instrs.len() - 6]
+            .iter()
+            .map(|i| i.format(&X64Frame::temp_name))
+            .collect();
+        assert_eq!(
+            text,
+            vec![
+                "mov QWORD PTR [rbp - 8], 3",
+                "mov t106, t100",
+                "mov t107, QWORD PTR [rbp]",
+                "imul t106, t107",
+                "mov t100, t106",
+            ]
+        );
+        assert_eq!(symbol(&Label("L12".to_string())), ".L12");
+        assert_eq!(symbol(&Label("getchar".to_string())), "tiger_getchar");
+    }
+
+    #[test]
+    fn call_test() {
+        // the eighth and seventh arguments are pushed, without padding
+        let mut gen = Gen::new();
+        let args = (0..8).map(Exp::Const).collect();
+        let call = Exp::Call(Box::new(Exp::Name(Label("f".to_string()))), args);
+        let instrs = f(vec![Stm::Exp(Box::new(call))], &mut gen);
+        let pushes = instrs
+            .iter()
+            .filter(|i| i.format(&X64Frame::temp_name).starts_with("push"))
+            .count();
+        assert_eq!(pushes, 2);
+        let calls: Vec<&Instr> = instrs
+            .iter()
+            .filter(|i| i.format(&X64Frame::temp_name) == "call tiger_f")
+            .collect();
+        assert_eq!(calls[0].uses(), &x64::ARGS);
+        assert!(instrs
+            .iter()
+            .any(|i| i.format(&X64Frame::temp_name) == "add rsp, 16"));
+        // after allocation, only registers are left
+        let mut frame = X64Frame::new(Label("f".to_string()), &[], &mut gen);
+        let allocation = crate::regalloc::f(instrs, &mut frame, &mut gen);
+        assert!(allocation.instrs.iter().all(|i| i
+            .uses()
+            .iter()
+            .chain(i.defs())
+            .all(|&t| is_precolored(t))));
+    }
+}
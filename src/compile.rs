@@ -0,0 +1,114 @@
+use crate::assem::Instr;
+use crate::canon;
+use crate::codegen;
+use crate::frame::{Fragment, Frame, X64Frame};
+use crate::regalloc;
+use crate::translate::MAIN;
+use crate::tree::{mov, Exp, Gen, Stm};
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::Command;
+use std::{env, fs, process};
+
+/*
+ * The native backend for x86-64 Linux: every function is canonicalized, its instructions
+ * selected and its temporaries given registers, and the assembly is linked with the
+ * runtime in runtime.c by the system's C compiler.
+ */
+
+pub const RUNTIME: &str = include_str!("runtime.c");
+
+/* s as the operand of .ascii. */
+fn ascii(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'"' | b'\\' => {
+                out.push('\\');
+                out.push(b as char);
+            }
+            0x20..=0x7e => out.push(b as char),
+            _ => out += &format!("\\{:03o}", b),
+        }
+    }
+    out
+}
+
+/*
+ * The instructions of a function body, before register allocation. Unless the program
+ * gives an int, tigermain returns 0, which the runtime makes the exit status.
+ */
+pub fn instructions(body: Stm, frame: &X64Frame, int_result: bool, gen: &mut Gen) -> Vec<Instr> {
+    let mut stms = canon::f(body, gen);
+    if frame.name().0 == MAIN && !int_result {
+        stms.push(mov(Exp::Temp(X64Frame::rv()), Exp::Const(0)));
+    }
+    codegen::f(stms, gen)
+}
+
+/* Writes the assembly of a translated program, giving the number of spills of each function. */
+pub fn assembly<W: Write>(
+    mut out: W,
+    fragments: Vec<Fragment<X64Frame>>,
+    int_result: bool,
+    gen: &mut Gen,
+) -> io::Result<Vec<(String, usize)>> {
+    writeln!(out, "\t.intel_syntax noprefix\n\t.text")?;
+    let mut strings = Vec::new();
+    let mut spills = Vec::new();
+    for fragment in fragments {
+        match fragment {
+            Fragment::Proc { body, mut frame } => {
+                let instrs = instructions(body, &frame, int_result, gen);
+                let allocation = regalloc::f(instrs, &mut frame, gen);
+                out.write_all(codegen::proc_entry_exit3(&frame, &allocation.instrs).as_bytes())?;
+                spills.push((frame.name().0.clone(), allocation.spills));
+            }
+            Fragment::Str(label, s) => strings.push((label, s)),
+        }
+    }
+    writeln!(out, "\t.section .rodata")?;
+    for (label, s) in strings {
+        writeln!(out, "\t.p2align 3\n{}:", codegen::symbol(&label))?;
+        writeln!(out, "\t.quad {}\n\t.ascii \"{}\"", s.len(), ascii(&s))?;
+    }
+    // the stack need not be executable
+    writeln!(out, "\t.section .note.GNU-stack,\"\",@progbits")?;
+    Ok(spills)
+}
+
+/* Assembles asm and links it with the runtime into the executable output, with $CC or cc. */
+pub fn link(asm: &[u8], output: &Path) -> Result<(), String> {
+    let dir = env::temp_dir().join(format!("rust-tigress-{}", process::id()));
+    let result = (|| {
+        fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let (asm_path, runtime_path) = (dir.join("prog.s"), dir.join("runtime.c"));
+        fs::write(&asm_path, asm).map_err(|e| e.to_string())?;
+        fs::write(&runtime_path, RUNTIME).map_err(|e| e.to_string())?;
+        let cc = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+        let status = Command::new(&cc)
+            .arg("-o")
+            .arg(output)
+            .arg(&asm_path)
+            .arg(&runtime_path)
+            .status()
+            .map_err(|e| format!("cannot run {}: {}", cc, e))?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(format!("{} failed with {}", cc, status))
+        }
+    })();
+    fs::remove_dir_all(&dir).ok();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ascii_test() {
+        assert_eq!(ascii("a\"b\\c\n"), "a\\\"b\\\\c\\012");
+    }
+}
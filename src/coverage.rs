@@ -0,0 +1,334 @@
+use crate::ast::{Dec, Expr, LValue, LineIndex, Span};
+use crate::interpret::Scope;
+use crate::trace::Tracer;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+
+/* How often one arm of a branch was taken. */
+#[derive(Clone, Copy, Debug)]
+enum Arm {
+    Node(Span),
+    /* implicit `else` of an `if` without one: the if's count minus the then-arm's */
+    Rest(Span, Span),
+}
+
+struct Branch {
+    at: Span,
+    arms: Vec<(&'static str, Arm)>,
+}
+
+/*
+ * A Tracer that counts how often every located expression was evaluated.
+ * The tree is walked up front, so expressions that never run are reported with 0.
+ */
+pub struct Coverage<'a> {
+    src: &'a str,
+    lines: LineIndex,
+    hits: HashMap<Span, u64>,
+    branches: Vec<Branch>,
+    functions: Vec<(String, Span)>, // name and body
+}
+
+fn span_of(e: &Expr) -> Option<Span> {
+    match *e {
+        Expr::Spanned(span, _) => Some(span),
+        _ => None,
+    }
+}
+
+impl<'a> Coverage<'a> {
+    pub fn new(ast: &Expr, src: &'a str) -> Self {
+        let mut cov = Coverage {
+            src,
+            lines: LineIndex::new(src),
+            hits: HashMap::new(),
+            branches: Vec::new(),
+            functions: Vec::new(),
+        };
+        cov.collect(ast, None);
+        cov
+    }
+
+    /* here is the span of the innermost Expr::Spanned around e */
+    fn collect(&mut self, e: &Expr, here: Option<Span>) {
+        match *e {
+            Expr::Spanned(span, ref e) => {
+                self.hits.insert(span, 0);
+                self.collect(e, Some(span));
+            }
+            Expr::IfNode(ref c, ref e1, ref e2) => {
+                if let (Some(at), Some(then)) = (here, span_of(e1)) {
+                    let other = match span_of(e2) {
+                        Some(span) => Arm::Node(span),
+                        None => Arm::Rest(at, then),
+                    };
+                    self.branches.push(Branch {
+                        at,
+                        arms: vec![("then", Arm::Node(then)), ("else", other)],
+                    });
+                }
+                self.collect(c, None);
+                self.collect(e1, None);
+                self.collect(e2, None);
+            }
+            Expr::For(_, ref st, ref en, ref body) => {
+                self.loop_branch(here, body);
+                self.collect(st, None);
+                self.collect(en, None);
+                self.collect(body, None);
+            }
+            Expr::Do(ref c, ref body) => {
+                self.loop_branch(here, body);
+                self.collect(c, None);
+                self.collect(body, None);
+            }
+            Expr::Let(ref decs, ref body) => {
+                for dec in decs {
+                    match *dec {
+                        Dec::Var(_, _, ref e, _) => self.collect(e, None),
+                        Dec::Fun(ref name, _, _, ref e) => {
+                            if let Some(span) = span_of(e) {
+                                self.functions.push((name.clone(), span));
+                            }
+                            self.collect(e, None);
+                        }
+                        Dec::Type(..) => {}
+                    }
+                }
+                self.collect(body, None);
+            }
+            Expr::LVal(ref l) | Expr::LAsgn(ref l, _) => {
+                self.collect_lval(l);
+                if let Expr::LAsgn(_, ref e) = *e {
+                    self.collect(e, None);
+                }
+            }
+            Expr::Neg(ref e) => self.collect(e, None),
+            Expr::OpNode(_, ref e1, ref e2) | Expr::NewArray(_, ref e1, ref e2) => {
+                self.collect(e1, None);
+                self.collect(e2, None);
+            }
+            Expr::Seq(ref es) | Expr::FunApp(_, ref es) => {
+                for e in es {
+                    self.collect(e, None);
+                }
+            }
+            Expr::NewStruct(_, ref fields) => {
+                for (_, e) in fields {
+                    self.collect(e, None);
+                }
+            }
+            Expr::Num(_) | Expr::Str(_) | Expr::Nil | Expr::Break => {}
+        }
+    }
+
+    fn collect_lval(&mut self, l: &LValue) {
+        match *l {
+            LValue::Id(_) => {}
+            LValue::Mem(ref l, _) => self.collect_lval(l),
+            LValue::Idx(ref l, ref e) => {
+                self.collect_lval(l);
+                self.collect(e, None);
+            }
+        }
+    }
+
+    fn loop_branch(&mut self, here: Option<Span>, body: &Expr) {
+        if let (Some(at), Some(body)) = (here, span_of(body)) {
+            self.branches.push(Branch {
+                at,
+                arms: vec![("body", Arm::Node(body))],
+            });
+        }
+    }
+
+    pub fn hits(&self, span: Span) -> Option<u64> {
+        self.hits.get(&span).copied()
+    }
+
+    fn arm_count(&self, arm: Arm) -> u64 {
+        match arm {
+            Arm::Node(span) => self.hits[&span],
+            Arm::Rest(whole, then) => self.hits[&whole] - self.hits[&then],
+        }
+    }
+
+    fn line(&self, span: Span) -> usize {
+        self.lines.line_col(span.lo).0
+    }
+
+    /* Per line, the count of the most executed expression starting on it. */
+    fn line_counts(&self) -> BTreeMap<usize, u64> {
+        let mut counts = BTreeMap::new();
+        for (&span, &n) in &self.hits {
+            let c = counts.entry(self.line(span)).or_insert(0);
+            *c = n.max(*c);
+        }
+        counts
+    }
+
+    fn sorted_branches(&self) -> Vec<&Branch> {
+        let mut branches: Vec<&Branch> = self.branches.iter().collect();
+        branches.sort_by_key(|b| b.at);
+        branches
+    }
+
+    /* Writes one LCOV record (as read by genhtml) for the source file called path. */
+    pub fn write_lcov<W: Write>(&self, mut out: W, path: &str) -> io::Result<()> {
+        writeln!(out, "TN:")?;
+        writeln!(out, "SF:{}", path)?;
+        let mut functions: Vec<&(String, Span)> = self.functions.iter().collect();
+        functions.sort_by_key(|f| f.1);
+        for (name, span) in &functions {
+            writeln!(out, "FN:{},{}", self.line(*span), name)?;
+        }
+        for (name, span) in &functions {
+            writeln!(out, "FNDA:{},{}", self.hits[span], name)?;
+        }
+        writeln!(out, "FNF:{}", functions.len())?;
+        let fnh = functions.iter().filter(|f| self.hits[&f.1] > 0).count();
+        writeln!(out, "FNH:{}", fnh)?;
+        let (mut brf, mut brh) = (0, 0);
+        for (block, b) in self.sorted_branches().into_iter().enumerate() {
+            let reached = self.hits[&b.at] > 0;
+            for (i, &(_, arm)) in b.arms.iter().enumerate() {
+                let taken = self.arm_count(arm);
+                let taken_str = if reached {
+                    taken.to_string()
+                } else {
+                    "-".to_string()
+                };
+                writeln!(
+                    out,
+                    "BRDA:{},{},{},{}",
+                    self.line(b.at),
+                    block,
+                    i,
+                    taken_str
+                )?;
+                brf += 1;
+                if taken > 0 {
+                    brh += 1;
+                }
+            }
+        }
+        writeln!(out, "BRF:{}", brf)?;
+        writeln!(out, "BRH:{}", brh)?;
+        let counts = self.line_counts();
+        for (line, n) in &counts {
+            writeln!(out, "DA:{},{}", line, n)?;
+        }
+        writeln!(out, "LF:{}", counts.len())?;
+        writeln!(out, "LH:{}", counts.values().filter(|&&n| n > 0).count())?;
+        writeln!(out, "end_of_record")
+    }
+
+    /*
+     * Writes the source with a count in front of every line, in the style of gcov:
+     * "-" marks lines without expressions and "#####" lines that never ran.
+     * Branches that were not taken are listed below their line.
+     */
+    pub fn write_annotated<W: Write>(&self, mut out: W) -> io::Result<()> {
+        let counts = self.line_counts();
+        let mut by_line: BTreeMap<usize, Vec<&Branch>> = BTreeMap::new();
+        for b in self.sorted_branches() {
+            by_line.entry(self.line(b.at)).or_default().push(b);
+        }
+        for (i, text) in self.src.lines().enumerate() {
+            let line = i + 1;
+            let count = match counts.get(&line) {
+                None => "-".to_string(),
+                Some(0) => "#####".to_string(),
+                Some(n) => n.to_string(),
+            };
+            writeln!(out, "{:>9}:{:5}:{}", count, line, text)?;
+            for b in by_line.get(&line).into_iter().flatten() {
+                if self.hits[&b.at] == 0 {
+                    continue;
+                }
+                for &(label, arm) in &b.arms {
+                    if self.arm_count(arm) == 0 {
+                        let (_, col) = self.lines.line_col(b.at.lo);
+                        writeln!(
+                            out,
+                            "{:>9}:{:5}: {} never taken (col {})",
+                            "", "", label, col
+                        )?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Tracer for Coverage<'a> {
+    fn enter_expr(&mut self, span: Span, _expr: &Expr, _scope: &Scope) {
+        *self.hits.entry(span).or_insert(0) += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{interpret, parse};
+
+    const PROG: &str = "let
+  function sign(x: int): int =
+    if x < 0 then -1 else (if x = 0 then 0 else 1)
+  function unused() = 3
+  var s := 0
+in
+  for i := 1 to 3 do s := s + sign(i);
+  if s > 100 then s := 0;
+  s
+end";
+
+    fn run() -> (Coverage<'static>, String, String) {
+        let ast = parse::parse_located(PROG);
+        let mut cov = Coverage::new(&ast, PROG);
+        interpret::f(&ast, &mut cov);
+        let (mut lcov, mut listing) = (Vec::new(), Vec::new());
+        cov.write_lcov(&mut lcov, "prog.tiger").unwrap();
+        cov.write_annotated(&mut listing).unwrap();
+        (
+            cov,
+            String::from_utf8(lcov).unwrap(),
+            String::from_utf8(listing).unwrap(),
+        )
+    }
+
+    #[test]
+    fn lcov_test() {
+        let (_, lcov, _) = run();
+        assert!(lcov.starts_with("TN:\nSF:prog.tiger\nFN:3,sign\nFN:4,unused\n"));
+        assert!(lcov.contains("FNDA:3,sign\nFNDA:0,unused\nFNF:2\nFNH:1\n"));
+        // sign: the first if only takes else, the second only else
+        assert!(lcov.contains("BRDA:3,0,0,0\nBRDA:3,0,1,3\n"));
+        assert!(lcov.contains("BRDA:3,1,0,0\nBRDA:3,1,1,3\n"));
+        assert!(lcov.contains("BRDA:7,2,0,3\n"));
+        // `if s > 100 then ...` has no else: the implicit one is taken once
+        assert!(lcov.contains("BRDA:8,3,0,0\nBRDA:8,3,1,1\n"));
+        assert!(lcov.contains("BRF:7\nBRH:4\n"));
+        assert!(lcov.contains("DA:4,0\n"));
+        assert!(lcov.ends_with("LF:7\nLH:6\nend_of_record\n"));
+    }
+
+    #[test]
+    fn annotated_test() {
+        let (_, _, listing) = run();
+        let lines: Vec<&str> = listing.lines().collect();
+        assert_eq!(lines[0], "        1:    1:let");
+        assert_eq!(lines[1], "        -:    2:  function sign(x: int): int =");
+        assert_eq!(
+            lines[2],
+            "        3:    3:    if x < 0 then -1 else (if x = 0 then 0 else 1)"
+        );
+        assert_eq!(lines[3], "         :     : then never taken (col 5)");
+        assert_eq!(lines[4], "         :     : then never taken (col 28)");
+        assert_eq!(lines[5], "    #####:    4:  function unused() = 3");
+        assert!(listing.contains(
+            ":    8:  if s > 100 then s := 0;\n         :     : then never taken (col 3)\n"
+        ));
+    }
+}
@@ -0,0 +1,386 @@
+use crate::ast::{Expr, LineIndex, Span, Value};
+use crate::interpret::Scope;
+use crate::trace::Tracer;
+use std::io::{BufRead, Write};
+
+const HELP: &str = "\
+commands:
+  s, step            stop at the next line, entering calls
+  n, next            stop at the next line of this function or its callers
+  o, finish          stop after returning from this function
+  c, continue        run until a breakpoint is hit
+  b, break LINE|FUN  set a breakpoint on a line or on entry to a function
+  d, delete N        delete breakpoint N
+  i, info            list breakpoints
+  p, print [NAME]    print a visible variable, or all of them
+  bt, backtrace      print the Tiger call stack
+  l, list            print the source around the current line
+  q, quit            abort the program";
+
+#[derive(Clone, Debug, PartialEq)]
+enum Breakpoint {
+    Line(usize),
+    Fun(String),
+}
+
+enum Mode {
+    Step,
+    Next(usize),   // stop at a new line at this depth or shallower
+    Finish(usize), // stop as soon as we are shallower than this depth
+    Continue,
+    Detached, // the command stream was closed: never stop again
+    Quit,     // abort the program
+}
+
+struct Frame {
+    name: String,
+    args: Vec<Value>,
+    open: Vec<Span>, // expressions entered but not yet exited; the last one is the current location
+    last_line: Option<usize>,
+}
+
+/*
+ * A source-level debugger, implemented as a Tracer.
+ * It stops before the first expression of the program and then whenever a
+ * stepping command or a breakpoint says so, reading commands from input, which should
+ * not be the stream the program reads with getchar. quit aborts the run.
+ */
+pub struct Debugger<'a, R: BufRead, W: Write> {
+    input: R,
+    out: W,
+    src: &'a str,
+    lines: LineIndex,
+    breakpoints: Vec<Option<Breakpoint>>, // deleted ones are None, so numbers stay stable
+    frames: Vec<Frame>,
+    mode: Mode,
+    stop_on_entry: bool, // a function breakpoint was hit
+}
+
+impl<'a, R: BufRead, W: Write> Debugger<'a, R, W> {
+    pub fn new(input: R, out: W, src: &'a str) -> Self {
+        Debugger {
+            input,
+            out,
+            src,
+            lines: LineIndex::new(src),
+            breakpoints: Vec::new(),
+            frames: vec![Frame {
+                name: "<main>".to_string(),
+                args: Vec::new(),
+                open: Vec::new(),
+                last_line: None,
+            }],
+            mode: Mode::Step,
+            stop_on_entry: false,
+        }
+    }
+
+    fn depth(&self) -> usize {
+        self.frames.len() - 1
+    }
+
+    fn source_line(&self, line: usize) -> Option<&'a str> {
+        self.src.lines().nth(line - 1)
+    }
+
+    fn should_stop(&mut self, line: usize, new_line: bool) -> bool {
+        if let Mode::Detached | Mode::Quit = self.mode {
+            return false;
+        }
+        if self.stop_on_entry {
+            self.stop_on_entry = false;
+            return true;
+        }
+        let depth = self.depth();
+        let hit = new_line && self.breakpoints.contains(&Some(Breakpoint::Line(line)));
+        hit || match self.mode {
+            Mode::Step => new_line,
+            Mode::Next(d) => new_line && depth <= d,
+            Mode::Finish(d) => depth < d,
+            Mode::Continue | Mode::Detached | Mode::Quit => false,
+        }
+    }
+
+    fn show_location(&mut self, span: Span) {
+        let (line, col) = self.lines.line_col(span.lo);
+        let name = self.frames.last().unwrap().name.clone();
+        let _ = writeln!(self.out, "stopped at {}:{} in {}", line, col, name);
+        if let Some(text) = self.source_line(line) {
+            let _ = writeln!(self.out, "{:5} | {}", line, text);
+        }
+    }
+
+    fn list(&mut self, span: Span) {
+        let (line, _) = self.lines.line_col(span.lo);
+        let first = line.saturating_sub(3).max(1);
+        for l in first..line + 4 {
+            if let Some(text) = self.source_line(l) {
+                let mark = if l == line { "=>" } else { "  " };
+                let _ = writeln!(self.out, "{} {:5} | {}", mark, l, text);
+            }
+        }
+    }
+
+    fn backtrace(&mut self) {
+        let mut lines = Vec::new();
+        for (i, frame) in self.frames.iter().rev().enumerate() {
+            let args = frame
+                .args
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            let call = if i == self.frames.len() - 1 {
+                frame.name.clone()
+            } else {
+                format!("{}({})", frame.name, args)
+            };
+            let loc = match frame.open.last() {
+                Some(span) => {
+                    let (line, col) = self.lines.line_col(span.lo);
+                    format!(" at {}:{}", line, col)
+                }
+                None => String::new(),
+            };
+            lines.push(format!("#{} {}{}", i, call, loc));
+        }
+        for l in lines {
+            let _ = writeln!(self.out, "{}", l);
+        }
+    }
+
+    fn set_breakpoint(&mut self, arg: &str) {
+        let bp = match arg.parse::<usize>() {
+            Ok(line) if line >= 1 => Breakpoint::Line(line),
+            Ok(_) => {
+                let _ = writeln!(self.out, "line numbers start at 1");
+                return;
+            }
+            Err(_) => Breakpoint::Fun(arg.to_string()),
+        };
+        self.breakpoints.push(Some(bp));
+        let n = self.breakpoints.len();
+        self.describe_breakpoint(n);
+    }
+
+    fn describe_breakpoint(&mut self, n: usize) {
+        let _ = match self.breakpoints[n - 1] {
+            Some(Breakpoint::Line(line)) => writeln!(self.out, "breakpoint {} at line {}", n, line),
+            Some(Breakpoint::Fun(ref name)) => {
+                writeln!(self.out, "breakpoint {} at function {}", n, name)
+            }
+            None => Ok(()),
+        };
+    }
+
+    fn print(&mut self, names: &[&str], scope: &Scope) {
+        if names.is_empty() {
+            for (name, val) in scope.variables() {
+                let _ = writeln!(self.out, "{} = {}", name, val);
+            }
+            return;
+        }
+        for &name in names {
+            let _ = match scope.lookup(name) {
+                Some(val) => writeln!(self.out, "{} = {}", name, val),
+                None => writeln!(self.out, "no variable {} in scope", name),
+            };
+        }
+    }
+
+    /* Reads and executes commands until one of them resumes execution. */
+    fn prompt(&mut self, span: Span, scope: &Scope) {
+        self.show_location(span);
+        loop {
+            let _ = write!(self.out, "(tdb) ");
+            let _ = self.out.flush();
+            let mut line = String::new();
+            match self.input.read_line(&mut line) {
+                Ok(0) | Err(_) => {
+                    self.mode = Mode::Detached;
+                    return;
+                }
+                Ok(_) => {}
+            }
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let depth = self.depth();
+            match words.as_slice() {
+                [] => {}
+                ["s"] | ["step"] => {
+                    self.mode = Mode::Step;
+                    return;
+                }
+                ["n"] | ["next"] => {
+                    self.mode = Mode::Next(depth);
+                    return;
+                }
+                ["o"] | ["finish"] => {
+                    self.mode = Mode::Finish(depth);
+                    return;
+                }
+                ["c"] | ["continue"] => {
+                    self.mode = Mode::Continue;
+                    return;
+                }
+                ["b", arg] | ["break", arg] => self.set_breakpoint(arg),
+                ["d", n] | ["delete", n] => match n.parse::<usize>() {
+                    Ok(n) if n >= 1 && n <= self.breakpoints.len() => {
+                        self.breakpoints[n - 1] = None;
+                    }
+                    _ => {
+                        let _ = writeln!(self.out, "no breakpoint {}", n);
+                    }
+                },
+                ["i"] | ["info"] => {
+                    for n in 1..=self.breakpoints.len() {
+                        self.describe_breakpoint(n);
+                    }
+                }
+                ["p", names @ ..] | ["print", names @ ..] => self.print(names, scope),
+                ["bt"] | ["backtrace"] => self.backtrace(),
+                ["l"] | ["list"] => self.list(span),
+                ["h"] | ["help"] => {
+                    let _ = writeln!(self.out, "{}", HELP);
+                }
+                ["q"] | ["quit"] => {
+                    self.mode = Mode::Quit;
+                    return;
+                }
+                _ => {
+                    let _ = writeln!(self.out, "unknown command: {} (try help)", line.trim());
+                }
+            }
+        }
+    }
+}
+
+impl<'a, R: BufRead, W: Write> Tracer for Debugger<'a, R, W> {
+    fn enter_expr(&mut self, span: Span, _expr: &Expr, scope: &Scope) {
+        let (line, _) = self.lines.line_col(span.lo);
+        let frame = self.frames.last_mut().unwrap();
+        frame.open.push(span);
+        let new_line = frame.last_line != Some(line);
+        frame.last_line = Some(line);
+        if self.should_stop(line, new_line) {
+            self.prompt(span, scope);
+        }
+    }
+    fn exit_expr(&mut self, _span: Span, _result: Option<&Value>) {
+        self.frames.last_mut().unwrap().open.pop();
+    }
+    fn call(&mut self, name: &str, args: &[Value]) {
+        if self
+            .breakpoints
+            .contains(&Some(Breakpoint::Fun(name.to_string())))
+        {
+            self.stop_on_entry = true;
+        }
+        self.frames.push(Frame {
+            name: name.to_string(),
+            args: args.to_vec(),
+            open: Vec::new(),
+            last_line: None,
+        });
+    }
+    fn ret(&mut self, name: &str, result: Option<&Value>) {
+        self.frames.pop();
+        if let (Mode::Finish(d), Some(val)) = (&self.mode, result) {
+            if self.depth() < *d && *val != Value::VUnit {
+                let _ = writeln!(self.out, "{} returned {}", name, val);
+            }
+        }
+    }
+    fn loop_iter(&mut self, _iteration: usize) {
+        self.frames.last_mut().unwrap().last_line = None;
+    }
+    fn aborted(&self) -> bool {
+        matches!(self.mode, Mode::Quit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{interpret, parse};
+    use std::io::Cursor;
+
+    fn debug(src: &str, commands: &str) -> (Value, String) {
+        let ast = parse::parse_located(src);
+        let mut out = Vec::new();
+        let val = interpret::f(
+            &ast,
+            &mut Debugger::new(Cursor::new(commands), &mut out, src),
+        );
+        (val, String::from_utf8(out).unwrap())
+    }
+
+    const PROG: &str = "let
+  function double(a: int): int =
+    a * 2
+  var r := 0
+in
+  r := double(3);
+  r := double(r);
+  r
+end";
+
+    #[test]
+    fn function_breakpoint_test() {
+        let (val, out) = debug(PROG, "break double\nc\nbt\np a\nc\nc\n");
+        assert_eq!(val, Value::VNum(12));
+        assert!(out.contains("breakpoint 1 at function double"));
+        assert!(out.contains("stopped at 3:5 in double\n    3 |     a * 2\n"));
+        assert!(out.contains("#0 double(3) at 3:5\n#1 <main> at 6:8\n"));
+        assert!(out.contains("a = 3\n"));
+        assert_eq!(out.matches("stopped at 3:5 in double").count(), 2);
+    }
+
+    #[test]
+    fn step_test() {
+        // step enters the call, next stays in main, finish leaves the callee
+        let (_, out) = debug(PROG, "b 6\nc\ns\no\np\nn\nc\n");
+        let stops: Vec<&str> = out
+            .lines()
+            .filter_map(|l| l.split("stopped at ").nth(1))
+            .collect();
+        assert_eq!(
+            stops,
+            vec![
+                "1:1 in <main>",
+                "6:3 in <main>",
+                "3:5 in double",
+                "7:3 in <main>",
+                "8:3 in <main>",
+            ]
+        );
+        assert!(out.contains("double returned 6"));
+        assert!(out.contains("r = 6\n"));
+    }
+
+    #[test]
+    fn quit_test() {
+        let ast = parse::parse_located(PROG);
+        let mut out = Vec::new();
+        let mut debugger = Debugger::new(Cursor::new("b double\nc\nq\n"), &mut out, PROG);
+        assert_eq!(
+            interpret::run(&ast, &[], &mut debugger),
+            Err(interpret::ABORTED.to_string())
+        );
+        assert_eq!(
+            String::from_utf8(out).unwrap().matches("stopped").count(),
+            2
+        );
+    }
+
+    #[test]
+    fn print_record_test() {
+        let src = "let type p = {x: int, n: a} type a = array of int
+  var v := p {x = 1, n = a [2] of 7}
+in
+  v.x
+end";
+        let (_, out) = debug(src, "b 4\nc\np v w\n");
+        assert!(out.contains("v = {x = 1, n = [7, 7]}\n"));
+        assert!(out.contains("no variable w in scope\n"));
+    }
+}
@@ -0,0 +1,325 @@
+use crate::ast::{LineIndex, Span};
+use crate::lint::Lint;
+use std::fmt;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
+pub enum Code {
+    Syntax,
+    UndefinedVariable,
+    UndefinedFunction,
+    UndefinedType,
+    Mismatch,
+    ArgumentCount,
+    NotRecord,
+    NoSuchField,
+    NotArray,
+    RecordFields,
+    OperandTypes,
+    IfBranches,
+    IfWithoutElse,
+    DuplicateDeclaration,
+    AliasCycle,
+    UntypedNil,
+    NoValue,
+    AssignToIndex,
+    BreakOutsideLoop,
+    WrongKind,
+    PendingResult,
+    Runtime,
+    Lint(Lint), // a finding of the lint pass, identified by the name of the lint
+}
+
+/*
+ * Every kind of diagnostic with its stable code and a one-line summary.
+ * Codes are never renumbered or reused; new kinds are appended.
+ */
+pub const CODES: &[(Code, &str, &str)] = &[
+    (Code::Syntax, "E0001", "syntax error"),
+    (
+        Code::UndefinedVariable,
+        "E0002",
+        "use of an undeclared variable",
+    ),
+    (
+        Code::UndefinedFunction,
+        "E0003",
+        "call of an undeclared function",
+    ),
+    (Code::UndefinedType, "E0004", "use of an undeclared type"),
+    (
+        Code::Mismatch,
+        "E0005",
+        "expression has the wrong type for its context",
+    ),
+    (
+        Code::ArgumentCount,
+        "E0006",
+        "call with the wrong number of arguments",
+    ),
+    (
+        Code::NotRecord,
+        "E0007",
+        "field access on a value that is not a record",
+    ),
+    (
+        Code::NoSuchField,
+        "E0008",
+        "access to a field the record type does not have",
+    ),
+    (
+        Code::NotArray,
+        "E0009",
+        "indexing or creating an array of a type that is not an array",
+    ),
+    (
+        Code::RecordFields,
+        "E0010",
+        "record literal whose fields differ from its type",
+    ),
+    (
+        Code::OperandTypes,
+        "E0011",
+        "comparison of operands of incompatible types",
+    ),
+    (
+        Code::IfBranches,
+        "E0012",
+        "if whose then and else parts have different types",
+    ),
+    (
+        Code::IfWithoutElse,
+        "E0013",
+        "if-then without else that produces a value, a record or nil included",
+    ),
+    (
+        Code::DuplicateDeclaration,
+        "E0014",
+        "name declared twice in one declaration group or record",
+    ),
+    (
+        Code::AliasCycle,
+        "E0015",
+        "type aliases that refer to each other without a record or array",
+    ),
+    (
+        Code::UntypedNil,
+        "E0016",
+        "nil whose record type cannot be determined",
+    ),
+    (
+        Code::NoValue,
+        "E0017",
+        "expression that produces no value where a value is needed",
+    ),
+    (
+        Code::AssignToIndex,
+        "E0018",
+        "assignment to the index variable of a for loop",
+    ),
+    (
+        Code::BreakOutsideLoop,
+        "E0019",
+        "break outside of a loop body",
+    ),
+    (
+        Code::WrongKind,
+        "E0020",
+        "variable used as a function or function used as a variable",
+    ),
+    (
+        Code::PendingResult,
+        "E0021",
+        "field or element of a recursive function's result before its type is inferred",
+    ),
+    (
+        Code::Runtime,
+        "E0022",
+        "runtime error of the interpreter, such as a subscript out of bounds",
+    ),
+];
+
+impl Code {
+    fn entry(self) -> &'static (Code, &'static str, &'static str) {
+        CODES.iter().find(|c| c.0 == self).unwrap()
+    }
+    pub fn id(self) -> &'static str {
+        match self {
+            Code::Lint(lint) => lint.name(),
+            _ => self.entry().1,
+        }
+    }
+    pub fn summary(self) -> &'static str {
+        match self {
+            Code::Lint(lint) => lint.summary(),
+            _ => self.entry().2,
+        }
+    }
+}
+
+/* A secondary location that helps explain a diagnostic. */
+#[derive(PartialEq, Clone, Debug)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Diagnostic {
+    pub code: Code,
+    pub severity: Severity,
+    pub message: String,
+    pub span: Option<Span>,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Diagnostic {
+    pub fn error(code: Code, span: Option<Span>, message: String) -> Self {
+        Diagnostic {
+            code,
+            severity: Severity::Error,
+            message,
+            span,
+            labels: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+    pub fn with_label(mut self, span: Span, message: String) -> Self {
+        self.labels.push(Label { span, message });
+        self
+    }
+    pub fn with_note(mut self, note: String) -> Self {
+        self.notes.push(note);
+        self
+    }
+
+    /*
+     * Renders the diagnostic in the style of rustc: a header, the location,
+     * the source lines involved with the primary span underlined by ^ and labels by -,
+     * then the notes.
+     */
+    pub fn render(&self, src: &str, path: &str) -> String {
+        let lines = LineIndex::new(src);
+        let mut out = format!("{}[{}]: {}\n", self.severity, self.code.id(), self.message);
+        let mut marks: Vec<(usize, usize, usize, char, &str)> = Vec::new(); // line, col, width, marker, text
+        for (span, marker, text) in self.span.iter().map(|&span| (span, '^', "")).chain(
+            self.labels
+                .iter()
+                .map(|l| (l.span, '-', l.message.as_str())),
+        ) {
+            let (line, col) = lines.line_col(span.lo);
+            let text_len = src.lines().nth(line - 1).map_or(0, |l| l.len());
+            let width = (span.hi - span.lo)
+                .min((text_len + 1).saturating_sub(col))
+                .max(1);
+            marks.push((line, col, width, marker, text));
+        }
+        let gutter = marks
+            .iter()
+            .map(|m| m.0.to_string().len())
+            .max()
+            .unwrap_or(0);
+        if let Some(span) = self.span {
+            let (line, col) = lines.line_col(span.lo);
+            out += &format!(
+                "{:gutter$}--> {}:{}:{}\n",
+                "",
+                path,
+                line,
+                col,
+                gutter = gutter
+            );
+        }
+        let mut shown: Vec<usize> = marks.iter().map(|m| m.0).collect();
+        shown.sort_unstable();
+        shown.dedup();
+        if !shown.is_empty() {
+            out += &format!("{:gutter$} |\n", "", gutter = gutter);
+        }
+        for line in shown {
+            let text = src.lines().nth(line - 1).unwrap_or("");
+            out += &format!("{:>gutter$} | {}\n", line, text, gutter = gutter);
+            for &(_, col, width, marker, label) in marks.iter().filter(|m| m.0 == line) {
+                let underline = marker.to_string().repeat(width);
+                let mark = format!("{:col$}{}", "", underline, col = col - 1);
+                let mark = if label.is_empty() {
+                    mark
+                } else {
+                    format!("{} {}", mark, label)
+                };
+                out += &format!("{:gutter$} | {}\n", "", mark, gutter = gutter);
+            }
+        }
+        for note in &self.notes {
+            out += &format!("{:gutter$} = note: {}\n", "", note, gutter = gutter);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn codes_test() {
+        let mut ids: Vec<&str> = CODES.iter().map(|c| c.1).collect();
+        ids.dedup();
+        assert_eq!(ids.len(), CODES.len());
+        for (i, id) in ids.iter().enumerate() {
+            assert_eq!(*id, format!("E{:04}", i + 1));
+        }
+        assert_eq!(Code::AliasCycle.id(), "E0015");
+        // the table in the README lists every code
+        let readme = include_str!("../README.md");
+        for &(_, id, summary) in CODES {
+            assert!(
+                readme.contains(&format!("| {} | {} |", id, summary)),
+                "{}",
+                id
+            );
+        }
+    }
+    #[test]
+    fn render_test() {
+        let src = "let var x := 1 in\n  x := \"a\"\nend";
+        let diag = Diagnostic::error(
+            Code::Mismatch,
+            Some(Span::new(25, 28)),
+            "assigned value: expected int, but got string".to_string(),
+        )
+        .with_label(Span::new(20, 21), "x has type int".to_string())
+        .with_note("strings cannot be converted to int".to_string());
+        assert_eq!(
+            diag.render(src, "a.tiger"),
+            "error[E0005]: assigned value: expected int, but got string
+ --> a.tiger:2:8
+  |
+2 |   x := \"a\"
+  |        ^^^
+  |   - x has type int
+  = note: strings cannot be converted to int
+"
+        );
+    }
+}
@@ -0,0 +1,365 @@
+use crate::ast::{Expr, TypeId, TypedExpr, Value};
+use crate::diagnostics::Diagnostic;
+use crate::eval;
+use crate::interpret;
+use crate::trace::Tracer;
+use crate::typing;
+use std::rc::Rc;
+
+pub type HostCall = Rc<dyn Fn(&[Value]) -> Result<Value, String>>;
+
+/*
+ * A function implemented in Rust, with the Tiger signature the interpreter checks calls against.
+ * ret is None for procedures, which return no value.
+ */
+#[derive(Clone)]
+pub struct HostFun {
+    pub params: Vec<TypeId>,
+    pub ret: Option<TypeId>,
+    pub call: HostCall,
+}
+
+/* Rust types that correspond to a Tiger type. */
+pub trait TigerType {
+    fn tiger_type() -> TypeId;
+}
+
+/* Conversion from a Tiger value, failing if the value has the wrong shape. */
+pub trait FromValue: Sized {
+    fn from_value(val: &Value) -> Result<Self, String>;
+}
+
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+}
+
+impl TigerType for i64 {
+    fn tiger_type() -> TypeId {
+        "int".to_string()
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(val: &Value) -> Result<Self, String> {
+        match *val {
+            Value::VNum(i) => Ok(i),
+            _ => Err(format!("expected an int, but got {}", val)),
+        }
+    }
+}
+
+impl IntoValue for i64 {
+    fn into_value(self) -> Value {
+        Value::VNum(self)
+    }
+}
+
+/* Tiger has no booleans: false is 0 and anything else is true. */
+impl TigerType for bool {
+    fn tiger_type() -> TypeId {
+        "int".to_string()
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(val: &Value) -> Result<Self, String> {
+        i64::from_value(val).map(|i| i != 0)
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Value {
+        Value::VNum(if self { 1 } else { 0 })
+    }
+}
+
+impl TigerType for String {
+    fn tiger_type() -> TypeId {
+        "string".to_string()
+    }
+}
+
+impl FromValue for String {
+    fn from_value(val: &Value) -> Result<Self, String> {
+        match *val {
+            Value::VStr(ref s) => Ok(s.clone()),
+            _ => Err(format!("expected a string, but got {}", val)),
+        }
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Value {
+        Value::VStr(self)
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Value {
+        Value::VStr(self.to_string())
+    }
+}
+
+/*
+ * Rust types that stand for a Tiger record type, the only types with nil as a value.
+ * Option is a Tiger type only around one of them.
+ */
+pub trait RecordType: TigerType {}
+
+/* None is nil. */
+impl<T: RecordType> TigerType for Option<T> {
+    fn tiger_type() -> TypeId {
+        T::tiger_type()
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(val: &Value) -> Result<Self, String> {
+        match *val {
+            Value::VNil => Ok(None),
+            _ => T::from_value(val).map(Some),
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> Value {
+        match self {
+            Some(x) => x.into_value(),
+            None => Value::VNil,
+        }
+    }
+}
+
+/* What a host closure may return: a value, nothing (a procedure), or a Result of either. */
+pub trait HostResult {
+    fn tiger_type() -> Option<TypeId>;
+    fn into_result(self) -> Result<Value, String>;
+}
+
+impl<T: TigerType + IntoValue> HostResult for T {
+    fn tiger_type() -> Option<TypeId> {
+        Some(T::tiger_type())
+    }
+    fn into_result(self) -> Result<Value, String> {
+        Ok(self.into_value())
+    }
+}
+
+impl HostResult for () {
+    fn tiger_type() -> Option<TypeId> {
+        None
+    }
+    fn into_result(self) -> Result<Value, String> {
+        Ok(Value::VUnit)
+    }
+}
+
+impl<T: HostResult> HostResult for Result<T, String> {
+    fn tiger_type() -> Option<TypeId> {
+        T::tiger_type()
+    }
+    fn into_result(self) -> Result<Value, String> {
+        self.and_then(T::into_result)
+    }
+}
+
+/*
+ * Rust closures that can be registered with Engine::register_fn.
+ * Implemented for closures of up to four arguments whose types implement FromValue.
+ */
+pub trait IntoHostFun<Args> {
+    fn into_host_fun(self) -> HostFun;
+}
+
+macro_rules! impl_into_host_fun {
+    ($($arg:ident),*) => {
+        impl<F, R, $($arg,)*> IntoHostFun<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + 'static,
+            R: HostResult,
+            $($arg: TigerType + FromValue,)*
+        {
+            #[allow(non_snake_case, unused_variables, unused_mut)]
+            fn into_host_fun(self) -> HostFun {
+                HostFun {
+                    params: vec![$($arg::tiger_type()),*],
+                    ret: R::tiger_type(),
+                    call: Rc::new(move |args: &[Value]| {
+                        let mut args = args.iter();
+                        $(let $arg = $arg::from_value(args.next().ok_or("too few arguments")?)?;)*
+                        self($($arg),*).into_result()
+                    }),
+                }
+            }
+        }
+    };
+}
+
+impl_into_host_fun!();
+impl_into_host_fun!(A);
+impl_into_host_fun!(A, B);
+impl_into_host_fun!(A, B, C);
+impl_into_host_fun!(A, B, C, D);
+
+/*
+ * Entry point for embedding Tiger in a Rust program.
+ * Functions registered here are callable from Tiger like functions declared with `function`.
+ */
+#[derive(Clone, Default)]
+pub struct Engine {
+    functions: Vec<(String, HostFun)>,
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Engine::default()
+    }
+
+    /* Registers a closure, taking its Tiger signature from the Rust one. */
+    pub fn register_fn<Args, F: IntoHostFun<Args>>(&mut self, name: &str, f: F) -> &mut Self {
+        self.register(name, f.into_host_fun())
+    }
+
+    /*
+     * Registers a function working on raw values with an explicit signature.
+     * A later registration under the same name replaces an earlier one.
+     */
+    pub fn register(&mut self, name: &str, fun: HostFun) -> &mut Self {
+        self.functions.retain(|(n, _)| n != name);
+        self.functions.push((name.to_string(), fun));
+        self
+    }
+
+    pub fn functions(&self) -> &[(String, HostFun)] {
+        &self.functions
+    }
+
+    /* Every problem that keeps src from running, without running it. */
+    pub fn check(&self, src: &str) -> Vec<Diagnostic> {
+        match typing::check(src, &self.functions) {
+            Ok(_) => Vec::new(),
+            Err(diags) => diags,
+        }
+    }
+
+    fn check_rendered(&self, src: &str) -> Result<(Expr, TypedExpr), String> {
+        match typing::check(src, &self.functions) {
+            Ok((ast, _, tast)) => Ok((ast, tast)),
+            Err(diags) => Err(diags
+                .iter()
+                .map(|d| d.render(src, "<eval>"))
+                .collect::<String>()),
+        }
+    }
+
+    pub fn eval(&self, src: &str) -> Result<Value, String> {
+        let (_, tast) = self.check_rendered(src)?;
+        eval::run(&tast, &self.functions)
+    }
+
+    /* Evaluates the source tree instead of the typed one, so that tracer sees every expression. */
+    pub fn eval_traced(&self, src: &str, tracer: &mut dyn Tracer) -> Result<Value, String> {
+        let (ast, _) = self.check_rendered(src)?;
+        interpret::run(&ast, &self.functions, tracer)
+    }
+
+    /* Evaluates src and converts the result into a Rust value. */
+    pub fn eval_as<T: FromValue>(&self, src: &str) -> Result<T, String> {
+        T::from_value(&self.eval(src)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn register_fn_test() {
+        let mut engine = Engine::new();
+        engine
+            .register_fn("add", |a: i64, b: i64| a + b)
+            .register_fn("greet", |name: String| format!("hello, {}", name))
+            .register_fn("answer", || 42i64);
+        assert_eq!(engine.eval_as::<i64>("add(answer(), 8)"), Ok(50));
+        assert_eq!(
+            engine.eval_as::<String>("greet(\"tiger\")"),
+            Ok("hello, tiger".to_string())
+        );
+        let add = &engine.functions()[0].1;
+        assert_eq!(add.params, vec!["int".to_string(), "int".to_string()]);
+        assert_eq!(add.ret, Some("int".to_string()));
+    }
+
+    #[test]
+    fn procedure_and_errors_test() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let sink = log.clone();
+        let mut engine = Engine::new();
+        engine
+            .register_fn("log", move |x: i64| sink.borrow_mut().push(x))
+            .register_fn("checked_div", |a: i64, b: i64| {
+                if b == 0 {
+                    Err("division by zero".to_string())
+                } else {
+                    Ok(a / b)
+                }
+            });
+        assert_eq!(engine.functions()[0].1.ret, None);
+        assert_eq!(
+            engine.eval("for i := 1 to 3 do log(i * 10)"),
+            Ok(Value::VUnit)
+        );
+        assert_eq!(*log.borrow(), vec![10, 20, 30]);
+        assert_eq!(engine.eval_as::<i64>("checked_div(7, 2)"), Ok(3));
+        assert_eq!(
+            engine.eval("checked_div(7, 0)"),
+            Err("division by zero".to_string())
+        );
+        // argument types are checked before the program runs
+        assert!(engine.eval("checked_div(\"7\", 1)").is_err());
+        // a Tiger declaration shadows the host function
+        assert_eq!(
+            engine.eval_as::<i64>(
+                "let function checked_div(a: int, b: int): int = 0 in checked_div(1, 1) end"
+            ),
+            Ok(0)
+        );
+    }
+
+    #[test]
+    fn host_result_test() {
+        let mut engine = Engine::new();
+        engine.register(
+            "lying",
+            HostFun {
+                params: vec![],
+                ret: Some("int".to_string()),
+                call: Rc::new(|_| Ok(Value::VNil)),
+            },
+        );
+        assert_eq!(
+            engine.eval("lying() + 1"),
+            Err("lying returned nil, which is not of its result type int".to_string())
+        );
+    }
+
+    struct Point;
+    impl TigerType for Point {
+        fn tiger_type() -> TypeId {
+            "point".to_string()
+        }
+    }
+    impl RecordType for Point {}
+
+    #[test]
+    fn option_test() {
+        assert_eq!(Option::<Point>::tiger_type(), "point");
+        assert_eq!(Option::<i64>::from_value(&Value::VNil), Ok(None));
+        assert_eq!(Option::<i64>::from_value(&Value::VNum(3)), Ok(Some(3)));
+        assert_eq!(None::<String>.into_value(), Value::VNil);
+        assert_eq!(Some("x").into_value(), Value::VStr("x".to_string()));
+        assert!(String::from_value(&Value::VNum(3)).is_err());
+    }
+}
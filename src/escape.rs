@@ -0,0 +1,198 @@
+use crate::ast::LineIndex;
+use crate::resolve::{BindingId, Kind, Resolution};
+use std::io::{self, Write};
+
+/*
+ * Which variables and parameters escape, i.e. are used by a function nested in the
+ * one that declares them. Those must live in memory, in the declaring function's frame;
+ * the others may be kept in registers.
+ */
+#[derive(PartialEq, Clone, Debug)]
+pub struct Escapes {
+    escaping: Vec<bool>, // indexed by BindingId
+}
+
+fn is_local(kind: Kind) -> bool {
+    matches!(kind, Kind::Var | Kind::Param | Kind::LoopIndex)
+}
+
+pub fn f(res: &Resolution) -> Escapes {
+    let mut escaping = vec![false; res.bindings.len()];
+    for u in &res.uses {
+        let b = &res.bindings[u.binding];
+        // a use can only see bindings of enclosing scopes, so a deeper use is in a nested function
+        if is_local(b.kind) && u.depth > b.depth {
+            escaping[u.binding] = true;
+        }
+    }
+    Escapes { escaping }
+}
+
+impl Escapes {
+    pub fn escapes(&self, id: BindingId) -> bool {
+        self.escaping[id]
+    }
+
+    /* Escaping bindings, in declaration order. */
+    pub fn escaping(&self) -> impl Iterator<Item = BindingId> + '_ {
+        (0..self.escaping.len()).filter(move |&id| self.escaping[id])
+    }
+
+    /*
+     * Writes one line per variable, parameter and loop index, in declaration order:
+     * "<main>.x var 1:14 escapes", with the owning function, the kind and where it is declared.
+     */
+    pub fn write<W: Write>(&self, mut out: W, res: &Resolution, src: &str) -> io::Result<()> {
+        let lines = LineIndex::new(src);
+        for (id, b) in res.bindings.iter().enumerate() {
+            let kind = match b.kind {
+                Kind::Var => "var",
+                Kind::Param => "param",
+                Kind::LoopIndex => "for",
+                Kind::Fun | Kind::Type => continue,
+            };
+            let owner = match b.owner {
+                Some(f) => res.bindings[f].name.as_str(),
+                None => "<main>",
+            };
+            let at = match b.span {
+                Some(span) => {
+                    let (line, col) = lines.line_col(span.lo);
+                    format!(" {}:{}", line, col)
+                }
+                None => String::new(),
+            };
+            let verdict = if self.escaping[id] {
+                "escapes"
+            } else {
+                "local"
+            };
+            writeln!(out, "{}.{} {}{} {}", owner, b.name, kind, at, verdict)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, resolve};
+
+    const LIBRARY: &[&str] = &["print", "getchar", "ord", "chr", "not"];
+
+    /* name and verdict of every local, with the owner */
+    fn escapes(src: &str) -> Vec<String> {
+        let (res, diags) = resolve::f(&parse::parse_located(src), LIBRARY);
+        assert!(diags.is_empty(), "{:?}", diags);
+        let esc = f(&res);
+        let mut out = Vec::new();
+        esc.write(&mut out, &res, src).unwrap();
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|l| {
+                let words: Vec<&str> = l.split(' ').collect();
+                format!("{} {}", words[0], words[words.len() - 1])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn queens_test() {
+        let src = "let
+  var N := 8
+  type intArray = array of int
+  var row := intArray [N] of 0
+  var col := intArray [N] of 0
+  var diag1 := intArray [N + N - 1] of 0
+  var diag2 := intArray [N + N - 1] of 0
+  function printboard() =
+    (for i := 0 to N - 1 do
+       (for j := 0 to N - 1 do print(if col[i] = j then \" O\" else \" .\");
+        print(\"\"));
+     print(\"\"))
+  function try(c: int) =
+    if c = N then printboard()
+    else (for r := 0 to N - 1 do
+      if row[r] = 0 & diag1[r + c] = 0 & diag2[r + 7 - c] = 0 then
+        (row[r] := 1; diag1[r + c] := 1; diag2[r + 7 - c] := 1;
+         col[c] := r;
+         try(c + 1);
+         row[r] := 0; diag1[r + c] := 0; diag2[r + 7 - c] := 0))
+in
+  try(0)
+end";
+        assert_eq!(
+            escapes(src),
+            vec![
+                "<main>.N escapes",
+                "<main>.row escapes",
+                "<main>.col escapes",
+                "<main>.diag1 escapes",
+                "<main>.diag2 escapes",
+                "printboard.i local",
+                "printboard.j local",
+                "try.c local",
+                "try.r local",
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_test() {
+        let src = "let
+  type any = {any: int}
+  var buffer := getchar()
+  function readint(any: any): int =
+    let var i := 0
+        function isdigit(s: string): int = ord(buffer) >= ord(\"0\") & ord(buffer) <= ord(\"9\")
+        function skipto() = while buffer = \" \" do buffer := getchar()
+    in skipto();
+       any.any := isdigit(buffer);
+       while isdigit(buffer) do (i := i * 10 + ord(buffer) - ord(\"0\"); buffer := getchar());
+       i
+    end
+  type list = {first: int, rest: list}
+  function readlist(): list =
+    let var any := any {any = 0}
+        var i := readint(any)
+    in if any.any then list {first = i, rest = readlist()} else nil
+    end
+  function merge(a: list, b: list): list =
+    if a = nil then b
+    else (if b = nil then a
+    else (if a.first < b.first then list {first = a.first, rest = merge(a.rest, b)}
+    else list {first = b.first, rest = merge(a, b.rest)}))
+in
+  merge(readlist(), readlist())
+end";
+        assert_eq!(
+            escapes(src),
+            vec![
+                "<main>.buffer escapes",
+                "readint.any local",
+                "readint.i local",
+                "isdigit.s local",
+                "readlist.any local",
+                "readlist.i local",
+                "merge.a local",
+                "merge.b local",
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_param_test() {
+        let src = "let function f(x: int, y: int): int =
+  let function g(): int = x + 1 in g() + y end
+in f(1, 2) end";
+        assert_eq!(escapes(src), vec!["f.x escapes", "f.y local"]);
+        let (res, _) = resolve::f(&parse::parse_located(src), &[]);
+        let esc = f(&res);
+        let names: Vec<&str> = esc
+            .escaping()
+            .map(|id| res.bindings[id].name.as_str())
+            .collect();
+        assert_eq!(names, vec!["x"]);
+    }
+}
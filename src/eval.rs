@@ -0,0 +1,418 @@
+use crate::ast::{Op, Ty, TypedDec, TypedExpr, TypedFunDec, Value};
+use crate::engine::HostFun;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/*
+ * Evaluation of typed trees. Since typing has accepted the program, operands, arguments
+ * and results are not checked again: the only errors left are those no type system
+ * catches, such as nil dereference and indexing out of bounds.
+ * As in interpret, functions see the variables of where they are declared, not of where
+ * they are called.
+ */
+
+enum LoopBreak {
+    LoopBreak,
+    Err(String),
+}
+
+enum Binding<'a> {
+    Var(&'a str, RefCell<Value>),
+    Funs(&'a [TypedDec]), // a group of consecutive function declarations
+}
+
+/* Environments are linked frames, shared by the functions declared in them. */
+struct Frame<'a> {
+    binding: Binding<'a>,
+    next: Env<'a>,
+}
+
+type Env<'a> = Option<Rc<Frame<'a>>>;
+type Fields = Rc<RefCell<Vec<(String, Value)>>>; /* contents of a record */
+type Elems = Rc<RefCell<Vec<Value>>>; /* contents of an array */
+
+fn push<'a>(env: &Env<'a>, binding: Binding<'a>) -> Env<'a> {
+    Some(Rc::new(Frame {
+        binding,
+        next: env.clone(),
+    }))
+}
+
+fn lookup_var<'e>(env: &'e Env, name: &str) -> &'e RefCell<Value> {
+    let mut env = env;
+    while let Some(ref frame) = *env {
+        match frame.binding {
+            Binding::Var(n, ref cell) if n == name => return cell,
+            _ => env = &frame.next,
+        }
+    }
+    panic!("internal error: unbound variable {} in a typed tree", name)
+}
+
+/* The declaration of a function and the environment it was declared in, which includes its group. */
+fn lookup_fun<'a>(env: &Env<'a>, name: &str) -> Option<(&'a TypedFunDec, Env<'a>)> {
+    let mut env = env;
+    while let Some(ref frame) = *env {
+        if let Binding::Funs(decs) = frame.binding {
+            for dec in decs {
+                match *dec {
+                    TypedDec::Fun(ref fundec) if fundec.0 == name => {
+                        return Some((fundec, Some(frame.clone())))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        env = &frame.next;
+    }
+    None
+}
+
+/* The integer a well-typed int expression evaluated to. */
+fn int(val: Value) -> i64 {
+    match val {
+        Value::VNum(i) => i,
+        _ => panic!("internal error: {} where an int was expected", val),
+    }
+}
+
+fn record(val: Value) -> Result<Fields, LoopBreak> {
+    match val {
+        Value::VRecord(fields) => Ok(fields),
+        Value::VNil => Err(LoopBreak::Err("nil record dereference".to_string())),
+        _ => panic!("internal error: {} where a record was expected", val),
+    }
+}
+
+/* Evaluates a[i] up to the bounds check. */
+fn index<'a>(
+    a: &'a TypedExpr,
+    i: &'a TypedExpr,
+    env: &Env<'a>,
+    host: &[(String, HostFun)],
+) -> Result<(Elems, usize), LoopBreak> {
+    let elems = match f_sub(a, env, host)? {
+        Value::VArray(elems) => elems,
+        val => panic!("internal error: {} where an array was expected", val),
+    };
+    let i = int(f_sub(i, env, host)?);
+    let len = elems.borrow().len();
+    if i < 0 || i as usize >= len {
+        return Err(LoopBreak::Err(format!(
+            "index {} out of bounds (length {})",
+            i, len
+        )));
+    }
+    Ok((elems, i as usize))
+}
+
+/* Integers wrap around on overflow, as in the compiled program. */
+fn binary(op: Op, ty: Ty, v1: Value, v2: Value) -> Result<Value, LoopBreak> {
+    let b = |cond: bool| Ok(Value::VNum(i64::from(cond)));
+    match (op, v1, v2) {
+        (Op::Eq, v1, v2) => b(v1 == v2),
+        (Op::Ne, v1, v2) => b(v1 != v2),
+        (_, Value::VStr(s1), Value::VStr(s2)) if ty == Ty::Str => match op {
+            Op::Lt => b(s1 < s2),
+            Op::Gt => b(s1 > s2),
+            Op::Le => b(s1 <= s2),
+            Op::Ge => b(s1 >= s2),
+            _ => panic!("internal error: {:?} on strings", op),
+        },
+        (_, v1, v2) => {
+            let (i1, i2) = (int(v1), int(v2));
+            match op {
+                Op::Add => Ok(Value::VNum(i1.wrapping_add(i2))),
+                Op::Sub => Ok(Value::VNum(i1.wrapping_sub(i2))),
+                Op::Mul => Ok(Value::VNum(i1.wrapping_mul(i2))),
+                Op::Div if i2 == 0 => Err(LoopBreak::Err("division by zero".to_string())),
+                Op::Div => Ok(Value::VNum(i1.wrapping_div(i2))),
+                Op::Lt => b(i1 < i2),
+                Op::Gt => b(i1 > i2),
+                Op::Le => b(i1 <= i2),
+                Op::Ge => b(i1 >= i2),
+                Op::Eq | Op::Ne | Op::And | Op::Or => unreachable!(),
+            }
+        }
+    }
+}
+
+/*
+ * The result of a host function, which has to agree with its signature since typing
+ * trusts it: nil only for a record type, no value only for a procedure.
+ */
+fn host_result(name: &str, fun: &HostFun, val: Value) -> Result<Value, LoopBreak> {
+    let ok = match (fun.ret.as_deref(), &val) {
+        (None, Value::VUnit) | (Some("int"), Value::VNum(_)) | (Some("string"), Value::VStr(_)) => {
+            true
+        }
+        (None | Some("int") | Some("string"), _) => false,
+        (Some(_), Value::VRecord(_) | Value::VArray(_) | Value::VNil) => true,
+        (Some(_), _) => false,
+    };
+    if ok {
+        Ok(val)
+    } else {
+        Err(LoopBreak::Err(format!(
+            "{} returned {}, which is not of its result type {}",
+            name,
+            val,
+            fun.ret.as_deref().unwrap_or("unit")
+        )))
+    }
+}
+
+fn call<'a>(
+    name: &str,
+    args: &'a [TypedExpr],
+    env: &Env<'a>,
+    host: &[(String, HostFun)],
+) -> Result<Value, LoopBreak> {
+    let mut vals = Vec::with_capacity(args.len());
+    for arg in args {
+        vals.push(f_sub(arg, env, host)?);
+    }
+    if let Some(((_, _, params, _, body), mut body_env)) = lookup_fun(env, name) {
+        for ((param, _, _), val) in params.iter().zip(vals) {
+            body_env = push(&body_env, Binding::Var(param, RefCell::new(val)));
+        }
+        return f_sub(body, &body_env, host);
+    }
+    match host.iter().find(|h| h.0 == name) {
+        Some((_, fun)) => {
+            let val = (fun.call)(&vals).map_err(LoopBreak::Err)?;
+            host_result(name, fun, val)
+        }
+        None => panic!("internal error: unbound function {} in a typed tree", name),
+    }
+}
+
+fn f_sub<'a>(
+    tast: &'a TypedExpr,
+    env: &Env<'a>,
+    host: &[(String, HostFun)],
+) -> Result<Value, LoopBreak> {
+    match *tast {
+        TypedExpr::Num(i) => Ok(Value::VNum(i)),
+        TypedExpr::Str(ref s) => Ok(Value::VStr(s.clone())),
+        TypedExpr::Nil => Ok(Value::VNil),
+        TypedExpr::Var(ref name, _, _) => Ok(lookup_var(env, name).borrow().clone()),
+        TypedExpr::Field(ref e, ref name, _) => {
+            let fields = record(f_sub(e, env, host)?)?;
+            let fields = fields.borrow();
+            let field = fields.iter().find(|field| field.0 == *name);
+            Ok(field.expect("typing checks field names").1.clone())
+        }
+        TypedExpr::Index(ref a, ref i, _) => {
+            let (elems, i) = index(a, i, env, host)?;
+            let val = elems.borrow()[i].clone();
+            Ok(val)
+        }
+        TypedExpr::Neg(ref e) => Ok(Value::VNum(int(f_sub(e, env, host)?).wrapping_neg())),
+        TypedExpr::OpNode(Op::And, _, ref e1, ref e2) => match int(f_sub(e1, env, host)?) {
+            0 => Ok(Value::VNum(0)),
+            _ => f_sub(e2, env, host),
+        },
+        TypedExpr::OpNode(Op::Or, _, ref e1, ref e2) => match int(f_sub(e1, env, host)?) {
+            0 => f_sub(e2, env, host),
+            i => Ok(Value::VNum(i)),
+        },
+        TypedExpr::OpNode(op, ty, ref e1, ref e2) => {
+            let v1 = f_sub(e1, env, host)?;
+            let v2 = f_sub(e2, env, host)?;
+            binary(op, ty, v1, v2)
+        }
+        TypedExpr::IfNode(ref c, _, ref e1, ref e2) => match int(f_sub(c, env, host)?) {
+            0 => f_sub(e2, env, host),
+            _ => f_sub(e1, env, host),
+        },
+        TypedExpr::Assign(ref lval, ref e) => {
+            let val = f_sub(e, env, host)?;
+            match **lval {
+                TypedExpr::Var(ref name, _, _) => {
+                    *lookup_var(env, name).borrow_mut() = val;
+                }
+                TypedExpr::Field(ref r, ref name, _) => {
+                    let fields = record(f_sub(r, env, host)?)?;
+                    let mut fields = fields.borrow_mut();
+                    let field = fields.iter_mut().find(|field| field.0 == *name);
+                    field.expect("typing checks field names").1 = val;
+                }
+                TypedExpr::Index(ref a, ref i, _) => {
+                    let (elems, i) = index(a, i, env, host)?;
+                    elems.borrow_mut()[i] = val;
+                }
+                _ => panic!("internal error: assignment to a value"),
+            }
+            Ok(Value::VUnit)
+        }
+        TypedExpr::Seq(ref es, _) => {
+            let mut val = Value::VUnit;
+            for e in es {
+                val = f_sub(e, env, host)?;
+            }
+            Ok(val)
+        }
+        TypedExpr::Let(ref decs, ref body, _) => {
+            let mut env = env.clone();
+            let mut i = 0;
+            while i < decs.len() {
+                match decs[i] {
+                    TypedDec::Var(ref name, _, _, ref e) => {
+                        let val = f_sub(e, &env, host)?;
+                        env = push(&env, Binding::Var(name, RefCell::new(val)));
+                        i += 1;
+                    }
+                    TypedDec::Fun(_) => {
+                        let n = decs[i..]
+                            .iter()
+                            .take_while(|dec| matches!(dec, TypedDec::Fun(_)))
+                            .count();
+                        env = push(&env, Binding::Funs(&decs[i..i + n]));
+                        i += n;
+                    }
+                }
+            }
+            f_sub(body, &env, host)
+        }
+        TypedExpr::For(ref var, _, ref st, ref en, ref body) => {
+            let st = int(f_sub(st, env, host)?);
+            let en = int(f_sub(en, env, host)?);
+            let env = push(env, Binding::Var(var, RefCell::new(Value::VNum(st))));
+            for i in st..=en {
+                *lookup_var(&env, var).borrow_mut() = Value::VNum(i);
+                match f_sub(body, &env, host) {
+                    Err(LoopBreak::LoopBreak) => break,
+                    result => result?,
+                };
+            }
+            Ok(Value::VUnit)
+        }
+        TypedExpr::While(ref c, ref body) => {
+            while int(f_sub(c, env, host)?) != 0 {
+                match f_sub(body, env, host) {
+                    Err(LoopBreak::LoopBreak) => break,
+                    result => result?,
+                };
+            }
+            Ok(Value::VUnit)
+        }
+        TypedExpr::FunApp(ref name, _, _, _, ref args) => call(name, args, env, host),
+        TypedExpr::NewStruct(_, ref fields) => {
+            let mut vals = Vec::with_capacity(fields.len());
+            for (name, e) in fields {
+                vals.push((name.clone(), f_sub(e, env, host)?));
+            }
+            Ok(Value::VRecord(Rc::new(RefCell::new(vals))))
+        }
+        TypedExpr::NewArray(_, ref n, ref init) => {
+            let n = int(f_sub(n, env, host)?);
+            if n < 0 {
+                return Err(LoopBreak::Err("invalid array size".to_string()));
+            }
+            let init = f_sub(init, env, host)?;
+            Ok(Value::VArray(Rc::new(RefCell::new(vec![init; n as usize]))))
+        }
+        TypedExpr::Break => Err(LoopBreak::LoopBreak),
+    }
+}
+
+/*
+ * Evaluates a tree typed with the given host functions in scope.
+ * Functions declared in the program shadow host functions of the same name.
+ */
+pub fn run(tast: &TypedExpr, host: &[(String, HostFun)]) -> Result<Value, String> {
+    match f_sub(tast, &None, host) {
+        Ok(result) => Ok(result),
+        Err(LoopBreak::LoopBreak) => Err("break outside loop was detected".to_string()),
+        Err(LoopBreak::Err(str)) => Err(str),
+    }
+}
+
+pub fn f(tast: &TypedExpr) -> Value {
+    match run(tast, &[]) {
+        Ok(result) => result,
+        Err(str) => panic!("eval::f: {}", str),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, typing};
+    fn eval(src: &str) -> Result<Value, String> {
+        let (_, tast) = typing::f(&parse::parse_located(src)).unwrap();
+        run(&tast, &[])
+    }
+    fn check(src: &str, val: Value) {
+        assert_eq!(eval(src), Ok(val));
+    }
+    #[test]
+    fn letex_test() {
+        check("let var x := 4 in x + x end", Value::VNum(8));
+        check(
+            "let var x := 4 in (let var x := 3 in x end) + x end",
+            Value::VNum(7),
+        );
+        check("2 & 3", Value::VNum(3));
+        check("0 | 155", Value::VNum(155));
+        check("\"abc\" < \"abd\"", Value::VNum(1));
+        check("\"a\" = \"a\"", Value::VNum(1));
+        check("let var x := 0 in if 0 then x := 1 end", Value::VUnit);
+    }
+    #[test]
+    fn arithmetic_test() {
+        let min = "(0 - 9223372036854775807 - 1)";
+        check(&format!("{} - 1", min), Value::VNum(i64::MAX));
+        check(&format!("{} / (0 - 1)", min), Value::VNum(i64::MIN));
+        check("4611686018427387904 * 2", Value::VNum(i64::MIN));
+        assert_eq!(eval("1 / (2 - 2)"), Err("division by zero".to_string()));
+    }
+    #[test]
+    fn fun_test() {
+        check(
+            "let function fib(n: int): int = if n < 2 then n else fib(n - 1) + fib(n - 2) in fib(15) end",
+            Value::VNum(610),
+        );
+        check(
+            "let function even(n: int): int = if n = 0 then 1 else odd(n - 1)
+                 function odd(n: int): int = if n = 0 then 0 else even(n - 1) in even(10) end",
+            Value::VNum(1),
+        );
+        // the x of the declaration, not of the call
+        check(
+            "let var x := 1 function get(): int = x in let var x := 2 in get() + x end end",
+            Value::VNum(3),
+        );
+        // a nested function updates the variable of its enclosing one
+        check(
+            "let function count(n: int): int =
+                 let var c := 0 function inc() = c := c + 1 in for i := 1 to n do inc(); c end
+             in count(5) end",
+            Value::VNum(5),
+        );
+    }
+    #[test]
+    fn record_array_test() {
+        check(
+            "let type p = {x: int} var a := p {x = 1} var b := a in b.x := 3; a.x end",
+            Value::VNum(3),
+        );
+        check(
+            "let type a = array of int var x := a [3] of 2 in x[1] := 7; x[0] + x[1] end",
+            Value::VNum(9),
+        );
+        check(
+            "let var s := 0 in for i := 1 to 10 do (if i > 3 then break; s := s + i); s end",
+            Value::VNum(6),
+        );
+        assert_eq!(
+            eval("let type p = {x: int} var a: p := nil in a.x end"),
+            Err("nil record dereference".to_string())
+        );
+        assert_eq!(
+            eval("let type a = array of int var x := a [3] of 2 in x[3] end"),
+            Err("index 3 out of bounds (length 3)".to_string())
+        );
+    }
+}
@@ -0,0 +1,134 @@
+use crate::assem::Instr;
+use crate::tree::Temp;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/*
+ * The control-flow graph of a function's instructions, one node per instruction:
+ * control goes from an instruction to the next one, or to the labels it jumps to.
+ */
+#[derive(Clone, Debug)]
+pub struct FlowGraph {
+    pub defs: Vec<Vec<Temp>>,
+    pub uses: Vec<Vec<Temp>>,
+    pub is_move: Vec<bool>,
+    pub succ: Vec<Vec<usize>>,
+    pub pred: Vec<Vec<usize>>,
+}
+
+impl FlowGraph {
+    pub fn new(instrs: &[Instr]) -> Self {
+        let labels: HashMap<_, _> = instrs
+            .iter()
+            .enumerate()
+            .filter_map(|(i, instr)| match *instr {
+                Instr::Label { ref label, .. } => Some((label, i)),
+                _ => None,
+            })
+            .collect();
+        let n = instrs.len();
+        let mut succ = vec![Vec::new(); n];
+        for (i, instr) in instrs.iter().enumerate() {
+            match *instr {
+                // jumps to labels outside the function, as to its end, have no node
+                Instr::Oper {
+                    jump: Some(ref targets),
+                    ..
+                } => succ[i].extend(targets.iter().filter_map(|l| labels.get(l).copied())),
+                _ if i + 1 < n => succ[i].push(i + 1),
+                _ => {}
+            }
+            succ[i].dedup();
+        }
+        let mut pred = vec![Vec::new(); n];
+        for (i, ss) in succ.iter().enumerate() {
+            for &s in ss {
+                pred[s].push(i);
+            }
+        }
+        FlowGraph {
+            defs: instrs.iter().map(|i| i.defs().to_vec()).collect(),
+            uses: instrs.iter().map(|i| i.uses().to_vec()).collect(),
+            is_move: instrs
+                .iter()
+                .map(|i| matches!(*i, Instr::Move { .. }))
+                .collect(),
+            succ,
+            pred,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.succ.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.succ.is_empty()
+    }
+}
+
+/* Escapes s for a double-quoted DOT string. */
+pub fn dot_string(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/* Writes the graph of the function name in DOT, labelling each node with its instruction. */
+pub fn write_dot<W: Write>(
+    mut out: W,
+    name: &str,
+    instrs: &[Instr],
+    graph: &FlowGraph,
+    temp_name: &dyn Fn(Temp) -> String,
+) -> io::Result<()> {
+    writeln!(out, "digraph \"{}\" {{", dot_string(name))?;
+    writeln!(out, "  node [shape=box, fontname=monospace];")?;
+    for (i, instr) in instrs.iter().enumerate() {
+        let text = instr.format(temp_name);
+        writeln!(out, "  n{} [label=\"{}: {}\"];", i, i, dot_string(&text))?;
+    }
+    for (i, ss) in graph.succ.iter().enumerate() {
+        for s in ss {
+            writeln!(out, "  n{} -> n{};", i, s)?;
+        }
+    }
+    writeln!(out, "}}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::Label;
+
+    #[test]
+    fn flow_test() {
+        let l = Label("L0".to_string());
+        let instrs = vec![
+            Instr::Label {
+                assem: "L0:".to_string(),
+                label: l.clone(),
+            },
+            Instr::oper("cmp 's0, 0", vec![], vec![Temp(100)]),
+            Instr::Oper {
+                assem: "jne L0".to_string(),
+                dst: vec![],
+                src: vec![],
+                jump: Some(vec![l, Label("L1".to_string())]),
+            },
+            Instr::Move {
+                assem: "mov 'd0, 's0".to_string(),
+                dst: Temp(101),
+                src: Temp(100),
+            },
+        ];
+        let graph = FlowGraph::new(&instrs);
+        // L1 is outside, so the jump only goes back to L0
+        assert_eq!(graph.succ, vec![vec![1], vec![2], vec![0], vec![]]);
+        assert_eq!(graph.pred[0], vec![2]);
+        assert_eq!(graph.is_move, vec![false, false, false, true]);
+        let mut out = Vec::new();
+        write_dot(&mut out, "f", &instrs, &graph, &|t| t.to_string()).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("n3 [label=\"3: mov t101, t100\"];"));
+        assert!(out.contains("n2 -> n0;"));
+    }
+}
@@ -0,0 +1,201 @@
+use crate::tree::{binop, mem, mov, seq, BinOp, Exp, Gen, Label, Stm, Temp};
+
+/* Where a variable lives: at an offset from the frame pointer, or in a temporary. */
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Access {
+    InFrame(i64),
+    InReg(Temp),
+}
+
+/*
+ * The layout of the activation record of one function, which depends on the target.
+ * Escaping formals and locals live in the frame so that nested functions can reach them
+ * through static links; the others get temporaries.
+ */
+pub trait Frame: Sized {
+    const WORD_SIZE: i64;
+
+    /* A frame for a function whose formals escape or not, in order. */
+    fn new(name: Label, formals: &[bool], gen: &mut Gen) -> Self;
+    fn name(&self) -> &Label;
+    /* Where the body sees each formal. */
+    fn formals(&self) -> &[Access];
+    /* Where the i-th argument of a call arrives, in a register or relative to the callee's fp. */
+    fn incoming_arg(i: usize) -> Access;
+    fn alloc_local(&mut self, escape: bool, gen: &mut Gen) -> Access;
+    /* Bytes of the frame taken by locals so far. */
+    fn frame_size(&self) -> i64;
+
+    /* The frame pointer and the register results are returned in. */
+    fn fp() -> Temp;
+    fn rv() -> Temp;
+    /* How to print t, e.g. with the name of the machine register it stands for. */
+    fn temp_name(t: Temp) -> String {
+        t.to_string()
+    }
+
+    /* The variable at access, given the address of the frame it lives in. */
+    fn exp(access: Access, fp: Exp) -> Exp {
+        match access {
+            Access::InFrame(k) => mem(binop(BinOp::Plus, fp, Exp::Const(k))),
+            Access::InReg(t) => Exp::Temp(t),
+        }
+    }
+    /* A call of a function of the runtime library, which takes no static link. */
+    fn external_call(name: &str, args: Vec<Exp>) -> Exp {
+        Exp::Call(Box::new(Exp::Name(Label(name.to_string()))), args)
+    }
+    /* Wraps body with the moves of the incoming arguments to where formals says they are. */
+    fn proc_entry_exit1(&self, body: Stm) -> Stm;
+}
+
+/* What translation produces: function bodies with their frames, and string literals. */
+#[derive(Clone, Debug)]
+pub enum Fragment<F> {
+    Proc { body: Stm, frame: F },
+    Str(Label, String),
+}
+
+/*
+ * The x86-64 frame, following the System V calling convention: the first six arguments
+ * arrive in rdi, rsi, rdx, rcx, r8 and r9, the others on the stack above the return
+ * address and the saved rbp; locals are below rbp.
+ */
+#[derive(Clone, Debug)]
+pub struct X64Frame {
+    name: Label,
+    formals: Vec<Access>,
+    incoming: Vec<Exp>, // where each formal arrives
+    locals: i64,        // words of the frame taken so far
+}
+
+pub mod x64 {
+    use crate::tree::Temp;
+
+    pub const REGISTERS: [&str; 16] = [
+        "rax", "rbx", "rcx", "rdx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10", "r11", "r12",
+        "r13", "r14", "r15",
+    ];
+    pub const RAX: Temp = Temp(0);
+    pub const RBX: Temp = Temp(1);
+    pub const RCX: Temp = Temp(2);
+    pub const RDX: Temp = Temp(3);
+    pub const RBP: Temp = Temp(6);
+    pub const RSP: Temp = Temp(7);
+    pub const R10: Temp = Temp(10);
+    pub const R11: Temp = Temp(11);
+    pub const ARGS: [Temp; 6] = [Temp(5), Temp(4), Temp(3), Temp(2), Temp(8), Temp(9)];
+    /* Registers a call may overwrite, and those it must preserve besides rbp and rsp. */
+    pub const CALLER_SAVES: [Temp; 9] = [
+        Temp(0),
+        Temp(2),
+        Temp(3),
+        Temp(4),
+        Temp(5),
+        Temp(8),
+        Temp(9),
+        Temp(10),
+        Temp(11),
+    ];
+    pub const CALLEE_SAVES: [Temp; 5] = [Temp(1), Temp(12), Temp(13), Temp(14), Temp(15)];
+}
+
+impl Frame for X64Frame {
+    const WORD_SIZE: i64 = 8;
+
+    fn new(name: Label, formals: &[bool], gen: &mut Gen) -> Self {
+        let mut frame = X64Frame {
+            name,
+            formals: Vec::with_capacity(formals.len()),
+            incoming: Vec::with_capacity(formals.len()),
+            locals: 0,
+        };
+        for (i, &escape) in formals.iter().enumerate() {
+            let (incoming, access) = match Self::incoming_arg(i) {
+                Access::InReg(reg) => (Exp::Temp(reg), frame.alloc_local(escape, gen)),
+                at => (Self::exp(at, Exp::Temp(Self::fp())), at),
+            };
+            frame.incoming.push(incoming);
+            frame.formals.push(access);
+        }
+        frame
+    }
+    fn name(&self) -> &Label {
+        &self.name
+    }
+    fn formals(&self) -> &[Access] {
+        &self.formals
+    }
+    fn incoming_arg(i: usize) -> Access {
+        match x64::ARGS.get(i) {
+            Some(&reg) => Access::InReg(reg),
+            // above the saved rbp and the return address
+            None => Access::InFrame((i - x64::ARGS.len()) as i64 * Self::WORD_SIZE + 16),
+        }
+    }
+    fn alloc_local(&mut self, escape: bool, gen: &mut Gen) -> Access {
+        if escape {
+            self.locals += 1;
+            Access::InFrame(-self.locals * Self::WORD_SIZE)
+        } else {
+            Access::InReg(gen.temp())
+        }
+    }
+    fn frame_size(&self) -> i64 {
+        self.locals * Self::WORD_SIZE
+    }
+    fn fp() -> Temp {
+        x64::RBP
+    }
+    fn rv() -> Temp {
+        x64::RAX
+    }
+    fn temp_name(t: Temp) -> String {
+        match x64::REGISTERS.get(t.0) {
+            Some(name) => name.to_string(),
+            None => t.to_string(),
+        }
+    }
+    fn proc_entry_exit1(&self, body: Stm) -> Stm {
+        let mut stms = Vec::with_capacity(self.formals.len() + 1);
+        for (&access, incoming) in self.formals.iter().zip(&self.incoming) {
+            let dst = Self::exp(access, Exp::Temp(Self::fp()));
+            if dst != *incoming {
+                stms.push(mov(dst, incoming.clone()));
+            }
+        }
+        stms.push(body);
+        seq(stms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn x64_test() {
+        let mut gen = Gen::new();
+        let formals = [true, false, false, false, false, false, false, true];
+        let frame = X64Frame::new(Label("f".to_string()), &formals, &mut gen);
+        let accesses = frame.formals();
+        assert_eq!(accesses[0], Access::InFrame(-8));
+        assert_eq!(accesses[1], Access::InReg(Temp(100)));
+        // the seventh and eighth arrive on the stack and stay there
+        assert_eq!(accesses[6], Access::InFrame(16));
+        assert_eq!(accesses[7], Access::InFrame(24));
+        assert_eq!(frame.frame_size(), 8);
+        let mut frame = frame;
+        assert_eq!(frame.alloc_local(true, &mut gen), Access::InFrame(-16));
+        assert_eq!(X64Frame::temp_name(Temp(6)), "rbp");
+        // one move for each formal that arrives in a register
+        let mut body = frame.proc_entry_exit1(Stm::Exp(Box::new(Exp::Const(0))));
+        let mut moves = 0;
+        while let Stm::Seq(first, rest) = body {
+            assert!(matches!(*first, Stm::Move(..)));
+            moves += 1;
+            body = *rest;
+        }
+        assert_eq!(moves, 6);
+    }
+}
@@ -1,20 +1,36 @@
-use crate::ast::{Dec, Expr, FunDec, LValue, Op, TypeId, Value};
+use crate::ast::{Dec, Expr, FunDec, LValue, Op, Span, Type, TypeId, Value};
+use crate::engine::HostFun;
+use crate::trace::Tracer;
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::rc::{Rc, Weak};
 
 enum LoopBreak {
-    LoopBreak,
+    Break,
     Err(String),
+    At(String, Span), // an error, once the innermost located expression around it is known
+}
+/*
+ * A Tiger function refers to the environment its group was declared in, which holds the
+ * group itself. The let evaluating the declarations owns that environment; functions
+ * cannot outlive it, so a weak reference suffices and no cycle is leaked.
+ */
+#[derive(Clone)]
+enum Fun {
+    Tiger(FunDec, Weak<RefCell<Env>>),
+    Host(HostFun),
 }
 type Env = (
     HashMap<String, usize>, /* holds pointers */
-    HashMap<String, FunDec>,
-    HashMap<String, ()>, /* types */
+    HashMap<String, Fun>,
+    HashMap<String, Type>, /* types */
 );
 type VarPool = Vec<Value>; /* vector for variables */
+type Elems = Rc<RefCell<Vec<Value>>>; /* contents of an array */
 
 fn get_var(name: &str, env: &Env, varpool: &mut VarPool) -> Result<Value, LoopBreak> {
     if let Some(&idx) = env.0.get(name) {
-        Ok(varpool[idx].clone()) // TODO fix, because struct type must not be cloned here
+        Ok(varpool[idx].clone()) // records and arrays are shared, not copied
     } else {
         Err(LoopBreak::Err("variable not found".to_string()))
     }
@@ -37,31 +53,63 @@ fn update_var(name: &str, val: Value, env: &Env, varpool: &mut VarPool) -> Resul
     }
 }
 
+/*
+ * A read-only view of the variables visible at some point of evaluation.
+ * Tracers receive one on every expression entry.
+ */
+pub struct Scope<'a> {
+    env: &'a Env,
+    varpool: &'a VarPool,
+}
+
+impl<'a> Scope<'a> {
+    pub fn lookup(&self, name: &str) -> Option<&'a Value> {
+        self.env.0.get(name).map(|&idx| &self.varpool[idx])
+    }
+    /* sorted by name */
+    pub fn variables(&self) -> Vec<(&'a str, &'a Value)> {
+        let mut vars: Vec<(&str, &Value)> = self
+            .env
+            .0
+            .iter()
+            .map(|(name, &idx)| (name.as_str(), &self.varpool[idx]))
+            .collect();
+        vars.sort_by(|a, b| a.0.cmp(b.0));
+        vars
+    }
+}
+
 fn define_fun(
     name: &str,
     args: &[(String, TypeId)],
     opt_ty: &Option<TypeId>,
     body: &Expr,
+    scope: &Rc<RefCell<Env>>,
     env: &Env,
 ) -> Env {
     let mut cp_funenv = env.1.clone();
     cp_funenv.insert(
         name.to_string(),
-        (
-            name.to_string(),
-            args.to_vec(),
-            opt_ty.clone(),
-            body.clone(),
+        Fun::Tiger(
+            (
+                name.to_string(),
+                args.to_vec(),
+                opt_ty.clone(),
+                body.clone(),
+            ),
+            Rc::downgrade(scope),
         ),
     );
     (env.0.clone(), cp_funenv, env.2.clone())
 }
-fn arithmetic(op: Op, v1: i64, v2: i64) -> i64 {
-    match op {
-        Op::Add => v1 + v2,
-        Op::Sub => v1 - v2,
-        Op::Mul => v1 * v2,
-        Op::Div => v1 / v2,
+/* Integers wrap around on overflow, as in the compiled program. */
+fn arithmetic(op: Op, v1: i64, v2: i64) -> Result<i64, LoopBreak> {
+    Ok(match op {
+        Op::Add => v1.wrapping_add(v2),
+        Op::Sub => v1.wrapping_sub(v2),
+        Op::Mul => v1.wrapping_mul(v2),
+        Op::Div if v2 == 0 => return Err(LoopBreak::Err("division by zero".to_string())),
+        Op::Div => v1.wrapping_div(v2),
         Op::Lt => {
             if v1 < v2 {
                 1
@@ -91,207 +139,422 @@ fn arithmetic(op: Op, v1: i64, v2: i64) -> i64 {
             }
         }
         _ => panic!("internal error (>_<)"),
+    })
+}
+
+/* Follows aliases to a record or array declaration, or to a name without one such as int. */
+fn resolve_alias<'a>(mut ty: &'a str, env: &'a Env) -> (&'a str, Option<&'a Type>) {
+    for _ in 0..=env.2.len() {
+        match env.2.get(ty) {
+            Some(Type::Id(ref x)) => ty = x,
+            decl => return (ty, decl),
+        }
     }
+    (ty, None) // a cycle of aliases, which typing rejects
 }
 
-fn type_check(val: &Value, ty: &str) -> Result<(), LoopBreak> {
-    if ty == "int" {
-        if let Value::VNum(_) = *val {
-            Ok(())
-        } else {
-            Err(LoopBreak::Err("type_check failed".to_string()))
+fn type_check(val: &Value, ty: &str, env: &Env) -> Result<(), LoopBreak> {
+    let ok = match (resolve_alias(ty, env), val) {
+        ((_, Some(Type::Field(_))), Value::VRecord(_) | Value::VNil) => true,
+        ((_, Some(Type::Array(_))), Value::VArray(_)) => true,
+        ((_, Some(_)), _) => false,
+        (("int", None), Value::VNum(_)) | (("string", None), Value::VStr(_)) => true,
+        ((name, None), _) => name != "int" && name != "string",
+    };
+    if ok {
+        Ok(())
+    } else {
+        Err(LoopBreak::Err("type_check failed".to_string()))
+    }
+}
+
+fn get_lval(
+    lval: &LValue,
+    env: &Env,
+    varpool: &mut VarPool,
+    tracer: &mut dyn Tracer,
+) -> Result<Value, LoopBreak> {
+    match *lval {
+        LValue::Id(ref x) => get_var(x, env, varpool),
+        LValue::Mem(ref l, ref name) => match get_lval(l, env, varpool, tracer)? {
+            Value::VRecord(fields) => match fields.borrow().iter().find(|field| field.0 == *name) {
+                Some((_, val)) => Ok(val.clone()),
+                None => Err(LoopBreak::Err(format!("record has no field {}", name))),
+            },
+            Value::VNil => Err(LoopBreak::Err("nil record dereference".to_string())),
+            _ => Err(LoopBreak::Err("field access to a non-record".to_string())),
+        },
+        LValue::Idx(ref l, ref e) => {
+            let (elems, i) = get_index(l, e, env, varpool, tracer)?;
+            let val = elems.borrow()[i].clone();
+            Ok(val)
         }
-    } else if ty == "string" {
-        if let Value::VStr(_) = *val {
+    }
+}
+
+/* Evaluates l[e] up to the bounds check. */
+fn get_index(
+    l: &LValue,
+    e: &Expr,
+    env: &Env,
+    varpool: &mut VarPool,
+    tracer: &mut dyn Tracer,
+) -> Result<(Elems, usize), LoopBreak> {
+    let ary = get_lval(l, env, varpool, tracer)?;
+    let idx = f_sub(e, env, varpool, tracer)?;
+    match (ary, idx) {
+        (Value::VArray(elems), Value::VNum(i)) => {
+            let len = elems.borrow().len();
+            if i < 0 || i as usize >= len {
+                return Err(LoopBreak::Err(format!(
+                    "index {} out of bounds (length {})",
+                    i, len
+                )));
+            }
+            Ok((elems, i as usize))
+        }
+        _ => Err(LoopBreak::Err("indexing of a non-array".to_string())),
+    }
+}
+
+fn set_lval(
+    lval: &LValue,
+    val: Value,
+    env: &Env,
+    varpool: &mut VarPool,
+    tracer: &mut dyn Tracer,
+) -> Result<(), LoopBreak> {
+    match *lval {
+        LValue::Id(ref name) => {
+            tracer.write_var(name, &val);
+            update_var(name, val, env, varpool)
+        }
+        LValue::Mem(ref l, ref name) => match get_lval(l, env, varpool, tracer)? {
+            Value::VRecord(fields) => {
+                match fields
+                    .borrow_mut()
+                    .iter_mut()
+                    .find(|field| field.0 == *name)
+                {
+                    Some(field) => {
+                        field.1 = val;
+                        Ok(())
+                    }
+                    None => Err(LoopBreak::Err(format!("record has no field {}", name))),
+                }
+            }
+            Value::VNil => Err(LoopBreak::Err("nil record dereference".to_string())),
+            _ => Err(LoopBreak::Err("field access to a non-record".to_string())),
+        },
+        LValue::Idx(ref l, ref e) => {
+            let (elems, i) = get_index(l, e, env, varpool, tracer)?;
+            elems.borrow_mut()[i] = val;
             Ok(())
-        } else {
-            Err(LoopBreak::Err("type_check failed".to_string()))
         }
-    } else {
-        panic!("type_check not implemented");
     }
 }
 
-fn f_sub(ast: &Expr, env: &Env, varpool: &mut VarPool, verbose: bool) -> Result<Value, LoopBreak> {
-    if verbose {
-        println!("env: {:?}, varpool: {:?}", env, varpool);
+/* Evaluates arguments from left to right, checking each against its parameter type. */
+fn eval_args(
+    es: &[Expr],
+    tys: &[&str],
+    env: &Env,
+    varpool: &mut VarPool,
+    tracer: &mut dyn Tracer,
+) -> Result<Vec<Value>, LoopBreak> {
+    if tys.len() != es.len() {
+        return Err(LoopBreak::Err("wrong number of argument(s)".to_string()));
+    }
+    let mut args = Vec::with_capacity(es.len());
+    for (e, ty) in es.iter().zip(tys) {
+        let val = f_sub(e, env, varpool, tracer)?;
+        type_check(&val, ty, env)?;
+        args.push(val);
     }
+    Ok(args)
+}
+
+fn f_sub(
+    ast: &Expr,
+    env: &Env,
+    varpool: &mut VarPool,
+    tracer: &mut dyn Tracer,
+) -> Result<Value, LoopBreak> {
     match *ast {
         Expr::Num(i) => Ok(Value::VNum(i)),
         Expr::Str(ref str) => Ok(Value::VStr(str.clone())),
-        Expr::LVal(LValue::Id(ref x)) => get_var(x, env, varpool),
-        Expr::LVal(_) => panic!("f_sub Expr::LVal"),
-        Expr::Neg(ref e) => match f_sub(e, env, varpool, verbose)? {
-            Value::VNum(i) => Ok(Value::VNum(-i)),
+        Expr::LVal(ref lval) => get_lval(lval, env, varpool, tracer),
+        Expr::Neg(ref e) => match f_sub(e, env, varpool, tracer)? {
+            Value::VNum(i) => Ok(Value::VNum(i.wrapping_neg())),
             _ => Err(LoopBreak::Err("Expr::Neg failed".to_string())),
         },
 
         Expr::OpNode(op, ref e1, ref e2) => match op {
             Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Lt | Op::Gt | Op::Le | Op::Ge => {
                 match (
-                    f_sub(e1, env, varpool, verbose)?,
-                    f_sub(e2, env, varpool, verbose)?,
+                    f_sub(e1, env, varpool, tracer)?,
+                    f_sub(e2, env, varpool, tracer)?,
                 ) {
-                    (Value::VNum(i1), Value::VNum(i2)) => Ok(Value::VNum(arithmetic(op, i1, i2))),
+                    (Value::VNum(i1), Value::VNum(i2)) => Ok(Value::VNum(arithmetic(op, i1, i2)?)),
+                    (Value::VStr(s1), Value::VStr(s2)) => match op {
+                        Op::Lt | Op::Gt | Op::Le | Op::Ge => {
+                            Ok(Value::VNum(arithmetic(op, s1.cmp(&s2) as i64, 0)?))
+                        }
+                        _ => Err(LoopBreak::Err("arithmetic operation failed".to_string())),
+                    },
                     _ => Err(LoopBreak::Err("arithmetic operation failed".to_string())),
                 }
             }
             Op::Eq | Op::Ne => {
                 let (v1, v2) = (
-                    f_sub(e1, env, varpool, verbose)?,
-                    f_sub(e2, env, varpool, verbose)?,
+                    f_sub(e1, env, varpool, tracer)?,
+                    f_sub(e2, env, varpool, tracer)?,
                 );
                 let res = (op == Op::Ne) ^ (v1 == v2);
                 Ok(Value::VNum(if res { 1 } else { 0 }))
             }
             Op::Or => {
-                let v1 = f_sub(e1, env, varpool, verbose)?;
+                let v1 = f_sub(e1, env, varpool, tracer)?;
                 match v1 {
-                    Value::VNum(0) => f_sub(e2, env, varpool, verbose),
+                    Value::VNum(0) => f_sub(e2, env, varpool, tracer),
                     Value::VNum(_) => Ok(v1),
                     _ => Err(LoopBreak::Err("type error in Op::Or".to_string())),
                 }
             }
             Op::And => {
-                let v1 = f_sub(e1, env, varpool, verbose)?;
+                let v1 = f_sub(e1, env, varpool, tracer)?;
                 match v1 {
                     Value::VNum(0) => Ok(v1),
-                    Value::VNum(_) => f_sub(e2, env, varpool, verbose),
+                    Value::VNum(_) => f_sub(e2, env, varpool, tracer),
                     _ => Err(LoopBreak::Err("type error in Op::And".to_string())),
                 }
             }
         },
         Expr::IfNode(ref cond, ref e_true, ref e_false) => {
-            match f_sub(cond, env, varpool, verbose)? {
-                Value::VNum(0) => f_sub(e_false, env, varpool, verbose),
-                Value::VNum(_) => f_sub(e_true, env, varpool, verbose),
+            match f_sub(cond, env, varpool, tracer)? {
+                Value::VNum(0) => f_sub(e_false, env, varpool, tracer),
+                Value::VNum(_) => f_sub(e_true, env, varpool, tracer),
                 _ => Err(LoopBreak::Err(
                     "Condition of if has to be an integer.".to_string(),
                 )),
             }
         }
         Expr::Nil => Ok(Value::VNil),
-        Expr::LAsgn(LValue::Id(ref name), ref e) => {
-            update_var(name, f_sub(e, env, varpool, verbose)?, env, varpool)?;
-            Ok(Value::VNil)
+        Expr::LAsgn(ref lval, ref e) => {
+            let val = f_sub(e, env, varpool, tracer)?;
+            set_lval(lval, val, env, varpool, tracer)?;
+            Ok(Value::VUnit)
         }
-        Expr::LAsgn(ref _lval, ref _e) => panic!("f_sub Expr::LAsgn"),
         Expr::Seq(ref es) => {
-            let mut val = Value::VNil;
+            let mut val = Value::VUnit;
             for e in es {
-                val = f_sub(e, env, varpool, verbose)?;
+                val = f_sub(e, env, varpool, tracer)?;
             }
             Ok(val)
         }
         Expr::Let(ref decs, ref e2) => {
             let mut cp_env = env.clone();
+            // the environments of the function groups declared so far, and of the current one
+            let mut scopes: Vec<Rc<RefCell<Env>>> = Vec::new();
+            let mut in_group = false;
             for dec in decs {
+                if in_group && !matches!(*dec, Dec::Fun(..)) {
+                    *scopes.last().unwrap().borrow_mut() = cp_env.clone();
+                    in_group = false;
+                }
                 match *dec {
-                    Dec::Var(ref name, ref opt_ty, ref e) => {
-                        let val = f_sub(e, env, varpool, verbose)?;
+                    Dec::Var(ref name, ref opt_ty, ref e, _) => {
+                        let val = f_sub(e, &cp_env, varpool, tracer)?;
                         // type-check
                         if let Some(ref ty) = *opt_ty {
-                            type_check(&val, ty)?;
+                            type_check(&val, ty, &cp_env)?;
                         }
+                        tracer.write_var(name, &val);
                         cp_env = define_var(name, val, &cp_env, varpool);
                     }
                     Dec::Fun(ref name, ref args, ref opt_ty, ref body) => {
-                        cp_env = define_fun(name, args, opt_ty, body, &cp_env);
+                        if !in_group {
+                            scopes.push(Rc::new(RefCell::new(cp_env.clone())));
+                            in_group = true;
+                        }
+                        let scope = scopes.last().unwrap();
+                        cp_env = define_fun(name, args, opt_ty, body, scope, &cp_env);
+                    }
+                    Dec::Type(ref name, ref ty, _) => {
+                        cp_env.2.insert(name.clone(), ty.clone());
                     }
-                    _ => panic!("f_sub Expr::Let not supported"),
                 }
             }
-            f_sub(e2, &cp_env, varpool, verbose)
+            if in_group {
+                *scopes.last().unwrap().borrow_mut() = cp_env.clone();
+            }
+            f_sub(e2, &cp_env, varpool, tracer)
         }
         Expr::For(ref var, ref st, ref en, ref body) => {
-            if let Value::VNum(st_val) = f_sub(st, env, varpool, verbose)? {
-                if let Value::VNum(en_val) = f_sub(en, env, varpool, verbose)? {
+            if let Value::VNum(st_val) = f_sub(st, env, varpool, tracer)? {
+                if let Value::VNum(en_val) = f_sub(en, env, varpool, tracer)? {
                     let cp_env = define_var(var, Value::VNum(st_val), env, varpool);
-                    for i in st_val..(en_val + 1) {
+                    for (iteration, i) in (st_val..(en_val + 1)).enumerate() {
+                        tracer.loop_iter(iteration);
+                        tracer.write_var(var, &Value::VNum(i));
                         update_var(var, Value::VNum(i), &cp_env, varpool)?;
-                        let result = f_sub(body, &cp_env, varpool, verbose);
-                        // TODO check if it is no result
-                        if let Err(LoopBreak::LoopBreak) = result {
-                            break;
-                        }
+                        match f_sub(body, &cp_env, varpool, tracer) {
+                            Err(LoopBreak::Break) => break,
+                            result => result?,
+                        };
                     }
-                    return Ok(Value::VNoResult);
+                    return Ok(Value::VUnit);
                 }
             }
             panic!("f_sub Expr::For");
         }
         Expr::Do(ref cond, ref body) => {
+            let mut iteration = 0;
             loop {
-                if let Value::VNum(cval) = f_sub(cond, env, varpool, verbose)? {
+                if let Value::VNum(cval) = f_sub(cond, env, varpool, tracer)? {
                     if cval == 0 {
                         break;
                     }
-                    let result = f_sub(body, env, varpool, verbose);
-                    // TODO check if it is no result
-                    if let Err(LoopBreak::LoopBreak) = result {
-                        break;
-                    }
+                    tracer.loop_iter(iteration);
+                    iteration += 1;
+                    match f_sub(body, env, varpool, tracer) {
+                        Err(LoopBreak::Break) => break,
+                        result => result?,
+                    };
                 }
             }
-            Ok(Value::VNoResult)
+            Ok(Value::VUnit)
         }
         Expr::FunApp(ref f, ref es) => {
             // evaluate arguments from left to right
             match env.1.get(f) {
-                Some((_, params, opt_retty, body)) => {
+                Some(Fun::Tiger((_, params, opt_retty, body), scope)) => {
                     let n = es.len();
-                    let mut args = vec![Value::VNum(0); n];
-                    if params.len() != n {
-                        return Err(LoopBreak::Err("wrong number of argument(s)".to_string()));
-                    }
-                    for i in 0..n {
-                        args[i] = f_sub(&es[i], env, varpool, verbose)?;
-                        type_check(&args[i], &params[i].1)?;
-                    }
-                    let mut cp_env = env.clone();
+                    let tys: Vec<&str> = params.iter().map(|p| p.1.as_str()).collect();
+                    let args = eval_args(es, &tys, env, varpool, tracer)?;
+                    tracer.call(f, &args);
+                    // the body sees the variables of where f is declared, not of the caller
+                    let decl_env = scope
+                        .upgrade()
+                        .expect("a function outlived its let")
+                        .borrow()
+                        .clone();
+                    let mut cp_env = decl_env.clone();
                     for i in 0..n {
+                        tracer.write_var(&params[i].0, &args[i]);
                         cp_env = define_var(&params[i].0, args[i].clone(), &cp_env, varpool);
                     }
-                    // TODO env handling
-                    let result = f_sub(body, &cp_env, varpool, verbose)?;
-                    if let Some(ref retty) = *opt_retty {
-                        type_check(&result, retty)?;
-                    }
-                    Ok(result)
+                    let result = f_sub(body, &cp_env, varpool, tracer).and_then(|result| {
+                        if let Some(ref retty) = *opt_retty {
+                            type_check(&result, retty, &decl_env)?;
+                        }
+                        Ok(result)
+                    });
+                    tracer.ret(f, result.as_ref().ok());
+                    result
+                }
+                Some(Fun::Host(host)) => {
+                    let tys: Vec<&str> = host.params.iter().map(|ty| ty.as_str()).collect();
+                    let args = eval_args(es, &tys, env, varpool, tracer)?;
+                    tracer.call(f, &args);
+                    let result = (host.call)(&args)
+                        .map_err(LoopBreak::Err)
+                        .and_then(|result| {
+                            if let Some(ref retty) = host.ret {
+                                type_check(&result, retty, env)?;
+                            }
+                            Ok(result)
+                        });
+                    tracer.ret(f, result.as_ref().ok());
+                    result
                 }
                 None => Err(LoopBreak::Err("function not found in FunApp".to_string())),
             }
         }
-        Expr::NewStruct(ref _tyname, ref _fields) => {
-            Err(LoopBreak::Err("f_sub Expr::NewStruct".to_string()))
+        Expr::NewStruct(ref _tyname, ref fields) => {
+            let mut vals = Vec::with_capacity(fields.len());
+            for (name, e) in fields {
+                vals.push((name.clone(), f_sub(e, env, varpool, tracer)?));
+            }
+            Ok(Value::VRecord(Rc::new(RefCell::new(vals))))
+        }
+        Expr::NewArray(ref _tyname, ref cnt, ref e) => {
+            let n = match f_sub(cnt, env, varpool, tracer)? {
+                Value::VNum(n) if n >= 0 => n as usize,
+                _ => return Err(LoopBreak::Err("invalid array size".to_string())),
+            };
+            let init = f_sub(e, env, varpool, tracer)?;
+            Ok(Value::VArray(Rc::new(RefCell::new(vec![init; n]))))
         }
-        Expr::NewArray(ref _tyname, ref _e, ref _cnt) => {
-            Err(LoopBreak::Err("f_sub Expr::NewArray".to_string()))
+        Expr::Break => Err(LoopBreak::Break),
+        Expr::Spanned(span, ref e) => {
+            tracer.enter_expr(span, e, &Scope { env, varpool });
+            if tracer.aborted() {
+                return Err(LoopBreak::Err(ABORTED.to_string()));
+            }
+            let result = f_sub(e, env, varpool, tracer);
+            tracer.exit_expr(span, result.as_ref().ok());
+            match result {
+                Err(LoopBreak::Err(e)) => Err(LoopBreak::At(e, span)),
+                result => result,
+            }
         }
-        Expr::Break => Err(LoopBreak::LoopBreak),
     }
 }
 
-pub fn f(ast: &Expr, verbose: bool) -> Value {
+/* The error of a run that its tracer aborted. */
+pub const ABORTED: &str = "aborted by the tracer";
+
+/*
+ * Evaluates ast with the given host functions in scope.
+ * Functions declared in the program shadow host functions of the same name.
+ */
+pub fn run(
+    ast: &Expr,
+    host: &[(String, HostFun)],
+    tracer: &mut dyn Tracer,
+) -> Result<Value, String> {
+    run_located(ast, host, tracer).map_err(|e| e.0)
+}
+
+/* Like run, giving with an error the innermost located expression it arose in, if any. */
+pub fn run_located(
+    ast: &Expr,
+    host: &[(String, HostFun)],
+    tracer: &mut dyn Tracer,
+) -> Result<Value, (String, Option<Span>)> {
+    let funs = host
+        .iter()
+        .map(|(name, fun)| (name.clone(), Fun::Host(fun.clone())))
+        .collect();
     match f_sub(
         ast,
-        &(HashMap::new(), HashMap::new(), HashMap::new()),
+        &(HashMap::new(), funs, HashMap::new()),
         &mut Vec::new(),
-        verbose,
+        tracer,
     ) {
+        Ok(result) => Ok(result),
+        Err(LoopBreak::Break) => Err(("break outside loop was detected".to_string(), None)),
+        Err(LoopBreak::Err(str)) => Err((str, None)),
+        Err(LoopBreak::At(str, span)) => Err((str, Some(span))),
+    }
+}
+
+pub fn f(ast: &Expr, tracer: &mut dyn Tracer) -> Value {
+    match run(ast, &[], tracer) {
         Ok(result) => result,
-        Err(LoopBreak::LoopBreak) => panic!("break outside loop was detected"),
-        Err(LoopBreak::Err(str)) => panic!("interpret::f: {}", str),
+        Err(str) => panic!("interpret::f: {}", str),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::trace::NoTracer;
     use crate::{interpret, parse};
     fn check(expr: &str, val: Value) {
         let ast = parse::parse(expr);
-        assert_eq!(interpret::f(&ast, false), val);
+        assert_eq!(interpret::f(&ast, &mut NoTracer), val);
     }
     #[test]
     fn letex_test() {
@@ -307,6 +570,19 @@ mod tests {
         );
     }
     #[test]
+    fn scope_test() {
+        check(
+            "let var x := 1 function get(): int = x in let var x := 2 in get() + x end end",
+            Value::VNum(3),
+        );
+        check(
+            "let function even(n: int): int = if n = 0 then 1 else odd(n - 1)
+                 function odd(n: int): int = if n = 0 then 0 else even(n - 1)
+             in odd(7) end",
+            Value::VNum(1),
+        );
+    }
+    #[test]
     fn comp_test() {
         check("2 < 5", Value::VNum(1));
         check("4 < 1", Value::VNum(0));
@@ -314,6 +590,50 @@ mod tests {
         check("2 = 5", Value::VNum(0));
         check("2 <> 4", Value::VNum(1));
         check("2 <> 2", Value::VNum(0));
+        check("4611686018427387904 * 2", Value::VNum(i64::MIN));
+        let ast = parse::parse("1 / (2 - 2)");
+        assert_eq!(
+            interpret::run(&ast, &[], &mut NoTracer),
+            Err("division by zero".to_string())
+        );
+        check("\"ab\" < \"b\"", Value::VNum(1));
+        check("\"b\" <= \"ab\"", Value::VNum(0));
+        check("\"ab\" >= \"ab\"", Value::VNum(1));
+    }
+    #[test]
+    fn record_test() {
+        check(
+            "let type p = {x: int, y: int} var a := p {x = 1, y = 2} in a.y := a.x + 4; a.y end",
+            Value::VNum(5),
+        );
+        check(
+            "let type p = {x: int} var a := p {x = 1} var b := a in b.x := 3; a.x end",
+            Value::VNum(3),
+        );
+        check(
+            "let type p = {x: int} in p {x = 1} = p {x = 1} end",
+            Value::VNum(0),
+        );
+    }
+    #[test]
+    fn type_dec_test() {
+        check(
+            "let type myint = int function inc(x: myint): myint = x + 1 in inc(2) end",
+            Value::VNum(3),
+        );
+        check(
+            "let type list = {hd: int, tl: list}
+                 function sum(l: list): int = if l = nil then 0 else l.hd + sum(l.tl)
+             in sum(list {hd = 1, tl = list {hd = 2, tl = nil}}) end",
+            Value::VNum(3),
+        );
+    }
+    #[test]
+    fn array_test() {
+        check(
+            "let type a = array of int var x := a [3] of 2 in x[1] := 7; x[0] + x[1] end",
+            Value::VNum(9),
+        );
     }
     #[test]
     fn logic_test() {
@@ -0,0 +1,496 @@
+use crate::frame::{Access, Fragment, Frame};
+use crate::translate::MAIN;
+use crate::tree::{BinOp, Exp, Label, RelOp, Stm, Temp};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+/*
+ * An interpreter of translated programs, to check translation before there is a backend.
+ * Temporaries are simulated per activation; memory is a byte array with an unmapped page
+ * at address 0 (so that nil cannot be dereferenced), a stack growing down from the end of
+ * the stack region, and a heap after it that the builtins allocate from.
+ * Words are WORD_SIZE-byte little-endian integers; a string is a word with its length
+ * followed by its bytes.
+ */
+
+const NULL_PAGE: usize = 4096;
+const STACK_SIZE: usize = 1 << 20;
+
+/* Why execution left a statement early: a jump to a label, or an error. */
+enum Exit {
+    Jump(Label),
+    Err(String),
+}
+
+impl From<String> for Exit {
+    fn from(e: String) -> Self {
+        Exit::Err(e)
+    }
+}
+
+type Temps = HashMap<Temp, i64>;
+
+pub struct Machine<'a, F> {
+    mem: Vec<u8>,
+    sp: usize,
+    strings: HashMap<&'a Label, i64>, // where each string fragment is
+    procs: HashMap<&'a str, (&'a Stm, &'a F)>,
+    out: &'a mut dyn Write,
+    input: &'a mut dyn Read,
+}
+
+impl<'a, F: Frame> Machine<'a, F> {
+    /* Loads the fragments of a program, whose print writes to out and getchar reads input. */
+    pub fn new(
+        fragments: &'a [Fragment<F>],
+        out: &'a mut dyn Write,
+        input: &'a mut dyn Read,
+    ) -> Self {
+        let mut machine = Machine {
+            mem: vec![0; NULL_PAGE + STACK_SIZE],
+            sp: NULL_PAGE + STACK_SIZE,
+            strings: HashMap::new(),
+            procs: HashMap::new(),
+            out,
+            input,
+        };
+        for fragment in fragments {
+            match *fragment {
+                Fragment::Proc {
+                    ref body,
+                    ref frame,
+                } => {
+                    machine.procs.insert(&frame.name().0, (body, frame));
+                }
+                Fragment::Str(ref label, ref s) => {
+                    let at = machine.new_string(s.as_bytes());
+                    machine.strings.insert(label, at);
+                }
+            }
+        }
+        machine
+    }
+
+    /* Runs the program, returning what its main function left in the result register. */
+    pub fn run(&mut self) -> Result<i64, String> {
+        self.call(MAIN, &[])
+    }
+
+    /* The string at address at, e.g. the result of a program of type string. */
+    pub fn string(&self, at: i64) -> Result<String, String> {
+        let bytes = self.string_bytes(at)?;
+        Ok(String::from_utf8_lossy(bytes).into_owned())
+    }
+
+    fn call(&mut self, name: &str, args: &[i64]) -> Result<i64, String> {
+        let Some(&(body, frame)) = self.procs.get(name) else {
+            return self.builtin(name, args);
+        };
+        // stack arguments go right above the callee's frame pointer
+        let top = (0..args.len())
+            .filter_map(|i| match F::incoming_arg(i) {
+                Access::InFrame(k) => Some(k + F::WORD_SIZE),
+                Access::InReg(_) => None,
+            })
+            .max()
+            .unwrap_or(0);
+        let fp = self.sp as i64 - top;
+        let sp = fp - frame.frame_size();
+        if sp < NULL_PAGE as i64 {
+            return Err(format!("stack overflow in {}", name));
+        }
+        let mut temps = Temps::new();
+        temps.insert(F::fp(), fp);
+        for (i, &arg) in args.iter().enumerate() {
+            match F::incoming_arg(i) {
+                Access::InReg(t) => {
+                    temps.insert(t, arg);
+                }
+                Access::InFrame(k) => self.store(fp + k, arg)?,
+            }
+        }
+        let saved_sp = std::mem::replace(&mut self.sp, sp as usize);
+        let result = self.stm(body, &mut temps);
+        self.sp = saved_sp;
+        match result {
+            Ok(()) => Ok(temps.get(&F::rv()).copied().unwrap_or(0)),
+            Err(Exit::Jump(label)) => Err(format!("jump to {} outside of {}", label, name)),
+            Err(Exit::Err(e)) => Err(e),
+        }
+    }
+
+    fn stm(&mut self, stm: &Stm, temps: &mut Temps) -> Result<(), Exit> {
+        match *stm {
+            Stm::Seq(..) => {
+                let mut stms = Vec::new();
+                flatten(stm, &mut stms);
+                self.stms(&stms, temps)
+            }
+            Stm::Label(_) => Ok(()),
+            Stm::Jump(ref e, _) => match **e {
+                Exp::Name(ref label) => Err(Exit::Jump(label.clone())),
+                _ => Err(Exit::Err("computed jumps are not supported".to_string())),
+            },
+            Stm::Cjump(op, ref a, ref b, ref t, ref f) => {
+                let a = self.exp(a, temps)?;
+                let b = self.exp(b, temps)?;
+                Err(Exit::Jump(if relop(op, a, b) { t } else { f }.clone()))
+            }
+            Stm::Move(ref dst, ref src) => self.mov(dst, src, temps),
+            Stm::Exp(ref e) => self.exp(e, temps).map(|_| ()),
+        }
+    }
+
+    /* Moves src to dst, which may be an ESEQ before canonicalization, as a checked access is. */
+    fn mov(&mut self, dst: &Exp, src: &Exp, temps: &mut Temps) -> Result<(), Exit> {
+        match *dst {
+            Exp::Temp(t) => {
+                let v = self.exp(src, temps)?;
+                temps.insert(t, v);
+                Ok(())
+            }
+            Exp::Mem(ref at) => {
+                let at = self.exp(at, temps)?;
+                let v = self.exp(src, temps)?;
+                Ok(self.store(at, v)?)
+            }
+            Exp::Eseq(ref s, ref dst) => {
+                self.stm(s, temps)?;
+                self.mov(dst, src, temps)
+            }
+            _ => Err(Exit::Err(
+                "move to neither a temporary nor memory".to_string(),
+            )),
+        }
+    }
+
+    /* Runs stms in order, following jumps to their labels and passing other jumps up. */
+    fn stms(&mut self, stms: &[&Stm], temps: &mut Temps) -> Result<(), Exit> {
+        let mut pc = 0;
+        while pc < stms.len() {
+            match self.stm(stms[pc], temps) {
+                Ok(()) => pc += 1,
+                Err(Exit::Jump(label)) => {
+                    match stms.iter().position(|s| **s == Stm::Label(label.clone())) {
+                        Some(i) => pc = i + 1,
+                        None => return Err(Exit::Jump(label)),
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    fn exp(&mut self, e: &Exp, temps: &mut Temps) -> Result<i64, Exit> {
+        match *e {
+            Exp::Const(k) => Ok(k),
+            Exp::Name(ref label) => match self.strings.get(label) {
+                Some(&at) => Ok(at),
+                None => Err(Exit::Err(format!("{} is not the label of a string", label))),
+            },
+            Exp::Temp(t) => match temps.get(&t) {
+                Some(&v) => Ok(v),
+                None => Err(Exit::Err(format!("{} is read before it is set", t))),
+            },
+            Exp::Binop(op, ref a, ref b) => {
+                let a = self.exp(a, temps)?;
+                let b = self.exp(b, temps)?;
+                Ok(binop(op, a, b)?)
+            }
+            Exp::Mem(ref at) => {
+                let at = self.exp(at, temps)?;
+                Ok(self.load(at)?)
+            }
+            Exp::Call(ref f, ref args) => {
+                let Exp::Name(ref label) = **f else {
+                    return Err(Exit::Err("computed calls are not supported".to_string()));
+                };
+                let mut vals = Vec::with_capacity(args.len());
+                for arg in args {
+                    vals.push(self.exp(arg, temps)?);
+                }
+                Ok(self.call(&label.0, &vals)?)
+            }
+            Exp::Eseq(ref s, ref e) => {
+                self.stm(s, temps)?;
+                self.exp(e, temps)
+            }
+        }
+    }
+
+    fn word(&self, at: i64) -> Result<usize, String> {
+        let w = F::WORD_SIZE as usize;
+        match usize::try_from(at) {
+            Ok(at) if at >= NULL_PAGE && at + w <= self.mem.len() => Ok(at),
+            _ => Err(format!("invalid memory access at {}", at)),
+        }
+    }
+
+    fn load(&self, at: i64) -> Result<i64, String> {
+        let at = self.word(at)?;
+        let mut bytes = [0; 8];
+        bytes[..F::WORD_SIZE as usize].copy_from_slice(&self.mem[at..at + F::WORD_SIZE as usize]);
+        Ok(i64::from_le_bytes(bytes))
+    }
+
+    fn store(&mut self, at: i64, v: i64) -> Result<(), String> {
+        let at = self.word(at)?;
+        let w = F::WORD_SIZE as usize;
+        self.mem[at..at + w].copy_from_slice(&v.to_le_bytes()[..w]);
+        Ok(())
+    }
+
+    /* Allocates size zeroed bytes on the heap, rounded up to whole words. */
+    fn alloc(&mut self, size: i64) -> Result<i64, String> {
+        let w = F::WORD_SIZE as usize;
+        let size = usize::try_from(size).map_err(|_| format!("allocation of {} bytes", size))?;
+        let at = self.mem.len();
+        self.mem.resize(at + size.div_ceil(w) * w, 0);
+        Ok(at as i64)
+    }
+
+    fn new_string(&mut self, bytes: &[u8]) -> i64 {
+        let w = F::WORD_SIZE as usize;
+        let at = self.mem.len();
+        self.mem.resize(at + w + bytes.len().div_ceil(w) * w, 0);
+        self.mem[at..at + w].copy_from_slice(&(bytes.len() as i64).to_le_bytes()[..w]);
+        self.mem[at + w..at + w + bytes.len()].copy_from_slice(bytes);
+        at as i64
+    }
+
+    fn string_bytes(&self, at: i64) -> Result<&[u8], String> {
+        let len = self.load(at)? as usize;
+        let start = at as usize + F::WORD_SIZE as usize;
+        match self.mem.get(start..start.saturating_add(len)) {
+            Some(bytes) => Ok(bytes),
+            None => Err(format!("invalid string at {}", at)),
+        }
+    }
+
+    /* The functions of the runtime and the standard library (see library.rs). */
+    fn builtin(&mut self, name: &str, args: &[i64]) -> Result<i64, String> {
+        let io_err = |e: std::io::Error| e.to_string();
+        match (name, args) {
+            ("print", &[s]) => {
+                let bytes = self.string_bytes(s)?.to_vec();
+                self.out.write_all(&bytes).map_err(io_err)?;
+                Ok(0)
+            }
+            ("printi", &[i]) => write!(self.out, "{}", i).map(|_| 0).map_err(io_err),
+            ("flush", &[]) => self.out.flush().map(|_| 0).map_err(io_err),
+            ("getchar", &[]) => {
+                let mut c = [0];
+                let n = self.input.read(&mut c).map_err(io_err)?;
+                Ok(self.new_string(&c[..n]))
+            }
+            ("ord", &[s]) => Ok(self.string_bytes(s)?.first().map_or(-1, |&c| i64::from(c))),
+            ("chr", &[i]) => match u8::try_from(i) {
+                Ok(c) => Ok(self.new_string(&[c])),
+                Err(_) => Err(format!("chr({}) is out of range", i)),
+            },
+            ("size", &[s]) => Ok(self.string_bytes(s)?.len() as i64),
+            ("substring", &[s, first, n]) => {
+                let bytes = self.string_bytes(s)?;
+                match (usize::try_from(first), usize::try_from(n)) {
+                    (Ok(first), Ok(n)) if first.saturating_add(n) <= bytes.len() => {
+                        let sub = bytes[first..first + n].to_vec();
+                        Ok(self.new_string(&sub))
+                    }
+                    _ => Err(format!(
+                        "substring({:?}, {}, {}) is out of range",
+                        self.string(s)?,
+                        first,
+                        n
+                    )),
+                }
+            }
+            ("concat", &[a, b]) => {
+                let mut bytes = self.string_bytes(a)?.to_vec();
+                bytes.extend_from_slice(self.string_bytes(b)?);
+                Ok(self.new_string(&bytes))
+            }
+            ("not", &[i]) => Ok((i == 0) as i64),
+            ("allocRecord", &[size]) => self.alloc(size),
+            ("initArray", &[n, init]) => {
+                if n < 0 {
+                    return Err(format!("array of negative size {}", n));
+                }
+                // the length is the word before the elements
+                let at = self.alloc((n + 1) * F::WORD_SIZE)?;
+                self.store(at, n)?;
+                let at = at + F::WORD_SIZE;
+                for k in 0..n {
+                    self.store(at + k * F::WORD_SIZE, init)?;
+                }
+                Ok(at)
+            }
+            ("failIndex", &[i, n]) => Err(format!("index {} out of bounds (length {})", i, n)),
+            ("failNil", &[]) => Err("nil record dereference".to_string()),
+            ("failDivision", &[]) => Err("division by zero".to_string()),
+            ("stringEqual", &[a, b]) => Ok((self.string_bytes(a)? == self.string_bytes(b)?) as i64),
+            ("stringCompare", &[a, b]) => {
+                Ok(self.string_bytes(a)?.cmp(self.string_bytes(b)?) as i64)
+            }
+            _ => Err(format!(
+                "call of undefined function {} with {} argument(s)",
+                name,
+                args.len()
+            )),
+        }
+    }
+}
+
+fn flatten<'s>(stm: &'s Stm, out: &mut Vec<&'s Stm>) {
+    match *stm {
+        Stm::Seq(ref s1, ref s2) => {
+            flatten(s1, out);
+            flatten(s2, out);
+        }
+        _ => out.push(stm),
+    }
+}
+
+fn binop(op: BinOp, a: i64, b: i64) -> Result<i64, String> {
+    Ok(match op {
+        BinOp::Plus => a.wrapping_add(b),
+        BinOp::Minus => a.wrapping_sub(b),
+        BinOp::Mul => a.wrapping_mul(b),
+        BinOp::Div if b == 0 => return Err("division by zero".to_string()),
+        BinOp::Div => a.wrapping_div(b),
+        BinOp::And => a & b,
+        BinOp::Or => a | b,
+        BinOp::Xor => a ^ b,
+        BinOp::Lshift => a.wrapping_shl(b as u32),
+        BinOp::Rshift => (a as u64).wrapping_shr(b as u32) as i64,
+        BinOp::Arshift => a.wrapping_shr(b as u32),
+    })
+}
+
+fn relop(op: RelOp, a: i64, b: i64) -> bool {
+    let (ua, ub) = (a as u64, b as u64);
+    match op {
+        RelOp::Eq => a == b,
+        RelOp::Ne => a != b,
+        RelOp::Lt => a < b,
+        RelOp::Gt => a > b,
+        RelOp::Le => a <= b,
+        RelOp::Ge => a >= b,
+        RelOp::Ult => ua < ub,
+        RelOp::Ule => ua <= ub,
+        RelOp::Ugt => ua > ub,
+        RelOp::Uge => ua >= ub,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Value;
+    use crate::frame::X64Frame;
+    use crate::tree::Gen;
+    use crate::{escape, eval, library, resolve, translate, typing};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /* Runs src with eval::run and with the IR interpreter, which must agree. */
+    fn agree(path: &str, src: &str, input: &'static [u8]) {
+        let out = Rc::new(RefCell::new(Vec::new()));
+        let host = library::functions(out.clone(), Rc::new(RefCell::new(input)));
+        let (ast, types, tast) = typing::check(src, &host).unwrap();
+        let expected = eval::run(&tast, &host).unwrap();
+        let (res, _) = resolve::f(&ast, library::NAMES);
+        let fragments: Vec<Fragment<X64Frame>> =
+            translate::f(&tast, &types, &escape::f(&res), &mut Gen::new());
+        let (mut ir_out, mut ir_input) = (Vec::new(), input);
+        let mut machine = Machine::new(&fragments, &mut ir_out, &mut ir_input);
+        let result = machine.run().unwrap_or_else(|e| panic!("{}: {}", path, e));
+        match expected {
+            Value::VNum(n) => assert_eq!(result, n, "{}", path),
+            Value::VStr(ref s) => assert_eq!(machine.string(result).as_ref(), Ok(s), "{}", path),
+            Value::VNil => assert_eq!(result, 0, "{}", path),
+            _ => {}
+        }
+        drop(machine);
+        assert_eq!(ir_out, *out.borrow(), "{}", path);
+    }
+
+    #[test]
+    fn samples_test() {
+        let mut paths: Vec<_> = std::fs::read_dir("sample")
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "tiger"))
+            .collect();
+        paths.sort();
+        assert!(!paths.is_empty());
+        for path in paths {
+            let src = std::fs::read_to_string(&path).unwrap();
+            agree(&path.display().to_string(), &src, b"");
+        }
+    }
+
+    #[test]
+    fn programs_test() {
+        let queens = "let
+  var n := 5
+  type intArray = array of int
+  var row := intArray [n] of 0
+  var col := intArray [n] of 0
+  var diag1 := intArray [n + n - 1] of 0
+  var diag2 := intArray [n + n - 1] of 0
+  var solutions := 0
+  function printboard() =
+    (solutions := solutions + 1;
+     for i := 0 to n - 1 do
+       (for j := 0 to n - 1 do print(if col[i] = j then \" O\" else \" .\");
+        print(chr(10)));
+     print(chr(10)))
+  function try(c: int) =
+    if c = n then printboard()
+    else (for r := 0 to n - 1 do
+      if row[r] = 0 & diag1[r + c] = 0 & diag2[r + n - 1 - c] = 0 then
+        (row[r] := 1; diag1[r + c] := 1; diag2[r + n - 1 - c] := 1;
+         col[c] := r;
+         try(c + 1);
+         row[r] := 0; diag1[r + c] := 0; diag2[r + n - 1 - c] := 0))
+in
+  try(0); solutions
+end";
+        agree("queens", queens, b"");
+        let strings = "let
+  var s := concat(getchar(), getchar())
+  function f(a: int, b: int, c: int, d: int, e: int, g: int, h: int, i: int): string =
+    substring(\"abcdefghi\", h, i)
+in
+  printi(size(s)); print(s); printi(ord(\"\")); printi(not(ord(s)));
+  if s <> \"xz\" & getchar() = \"\" then print(f(1, 2, 3, 4, 5, 6, 2, 3));
+  concat(s, chr(33))
+end";
+        agree("strings", strings, b"xy");
+        agree("ordering", "(\"ab\" < \"b\") + (\"b\" <= \"ab\") * 2", b"");
+    }
+
+    #[test]
+    fn runtime_test() {
+        let run = |src: &str| {
+            let (ast, types, tast) = typing::check(src, &[]).unwrap();
+            let (res, _) = resolve::f(&ast, &[]);
+            let fragments: Vec<Fragment<X64Frame>> =
+                translate::f(&tast, &types, &escape::f(&res), &mut Gen::new());
+            Machine::new(&fragments, &mut Vec::new(), &mut &b""[..]).run()
+        };
+        assert_eq!(run("1 / (2 - 2)"), Err("division by zero".to_string()));
+        assert_eq!(run("5 / 0"), Err("division by zero".to_string()));
+        let min = "let var m := -9223372036854775807 - 1 in m / -1 = m end";
+        assert_eq!(run(min), Ok(1));
+        let nil = "let type r = {x: int} var v: r := nil in v.x end";
+        assert_eq!(run(nil), Err("nil record dereference".to_string()));
+        let index = "let type a = array of int var x := a[2] of 0 in x[2] := 1 end";
+        let err = "index 2 out of bounds (length 2)";
+        assert_eq!(run(index), Err(err.to_string()));
+        let index = "let type a = array of int var x := a[2] of 0 in x[-1] end";
+        let err = "index -1 out of bounds (length 2)";
+        assert_eq!(run(index), Err(err.to_string()));
+    }
+}
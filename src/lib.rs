@@ -1,4 +1,30 @@
+pub mod assem;
 pub mod ast;
+pub mod canon;
+pub mod cgen;
+pub mod codegen;
+pub mod compile;
+pub mod coverage;
+pub mod debug;
+pub mod diagnostics;
+pub mod engine;
+pub mod escape;
+pub mod eval;
+pub mod flow;
+pub mod frame;
 pub mod interpret;
+pub mod ir_interpret;
+pub mod library;
+pub mod lift;
+pub mod lint;
+pub mod liveness;
+pub mod llvm;
 pub mod parse;
-//pub mod typing;
+pub mod profile;
+pub mod regalloc;
+pub mod resolve;
+pub mod trace;
+pub mod translate;
+pub mod tree;
+pub mod typing;
+pub mod wat;
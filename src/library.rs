@@ -0,0 +1,111 @@
+use crate::engine::{HostFun, IntoHostFun};
+use std::cell::RefCell;
+use std::io::{Read, Write};
+use std::rc::Rc;
+
+/*
+ * The standard library of Tiger, as in Appel's book, plus printi to print an int.
+ * print writes to out and getchar reads from input; getchar returns "" at the end of input.
+ */
+pub const NAMES: &[&str] = &[
+    "print",
+    "printi",
+    "flush",
+    "getchar",
+    "ord",
+    "chr",
+    "size",
+    "substring",
+    "concat",
+    "not",
+];
+
+pub fn functions(
+    out: Rc<RefCell<dyn Write>>,
+    input: Rc<RefCell<dyn Read>>,
+) -> Vec<(String, HostFun)> {
+    let (print_out, printi_out, flush_out) = (out.clone(), out.clone(), out);
+    let io_err = |e: std::io::Error| e.to_string();
+    let funs = vec![
+        (move |s: String| {
+            print_out
+                .borrow_mut()
+                .write_all(s.as_bytes())
+                .map_err(io_err)
+        })
+        .into_host_fun(),
+        (move |i: i64| write!(printi_out.borrow_mut(), "{}", i).map_err(io_err)).into_host_fun(),
+        (move || flush_out.borrow_mut().flush().map_err(io_err)).into_host_fun(),
+        (move || {
+            let mut c = [0];
+            match input.borrow_mut().read(&mut c).map_err(io_err)? {
+                0 => Ok(String::new()),
+                _ => Ok((c[0] as char).to_string()),
+            }
+        })
+        .into_host_fun(),
+        (|s: String| s.bytes().next().map_or(-1, i64::from)).into_host_fun(),
+        (|i: i64| match u8::try_from(i) {
+            Ok(c) => Ok((c as char).to_string()),
+            Err(_) => Err(format!("chr({}) is out of range", i)),
+        })
+        .into_host_fun(),
+        (|s: String| s.len() as i64).into_host_fun(),
+        (|s: String, first: i64, n: i64| {
+            let range = usize::try_from(first)
+                .ok()
+                .zip(usize::try_from(n).ok())
+                .and_then(|(first, n)| Some(first..first.checked_add(n)?));
+            match range.and_then(|range| s.get(range)) {
+                Some(sub) => Ok(sub.to_string()),
+                None => Err(format!(
+                    "substring({:?}, {}, {}) is out of range",
+                    s, first, n
+                )),
+            }
+        })
+        .into_host_fun(),
+        (|a: String, b: String| a + &b).into_host_fun(),
+        (|i: i64| i == 0).into_host_fun(),
+    ];
+    NAMES
+        .iter()
+        .map(|name| name.to_string())
+        .zip(funs)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::Engine;
+    use crate::typing;
+
+    #[test]
+    fn library_test() {
+        let out = Rc::new(RefCell::new(Vec::new()));
+        let input = Rc::new(RefCell::new(&b"ab"[..]));
+        let mut engine = Engine::new();
+        for (name, fun) in functions(out.clone(), input) {
+            engine.register(&name, fun);
+        }
+        let src = "let var c := getchar() in
+  print(concat(c, getchar())); printi(ord(\"A\")); print(chr(10));
+  print(substring(\"tiger\", 1, 3)); flush();
+  size(getchar()) + not(0)
+end";
+        assert_eq!(engine.eval_as::<i64>(src), Ok(1));
+        assert_eq!(
+            String::from_utf8(out.borrow().clone()).unwrap(),
+            "ab65\nige"
+        );
+        assert!(engine.eval("substring(\"tiger\", 4, 2)").is_err());
+        assert!(engine.eval("substring(\"abc\", -1, 1)").is_err());
+        assert!(engine.eval("substring(\"abc\", 1, -1)").is_err());
+        assert!(engine
+            .eval("substring(\"abc\", 1, 9223372036854775807)")
+            .is_err());
+        // typing knows the signatures
+        assert!(typing::check("ord(1)", engine.functions()).is_err());
+    }
+}
@@ -0,0 +1,417 @@
+use crate::ast::{Dec, Expr, LValue, Op, Span};
+use crate::diagnostics::{Code, Diagnostic, Severity};
+use crate::resolve::{BindingId, Kind, Resolution};
+use std::collections::HashMap;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
+pub enum Lint {
+    UnusedVariable,
+    UnusedFunction,
+    UnusedType,
+    UnusedParameter,
+    UnreachableCode,
+    ConstantCondition,
+}
+
+/* Every lint with the name used on the command line and a one-line summary. */
+pub const LINTS: &[(Lint, &str, &str)] = &[
+    (
+        Lint::UnusedVariable,
+        "unused-variable",
+        "variable that is never used",
+    ),
+    (
+        Lint::UnusedFunction,
+        "unused-function",
+        "function that is never called, except by itself",
+    ),
+    (
+        Lint::UnusedType,
+        "unused-type",
+        "type that is never used, except by itself",
+    ),
+    (
+        Lint::UnusedParameter,
+        "unused-parameter",
+        "parameter that is never used",
+    ),
+    (
+        Lint::UnreachableCode,
+        "unreachable-code",
+        "expressions after a break in a sequence",
+    ),
+    (
+        Lint::ConstantCondition,
+        "constant-condition",
+        "if whose condition is a constant",
+    ),
+];
+
+impl Lint {
+    pub fn name(self) -> &'static str {
+        LINTS.iter().find(|l| l.0 == self).unwrap().1
+    }
+    pub fn summary(self) -> &'static str {
+        LINTS.iter().find(|l| l.0 == self).unwrap().2
+    }
+    pub fn from_name(name: &str) -> Option<Lint> {
+        LINTS.iter().find(|l| l.1 == name).map(|l| l.0)
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+/* The level of every lint; those not set explicitly warn. */
+#[derive(Clone, Debug, Default)]
+pub struct Levels {
+    levels: HashMap<Lint, Level>,
+}
+
+impl Levels {
+    pub fn set(&mut self, lint: Lint, level: Level) {
+        self.levels.insert(lint, level);
+    }
+    pub fn get(&self, lint: Lint) -> Level {
+        self.levels.get(&lint).copied().unwrap_or(Level::Warn)
+    }
+}
+
+struct Ctx<'a> {
+    levels: &'a Levels,
+    span: Option<Span>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Ctx<'_> {
+    fn emit(&mut self, mut d: Diagnostic) {
+        let lint = match d.code {
+            Code::Lint(lint) => lint,
+            _ => unreachable!(),
+        };
+        d.severity = match self.levels.get(lint) {
+            Level::Allow => return,
+            Level::Warn => Severity::Warning,
+            Level::Deny => Severity::Error,
+        };
+        self.diagnostics.push(d);
+    }
+
+    fn expr(&mut self, ast: &Expr) {
+        match *ast {
+            Expr::Num(_) | Expr::Str(_) | Expr::Nil | Expr::Break => {}
+            Expr::LVal(ref l) => self.lval(l),
+            Expr::Neg(ref e) => self.expr(e),
+            Expr::LAsgn(ref l, ref e) => {
+                self.lval(l);
+                self.expr(e);
+            }
+            Expr::OpNode(_, ref e1, ref e2) | Expr::Do(ref e1, ref e2) => {
+                self.expr(e1);
+                self.expr(e2);
+            }
+            Expr::IfNode(ref c, ref e1, ref e2) => {
+                if let Some(v) = constant(c) {
+                    let branch = if v != 0 { "then" } else { "else" };
+                    self.emit(Diagnostic::error(
+                        Code::Lint(Lint::ConstantCondition),
+                        span_of(c).or(self.span),
+                        format!(
+                            "the condition of this if is always {}, so only its {} part is evaluated",
+                            v, branch
+                        ),
+                    ));
+                }
+                self.expr(c);
+                self.expr(e1);
+                self.expr(e2);
+            }
+            Expr::Seq(ref es) => {
+                let brk = es.iter().position(|e| matches!(*strip(e), Expr::Break));
+                if let Some(i) = brk.filter(|&i| i + 1 < es.len()) {
+                    let span = es[i + 1..]
+                        .iter()
+                        .filter_map(span_of)
+                        .reduce(Span::join)
+                        .or(self.span);
+                    let mut d = Diagnostic::error(
+                        Code::Lint(Lint::UnreachableCode),
+                        span,
+                        "unreachable expression".to_string(),
+                    );
+                    if let Some(b) = span_of(&es[i]) {
+                        d = d.with_label(
+                            b,
+                            "any code following this break is unreachable".to_string(),
+                        );
+                    }
+                    self.emit(d);
+                }
+                for e in es {
+                    self.expr(e);
+                }
+            }
+            Expr::Let(ref decs, ref body) => {
+                for dec in decs {
+                    match *dec {
+                        Dec::Var(_, _, ref e, _) | Dec::Fun(_, _, _, ref e) => self.expr(e),
+                        Dec::Type(..) => {}
+                    }
+                }
+                self.expr(body);
+            }
+            Expr::For(_, ref st, ref en, ref body) => {
+                self.expr(st);
+                self.expr(en);
+                self.expr(body);
+            }
+            Expr::FunApp(_, ref es) => {
+                for e in es {
+                    self.expr(e);
+                }
+            }
+            Expr::NewStruct(_, ref fields) => {
+                for (_, e) in fields {
+                    self.expr(e);
+                }
+            }
+            Expr::NewArray(_, ref n, ref init) => {
+                self.expr(n);
+                self.expr(init);
+            }
+            Expr::Spanned(span, ref e) => {
+                let saved = self.span;
+                self.span = Some(span);
+                self.expr(e);
+                self.span = saved;
+            }
+        }
+    }
+
+    fn lval(&mut self, l: &LValue) {
+        match *l {
+            LValue::Id(_) => {}
+            LValue::Mem(ref l, _) => self.lval(l),
+            LValue::Idx(ref l, ref e) => {
+                self.lval(l);
+                self.expr(e);
+            }
+        }
+    }
+}
+
+fn span_of(e: &Expr) -> Option<Span> {
+    match *e {
+        Expr::Spanned(span, _) => Some(span),
+        _ => None,
+    }
+}
+
+fn strip(e: &Expr) -> &Expr {
+    match *e {
+        Expr::Spanned(_, ref e) => strip(e),
+        _ => e,
+    }
+}
+
+/* The value of e if it is made only of integer literals, as in `if 1 then ...` or `if 2 > 3 then ...`. */
+fn constant(e: &Expr) -> Option<i64> {
+    match *strip(e) {
+        Expr::Num(n) => Some(n),
+        Expr::Neg(ref e) => constant(e)?.checked_neg(),
+        Expr::OpNode(op, ref e1, ref e2) => {
+            let (v1, v2) = (constant(e1)?, constant(e2)?);
+            match op {
+                Op::Add => v1.checked_add(v2),
+                Op::Sub => v1.checked_sub(v2),
+                Op::Mul => v1.checked_mul(v2),
+                Op::Div => v1.checked_div(v2),
+                Op::Eq => Some(i64::from(v1 == v2)),
+                Op::Ne => Some(i64::from(v1 != v2)),
+                Op::Lt => Some(i64::from(v1 < v2)),
+                Op::Gt => Some(i64::from(v1 > v2)),
+                Op::Le => Some(i64::from(v1 <= v2)),
+                Op::Ge => Some(i64::from(v1 >= v2)),
+                Op::Or => Some(if v1 != 0 { v1 } else { v2 }),
+                Op::And => Some(if v1 == 0 { v1 } else { v2 }),
+            }
+        }
+        _ => None,
+    }
+}
+
+/* Whether the use owned by owner is inside the declaration of id, i.e. is a recursive one. */
+fn within(res: &Resolution, mut owner: Option<BindingId>, id: BindingId) -> bool {
+    while let Some(o) = owner {
+        if o == id {
+            return true;
+        }
+        owner = res.bindings[o].owner;
+    }
+    false
+}
+
+/*
+ * Lints a program that resolved without errors. Returns the findings of the lints that
+ * are not allowed, as warnings or, for denied lints, errors, sorted by position.
+ */
+pub fn f(ast: &Expr, res: &Resolution, levels: &Levels) -> Vec<Diagnostic> {
+    let mut ctx = Ctx {
+        levels,
+        span: None,
+        diagnostics: Vec::new(),
+    };
+    let mut used = vec![false; res.bindings.len()];
+    for u in &res.uses {
+        if !within(res, u.owner, u.binding) {
+            used[u.binding] = true;
+        }
+    }
+    for (id, b) in res.bindings.iter().enumerate() {
+        // predeclared names have no span
+        if used[id] || b.span.is_none() {
+            continue;
+        }
+        let (lint, what) = match b.kind {
+            Kind::Var => (Lint::UnusedVariable, "variable"),
+            Kind::Param => (Lint::UnusedParameter, "parameter"),
+            Kind::Fun => (Lint::UnusedFunction, "function"),
+            Kind::Type => (Lint::UnusedType, "type"),
+            Kind::LoopIndex => continue,
+        };
+        let message = match (b.kind, b.owner) {
+            (Kind::Param, Some(fun)) => {
+                format!("unused parameter {} of {}", b.name, res.bindings[fun].name)
+            }
+            _ => format!("unused {} {}", what, b.name),
+        };
+        ctx.emit(Diagnostic::error(Code::Lint(lint), b.span, message));
+    }
+    ctx.expr(ast);
+    let mut diags = ctx.diagnostics;
+    diags.sort_by_key(|d| d.span);
+    // like rustc, say once per lint how it was enabled
+    for i in 0..diags.len() {
+        if diags[..i].iter().all(|d| d.code != diags[i].code) {
+            let name = diags[i].code.id();
+            let note = match diags[i].severity {
+                Severity::Warning => format!("`--allow {}` silences this", name),
+                Severity::Error => format!("requested by `--deny {}`", name),
+            };
+            diags[i].notes.push(note);
+        }
+    }
+    diags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, resolve};
+
+    /* lint name and message of every finding */
+    fn lint(src: &str, levels: &Levels) -> Vec<(&'static str, String)> {
+        let ast = parse::parse_located(src);
+        let (res, diags) = resolve::f(&ast, &["print"]);
+        assert!(diags.is_empty(), "{:?}", diags);
+        f(&ast, &res, levels)
+            .into_iter()
+            .map(|d| (d.code.id(), d.message))
+            .collect()
+    }
+
+    #[test]
+    fn unused_test() {
+        let src = "let
+  type list = {first: int, rest: list}
+  type pair = {a: int, b: int}
+  var p: pair := nil
+  var unused := 0
+  function loop(n: int, step: int): int = if n = 0 then 0 else loop(n - 1, step)
+  function main(k: int) = print(\"x\")
+in
+  main(p.a)
+end";
+        assert_eq!(
+            lint(src, &Levels::default()),
+            vec![
+                ("unused-type", "unused type list".to_string()),
+                ("unused-variable", "unused variable unused".to_string()),
+                ("unused-function", "unused function loop".to_string()),
+                ("unused-parameter", "unused parameter k of main".to_string()),
+            ]
+        );
+        // an unused variable is underlined at its name
+        let (ast, lo) = (parse::parse_located(src), src.find("unused").unwrap());
+        let (res, _) = resolve::f(&ast, &["print"]);
+        let diags = f(&ast, &res, &Levels::default());
+        assert_eq!(diags[1].span, Some(Span::new(lo, lo + "unused".len())));
+    }
+
+    #[test]
+    fn dead_code_test() {
+        let src = "let var x := 0 in
+  while 1 do (x := x + 1; break; print(\"never\"));
+  if 2 > 3 then x := 1;
+  if x then x := 2
+end";
+        let mut levels = Levels::default();
+        assert_eq!(
+            lint(src, &levels),
+            vec![
+                ("unreachable-code", "unreachable expression".to_string()),
+                (
+                    "constant-condition",
+                    "the condition of this if is always 0, so only its else part is evaluated"
+                        .to_string()
+                ),
+            ]
+        );
+        levels.set(Lint::UnreachableCode, Level::Allow);
+        levels.set(Lint::ConstantCondition, Level::Deny);
+        let ast = parse::parse_located(src);
+        let (res, _) = resolve::f(&ast, &["print"]);
+        let diags = f(&ast, &res, &levels);
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].severity, Severity::Error);
+        // the README lists every lint
+        let readme = include_str!("../README.md");
+        for &(_, name, summary) in LINTS {
+            assert!(
+                readme.contains(&format!("| {} | {} |", name, summary)),
+                "{}",
+                name
+            );
+        }
+        assert_eq!(
+            diags[0].notes,
+            vec!["requested by `--deny constant-condition`"]
+        );
+    }
+
+    #[test]
+    fn subscript_test() {
+        let src = "let type a = array of int var x := a[2] of 0 in
+  x[if 1 then 0 else 1] := x[if 0 then 0 else 1]
+end";
+        assert_eq!(
+            lint(src, &Levels::default()),
+            vec![
+                (
+                    "constant-condition",
+                    "the condition of this if is always 1, so only its then part is evaluated"
+                        .to_string()
+                ),
+                (
+                    "constant-condition",
+                    "the condition of this if is always 0, so only its else part is evaluated"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,176 @@
+use crate::flow::{dot_string, FlowGraph};
+use crate::tree::Temp;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+
+/*
+ * Liveness of temporaries over a flow graph, by iterating the dataflow equations
+ *   in[n] = use[n] + (out[n] - def[n]),  out[n] = union of in[s] for successors s
+ * to a fixpoint, visiting the nodes backwards so that few passes are needed.
+ */
+#[derive(Clone, Debug)]
+pub struct Liveness {
+    pub live_in: Vec<BTreeSet<Temp>>,
+    pub live_out: Vec<BTreeSet<Temp>>,
+}
+
+impl Liveness {
+    pub fn new(graph: &FlowGraph) -> Self {
+        let n = graph.len();
+        let mut live_in = vec![BTreeSet::new(); n];
+        let mut live_out = vec![BTreeSet::new(); n];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for i in (0..n).rev() {
+                let out: BTreeSet<Temp> = graph.succ[i]
+                    .iter()
+                    .flat_map(|&s| live_in[s].iter().copied())
+                    .collect();
+                let mut in_: BTreeSet<Temp> = graph.uses[i].iter().copied().collect();
+                in_.extend(out.iter().filter(|t| !graph.defs[i].contains(t)));
+                if in_ != live_in[i] || out != live_out[i] {
+                    changed = true;
+                    live_in[i] = in_;
+                    live_out[i] = out;
+                }
+            }
+        }
+        Liveness { live_in, live_out }
+    }
+}
+
+/*
+ * The interference graph: two temporaries interfere when one is defined where the other
+ * is live afterwards, so they cannot share a register. The destination of a move does
+ * not interfere with its source for that move alone, so that the two may be coalesced;
+ * moves lists such pairs.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct Interference {
+    pub adj: BTreeMap<Temp, BTreeSet<Temp>>, // every temporary, with those it interferes with
+    pub moves: Vec<(Temp, Temp)>,            // destination and source of every move
+}
+
+impl Interference {
+    pub fn new(graph: &FlowGraph, live: &Liveness) -> Self {
+        let mut ig = Interference::default();
+        for i in 0..graph.len() {
+            for &t in graph.defs[i].iter().chain(&graph.uses[i]) {
+                ig.adj.entry(t).or_default();
+            }
+            let src = if graph.is_move[i] {
+                let (dst, src) = (graph.defs[i][0], graph.uses[i][0]);
+                ig.moves.push((dst, src));
+                Some(src)
+            } else {
+                None
+            };
+            for &d in &graph.defs[i] {
+                for &t in &live.live_out[i] {
+                    if t != d && Some(t) != src {
+                        ig.add_edge(d, t);
+                    }
+                }
+            }
+        }
+        ig
+    }
+
+    fn add_edge(&mut self, a: Temp, b: Temp) {
+        self.adj.entry(a).or_default().insert(b);
+        self.adj.entry(b).or_default().insert(a);
+    }
+
+    pub fn interferes(&self, a: Temp, b: Temp) -> bool {
+        self.adj.get(&a).is_some_and(|ts| ts.contains(&b))
+    }
+
+    /* Writes the graph in DOT, with dashed edges between temporaries related by a move. */
+    pub fn write_dot<W: Write>(
+        &self,
+        mut out: W,
+        name: &str,
+        temp_name: &dyn Fn(Temp) -> String,
+    ) -> io::Result<()> {
+        let node = |t: Temp| format!("\"{}\"", dot_string(&temp_name(t)));
+        writeln!(out, "graph \"{}\" {{", dot_string(name))?;
+        for (&a, ts) in &self.adj {
+            writeln!(out, "  {};", node(a))?;
+            for &b in ts.range(a..) {
+                writeln!(out, "  {} -- {};", node(a), node(b))?;
+            }
+        }
+        let mut moves: Vec<(Temp, Temp)> = self
+            .moves
+            .iter()
+            .map(|&(a, b)| (a.min(b), a.max(b)))
+            .filter(|&(a, b)| a != b && !self.interferes(a, b))
+            .collect();
+        moves.sort();
+        moves.dedup();
+        for (a, b) in moves {
+            writeln!(out, "  {} -- {} [style=dashed];", node(a), node(b))?;
+        }
+        writeln!(out, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assem::Instr;
+    use crate::tree::Label;
+
+    /* The loop of Appel's Graph 10.1, which sums into c and returns it. */
+    #[test]
+    fn loop_test() {
+        let (a, b, c, rv) = (Temp(100), Temp(101), Temp(102), Temp(0));
+        let (l1, l2) = (Label("L1".to_string()), Label("L2".to_string()));
+        let label = |l: &Label| Instr::Label {
+            assem: format!("{}:", l),
+            label: l.clone(),
+        };
+        let instrs = vec![
+            Instr::oper("mov 'd0, 0", vec![a], vec![]),
+            label(&l1),
+            Instr::oper("lea 'd0, ['s0 + 1]", vec![b], vec![a]),
+            Instr::oper("add 'd0, 's1", vec![c], vec![c, b]),
+            Instr::oper("lea 'd0, ['s0 + 's0]", vec![a], vec![b]),
+            Instr::oper("cmp 's0, 10", vec![], vec![a]),
+            Instr::Oper {
+                assem: "jl L1".to_string(),
+                dst: vec![],
+                src: vec![],
+                jump: Some(vec![l1.clone(), l2.clone()]),
+            },
+            label(&l2),
+            Instr::Move {
+                assem: "mov 'd0, 's0".to_string(),
+                dst: rv,
+                src: c,
+            },
+            Instr::oper("", vec![], vec![rv]),
+        ];
+        let graph = FlowGraph::new(&instrs);
+        assert_eq!(graph.succ[6], vec![1, 7]);
+        let live = Liveness::new(&graph);
+        let set = |ts: &[Temp]| ts.iter().copied().collect::<BTreeSet<Temp>>();
+        // c is live everywhere in the loop, as it is used before it is defined
+        assert_eq!(live.live_in[0], set(&[c]));
+        assert_eq!(live.live_out[2], set(&[b, c]));
+        assert_eq!(live.live_out[4], set(&[a, c]));
+        assert_eq!(live.live_out[6], set(&[a, c]));
+        let ig = Interference::new(&graph, &live);
+        assert!(ig.interferes(a, c) && ig.interferes(b, c));
+        assert!(!ig.interferes(a, b));
+        // rv := c does not make rv and c interfere
+        assert!(!ig.interferes(rv, c));
+        assert_eq!(ig.moves, vec![(rv, c)]);
+        let mut out = Vec::new();
+        ig.write_dot(&mut out, "f", &|t| t.to_string()).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("\"t100\" -- \"t102\";"));
+        assert!(out.contains("\"t0\" -- \"t102\" [style=dashed];"));
+    }
+}
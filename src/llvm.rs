@@ -0,0 +1,828 @@
+use crate::ast::{ty_of_ast, Op, Ty, TyDef, TypeTable, TypedDec, TypedExpr};
+use crate::escape::Escapes;
+use crate::library;
+use crate::resolve::BindingId;
+use crate::translate::{self, MAIN};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+
+/*
+ * The LLVM backend: typed trees become a module of textual LLVM IR, to be compiled with
+ * the runtime of the native backend (runtime.c), e.g. by clang -O2. Ints are i64, and
+ * strings, records and arrays are pointers to the heap. Every function keeps its escaping
+ * variables in a frame struct on its stack, whose first member is the static link, the
+ * frame of the function it is nested in; nested functions take that frame as their first
+ * parameter. Other variables get an alloca each, which LLVM promotes to registers.
+ * Field accesses, subscripts and divisions branch to a runtime function that exits when
+ * they are invalid, as arrays keep their length before their elements.
+ * Pointers are opaque (ptr), so LLVM 14 needs -opaque-pointers and older versions fail.
+ */
+
+/* The runtime functions that the module calls, as in runtime.c. */
+const DECLARATIONS: &str = "declare ptr @tiger_allocRecord(i64)
+declare ptr @tiger_initArray(i64, i64)
+declare i64 @tiger_stringEqual(ptr, ptr)
+declare i64 @tiger_stringCompare(ptr, ptr)
+declare void @tiger_print(ptr)
+declare void @tiger_printi(i64)
+declare void @tiger_flush()
+declare ptr @tiger_getchar()
+declare i64 @tiger_ord(ptr)
+declare ptr @tiger_chr(i64)
+declare i64 @tiger_size(ptr)
+declare ptr @tiger_substring(ptr, i64, i64)
+declare ptr @tiger_concat(ptr, ptr)
+declare i64 @tiger_not(i64)
+declare void @tiger_failIndex(i64, i64)
+declare void @tiger_failNil()
+declare void @tiger_failDivision()
+";
+
+/* A function being generated, the one it is nested in, and the types of its frame's members. */
+struct Level {
+    name: String,
+    parent: Option<usize>,
+    frame: Vec<&'static str>, // the static link first
+    uses_frame: bool,
+}
+
+#[derive(Clone)]
+enum Loc {
+    Local(String), // an alloca
+    Frame(usize),  // a member of the frame
+}
+
+#[derive(Clone)]
+enum Entry {
+    Var(usize, Loc, Ty), // level
+    Fun(usize, String),  // level of the function itself, LLVM name
+}
+
+type Env = HashMap<BindingId, Entry>;
+
+/* The blocks of a function body, and the allocas of its entry block. */
+struct Body {
+    code: String,
+    allocas: String,
+    block: String, // the label of the block being generated
+}
+
+impl Body {
+    fn new() -> Body {
+        Body {
+            code: String::new(),
+            allocas: String::new(),
+            block: "entry".to_string(),
+        }
+    }
+
+    fn line(&mut self, s: &str) {
+        self.code += "  ";
+        self.code += s;
+        self.code.push('\n');
+    }
+
+    fn label(&mut self, label: &str) {
+        self.code += &format!("{}:\n", label);
+        self.block = label.to_string();
+    }
+
+    fn br(&mut self, label: &str) {
+        self.line(&format!("br label %{}", label));
+    }
+}
+
+fn lltype(ty: Ty) -> &'static str {
+    match ty {
+        Ty::Int | Ty::Unknown => "i64",
+        Ty::Unit => "void",
+        Ty::Str | Ty::Nil | Ty::Record(_) | Ty::Array(_) => "ptr",
+        Ty::Var(_) => unreachable!(),
+    }
+}
+
+/* s as the contents of an LLVM array of i8. */
+fn ll_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 3);
+    out += "c\"";
+    for b in s.bytes() {
+        match b {
+            0x20..=0x7e if b != b'"' && b != b'\\' => out.push(b as char),
+            _ => out += &format!("\\{:02X}", b),
+        }
+    }
+    out.push('"');
+    out
+}
+
+struct Ctx<'a> {
+    types: &'a TypeTable,
+    escapes: &'a Escapes,
+    levels: Vec<Level>,
+    labels: HashSet<String>, // function names taken
+    names: usize,            // locals, temporaries and labels made so far
+    strings: Vec<String>,
+    breaks: Vec<String>, // where break goes, innermost last
+    funs: Vec<String>,
+}
+
+impl Ctx<'_> {
+    fn fun_name(&mut self, name: &str) -> String {
+        let mut label = name.to_string();
+        let mut k = 1;
+        while self.labels.contains(&label) {
+            label = format!("{}_{}", name, k);
+            k += 1;
+        }
+        self.labels.insert(label.clone());
+        format!("tiger_{}", label)
+    }
+
+    /* A fresh name for a local, which stays apart from other locals and from temporaries. */
+    fn local(&mut self, name: &str) -> String {
+        self.names += 1;
+        format!("%{}_{}", name, self.names)
+    }
+
+    fn temp(&mut self) -> String {
+        self.names += 1;
+        format!("%t{}", self.names)
+    }
+
+    fn label(&mut self, what: &str) -> String {
+        self.names += 1;
+        format!("{}{}", what, self.names)
+    }
+
+    /* Continues if c, an i1, is true, and calls fail, which does not return, otherwise. */
+    fn guard(&mut self, c: &str, fail: &str, body: &mut Body) {
+        let (ok, bad) = (self.label("ok"), self.label("fail"));
+        body.line(&format!("br i1 {}, label %{}, label %{}", c, ok, bad));
+        body.label(&bad);
+        body.line(&format!("call void {}", fail));
+        body.line("unreachable");
+        body.label(&ok);
+    }
+
+    /* The struct type of record type i and the index and type of its field name. */
+    fn field(&self, ty: Ty, name: &str) -> (String, usize, Ty) {
+        match ty {
+            Ty::Record(i) => match self.types[i] {
+                TyDef::Record(ref record, ref fields) => {
+                    let k = fields.iter().position(|(f, _)| f == name).unwrap();
+                    (format!("%{}_{}", record, i), k, fields[k].1)
+                }
+                TyDef::Array(..) => unreachable!(),
+            },
+            _ => panic!(
+                "internal error: field {} of a value that is not a record",
+                name
+            ),
+        }
+    }
+
+    fn elem(&self, ty: Ty) -> Ty {
+        match ty {
+            Ty::Array(i) => match self.types[i] {
+                TyDef::Array(_, elem) => elem,
+                TyDef::Record(..) => unreachable!(),
+            },
+            _ => panic!("internal error: element of a value that is not an array"),
+        }
+    }
+
+    /* A pointer to the frame of level to, as seen from level from, which it encloses. */
+    fn frame(&mut self, mut from: usize, to: usize, body: &mut Body) -> String {
+        self.levels[to].uses_frame = true;
+        if from == to {
+            return "%frame".to_string();
+        }
+        // the static link is a parameter, so only the frames above are needed
+        let mut p = "%link".to_string();
+        from = self.levels[from].parent.unwrap();
+        while from != to {
+            self.levels[from].uses_frame = true;
+            let t = self.temp();
+            body.line(&format!("{} = load ptr, ptr {}", t, p));
+            p = t;
+            from = self.levels[from]
+                .parent
+                .expect("frame above the main program");
+        }
+        p
+    }
+
+    /* Gives variable name a place in level, in its frame if it escapes. */
+    fn place(&mut self, name: &str, ty: Ty, escape: bool, level: usize, body: &mut Body) -> Loc {
+        if escape {
+            self.levels[level].frame.push(lltype(ty));
+            Loc::Frame(self.levels[level].frame.len() - 1)
+        } else {
+            let p = self.local(name);
+            body.allocas += &format!("  {} = alloca {}\n", p, lltype(ty));
+            Loc::Local(p)
+        }
+    }
+
+    /* The address of a variable at var_level, from level. */
+    fn address(&mut self, var_level: usize, loc: &Loc, level: usize, body: &mut Body) -> String {
+        match *loc {
+            Loc::Local(ref p) => p.clone(),
+            Loc::Frame(k) => {
+                let frame = self.frame(level, var_level, body);
+                let t = self.temp();
+                body.line(&format!(
+                    "{} = getelementptr %{}.frame, ptr {}, i32 0, i32 {}",
+                    t, self.levels[var_level].name, frame, k
+                ));
+                t
+            }
+        }
+    }
+
+    /* The address that lval, a variable, field or element, is assigned through. */
+    fn lvalue(&mut self, lval: &TypedExpr, env: &Env, level: usize, body: &mut Body) -> String {
+        match *lval {
+            TypedExpr::Var(ref name, id, _) => match env.get(&id) {
+                Some(Entry::Var(var_level, loc, _)) => {
+                    let (var_level, loc) = (*var_level, loc.clone());
+                    self.address(var_level, &loc, level, body)
+                }
+                _ => panic!("internal error: unbound variable {}", name),
+            },
+            TypedExpr::Field(ref r, ref name, _) => {
+                let (record, k, _) = self.field(ty_of_ast(r), name);
+                let r = self.expr(r, env, level, body);
+                let c = self.temp();
+                body.line(&format!("{} = icmp ne ptr {}, null", c, r));
+                self.guard(&c, "@tiger_failNil()", body);
+                let t = self.temp();
+                body.line(&format!(
+                    "{} = getelementptr {}, ptr {}, i32 0, i32 {}",
+                    t, record, r, k
+                ));
+                t
+            }
+            TypedExpr::Index(ref a, ref i, _) => {
+                let elem = lltype(self.elem(ty_of_ast(a)));
+                let a = self.expr(a, env, level, body);
+                let i = self.expr(i, env, level, body);
+                let (p, n, c) = (self.temp(), self.temp(), self.temp());
+                body.line(&format!("{} = getelementptr i64, ptr {}, i64 -1", p, a));
+                body.line(&format!("{} = load i64, ptr {}", n, p));
+                // unsigned, so that a negative index is out of bounds too
+                body.line(&format!("{} = icmp ult i64 {}, {}", c, i, n));
+                let fail = format!("@tiger_failIndex(i64 {}, i64 {})", i, n);
+                self.guard(&c, &fail, body);
+                let t = self.temp();
+                body.line(&format!(
+                    "{} = getelementptr {}, ptr {}, i64 {}",
+                    t, elem, a, i
+                ));
+                t
+            }
+            _ => panic!("internal error: assignment to an expression that is not an lvalue"),
+        }
+    }
+
+    fn compare(&mut self, op: Op, ty: Ty, a: &str, b: &str, body: &mut Body) -> String {
+        let pred = match op {
+            Op::Eq => "eq",
+            Op::Ne => "ne",
+            Op::Lt => "slt",
+            Op::Gt => "sgt",
+            Op::Le => "sle",
+            Op::Ge => "sge",
+            _ => unreachable!(),
+        };
+        let t = self.temp();
+        match ty {
+            Ty::Str => {
+                let (fun, pred) = match op {
+                    Op::Eq => ("tiger_stringEqual", "ne"),
+                    Op::Ne => ("tiger_stringEqual", "eq"),
+                    _ => ("tiger_stringCompare", pred),
+                };
+                let r = self.temp();
+                body.line(&format!("{} = call i64 @{}(ptr {}, ptr {})", r, fun, a, b));
+                body.line(&format!("{} = icmp {} i64 {}, 0", t, pred, r));
+            }
+            _ => body.line(&format!(
+                "{} = icmp {} {} {}, {}",
+                t,
+                pred,
+                lltype(ty),
+                a,
+                b
+            )),
+        }
+        t
+    }
+
+    /* Generates e as a condition, giving an i1. */
+    fn cond(&mut self, e: &TypedExpr, env: &Env, level: usize, body: &mut Body) -> String {
+        match *e {
+            TypedExpr::OpNode(op, ty, ref e1, ref e2)
+                if !matches!(op, Op::Add | Op::Sub | Op::Mul | Op::Div | Op::And | Op::Or) =>
+            {
+                let a = self.expr(e1, env, level, body);
+                let b = self.expr(e2, env, level, body);
+                self.compare(op, ty, &a, &b, body)
+            }
+            _ => {
+                let v = self.expr(e, env, level, body);
+                let t = self.temp();
+                body.line(&format!("{} = icmp ne i64 {}, 0", t, v));
+                t
+            }
+        }
+    }
+
+    /* Generates the blocks of e into body, giving its value, or nothing if it has none. */
+    /*
+     * a / b, failing if b is 0. sdiv of the smallest int by -1 is undefined, so that
+     * division is a negation, which wraps as the interpreter does.
+     */
+    fn divide(&mut self, a: &str, b: &str, body: &mut Body) -> String {
+        let t = self.temp();
+        if let Ok(d) = b.parse::<i64>() {
+            if d != 0 && d != -1 {
+                body.line(&format!("{} = sdiv i64 {}, {}", t, a, b));
+                return t;
+            }
+        }
+        let c = self.temp();
+        body.line(&format!("{} = icmp ne i64 {}, 0", c, b));
+        self.guard(&c, "@tiger_failDivision()", body);
+        let (m, d, q, neg) = (self.temp(), self.temp(), self.temp(), self.temp());
+        body.line(&format!("{} = icmp eq i64 {}, -1", m, b));
+        body.line(&format!("{} = select i1 {}, i64 1, i64 {}", d, m, b));
+        body.line(&format!("{} = sdiv i64 {}, {}", q, a, d));
+        body.line(&format!("{} = sub i64 0, {}", neg, a));
+        body.line(&format!("{} = select i1 {}, i64 {}, i64 {}", t, m, neg, q));
+        t
+    }
+
+    fn expr(&mut self, e: &TypedExpr, env: &Env, level: usize, body: &mut Body) -> String {
+        match *e {
+            TypedExpr::Num(i) => i.to_string(),
+            TypedExpr::Str(ref s) => {
+                self.strings.push(s.clone());
+                format!("@str{}", self.strings.len() - 1)
+            }
+            TypedExpr::Nil => "null".to_string(),
+            TypedExpr::Var(ref name, id, _) => match env.get(&id) {
+                Some(Entry::Var(var_level, loc, ty)) => {
+                    let (var_level, loc, ty) = (*var_level, loc.clone(), *ty);
+                    let p = self.address(var_level, &loc, level, body);
+                    let t = self.temp();
+                    body.line(&format!("{} = load {}, ptr {}", t, lltype(ty), p));
+                    t
+                }
+                _ => panic!("internal error: unbound variable {}", name),
+            },
+            TypedExpr::Field(..) | TypedExpr::Index(..) => {
+                let p = self.lvalue(e, env, level, body);
+                let t = self.temp();
+                body.line(&format!("{} = load {}, ptr {}", t, lltype(ty_of_ast(e)), p));
+                t
+            }
+            TypedExpr::Neg(ref e) => {
+                let v = self.expr(e, env, level, body);
+                let t = self.temp();
+                body.line(&format!("{} = sub i64 0, {}", t, v));
+                t
+            }
+            TypedExpr::OpNode(op @ (Op::And | Op::Or), _, ref e1, ref e2) => {
+                // e1 & e2 is if e1 then e2 else 0, e1 | e2 is e1 if it is not 0, else e2
+                let a = self.expr(e1, env, level, body);
+                let c = self.temp();
+                body.line(&format!("{} = icmp ne i64 {}, 0", c, a));
+                let (rhs, join) = (self.label("rhs"), self.label("join"));
+                let from = body.block.clone();
+                let short = if op == Op::And {
+                    body.line(&format!("br i1 {}, label %{}, label %{}", c, rhs, join));
+                    "0".to_string()
+                } else {
+                    body.line(&format!("br i1 {}, label %{}, label %{}", c, join, rhs));
+                    a
+                };
+                body.label(&rhs);
+                let b = self.expr(e2, env, level, body);
+                let rhs_end = body.block.clone();
+                body.br(&join);
+                body.label(&join);
+                let t = self.temp();
+                body.line(&format!(
+                    "{} = phi i64 [{}, %{}], [{}, %{}]",
+                    t, short, from, b, rhs_end
+                ));
+                t
+            }
+            TypedExpr::OpNode(op, ty, ref e1, ref e2) => {
+                let a = self.expr(e1, env, level, body);
+                let b = self.expr(e2, env, level, body);
+                // without nsw, so that they wrap
+                let inst = match op {
+                    Op::Add => "add",
+                    Op::Sub => "sub",
+                    Op::Mul => "mul",
+                    Op::Div => return self.divide(&a, &b, body),
+                    _ => {
+                        let c = self.compare(op, ty, &a, &b, body);
+                        let t = self.temp();
+                        body.line(&format!("{} = zext i1 {} to i64", t, c));
+                        return t;
+                    }
+                };
+                let t = self.temp();
+                body.line(&format!("{} = {} i64 {}, {}", t, inst, a, b));
+                t
+            }
+            TypedExpr::IfNode(ref c, ty, ref e1, ref e2) => {
+                let c = self.cond(c, env, level, body);
+                let (then, els, join) =
+                    (self.label("then"), self.label("else"), self.label("join"));
+                body.line(&format!("br i1 {}, label %{}, label %{}", c, then, els));
+                body.label(&then);
+                let v1 = self.expr(e1, env, level, body);
+                let then_end = body.block.clone();
+                body.br(&join);
+                body.label(&els);
+                let v2 = self.expr(e2, env, level, body);
+                let else_end = body.block.clone();
+                body.br(&join);
+                body.label(&join);
+                if ty == Ty::Unit {
+                    return String::new();
+                }
+                let t = self.temp();
+                body.line(&format!(
+                    "{} = phi {} [{}, %{}], [{}, %{}]",
+                    t,
+                    lltype(ty),
+                    v1,
+                    then_end,
+                    v2,
+                    else_end
+                ));
+                t
+            }
+            TypedExpr::Assign(ref lval, ref e) => {
+                let p = self.lvalue(lval, env, level, body);
+                let v = self.expr(e, env, level, body);
+                body.line(&format!(
+                    "store {} {}, ptr {}",
+                    lltype(ty_of_ast(lval)),
+                    v,
+                    p
+                ));
+                String::new()
+            }
+            TypedExpr::Seq(ref es, ty) => {
+                let mut v = String::new();
+                for e in es {
+                    v = self.expr(e, env, level, body);
+                }
+                if ty == Ty::Unit {
+                    String::new()
+                } else {
+                    v
+                }
+            }
+            TypedExpr::Let(ref decs, ref e, ty) => {
+                let mut env = env.clone();
+                let mut i = 0;
+                while i < decs.len() {
+                    match decs[i] {
+                        TypedDec::Var(ref name, id, var_ty, ref init) => {
+                            let init = self.expr(init, &env, level, body);
+                            let escape = self.escapes.escapes(id);
+                            let loc = self.place(name, var_ty, escape, level, body);
+                            let p = self.address(level, &loc, level, body);
+                            body.line(&format!("store {} {}, ptr {}", lltype(var_ty), init, p));
+                            env.insert(id, Entry::Var(level, loc, var_ty));
+                            i += 1;
+                        }
+                        TypedDec::Fun(_) => {
+                            let n = decs[i..]
+                                .iter()
+                                .take_while(|dec| matches!(dec, TypedDec::Fun(_)))
+                                .count();
+                            self.fun_group(&decs[i..i + n], &mut env, level);
+                            i += n;
+                        }
+                    }
+                }
+                let v = self.expr(e, &env, level, body);
+                if ty == Ty::Unit {
+                    String::new()
+                } else {
+                    v
+                }
+            }
+            TypedExpr::For(ref var, id, ref lo, ref hi, ref e) => {
+                let lo = self.expr(lo, env, level, body);
+                let hi = self.expr(hi, env, level, body);
+                let escape = self.escapes.escapes(id);
+                let loc = self.place(var, Ty::Int, escape, level, body);
+                let i = self.address(level, &loc, level, body);
+                body.line(&format!("store i64 {}, ptr {}", lo, i));
+                let mut env = env.clone();
+                env.insert(id, Entry::Var(level, loc, Ty::Int));
+                // compares before incrementing, so that hi may be the largest int
+                let (loop_, next, done) =
+                    (self.label("for"), self.label("next"), self.label("done"));
+                let c = self.temp();
+                body.line(&format!("{} = icmp sle i64 {}, {}", c, lo, hi));
+                body.line(&format!("br i1 {}, label %{}, label %{}", c, loop_, done));
+                body.label(&loop_);
+                self.breaks.push(done.clone());
+                self.expr(e, &env, level, body);
+                self.breaks.pop();
+                let (v, c) = (self.temp(), self.temp());
+                body.line(&format!("{} = load i64, ptr {}", v, i));
+                body.line(&format!("{} = icmp sge i64 {}, {}", c, v, hi));
+                body.line(&format!("br i1 {}, label %{}, label %{}", c, done, next));
+                body.label(&next);
+                let w = self.temp();
+                body.line(&format!("{} = add i64 {}, 1", w, v));
+                body.line(&format!("store i64 {}, ptr {}", w, i));
+                body.br(&loop_);
+                body.label(&done);
+                String::new()
+            }
+            TypedExpr::While(ref c, ref e) => {
+                let (test, loop_, done) =
+                    (self.label("while"), self.label("body"), self.label("done"));
+                body.br(&test);
+                body.label(&test);
+                let c = self.cond(c, env, level, body);
+                body.line(&format!("br i1 {}, label %{}, label %{}", c, loop_, done));
+                body.label(&loop_);
+                self.breaks.push(done.clone());
+                self.expr(e, env, level, body);
+                self.breaks.pop();
+                body.br(&test);
+                body.label(&done);
+                String::new()
+            }
+            TypedExpr::FunApp(ref name, id, _, ret, ref args) => {
+                let mut vals = Vec::with_capacity(args.len() + 1);
+                for arg in args {
+                    let v = self.expr(arg, env, level, body);
+                    vals.push(format!("{} {}", lltype(ty_of_ast(arg)), v));
+                }
+                let fun = match env.get(&id) {
+                    Some(Entry::Fun(fun_level, llname)) => {
+                        let (fun_level, llname) = (*fun_level, llname.clone());
+                        let parent = self.levels[fun_level].parent.unwrap();
+                        let link = self.frame(level, parent, body);
+                        vals.insert(0, format!("ptr {}", link));
+                        llname
+                    }
+                    _ => format!("tiger_{}", name),
+                };
+                let call = format!("call {} @{}({})", lltype(ret), fun, vals.join(", "));
+                if ret == Ty::Unit {
+                    body.line(&call);
+                    String::new()
+                } else {
+                    let t = self.temp();
+                    body.line(&format!("{} = {}", t, call));
+                    t
+                }
+            }
+            TypedExpr::NewStruct(ty, ref fields) => {
+                let r = self.temp();
+                body.line(&format!(
+                    "{} = call ptr @tiger_allocRecord(i64 {})",
+                    r,
+                    8 * fields.len()
+                ));
+                for (name, e) in fields {
+                    let (record, k, field_ty) = self.field(ty, name);
+                    let v = self.expr(e, env, level, body);
+                    let p = self.temp();
+                    body.line(&format!(
+                        "{} = getelementptr {}, ptr {}, i32 0, i32 {}",
+                        p, record, r, k
+                    ));
+                    body.line(&format!("store {} {}, ptr {}", lltype(field_ty), v, p));
+                }
+                r
+            }
+            TypedExpr::NewArray(ty, ref n, ref init) => {
+                let n = self.expr(n, env, level, body);
+                let mut v = self.expr(init, env, level, body);
+                if lltype(self.elem(ty)) == "ptr" {
+                    let t = self.temp();
+                    body.line(&format!("{} = ptrtoint ptr {} to i64", t, v));
+                    v = t;
+                }
+                let a = self.temp();
+                body.line(&format!(
+                    "{} = call ptr @tiger_initArray(i64 {}, i64 {})",
+                    a, n, v
+                ));
+                a
+            }
+            TypedExpr::Break => {
+                let done = self.breaks.last().expect("break outside of a loop").clone();
+                body.br(&done);
+                // what follows the break in its sequence is unreachable
+                let dead = self.label("dead");
+                body.label(&dead);
+                String::new()
+            }
+        }
+    }
+
+    /* Declares every function of a group first, so that members can call each other. */
+    fn fun_group(&mut self, decs: &[TypedDec], env: &mut Env, level: usize) {
+        let mut levels = Vec::with_capacity(decs.len());
+        for dec in decs {
+            if let TypedDec::Fun((ref name, id, ..)) = *dec {
+                let llname = self.fun_name(name);
+                self.levels.push(Level {
+                    name: llname.clone(),
+                    parent: Some(level),
+                    frame: vec!["ptr"],
+                    uses_frame: false,
+                });
+                let fun_level = self.levels.len() - 1;
+                env.insert(id, Entry::Fun(fun_level, llname));
+                levels.push(fun_level);
+            }
+        }
+        let breaks = std::mem::take(&mut self.breaks);
+        for (dec, fun_level) in decs.iter().zip(levels) {
+            if let TypedDec::Fun((_, _, ref params, ret, ref e)) = *dec {
+                let mut body = Body::new();
+                let mut formals = vec!["ptr %link".to_string()];
+                let mut body_env = env.clone();
+                for &(ref param, id, ty) in params {
+                    let escape = self.escapes.escapes(id);
+                    let formal = self.local(param);
+                    let loc = if escape {
+                        self.place(param, ty, true, fun_level, &mut body)
+                    } else {
+                        let p = format!("{}.addr", formal);
+                        body.allocas += &format!("  {} = alloca {}\n", p, lltype(ty));
+                        Loc::Local(p)
+                    };
+                    let p = self.address(fun_level, &loc, fun_level, &mut body);
+                    body.line(&format!("store {} {}, ptr {}", lltype(ty), formal, p));
+                    formals.push(format!("{} {}", lltype(ty), formal));
+                    body_env.insert(id, Entry::Var(fun_level, loc, ty));
+                }
+                let v = self.expr(e, &body_env, fun_level, &mut body);
+                if ret == Ty::Unit {
+                    body.line("ret void");
+                } else {
+                    body.line(&format!("ret {} {}", lltype(ret), v));
+                }
+                let head = format!(
+                    "define internal {} @{}({})",
+                    lltype(ret),
+                    self.levels[fun_level].name,
+                    formals.join(", ")
+                );
+                self.function(fun_level, head, "%link", body);
+            }
+        }
+        self.breaks = breaks;
+    }
+
+    /* Adds the function at level, with its frame if it uses one. */
+    fn function(&mut self, level: usize, head: String, link: &str, body: Body) {
+        let mut text = format!("{} {{\nentry:\n", head);
+        if self.levels[level].uses_frame {
+            text += &format!("  %frame = alloca %{}.frame\n", self.levels[level].name);
+            text += &format!("  store ptr {}, ptr %frame\n", link);
+        }
+        text += &body.allocas;
+        text += &body.code;
+        text += "}\n";
+        self.funs.push(text);
+    }
+
+    fn write<W: Write>(&self, mut out: W) -> io::Result<()> {
+        writeln!(
+            out,
+            "; Generated by rust-tigress; compile with clang together with runtime.c."
+        )?;
+        writeln!(out)?;
+        for (i, def) in self.types.iter().enumerate() {
+            if let TyDef::Record(ref name, ref fields) = *def {
+                let fields: Vec<_> = fields.iter().map(|&(_, ty)| lltype(ty)).collect();
+                writeln!(out, "%{}_{} = type {{ {} }}", name, i, fields.join(", "))?;
+            }
+        }
+        for level in self.levels.iter().filter(|l| l.uses_frame) {
+            writeln!(
+                out,
+                "%{}.frame = type {{ {} }}",
+                level.name,
+                level.frame.join(", ")
+            )?;
+        }
+        if !self.strings.is_empty() {
+            writeln!(out)?;
+        }
+        for (i, s) in self.strings.iter().enumerate() {
+            writeln!(
+                out,
+                "@str{} = private unnamed_addr constant {{ i64, [{} x i8] }} {{ i64 {}, [{} x i8] {} }}, align 8",
+                i,
+                s.len(),
+                s.len(),
+                s.len(),
+                ll_string(s)
+            )?;
+        }
+        writeln!(out)?;
+        out.write_all(DECLARATIONS.as_bytes())?;
+        for fun in &self.funs {
+            writeln!(out, "\n{}", fun.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+/*
+ * Writes tast, typed with the given types, as LLVM IR. escapes come from the resolution
+ * of the located tree tast was typed from, as for translate. The program gives its value if it
+ * is an int, 0 otherwise, which the runtime makes the exit status.
+ */
+pub fn write<W: Write>(
+    out: W,
+    tast: &TypedExpr,
+    types: &TypeTable,
+    escapes: &Escapes,
+) -> io::Result<()> {
+    let mut labels: HashSet<String> = translate::RUNTIME
+        .iter()
+        .chain(library::NAMES)
+        .map(|s| s.to_string())
+        .collect();
+    labels.insert(MAIN.to_string());
+    let mut ctx = Ctx {
+        types,
+        escapes,
+        levels: vec![Level {
+            name: format!("tiger_{}", MAIN),
+            parent: None,
+            frame: vec!["ptr"],
+            uses_frame: false,
+        }],
+        labels,
+        names: 0,
+        strings: Vec::new(),
+        breaks: Vec::new(),
+        funs: Vec::new(),
+    };
+    let mut body = Body::new();
+    let v = ctx.expr(tast, &Env::new(), 0, &mut body);
+    if ty_of_ast(tast) == Ty::Int {
+        body.line(&format!("ret i64 {}", v));
+    } else {
+        body.line("ret i64 0");
+    }
+    let head = format!("define i64 @tiger_{}()", MAIN);
+    ctx.function(0, head, "null", body);
+    ctx.write(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{escape, resolve, typing};
+
+    fn generate(src: &str) -> String {
+        let (ast, types, tast) = typing::check(src, &[]).unwrap();
+        let (res, _) = resolve::f(&ast, &[]);
+        let mut out = Vec::new();
+        write(&mut out, &tast, &types, &escape::f(&res)).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn frame_test() {
+        let out = generate(
+            "let function f(x: int, y: int): int =
+  let function g(): int = x + 1 in g() + y end
+in f(1, 2) end",
+        );
+        // x escapes to g, so it is stored in the frame of f; y gets an alloca
+        assert!(out.contains("%tiger_f.frame = type { ptr, i64 }"));
+        assert!(out.contains("define internal i64 @tiger_g(ptr %link) {"));
+        assert!(out.contains("define internal i64 @tiger_f(ptr %link, i64 %x_1, i64 %y_3) {"));
+        assert!(out.contains("  %y_3.addr = alloca i64\n"));
+        assert!(out.contains("getelementptr %tiger_f.frame, ptr %link, i32 0, i32 1"));
+        assert!(out.contains("call i64 @tiger_g(ptr %frame)"));
+        // the main program has no escaping variables, but its frame is the static link of f
+        assert!(out.contains("%tiger_tigermain.frame = type { ptr }"));
+        assert_eq!(ll_string("a\"\n"), "c\"a\\22\\0A\"");
+    }
+}
@@ -1,14 +1,50 @@
+use calc::ast::{ty_of_ast, Expr, Span, Ty, TypeTable, TypedExpr, Value};
+use calc::cgen;
+use calc::compile;
+use calc::coverage::Coverage;
+use calc::debug::Debugger;
+use calc::diagnostics::{Code, Diagnostic, Severity};
+use calc::engine::HostFun;
+use calc::escape;
+use calc::eval;
+use calc::flow::{self, FlowGraph};
+use calc::frame::{Fragment, Frame, X64Frame};
 use calc::interpret;
+use calc::library;
+use calc::lint::{self, Level, Levels, Lint};
+use calc::liveness::{Interference, Liveness};
+use calc::llvm;
 use calc::parse;
-use clap::{Arg, Command};
+use calc::profile::Profiler;
+use calc::resolve;
+use calc::trace::{JsonTracer, LineTracer, NoTracer, Tracer};
+use calc::translate;
+use calc::tree::Gen;
+use calc::typing;
+use calc::wat;
+use clap::{Arg, ArgAction, Command};
+use std::cell::RefCell;
 use std::fs::File;
 use std::io;
 use std::io::{Read, Write};
+use std::path::Path;
+use std::rc::Rc;
 
 #[allow(non_snake_case)]
 struct Args {
     flag_verbose: bool,
-    arg_INPUT: String, // needs allow(non_snake_case) because of this line
+    flag_trace: Option<String>,
+    flag_debug: bool,
+    flag_commands: Option<String>,
+    flag_profile: bool,
+    flag_folded: Option<String>,
+    flag_lcov: Option<String>,
+    flag_annotate: Option<String>,
+    flag_no_typecheck: bool,
+    flag_emit: Option<String>,
+    flag_lints: Levels,
+    cmd_compile: Option<String>, // where compile writes the executable
+    arg_INPUT: String,           // needs allow(non_snake_case) because of this line
 }
 
 fn get_args() -> Args {
@@ -20,7 +56,90 @@ fn get_args() -> Args {
             Arg::new("verbose")
                 .short('v')
                 .long("verbose")
-                .help("Verbose output"),
+                .action(ArgAction::SetTrue)
+                .help("Verbose output (implies --trace=pretty)"),
+        )
+        .arg(
+            Arg::new("trace")
+                .long("trace")
+                .value_name("FORMAT")
+                .value_parser(["pretty", "json"])
+                .help("Traces evaluation to stderr, as indented lines or as JSON lines"),
+        )
+        .arg(
+            Arg::new("debug")
+                .short('d')
+                .long("debug")
+                .action(ArgAction::SetTrue)
+                .requires("INPUT")
+                .help(
+                    "Runs the program under the interactive debugger, reading commands from the terminal",
+                ),
+        )
+        .arg(
+            Arg::new("commands")
+                .long("commands")
+                .value_name("FILE")
+                .requires("debug")
+                .help("Reads debugger commands from FILE instead of the terminal"),
+        )
+        .arg(
+            Arg::new("profile")
+                .long("profile")
+                .action(ArgAction::SetTrue)
+                .help("Prints per-function and per-loop costs to stderr after the run"),
+        )
+        .arg(
+            Arg::new("folded").long("folded").value_name("FILE").help(
+                "Writes profiled stacks to FILE in the folded format used by flamegraph tools",
+            ),
+        )
+        .arg(
+            Arg::new("lcov")
+                .long("lcov")
+                .value_name("FILE")
+                .requires("INPUT")
+                .help("Writes expression and branch coverage of the run to FILE in LCOV format"),
+        )
+        .arg(
+            Arg::new("annotate")
+                .long("annotate")
+                .value_name("FILE")
+                .help("Writes the source annotated with execution counts to FILE"),
+        )
+        .arg(
+            Arg::new("no-typecheck")
+                .long("no-typecheck")
+                .action(ArgAction::SetTrue)
+                .help("Runs the program without checking its types first"),
+        )
+        .arg(
+            Arg::new("emit")
+                .long("emit")
+                .value_name("WHAT")
+                .value_parser([
+                    "escapes",
+                    "ir",
+                    "asm",
+                    "cfg",
+                    "interference",
+                    "spills",
+                    "c",
+                    "wat",
+                    "llvm",
+                ])
+                .help("Prints the result of an analysis instead of running the program"),
+        )
+        .args(
+            [("allow", 'A'), ("warn", 'W'), ("deny", 'D')].map(|(level, short)| {
+                Arg::new(level)
+                    .short(short)
+                    .long(level)
+                    .value_name("LINT")
+                    .action(ArgAction::Append)
+                    .value_parser(lint::LINTS.iter().map(|l| l.1).collect::<Vec<_>>())
+                    .help(format!("Sets the level of a lint to {}", level))
+            }),
         )
         .arg(
             Arg::new("INPUT")
@@ -28,15 +147,132 @@ fn get_args() -> Args {
                 .required(false)
                 .index(1),
         )
+        .args_conflicts_with_subcommands(true)
+        .subcommand(
+            Command::new("compile")
+                .about("Compiles a program to an x86-64 Linux executable, linked by cc")
+                .arg(
+                    Arg::new("output").short('o').value_name("FILE").help(
+                        "Writes the executable to FILE instead of INPUT without its extension",
+                    ),
+                )
+                .arg(
+                    Arg::new("INPUT")
+                        .help("Sets the input file to use")
+                        .required(true)
+                        .index(1),
+                ),
+        )
         .get_matches();
+    // the flags above conflict with compile, so they keep their defaults when it is used
+    let compile = matches.subcommand_matches("compile");
+    // like rustc, a later flag overrides an earlier one for the same lint
+    let mut lint_flags = Vec::new();
+    for (id, level) in [
+        ("allow", Level::Allow),
+        ("warn", Level::Warn),
+        ("deny", Level::Deny),
+    ] {
+        if let (Some(indices), Some(names)) =
+            (matches.indices_of(id), matches.get_many::<String>(id))
+        {
+            lint_flags.extend(indices.zip(names).map(|(i, name)| (i, level, name)));
+        }
+    }
+    lint_flags.sort_by_key(|f| f.0);
+    let mut flag_lints = Levels::default();
+    for (_, level, name) in lint_flags {
+        flag_lints.set(Lint::from_name(name).unwrap(), level);
+    }
     Args {
-        flag_verbose: matches.contains_id("verbose"),
-        arg_INPUT: matches
+        flag_verbose: matches.get_flag("verbose"),
+        flag_trace: matches.get_one::<String>("trace").cloned(),
+        flag_debug: matches.get_flag("debug"),
+        flag_commands: matches.get_one::<String>("commands").cloned(),
+        flag_profile: matches.get_flag("profile"),
+        flag_folded: matches.get_one::<String>("folded").cloned(),
+        flag_lcov: matches.get_one::<String>("lcov").cloned(),
+        flag_annotate: matches.get_one::<String>("annotate").cloned(),
+        flag_no_typecheck: matches.get_flag("no-typecheck"),
+        flag_emit: matches.get_one::<String>("emit").cloned(),
+        flag_lints,
+        cmd_compile: compile.map(|sub| match sub.get_one::<String>("output") {
+            Some(output) => output.clone(),
+            None => {
+                let input = Path::new(sub.get_one::<String>("INPUT").unwrap());
+                input.with_extension("").display().to_string()
+            }
+        }),
+        arg_INPUT: compile
+            .unwrap_or(&matches)
             .get_one::<String>("INPUT")
             .unwrap_or(&"".to_string())
             .to_string(),
     }
 }
+/* Prints diagnostics to stderr and exits. */
+fn report(diags: &[Diagnostic], src: &str, path: &str) -> ! {
+    for diag in diags {
+        eprintln!("{}", diag.render(src, path));
+    }
+    eprintln!("{} error(s) found", diags.len());
+    std::process::exit(1)
+}
+
+/* Reports an error of the running program, located if span is known, and exits with 1. */
+fn runtime_error(message: String, span: Option<Span>, src: &str, path: &str) -> ! {
+    io::stdout().flush().ok();
+    let diag = Diagnostic::error(Code::Runtime, span, message);
+    eprintln!("{}", diag.render(src, path));
+    std::process::exit(1)
+}
+
+/* Translates a typed program for x86-64, with the standard library as external functions. */
+fn x64_fragments(
+    ast: &Expr,
+    types: &TypeTable,
+    tast: &TypedExpr,
+    gen: &mut Gen,
+) -> Vec<Fragment<X64Frame>> {
+    let (res, _) = resolve::f(ast, library::NAMES);
+    translate::f(tast, types, &escape::f(&res), gen)
+}
+
+/* Writes the flow graph or the interference graph of every function in DOT. */
+fn write_graphs(
+    what: &str,
+    fragments: Vec<Fragment<X64Frame>>,
+    int_result: bool,
+    gen: &mut Gen,
+) -> io::Result<()> {
+    for fragment in fragments {
+        if let Fragment::Proc { body, frame } = fragment {
+            let instrs = compile::instructions(body, &frame, int_result, gen);
+            let graph = FlowGraph::new(&instrs);
+            let name = &frame.name().0;
+            if what == "cfg" {
+                flow::write_dot(io::stdout(), name, &instrs, &graph, &X64Frame::temp_name)?;
+            } else {
+                let ig = Interference::new(&graph, &Liveness::new(&graph));
+                ig.write_dot(io::stdout(), name, &X64Frame::temp_name)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/* Runs ast, parsed from src, with the interpreter, which tracer follows. */
+fn interpret(
+    ast: &Expr,
+    host: &[(String, HostFun)],
+    tracer: &mut dyn Tracer,
+    src: &str,
+    path: &str,
+) -> Value {
+    interpret::run_located(ast, host, tracer)
+        .unwrap_or_else(|(e, span)| runtime_error(e, span, src, path))
+}
+
 fn main() {
     let args: Args = get_args();
     if args.flag_verbose {
@@ -55,12 +291,178 @@ fn main() {
         }
     } else {
         // Reads from file
-        let mut fp = File::open(args.arg_INPUT).unwrap_or_else(|e| panic!("{e}"));
+        let mut fp = File::open(&args.arg_INPUT).unwrap_or_else(|e| panic!("{e}"));
         fp.read_to_string(&mut s).unwrap_or_else(|e| panic!("{e}"));
     }
-    let ast = parse::parse(&s);
+    let path = if args.arg_INPUT.is_empty() {
+        "<stdin>"
+    } else {
+        args.arg_INPUT.as_str()
+    };
+    // print and getchar use stdout and stdin, like the interpreter's own output
+    let host = library::functions(
+        Rc::new(RefCell::new(io::stdout())),
+        Rc::new(RefCell::new(io::stdin())),
+    );
+    let (ast, typed) = if args.flag_no_typecheck {
+        let ast = parse::parse_diagnostic(&s).unwrap_or_else(|diag| report(&[diag], &s, path));
+        (ast, None)
+    } else {
+        match typing::check(&s, &host) {
+            Ok((ast, types, tast)) => (ast, Some((types, tast))),
+            Err(diags) => report(&diags, &s, path),
+        }
+    };
+    if !args.flag_no_typecheck {
+        let (res, _) = resolve::f(&ast, library::NAMES);
+        let (warnings, denied): (Vec<Diagnostic>, Vec<Diagnostic>) =
+            lint::f(&ast, &res, &args.flag_lints)
+                .into_iter()
+                .partition(|d| d.severity == Severity::Warning);
+        for diag in &warnings {
+            eprintln!("{}", diag.render(&s, path));
+        }
+        if !denied.is_empty() {
+            report(&denied, &s, path);
+        }
+    }
     if args.flag_verbose {
         println!("{:?}", ast);
     }
-    println!("result = {}", interpret::f(&ast, args.flag_verbose));
+    if let (Some(output), Some((types, tast))) = (&args.cmd_compile, &typed) {
+        let mut gen = Gen::new();
+        let fragments = x64_fragments(&ast, types, tast, &mut gen);
+        let mut asm = Vec::new();
+        compile::assembly(&mut asm, fragments, ty_of_ast(tast) == Ty::Int, &mut gen)
+            .map(drop)
+            .unwrap_or_else(|e| panic!("{e}"));
+        if let Err(e) = compile::link(&asm, Path::new(output)) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Some(what) = args.flag_emit.as_deref() {
+        match what {
+            "escapes" => {
+                let (res, _) = resolve::f(&ast, library::NAMES);
+                escape::f(&res)
+                    .write(io::stdout(), &res, &s)
+                    .unwrap_or_else(|e| panic!("{e}"));
+            }
+            "c" | "wat" | "llvm" => {
+                let Some((types, tast)) = typed else {
+                    eprintln!("--emit={} needs the types of the program, so it cannot be used with --no-typecheck", what);
+                    std::process::exit(1)
+                };
+                let (res, _) = resolve::f(&ast, library::NAMES);
+                let escapes = escape::f(&res);
+                match what {
+                    "c" => cgen::write(io::stdout(), &tast, &types, &escapes),
+                    "wat" => wat::write(io::stdout(), &tast, &types, &escapes),
+                    _ => llvm::write(io::stdout(), &tast, &types, &escapes),
+                }
+                .unwrap_or_else(|e| panic!("{e}"));
+            }
+            "ir" | "asm" | "cfg" | "interference" | "spills" => {
+                let Some((types, tast)) = typed else {
+                    eprintln!("--emit={} needs the types of the program, so it cannot be used with --no-typecheck", what);
+                    std::process::exit(1)
+                };
+                let mut gen = Gen::new();
+                let fragments = x64_fragments(&ast, &types, &tast, &mut gen);
+                let int_result = ty_of_ast(&tast) == Ty::Int;
+                match what {
+                    "ir" => translate::write(io::stdout(), &fragments),
+                    "asm" => {
+                        compile::assembly(io::stdout(), fragments, int_result, &mut gen).map(drop)
+                    }
+                    "spills" => compile::assembly(io::sink(), fragments, int_result, &mut gen).map(
+                        |spills| {
+                            for (name, n) in spills {
+                                println!("{}: {} spilled", name, n);
+                            }
+                        },
+                    ),
+                    _ => write_graphs(what, fragments, int_result, &mut gen),
+                }
+                .unwrap_or_else(|e| panic!("{e}"));
+            }
+            _ => unreachable!(),
+        }
+        return;
+    }
+    if args.flag_profile || args.flag_folded.is_some() {
+        let mut profiler = Profiler::new(&s);
+        println!(
+            "result = {}",
+            interpret(&ast, &host, &mut profiler, &s, path)
+        );
+        profiler.finish();
+        if args.flag_profile {
+            profiler
+                .write_table(io::stderr())
+                .unwrap_or_else(|e| panic!("{e}"));
+        }
+        if let Some(path) = args.flag_folded {
+            let fp = File::create(path).unwrap_or_else(|e| panic!("{e}"));
+            profiler.write_folded(fp).unwrap_or_else(|e| panic!("{e}"));
+        }
+        return;
+    }
+    if args.flag_lcov.is_some() || args.flag_annotate.is_some() {
+        let mut coverage = Coverage::new(&ast, &s);
+        println!(
+            "result = {}",
+            interpret(&ast, &host, &mut coverage, &s, path)
+        );
+        if let Some(path) = args.flag_lcov {
+            let fp = File::create(path).unwrap_or_else(|e| panic!("{e}"));
+            coverage
+                .write_lcov(fp, &args.arg_INPUT)
+                .unwrap_or_else(|e| panic!("{e}"));
+        }
+        if let Some(path) = args.flag_annotate {
+            let fp = File::create(path).unwrap_or_else(|e| panic!("{e}"));
+            coverage
+                .write_annotated(fp)
+                .unwrap_or_else(|e| panic!("{e}"));
+        }
+        return;
+    }
+    let trace = match args.flag_trace {
+        Some(format) => Some(format),
+        None if args.flag_verbose => Some("pretty".to_string()),
+        None => None,
+    };
+    // tracers follow the source tree, so only untraced runs can use the typed one
+    if let (Some((_, tast)), None, false) = (&typed, &trace, args.flag_debug) {
+        // typed trees are not located
+        let result = eval::run(tast, &host).unwrap_or_else(|e| runtime_error(e, None, &s, path));
+        println!("result = {}", result);
+        return;
+    }
+    let mut tracer: Box<dyn Tracer> = match trace.as_deref() {
+        _ if args.flag_debug => {
+            // not stdin, which getchar reads
+            let path = args.flag_commands.as_deref().unwrap_or("/dev/tty");
+            let commands = File::open(path).unwrap_or_else(|e| panic!("{path}: {e}"));
+            Box::new(Debugger::new(
+                io::BufReader::new(commands),
+                io::stdout(),
+                &s,
+            ))
+        }
+        Some("pretty") => Box::new(LineTracer::new(io::stderr(), &s)),
+        Some("json") => Box::new(JsonTracer::new(io::stderr(), &s)),
+        _ => Box::new(NoTracer),
+    };
+    let result = interpret::run_located(&ast, &host, tracer.as_mut());
+    if tracer.aborted() {
+        return;
+    }
+    println!(
+        "result = {}",
+        result.unwrap_or_else(|(e, span)| runtime_error(e, span, &s, path))
+    );
 }
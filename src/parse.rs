@@ -1,4 +1,5 @@
-use crate::ast::{Dec, Expr, Field, LValue, Op, Type, TypeField};
+use crate::ast::{Dec, Expr, Field, LValue, Op, Span, Type, TypeField};
+use crate::diagnostics::{Code, Diagnostic};
 
 /*
  * Example: ([(1, +), (2, -)], 4) ==> (4 + 1) - 2
@@ -16,9 +17,32 @@ where
     ast
 }
 
+fn span_of(e: &Expr) -> Option<Span> {
+    match *e {
+        Expr::Spanned(span, _) => Some(span),
+        _ => None,
+    }
+}
+
+/*
+ * Wraps e in Expr::Spanned, unless e already carries exactly this span.
+ */
+fn spanned(lo: usize, hi: usize, e: Expr) -> Expr {
+    let span = Span::new(lo, hi);
+    if span_of(&e) == Some(span) {
+        return e;
+    }
+    Expr::Spanned(span, Box::new(e))
+}
+
 pub fn fold_op(first: Expr, ops: Vec<(Expr, Op)>) -> Expr {
     fold_to_ast((ops, first), |t, e1, e2| {
-        Expr::OpNode(t, Box::new(e1), Box::new(e2))
+        let span = span_of(&e1).and_then(|s1| span_of(&e2).map(|s2| s1.join(s2)));
+        let e = Expr::OpNode(t, Box::new(e1), Box::new(e2));
+        match span {
+            Some(span) => spanned(span.lo, span.hi, e),
+            None => e,
+        }
     })
 }
 
@@ -26,24 +50,31 @@ peg::parser! {
     grammar tigress_grammar() for str  {
         pub rule top_expr() -> Expr
             = space()* e:expr() space()* { e }
-        rule expr() -> Expr
+        rule spanned(r: rule<Expr>) -> Expr
+            = lo:position!() e:r() hi:position!() { spanned(lo, hi, e) }
+        rule expr() -> Expr = spanned(<expr_()>)
+        rule expr_() -> Expr
             = "for" space()+ i:id() space()* ":=" space()* s:expr() space()+ "to" space()+ u:expr() space()+ "do" space()+ e:expr() { Expr::For(i, Box::new(s), Box::new(u), Box::new(e)) }
             / "while" space()+ c:expr() space()+ "do" space()+ e:expr() { Expr::Do(Box::new(c), Box::new(e)) }
             / expr0()
-        rule expr0() -> Expr /* if */
+        rule expr0() -> Expr = spanned(<expr0_()>)
+        rule expr0_() -> Expr /* if */
             = "if" space()* c:expr() space()* "then" space()* e1:expr1() !(space()+ "else") { Expr::IfNode(Box::new(c), Box::new(e1), Box::new(Expr::Nil)) }
             / expr1()
-        rule expr1() -> Expr /* ifelse */
+        rule expr1() -> Expr = spanned(<expr1_()>)
+        rule expr1_() -> Expr /* ifelse */
             = "if" space()* c:expr() space()* "then" space()* e1:expr2() space()* "else" space()* e2:expr2() { Expr::IfNode(Box::new(c), Box::new(e1), Box::new(e2)) }
             / expr2()
-        rule expr2() -> Expr /* := */
+        rule expr2() -> Expr = spanned(<expr2_()>)
+        rule expr2_() -> Expr /* := */
             = l:lvalue() space()* ":=" space()* e:expr3() { Expr::LAsgn(l, Box::new(e)) }
             / expr3()
         rule expr3() -> Expr /* "|" */
             = e:expr4() ls:(space()* "|" space()* e:expr4() { (e, Op::Or) })* { fold_op(e, ls) }
         rule expr4() -> Expr /* "&" */
             = e:expr5() ls:(space()* "&" space()* e:expr5() { (e, Op::And) })* { fold_op(e, ls) }
-        rule expr5() -> Expr /* %nonassoc "=" "<>" "<" ">" "<=" ">=" */
+        rule expr5() -> Expr = spanned(<expr5_()>)
+        rule expr5_() -> Expr /* %nonassoc "=" "<>" "<" ">" "<=" ">=" */
             = e1:expr6() space()* op:op5() space()* e2:expr6() { Expr::OpNode(op, Box::new(e1), Box::new(e2)) }
             / expr6()
         rule op5() -> Op /* %nonassoc "=" "<>" "<" ">" "<=" ">=" */
@@ -65,10 +96,12 @@ peg::parser! {
         rule op7() -> Op /* %left "*" "/" */
             = "*" { Op::Mul }
             / "/" { Op::Div }
-        rule expr8() -> Expr /* %right unary_minus */
+        rule expr8() -> Expr = spanned(<expr8_()>)
+        rule expr8_() -> Expr /* %right unary_minus */
             = "-" space()* e:expr8() { Expr::Neg(Box::new(e)) }
             / primary()
-        rule primary() -> Expr
+        rule primary() -> Expr = spanned(<primary_()>)
+        rule primary_() -> Expr
             = "let" space()+ decs:declaration_list() space()+ "in" space()+ es:expr_seq_opt() space()+ "end" { Expr::Let(decs, Box::new(Expr::Seq(es))) }
             / string_constant()
             / integer_constant()
@@ -111,12 +144,12 @@ peg::parser! {
         rule declaration_list() -> Vec<Dec>
             = declaration() ++ (space()*)
         rule declaration() -> Dec
-            = "type" space()+ tyid:id() space()* "=" space()* ty:type_() { Dec::Type(tyid, ty) }
-            / "var" space()+ i:id() space()* ty:(":" space()* ty:id() space()* { ty })? ":=" space()* e:expr() { Dec::Var(i, ty, e) }
+            = "type" space()+ lo:position!() tyid:id() hi:position!() space()* "=" space()* ty:type_() { Dec::Type(tyid, ty, Some(Span::new(lo, hi))) }
+            / "var" space()+ lo:position!() i:id() hi:position!() space()* ty:(":" space()* ty:id() space()* { ty })? ":=" space()* e:expr() { Dec::Var(i, ty, e, Some(Span::new(lo, hi))) }
             / "function" space()+ fname:id() space()* "(" space()* fields:type_fields_opt() space()* ")" space()* retty:(":" space()* t:id() space()* { t })? "=" space()* e:expr() { Dec::Fun(fname, fields, retty, e) }
 
         rule integer_constant() -> Expr
-            = mstr:$(['0'..='9']+) { Expr::Num(mstr.parse().unwrap()) }
+            = mstr:$(['0'..='9']+) {? mstr.parse().map(Expr::Num).or(Err("an integer of at most 9223372036854775807")) }
         rule string_constant() -> Expr
             = "\"" s:str_internal() "\"" { Expr::Str(s) }
         rule str_internal() -> String
@@ -139,15 +172,39 @@ peg::parser! {
 }
 
 pub fn parse(s: &str) -> Expr {
-    match tigress_grammar::top_expr(s) {
+    parse_located(s).without_spans()
+}
+
+/*
+ * Same as parse, but every expression is wrapped in Expr::Spanned
+ * so that later passes can point back into s.
+ */
+pub fn parse_located(s: &str) -> Expr {
+    match try_parse_located(s) {
         Ok(ast) => ast,
         Err(err) => {
-            println!("{:?} in parsing {}", err, s);
+            println!("{} in parsing {}", err, s);
             panic!("{err}")
         }
     }
 }
 
+pub fn try_parse_located(s: &str) -> Result<Expr, String> {
+    tigress_grammar::top_expr(s).map_err(|err| err.to_string())
+}
+
+/* Same as try_parse_located, reporting a failure as a diagnostic located where parsing stopped. */
+pub fn parse_diagnostic(s: &str) -> Result<Expr, Diagnostic> {
+    tigress_grammar::top_expr(s).map_err(|err| {
+        let lo = err.location.offset;
+        Diagnostic::error(
+            Code::Syntax,
+            Some(Span::new(lo, (lo + 1).min(s.len()))),
+            format!("syntax error: expected {}", err.expected),
+        )
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -195,8 +252,8 @@ mod tests {
             parse("let var x:= 4 var y:=3 in x + y end"),
             Let(
                 vec![
-                    Dec::Var("x".to_string(), None, Num(4)),
-                    Dec::Var("y".to_string(), None, Num(3)),
+                    Dec::Var("x".to_string(), None, Num(4), None),
+                    Dec::Var("y".to_string(), None, Num(3), None),
                 ],
                 Box::new(Seq(vec![OpNode(
                     Op::Add,
@@ -208,7 +265,11 @@ mod tests {
         assert_eq!(
             parse("let type i = int in 3 end"),
             Let(
-                vec![Dec::Type("i".to_string(), Type::Id("int".to_string()))],
+                vec![Dec::Type(
+                    "i".to_string(),
+                    Type::Id("int".to_string()),
+                    None
+                )],
                 Box::new(Seq(vec![Num(3)])),
             )
         );
@@ -216,11 +277,12 @@ mod tests {
             parse("let type int_array = array of int var x := int_array [4] of 0 in x end"),
             Let(
                 vec![
-                    Dec::Type("int_array".to_string(), Array("int".to_string())),
+                    Dec::Type("int_array".to_string(), Array("int".to_string()), None),
                     Dec::Var(
                         "x".to_string(),
                         None,
                         NewArray("int_array".to_string(), Box::new(Num(4)), Box::new(Num(0))),
+                        None,
                     )
                 ],
                 Box::new(Seq(vec![LVal(LValue::Id("x".to_string()))])),
@@ -232,6 +294,7 @@ mod tests {
                 vec![Dec::Type(
                     "web".to_string(),
                     Type::Field(vec![("dat".to_string(), "int".to_string())]),
+                    None,
                 )],
                 Box::new(Seq(vec![NewStruct(
                     "web".to_string(),
@@ -264,6 +327,15 @@ mod tests {
         );
     }
     #[test]
+    fn integer_test() {
+        assert_eq!(parse("9223372036854775807"), Num(i64::MAX));
+        let d = parse_diagnostic("1 + 99999999999999999999").unwrap_err();
+        assert_eq!(d.code, Code::Syntax);
+        assert!(d
+            .message
+            .contains("an integer of at most 9223372036854775807"));
+    }
+    #[test]
     fn lvalue_member_test() {
         use crate::ast::LValue::*;
         assert_eq!(
@@ -0,0 +1,231 @@
+use crate::ast::{Expr, LineIndex, Span, Value};
+use crate::interpret::Scope;
+use crate::trace::Tracer;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+/*
+ * Costs attributed to one function or loop.
+ * A step is the evaluation of one located expression.
+ */
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Stats {
+    pub calls: u64, // for loops, the number of times the loop was entered
+    pub iterations: u64,
+    pub incl_steps: u64,
+    pub excl_steps: u64,
+    pub incl_time: Duration,
+    pub excl_time: Duration,
+}
+
+struct Frame {
+    key: String,
+    path: String, // key of every enclosing frame joined by ';', as in folded stacks
+    loop_span: Option<Span>,
+    start: Instant,
+    steps_at_entry: u64,
+    child_time: Duration,
+}
+
+/*
+ * A Tracer that profiles Tiger functions and loops.
+ * Loops are named after their keyword and position, e.g. "for@3:5".
+ * Recursive activations are counted once in inclusive figures.
+ */
+pub struct Profiler {
+    lines: LineIndex,
+    steps: u64,
+    stack: Vec<Frame>,
+    stats: HashMap<String, Stats>,
+    folded: HashMap<String, u64>,
+}
+
+pub const ROOT: &str = "<main>";
+
+impl Profiler {
+    pub fn new(src: &str) -> Self {
+        let mut profiler = Profiler {
+            lines: LineIndex::new(src),
+            steps: 0,
+            stack: Vec::new(),
+            stats: HashMap::new(),
+            folded: HashMap::new(),
+        };
+        profiler.push(ROOT.to_string(), None);
+        profiler
+    }
+
+    fn push(&mut self, key: String, loop_span: Option<Span>) {
+        let path = match self.stack.last() {
+            Some(frame) => format!("{};{}", frame.path, key),
+            None => key.clone(),
+        };
+        self.stats.entry(key.clone()).or_default().calls += 1;
+        self.stack.push(Frame {
+            key,
+            path,
+            loop_span,
+            start: Instant::now(),
+            steps_at_entry: self.steps,
+            child_time: Duration::ZERO,
+        });
+    }
+
+    fn pop(&mut self) {
+        let frame = match self.stack.pop() {
+            Some(frame) => frame,
+            None => return,
+        };
+        let elapsed = frame.start.elapsed();
+        let recursive = self.stack.iter().any(|f| f.key == frame.key);
+        let stats = self.stats.get_mut(&frame.key).unwrap();
+        stats.excl_time += elapsed.saturating_sub(frame.child_time);
+        if !recursive {
+            stats.incl_steps += self.steps - frame.steps_at_entry;
+            stats.incl_time += elapsed;
+        }
+        if let Some(parent) = self.stack.last_mut() {
+            parent.child_time += elapsed;
+        }
+    }
+
+    /* Closes every open frame, including the root. Called once evaluation is over. */
+    pub fn finish(&mut self) {
+        while !self.stack.is_empty() {
+            self.pop();
+        }
+    }
+
+    pub fn stats(&self) -> &HashMap<String, Stats> {
+        &self.stats
+    }
+
+    /* Sorted by inclusive steps, then by name. */
+    pub fn write_table<W: Write>(&self, mut out: W) -> io::Result<()> {
+        let mut rows: Vec<(&String, &Stats)> = self.stats.iter().collect();
+        rows.sort_by(|a, b| b.1.incl_steps.cmp(&a.1.incl_steps).then(a.0.cmp(b.0)));
+        let width = rows.iter().map(|r| r.0.len()).max().unwrap_or(0).max(8);
+        writeln!(
+            out,
+            "{:width$} {:>8} {:>10} {:>12} {:>12} {:>10} {:>10}",
+            "name",
+            "calls",
+            "iterations",
+            "incl. steps",
+            "excl. steps",
+            "incl. ms",
+            "excl. ms",
+            width = width
+        )?;
+        for (name, s) in rows {
+            writeln!(
+                out,
+                "{:width$} {:>8} {:>10} {:>12} {:>12} {:>10.3} {:>10.3}",
+                name,
+                s.calls,
+                s.iterations,
+                s.incl_steps,
+                s.excl_steps,
+                s.incl_time.as_secs_f64() * 1e3,
+                s.excl_time.as_secs_f64() * 1e3,
+                width = width
+            )?;
+        }
+        Ok(())
+    }
+
+    /*
+     * Writes stacks in the folded format read by flamegraph tools:
+     * one "<main>;f;g 42" line per distinct stack, weighted by exclusive steps.
+     */
+    pub fn write_folded<W: Write>(&self, mut out: W) -> io::Result<()> {
+        let mut stacks: Vec<(&String, &u64)> = self.folded.iter().collect();
+        stacks.sort();
+        for (path, steps) in stacks {
+            writeln!(out, "{} {}", path, steps)?;
+        }
+        Ok(())
+    }
+}
+
+impl Tracer for Profiler {
+    fn enter_expr(&mut self, span: Span, expr: &Expr, _scope: &Scope) {
+        let keyword = match *expr {
+            Expr::For(..) => Some("for"),
+            Expr::Do(..) => Some("while"),
+            _ => None,
+        };
+        if let Some(keyword) = keyword {
+            let (line, col) = self.lines.line_col(span.lo);
+            self.push(format!("{}@{}:{}", keyword, line, col), Some(span));
+        }
+        self.steps += 1;
+        let frame = self.stack.last().unwrap();
+        self.stats.get_mut(&frame.key).unwrap().excl_steps += 1;
+        match self.folded.get_mut(&frame.path) {
+            Some(count) => *count += 1,
+            None => {
+                self.folded.insert(frame.path.clone(), 1);
+            }
+        }
+    }
+    fn exit_expr(&mut self, span: Span, _result: Option<&Value>) {
+        if self.stack.last().and_then(|f| f.loop_span) == Some(span) {
+            self.pop();
+        }
+    }
+    fn call(&mut self, name: &str, _args: &[Value]) {
+        self.push(name.to_string(), None);
+    }
+    fn ret(&mut self, _name: &str, _result: Option<&Value>) {
+        self.pop();
+    }
+    fn loop_iter(&mut self, _iteration: usize) {
+        let frame = self.stack.last().unwrap();
+        self.stats.get_mut(&frame.key).unwrap().iterations += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{interpret, parse};
+
+    fn profile(src: &str) -> Profiler {
+        let ast = parse::parse_located(src);
+        let mut profiler = Profiler::new(src);
+        interpret::f(&ast, &mut profiler);
+        profiler.finish();
+        profiler
+    }
+
+    #[test]
+    fn recursion_test() {
+        let p = profile("let function f(n: int): int = if n = 0 then 0 else f(n - 1) in f(2) end");
+        let f = &p.stats()["f"];
+        assert_eq!(f.calls, 3);
+        // each activation evaluates `if`, `n = 0`, `n`, `0`, then either `0` or `f(n - 1)`, `n - 1`, `n`, `1`
+        assert_eq!(f.excl_steps, 3 * 4 + 2 * 4 + 1);
+        assert_eq!(f.incl_steps, f.excl_steps);
+        let main = &p.stats()[ROOT];
+        assert_eq!(main.calls, 1);
+        assert_eq!(main.incl_steps, main.excl_steps + f.incl_steps);
+    }
+
+    #[test]
+    fn loop_test() {
+        let p = profile("let var x := 0 in\n  for i := 1 to 3 do x := x + i;\n  x end");
+        let l = &p.stats()["for@2:3"];
+        assert_eq!(l.calls, 1);
+        assert_eq!(l.iterations, 3);
+        let mut folded = Vec::new();
+        p.write_folded(&mut folded).unwrap();
+        let folded = String::from_utf8(folded).unwrap();
+        let lines: Vec<&str> = folded.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("<main> "));
+        // the loop header, its bounds and 3 * (`x := x + i`, `x + i`, `x`, `i`)
+        assert_eq!(lines[1], "<main>;for@2:3 15");
+    }
+}
@@ -0,0 +1,494 @@
+use crate::assem::Instr;
+use crate::codegen::{disp, is_precolored};
+use crate::flow::FlowGraph;
+use crate::frame::{x64, Access, Frame, X64Frame};
+use crate::liveness::Liveness;
+use crate::tree::{Gen, Temp};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/*
+ * Register allocation for x86-64 by iterated register coalescing (George and Appel, as in
+ * chapter 11 of Appel's book): build the interference graph, then simplify, coalesce,
+ * freeze and pick potential spills until the graph is empty, and select colors. Temporaries
+ * that get no color are actually spilled to the frame, the program is rewritten with a
+ * short-lived temporary around each of their uses and definitions, and allocation starts over.
+ */
+
+/* The registers that temporaries can get: all but rsp and rbp, caller-saved ones first. */
+pub const COLORS: [Temp; 14] = [
+    Temp(0),
+    Temp(2),
+    Temp(3),
+    Temp(4),
+    Temp(5),
+    Temp(8),
+    Temp(9),
+    Temp(10),
+    Temp(11),
+    Temp(1),
+    Temp(12),
+    Temp(13),
+    Temp(14),
+    Temp(15),
+];
+const K: usize = COLORS.len();
+const INFINITE: usize = usize::MAX / 2;
+
+/* The outcome of allocating the registers of one function. */
+#[derive(Clone, Debug)]
+pub struct Allocation {
+    pub instrs: Vec<Instr>, // with registers only, and without coalesced moves
+    pub spills: usize,      // temporaries spilled to the frame, over all rounds
+    pub rounds: usize,
+}
+
+#[derive(Default)]
+struct Graph {
+    initial: BTreeSet<Temp>,
+    simplify_worklist: BTreeSet<Temp>,
+    freeze_worklist: BTreeSet<Temp>,
+    spill_worklist: BTreeSet<Temp>,
+    spilled_nodes: BTreeSet<Temp>,
+    coalesced_nodes: BTreeSet<Temp>,
+    colored_nodes: BTreeSet<Temp>,
+    select_stack: Vec<Temp>,
+    // moves, as indices of instructions
+    moves: HashMap<usize, (Temp, Temp)>,
+    coalesced_moves: BTreeSet<usize>,
+    constrained_moves: BTreeSet<usize>,
+    frozen_moves: BTreeSet<usize>,
+    worklist_moves: BTreeSet<usize>,
+    active_moves: BTreeSet<usize>,
+    adj_set: HashSet<(Temp, Temp)>,
+    adj_list: HashMap<Temp, Vec<Temp>>,
+    degree: HashMap<Temp, usize>,
+    move_list: HashMap<Temp, BTreeSet<usize>>,
+    alias: HashMap<Temp, Temp>,
+    color: HashMap<Temp, Temp>,
+    // temporaries made by spilling, which are not worth spilling again
+    no_spill: HashSet<Temp>,
+}
+
+impl Graph {
+    fn build(&mut self, graph: &FlowGraph, live: &Liveness) {
+        for i in 0..graph.len() {
+            let mut live_now: BTreeSet<Temp> = live.live_out[i].clone();
+            for &t in graph.defs[i].iter().chain(&graph.uses[i]) {
+                if is_precolored(t) {
+                    self.degree.insert(t, INFINITE);
+                    self.color.insert(t, t);
+                } else {
+                    self.initial.insert(t);
+                }
+            }
+            if graph.is_move[i] {
+                let (dst, src) = (graph.defs[i][0], graph.uses[i][0]);
+                live_now.remove(&src);
+                self.move_list.entry(dst).or_default().insert(i);
+                self.move_list.entry(src).or_default().insert(i);
+                self.moves.insert(i, (dst, src));
+                self.worklist_moves.insert(i);
+            }
+            live_now.extend(&graph.defs[i]);
+            for &d in &graph.defs[i] {
+                for &l in &live_now {
+                    self.add_edge(l, d);
+                }
+            }
+        }
+    }
+
+    fn add_edge(&mut self, u: Temp, v: Temp) {
+        if u == v || self.adj_set.contains(&(u, v)) {
+            return;
+        }
+        self.adj_set.insert((u, v));
+        self.adj_set.insert((v, u));
+        for (a, b) in [(u, v), (v, u)] {
+            if !is_precolored(a) {
+                self.adj_list.entry(a).or_default().push(b);
+                *self.degree.entry(a).or_default() += 1;
+            }
+        }
+    }
+
+    fn degree(&self, t: Temp) -> usize {
+        self.degree.get(&t).copied().unwrap_or(0)
+    }
+
+    fn make_worklist(&mut self) {
+        for n in std::mem::take(&mut self.initial) {
+            if self.degree(n) >= K {
+                self.spill_worklist.insert(n);
+            } else if self.move_related(n) {
+                self.freeze_worklist.insert(n);
+            } else {
+                self.simplify_worklist.insert(n);
+            }
+        }
+    }
+
+    fn adjacent(&self, n: Temp) -> Vec<Temp> {
+        let on_stack: HashSet<&Temp> = self.select_stack.iter().collect();
+        self.adj_list
+            .get(&n)
+            .into_iter()
+            .flatten()
+            .filter(|t| !on_stack.contains(t) && !self.coalesced_nodes.contains(t))
+            .copied()
+            .collect()
+    }
+
+    fn node_moves(&self, n: Temp) -> Vec<usize> {
+        self.move_list
+            .get(&n)
+            .into_iter()
+            .flatten()
+            .filter(|m| self.active_moves.contains(m) || self.worklist_moves.contains(m))
+            .copied()
+            .collect()
+    }
+
+    fn move_related(&self, n: Temp) -> bool {
+        !self.node_moves(n).is_empty()
+    }
+
+    fn simplify(&mut self) {
+        let n = self.simplify_worklist.pop_first().unwrap();
+        self.select_stack.push(n);
+        for m in self.adjacent(n) {
+            self.decrement_degree(m);
+        }
+    }
+
+    fn decrement_degree(&mut self, m: Temp) {
+        let d = self.degree(m);
+        self.degree.insert(m, d.saturating_sub(1));
+        if d == K {
+            let mut nodes = self.adjacent(m);
+            nodes.push(m);
+            self.enable_moves(&nodes);
+            self.spill_worklist.remove(&m);
+            if self.move_related(m) {
+                self.freeze_worklist.insert(m);
+            } else {
+                self.simplify_worklist.insert(m);
+            }
+        }
+    }
+
+    fn enable_moves(&mut self, nodes: &[Temp]) {
+        for &n in nodes {
+            for m in self.node_moves(n) {
+                if self.active_moves.remove(&m) {
+                    self.worklist_moves.insert(m);
+                }
+            }
+        }
+    }
+
+    fn add_work_list(&mut self, u: Temp) {
+        if !is_precolored(u) && !self.move_related(u) && self.degree(u) < K {
+            self.freeze_worklist.remove(&u);
+            self.simplify_worklist.insert(u);
+        }
+    }
+
+    /* George's test, for coalescing with a precolored node. */
+    fn ok(&self, t: Temp, r: Temp) -> bool {
+        self.degree(t) < K || is_precolored(t) || self.adj_set.contains(&(t, r))
+    }
+
+    /* Briggs's test: the merged node has fewer than K neighbours of significant degree. */
+    fn conservative(&self, nodes: &[Temp]) -> bool {
+        let nodes: BTreeSet<&Temp> = nodes.iter().collect();
+        nodes.into_iter().filter(|&&n| self.degree(n) >= K).count() < K
+    }
+
+    fn get_alias(&self, n: Temp) -> Temp {
+        if self.coalesced_nodes.contains(&n) {
+            self.get_alias(self.alias[&n])
+        } else {
+            n
+        }
+    }
+
+    fn coalesce(&mut self) {
+        let m = self.worklist_moves.pop_first().unwrap();
+        let (x, y) = self.moves[&m];
+        let (x, y) = (self.get_alias(x), self.get_alias(y));
+        let (u, v) = if is_precolored(y) { (y, x) } else { (x, y) };
+        if u == v {
+            self.coalesced_moves.insert(m);
+            self.add_work_list(u);
+        } else if is_precolored(v) || self.adj_set.contains(&(u, v)) {
+            self.constrained_moves.insert(m);
+            self.add_work_list(u);
+            self.add_work_list(v);
+        } else if (is_precolored(u) && self.adjacent(v).iter().all(|&t| self.ok(t, u)))
+            || (!is_precolored(u) && {
+                let mut nodes = self.adjacent(u);
+                nodes.extend(self.adjacent(v));
+                self.conservative(&nodes)
+            })
+        {
+            self.coalesced_moves.insert(m);
+            self.combine(u, v);
+            self.add_work_list(u);
+        } else {
+            self.active_moves.insert(m);
+        }
+    }
+
+    fn combine(&mut self, u: Temp, v: Temp) {
+        if !self.freeze_worklist.remove(&v) {
+            self.spill_worklist.remove(&v);
+        }
+        self.coalesced_nodes.insert(v);
+        self.alias.insert(v, u);
+        let v_moves = self.move_list.get(&v).cloned().unwrap_or_default();
+        self.move_list.entry(u).or_default().extend(v_moves);
+        self.enable_moves(&[v]);
+        for t in self.adjacent(v) {
+            self.add_edge(t, u);
+            self.decrement_degree(t);
+        }
+        if self.degree(u) >= K && self.freeze_worklist.remove(&u) {
+            self.spill_worklist.insert(u);
+        }
+    }
+
+    fn freeze(&mut self) {
+        let u = self.freeze_worklist.pop_first().unwrap();
+        self.simplify_worklist.insert(u);
+        self.freeze_moves(u);
+    }
+
+    fn freeze_moves(&mut self, u: Temp) {
+        for m in self.node_moves(u) {
+            let (x, y) = self.moves[&m];
+            let v = if self.get_alias(y) == self.get_alias(u) {
+                self.get_alias(x)
+            } else {
+                self.get_alias(y)
+            };
+            self.active_moves.remove(&m);
+            self.frozen_moves.insert(m);
+            if !is_precolored(v) && self.node_moves(v).is_empty() && self.degree(v) < K {
+                self.freeze_worklist.remove(&v);
+                self.simplify_worklist.insert(v);
+            }
+        }
+    }
+
+    /* Picks the node of highest degree, preferring those not made by an earlier spill. */
+    fn select_spill(&mut self) {
+        let m = *self
+            .spill_worklist
+            .iter()
+            .max_by_key(|&&t| (!self.no_spill.contains(&t), self.degree(t)))
+            .unwrap();
+        self.spill_worklist.remove(&m);
+        self.simplify_worklist.insert(m);
+        self.freeze_moves(m);
+    }
+
+    fn assign_colors(&mut self) {
+        while let Some(n) = self.select_stack.pop() {
+            let mut ok_colors: Vec<Temp> = COLORS.to_vec();
+            for &w in self.adj_list.get(&n).into_iter().flatten() {
+                let w = self.get_alias(w);
+                if let Some(c) = self.color.get(&w) {
+                    ok_colors.retain(|r| r != c);
+                }
+            }
+            match ok_colors.first() {
+                None => {
+                    self.spilled_nodes.insert(n);
+                }
+                Some(&c) => {
+                    self.colored_nodes.insert(n);
+                    self.color.insert(n, c);
+                }
+            }
+        }
+        for &n in &self.coalesced_nodes {
+            if let Some(&c) = self.color.get(&self.get_alias(n)) {
+                self.color.insert(n, c);
+            }
+        }
+    }
+}
+
+/*
+ * Gives every spilled temporary a slot in the frame, loading it into a new temporary
+ * before each instruction that reads it and storing that after each that writes it.
+ * An instruction that both reads and writes it uses one new temporary for both, as
+ * two-address instructions require.
+ */
+fn rewrite(
+    instrs: Vec<Instr>,
+    spilled: &BTreeSet<Temp>,
+    frame: &mut X64Frame,
+    gen: &mut Gen,
+    no_spill: &mut HashSet<Temp>,
+) -> Vec<Instr> {
+    let slots: HashMap<Temp, i64> = spilled
+        .iter()
+        .map(|&t| match frame.alloc_local(true, gen) {
+            Access::InFrame(k) => (t, k),
+            Access::InReg(_) => unreachable!(),
+        })
+        .collect();
+    let mut out = Vec::with_capacity(instrs.len());
+    for instr in instrs {
+        let mut renamed: HashMap<Temp, Temp> = HashMap::new();
+        for &t in instr.uses().iter().chain(instr.defs()) {
+            if spilled.contains(&t) && !renamed.contains_key(&t) {
+                let new = gen.temp();
+                no_spill.insert(new);
+                renamed.insert(t, new);
+            }
+        }
+        let (uses, defs) = (instr.uses().to_vec(), instr.defs().to_vec());
+        for (t, &new) in &renamed {
+            if uses.contains(t) {
+                let assem = format!("mov 'd0, QWORD PTR ['s0{}]", disp(slots[t]));
+                out.push(Instr::oper(assem, vec![new], vec![x64::RBP]));
+            }
+        }
+        out.push(instr.rename(&|t| renamed.get(&t).copied().unwrap_or(t)));
+        for (t, &new) in &renamed {
+            if defs.contains(t) {
+                let assem = format!("mov QWORD PTR ['s0{}], 's1", disp(slots[t]));
+                out.push(Instr::oper(assem, vec![], vec![x64::RBP, new]));
+            }
+        }
+    }
+    out
+}
+
+/* Allocates registers for the instructions of the function of frame, growing it by its spills. */
+pub fn f(mut instrs: Vec<Instr>, frame: &mut X64Frame, gen: &mut Gen) -> Allocation {
+    let mut no_spill = HashSet::new();
+    let mut spills = 0;
+    let mut rounds = 0;
+    loop {
+        rounds += 1;
+        let flow = FlowGraph::new(&instrs);
+        let live = Liveness::new(&flow);
+        let mut g = Graph {
+            no_spill,
+            ..Graph::default()
+        };
+        g.build(&flow, &live);
+        g.make_worklist();
+        loop {
+            if !g.simplify_worklist.is_empty() {
+                g.simplify();
+            } else if !g.worklist_moves.is_empty() {
+                g.coalesce();
+            } else if !g.freeze_worklist.is_empty() {
+                g.freeze();
+            } else if !g.spill_worklist.is_empty() {
+                g.select_spill();
+            } else {
+                break;
+            }
+        }
+        g.assign_colors();
+        if g.spilled_nodes.is_empty() {
+            let color = |t: Temp| g.color[&t];
+            let instrs = instrs
+                .into_iter()
+                .enumerate()
+                .filter(|(i, _)| !g.coalesced_moves.contains(i))
+                .map(|(_, instr)| instr.rename(&color))
+                .collect();
+            return Allocation {
+                instrs,
+                spills,
+                rounds,
+            };
+        }
+        spills += g.spilled_nodes.len();
+        no_spill = g.no_spill;
+        instrs = rewrite(instrs, &g.spilled_nodes, frame, gen, &mut no_spill);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::Fragment;
+    use crate::{compile, escape, library, resolve, translate, typing};
+    use std::cell::RefCell;
+    use std::io;
+    use std::rc::Rc;
+
+    fn moves(instrs: &[Instr]) -> usize {
+        instrs
+            .iter()
+            .filter(|i| matches!(i, Instr::Move { dst, src, .. } if dst != src))
+            .count()
+    }
+
+    /*
+     * Allocates every function of src, by name and with its moves before allocation,
+     * checking that only registers are left.
+     */
+    fn allocate(src: &str) -> HashMap<String, (usize, Allocation)> {
+        let host = library::functions(
+            Rc::new(RefCell::new(io::sink())),
+            Rc::new(RefCell::new(io::empty())),
+        );
+        let (ast, types, tast) = typing::check(src, &host).unwrap();
+        let (res, _) = resolve::f(&ast, library::NAMES);
+        let mut gen = Gen::new();
+        let fragments: Vec<Fragment<X64Frame>> =
+            translate::f(&tast, &types, &escape::f(&res), &mut gen);
+        let mut out = HashMap::new();
+        for fragment in fragments {
+            if let Fragment::Proc { body, mut frame } = fragment {
+                let instrs = compile::instructions(body, &frame, true, &mut gen);
+                let locals = frame.frame_size();
+                let before = moves(&instrs);
+                let allocation = f(instrs, &mut frame, &mut gen);
+                assert!(allocation.instrs.iter().all(|i| i
+                    .uses()
+                    .iter()
+                    .chain(i.defs())
+                    .all(|&t| is_precolored(t))));
+                // every spill takes a slot of its own
+                assert_eq!(frame.frame_size() - locals, allocation.spills as i64 * 8);
+                out.insert(frame.name().0.clone(), (before, allocation));
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn coalesce_test() {
+        let allocations = allocate(include_str!("../sample/fib.tiger"));
+        // only the saves of the two callee-saved registers that hold values across calls spill
+        let (before, fib) = &allocations["fib"];
+        assert_eq!(fib.spills, 2);
+        assert_eq!(fib.rounds, 2);
+        // the moves of arguments, results and saved registers are mostly coalesced away
+        let after = moves(&fib.instrs);
+        assert!(after * 2 < *before, "{} of {} moves left", after, before);
+        assert_eq!(allocations["tigermain"].1.spills, 0);
+    }
+
+    #[test]
+    fn pressure_test() {
+        // twenty values live across calls, and eight arguments used in a loop with a call
+        let allocations = allocate(include_str!("../sample/pressure.tiger"));
+        assert_eq!(allocations["id"].1.spills, 0);
+        assert!(allocations["mix"].1.spills > 0);
+        let main = &allocations["tigermain"].1;
+        // at most five of them fit in callee-saved registers
+        assert!(main.spills >= 20 - 5);
+        assert!(main.rounds > 1);
+    }
+}
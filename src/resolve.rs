@@ -0,0 +1,465 @@
+use crate::ast::{Dec, Expr, LValue, Span, Type};
+use crate::diagnostics::{Code, Diagnostic};
+use std::collections::HashMap;
+
+pub type BindingId = usize;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Kind {
+    Var,
+    Param,
+    LoopIndex,
+    Fun,
+    Type,
+}
+
+/*
+ * One declaration. span is that of its name for a variable, otherwise that of the
+ * expression it belongs to: the body of a function or of the function declaring a parameter, the for
+ * loop of an index and the let of a type. Predeclared names have none.
+ */
+#[derive(PartialEq, Clone, Debug)]
+pub struct Binding {
+    pub name: String,
+    pub kind: Kind,
+    pub span: Option<Span>,
+    pub depth: usize,             // number of function declarations around it
+    pub owner: Option<BindingId>, // the innermost of those functions, None in the main program
+}
+
+/* One occurrence of a name, located at the innermost located expression around it. */
+#[derive(PartialEq, Clone, Debug)]
+pub struct Use {
+    pub binding: BindingId,
+    pub span: Option<Span>,
+    pub depth: usize,
+    pub owner: Option<BindingId>, // the innermost function or type declaration it is part of
+}
+
+/*
+ * Where a name is declared, by the address of its node in the tree resolved: a declaration,
+ * a parameter of a function declaration, the index of a for loop, or the host function at
+ * an index of those predeclared.
+ */
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum Site {
+    Dec(*const Dec),
+    Param(*const Dec, usize),
+    For(*const Expr),
+    Host(usize),
+}
+
+/*
+ * The def table, indexed by BindingId, the use table, in evaluation order, and the
+ * BindingId of every declaration site, which typing gives typed trees.
+ */
+#[derive(PartialEq, Clone, Debug, Default)]
+pub struct Resolution {
+    pub bindings: Vec<Binding>,
+    pub uses: Vec<Use>,
+    pub sites: HashMap<Site, BindingId>,
+}
+
+impl Resolution {
+    pub fn uses_of(&self, id: BindingId) -> impl Iterator<Item = &Use> {
+        self.uses.iter().filter(move |u| u.binding == id)
+    }
+    /* The binding of the innermost use around position pos of the source, as "go to definition" needs. */
+    pub fn definition_at(&self, pos: usize) -> Option<&Binding> {
+        self.uses
+            .iter()
+            .filter(|u| u.span.is_some_and(|s| s.lo <= pos && pos < s.hi))
+            .min_by_key(|u| u.span.map(|s| s.hi - s.lo))
+            .map(|u| &self.bindings[u.binding])
+    }
+}
+
+#[derive(Clone, Default)]
+struct Scope {
+    values: HashMap<String, BindingId>, // variables and functions share one namespace
+    types: HashMap<String, BindingId>,
+}
+
+struct Ctx {
+    res: Resolution,
+    diagnostics: Vec<Diagnostic>,
+    span: Option<Span>,
+    depth: usize,
+    owner: Option<BindingId>,
+}
+
+fn span_of(e: &Expr) -> Option<Span> {
+    match *e {
+        Expr::Spanned(span, _) => Some(span),
+        _ => None,
+    }
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for j in 0..b.len() {
+            let cur = row[j + 1];
+            row[j + 1] = (prev + usize::from(ca != b[j]))
+                .min(row[j] + 1)
+                .min(cur + 1);
+            prev = cur;
+        }
+    }
+    row[b.len()]
+}
+
+/* The closest of candidates to name, if it is close enough to be a likely typo. */
+pub fn suggest<'a, I: IntoIterator<Item = &'a str>>(name: &str, candidates: I) -> Option<&'a str> {
+    let limit = name.chars().count().div_ceil(3).max(1);
+    candidates
+        .into_iter()
+        .map(|c| (edit_distance(name, c), c))
+        .filter(|&(d, _)| d <= limit)
+        .min()
+        .map(|(_, c)| c)
+}
+
+impl Ctx {
+    fn declare(
+        &mut self,
+        scope: &mut Scope,
+        name: &str,
+        kind: Kind,
+        span: Option<Span>,
+        site: Option<Site>,
+    ) -> BindingId {
+        let id = self.res.bindings.len();
+        if let Some(site) = site {
+            self.res.sites.insert(site, id);
+        }
+        self.res.bindings.push(Binding {
+            name: name.to_string(),
+            kind,
+            span,
+            depth: self.depth,
+            owner: self.owner,
+        });
+        let table = match kind {
+            Kind::Type => &mut scope.types,
+            _ => &mut scope.values,
+        };
+        table.insert(name.to_string(), id);
+        id
+    }
+
+    /* Links a use of name, or reports it with a suggestion among the names of the wanted kinds. */
+    fn refer(&mut self, scope: &Scope, name: &str, code: Code) {
+        let table = match code {
+            Code::UndefinedType => &scope.types,
+            _ => &scope.values,
+        };
+        if let Some(&binding) = table.get(name) {
+            self.res.uses.push(Use {
+                binding,
+                span: self.span,
+                depth: self.depth,
+                owner: self.owner,
+            });
+            return;
+        }
+        let what = match code {
+            Code::UndefinedVariable => "variable",
+            Code::UndefinedFunction => "function",
+            _ => "type",
+        };
+        let bindings = &self.res.bindings;
+        let candidates = table.iter().filter_map(|(n, &id)| {
+            let fun = bindings[id].kind == Kind::Fun;
+            let wanted = match code {
+                Code::UndefinedFunction => fun,
+                Code::UndefinedVariable => !fun,
+                _ => true,
+            };
+            if wanted {
+                Some(n.as_str())
+            } else {
+                None
+            }
+        });
+        let mut d = Diagnostic::error(code, self.span, format!("undefined {} {}", what, name));
+        if let Some(s) = suggest(name, candidates) {
+            d = d.with_note(format!("did you mean `{}`?", s));
+        }
+        self.diagnostics.push(d);
+    }
+
+    fn lval(&mut self, l: &LValue, scope: &Scope) {
+        match *l {
+            LValue::Id(ref x) => self.refer(scope, x, Code::UndefinedVariable),
+            LValue::Mem(ref l, _) => self.lval(l, scope),
+            LValue::Idx(ref l, ref e) => {
+                self.lval(l, scope);
+                self.expr(e, scope);
+            }
+        }
+    }
+
+    /* Declares every name of a group first, so that members can refer to each other. */
+    fn fun_group(&mut self, decs: &[Dec], scope: &mut Scope) {
+        let mut ids = Vec::with_capacity(decs.len());
+        for dec in decs {
+            if let Dec::Fun(ref name, ref params, ref opt_ret, ref body) = *dec {
+                for (_, ty) in params {
+                    self.refer(scope, ty, Code::UndefinedType);
+                }
+                if let Some(ref ty) = *opt_ret {
+                    self.refer(scope, ty, Code::UndefinedType);
+                }
+                let site = Some(Site::Dec(dec));
+                ids.push(self.declare(scope, name, Kind::Fun, span_of(body), site));
+            }
+        }
+        for (dec, id) in decs.iter().zip(ids) {
+            if let Dec::Fun(_, ref params, _, ref body) = *dec {
+                let mut body_scope = scope.clone();
+                let saved = self.owner;
+                self.depth += 1;
+                self.owner = Some(id);
+                for (k, (param, _)) in params.iter().enumerate() {
+                    let site = Some(Site::Param(dec, k));
+                    self.declare(&mut body_scope, param, Kind::Param, span_of(body), site);
+                }
+                self.expr(body, &body_scope);
+                self.depth -= 1;
+                self.owner = saved;
+            }
+        }
+    }
+
+    fn type_group(&mut self, decs: &[Dec], scope: &mut Scope) {
+        let mut ids = Vec::with_capacity(decs.len());
+        for dec in decs {
+            if let Dec::Type(ref name, _, _) = *dec {
+                ids.push(self.declare(scope, name, Kind::Type, self.span, Some(Site::Dec(dec))));
+            }
+        }
+        let saved = self.owner;
+        for (dec, id) in decs.iter().zip(ids) {
+            self.owner = Some(id);
+            match *dec {
+                Dec::Type(_, Type::Id(ref ty), _) | Dec::Type(_, Type::Array(ref ty), _) => {
+                    self.refer(scope, ty, Code::UndefinedType)
+                }
+                Dec::Type(_, Type::Field(ref fields), _) => {
+                    for (_, ty) in fields {
+                        self.refer(scope, ty, Code::UndefinedType);
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.owner = saved;
+    }
+
+    fn expr(&mut self, ast: &Expr, scope: &Scope) {
+        match *ast {
+            Expr::Num(_) | Expr::Str(_) | Expr::Nil | Expr::Break => {}
+            Expr::LVal(ref l) => self.lval(l, scope),
+            Expr::Neg(ref e) => self.expr(e, scope),
+            Expr::OpNode(_, ref e1, ref e2) => {
+                self.expr(e1, scope);
+                self.expr(e2, scope);
+            }
+            Expr::IfNode(ref c, ref e1, ref e2) => {
+                self.expr(c, scope);
+                self.expr(e1, scope);
+                self.expr(e2, scope);
+            }
+            Expr::LAsgn(ref l, ref e) => {
+                self.lval(l, scope);
+                self.expr(e, scope);
+            }
+            Expr::Seq(ref es) => {
+                for e in es {
+                    self.expr(e, scope);
+                }
+            }
+            Expr::Let(ref decs, ref body) => {
+                let mut cp_scope = scope.clone();
+                let mut i = 0;
+                while i < decs.len() {
+                    let mut j = i + 1;
+                    while j < decs.len()
+                        && std::mem::discriminant(&decs[j]) == std::mem::discriminant(&decs[i])
+                    {
+                        j += 1;
+                    }
+                    match decs[i] {
+                        Dec::Fun(..) => self.fun_group(&decs[i..j], &mut cp_scope),
+                        Dec::Type(..) => self.type_group(&decs[i..j], &mut cp_scope),
+                        Dec::Var(..) => {
+                            for dec in &decs[i..j] {
+                                if let Dec::Var(ref name, ref opt_ty, ref e, span) = *dec {
+                                    if let Some(ref ty) = *opt_ty {
+                                        self.refer(&cp_scope, ty, Code::UndefinedType);
+                                    }
+                                    self.expr(e, &cp_scope);
+                                    let site = Some(Site::Dec(dec));
+                                    self.declare(&mut cp_scope, name, Kind::Var, span, site);
+                                }
+                            }
+                        }
+                    }
+                    i = j;
+                }
+                self.expr(body, &cp_scope);
+            }
+            Expr::For(ref var, ref st, ref en, ref body) => {
+                self.expr(st, scope);
+                self.expr(en, scope);
+                let mut cp_scope = scope.clone();
+                let site = Some(Site::For(ast));
+                self.declare(&mut cp_scope, var, Kind::LoopIndex, self.span, site);
+                self.expr(body, &cp_scope);
+            }
+            Expr::Do(ref c, ref body) => {
+                self.expr(c, scope);
+                self.expr(body, scope);
+            }
+            Expr::FunApp(ref f, ref es) => {
+                self.refer(scope, f, Code::UndefinedFunction);
+                for e in es {
+                    self.expr(e, scope);
+                }
+            }
+            Expr::NewStruct(ref ty, ref fields) => {
+                self.refer(scope, ty, Code::UndefinedType);
+                for (_, e) in fields {
+                    self.expr(e, scope);
+                }
+            }
+            Expr::NewArray(ref ty, ref n, ref init) => {
+                self.refer(scope, ty, Code::UndefinedType);
+                self.expr(n, scope);
+                self.expr(init, scope);
+            }
+            Expr::Spanned(span, ref e) => {
+                let saved = self.span;
+                self.span = Some(span);
+                self.expr(e, scope);
+                self.span = saved;
+            }
+        }
+    }
+}
+
+/*
+ * Links every name in ast to its declaration, with int, string and the functions
+ * named in host predeclared. Unbound names are reported and left out of the use table.
+ */
+pub fn f(ast: &Expr, host: &[&str]) -> (Resolution, Vec<Diagnostic>) {
+    let mut ctx = Ctx {
+        res: Resolution::default(),
+        diagnostics: Vec::new(),
+        span: None,
+        depth: 0,
+        owner: None,
+    };
+    let mut scope = Scope::default();
+    for ty in ["int", "string"] {
+        ctx.declare(&mut scope, ty, Kind::Type, None, None);
+    }
+    for (k, name) in host.iter().enumerate() {
+        ctx.declare(&mut scope, name, Kind::Fun, None, Some(Site::Host(k)));
+    }
+    ctx.expr(ast, &scope);
+    (ctx.res, ctx.diagnostics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn resolve(src: &str) -> (Resolution, Vec<Diagnostic>) {
+        f(&parse::parse_located(src), &["print"])
+    }
+
+    #[test]
+    fn shadowing_test() {
+        let src = "let var x := 4 in let var x := x + 1 in x end + x end";
+        let (res, diags) = resolve(src);
+        assert!(diags.is_empty());
+        let xs: Vec<BindingId> = (0..res.bindings.len())
+            .filter(|&id| res.bindings[id].name == "x")
+            .collect();
+        assert_eq!(xs.len(), 2);
+        // the initializer of the inner x still sees the outer one
+        let targets: Vec<BindingId> = res.uses.iter().map(|u| u.binding).collect();
+        assert_eq!(targets, vec![xs[0], xs[1], xs[0]]);
+        assert_eq!(res.uses_of(xs[0]).count(), 2);
+        // the last x, at offset 48
+        assert_eq!(res.definition_at(48).map(|b| b.kind), Some(Kind::Var));
+        assert_eq!(res.definition_at(48), Some(&res.bindings[xs[0]]));
+    }
+
+    #[test]
+    fn groups_test() {
+        let src = "let
+  type tree = {l: forest} type forest = array of tree
+  function even(n: int): int = if n = 0 then 1 else odd(n - 1)
+  function odd(n: int): int = if n = 0 then 0 else even(n - 1)
+  var k := 0
+in
+  for i := 1 to 2 do k := k + even(i); print(k)
+end";
+        let (res, diags) = resolve(src);
+        assert!(diags.is_empty(), "{:?}", diags);
+        let kinds = |name: &str| -> Vec<(Kind, usize)> {
+            res.bindings
+                .iter()
+                .filter(|b| b.name == name)
+                .map(|b| (b.kind, b.depth))
+                .collect()
+        };
+        assert_eq!(kinds("n"), vec![(Kind::Param, 1), (Kind::Param, 1)]);
+        assert_eq!(kinds("i"), vec![(Kind::LoopIndex, 0)]);
+        let odd = res.bindings.iter().position(|b| b.name == "odd").unwrap();
+        let call = res.uses_of(odd).next().unwrap();
+        assert_eq!(call.depth, 1);
+    }
+
+    #[test]
+    fn suggestion_test() {
+        let (_, diags) = resolve("let var count := 0 function fib(n: int): int = n in cuont + fibb(1) + prnt(1) + zzz end");
+        let notes: Vec<(&str, Option<&String>)> = diags
+            .iter()
+            .map(|d| (d.message.as_str(), d.notes.first()))
+            .collect();
+        assert_eq!(
+            notes[0],
+            (
+                "undefined variable cuont",
+                Some(&"did you mean `count`?".to_string())
+            )
+        );
+        assert_eq!(
+            notes[1],
+            (
+                "undefined function fibb",
+                Some(&"did you mean `fib`?".to_string())
+            )
+        );
+        assert_eq!(
+            notes[2],
+            (
+                "undefined function prnt",
+                Some(&"did you mean `print`?".to_string())
+            )
+        );
+        assert_eq!(notes[3], ("undefined variable zzz", None));
+        let (_, diags) = resolve("let var a: strin := \"\" in a end");
+        assert_eq!(diags[0].code, Code::UndefinedType);
+        assert_eq!(diags[0].notes, vec!["did you mean `string`?".to_string()]);
+        assert_eq!(suggest("x", ["y", "xs"]), Some("xs"));
+    }
+}
@@ -0,0 +1,168 @@
+/*
+ * The runtime of compiled Tiger programs: allocation, string operations, the failures of
+ * checks and the standard library. Strings are a 64-bit length followed by the bytes, and
+ * arrays point just past their length, as in the IR interpreter. Tiger functions are
+ * prefixed with tiger_ so as not to clash with libc.
+ */
+#include <inttypes.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+struct string {
+    int64_t length;
+    unsigned char chars[];
+};
+
+static void fail(const char *message) {
+    fflush(stdout);
+    fprintf(stderr, "%s\n", message);
+    exit(1);
+}
+
+static void *alloc(int64_t size) {
+    void *p = calloc(size > 0 ? size : 1, 1);
+    if (p == NULL) {
+        fail("out of memory");
+    }
+    return p;
+}
+
+static struct string *new_string(const unsigned char *chars, int64_t length) {
+    struct string *s = alloc(sizeof(struct string) + length);
+    s->length = length;
+    memcpy(s->chars, chars, length);
+    return s;
+}
+
+int64_t *tiger_allocRecord(int64_t size) {
+    return alloc(size);
+}
+
+int64_t *tiger_initArray(int64_t n, int64_t init) {
+    if (n < 0) {
+        fail("array of negative size");
+    }
+    int64_t *a = alloc((n + 1) * sizeof(int64_t));
+    *a++ = n;
+    for (int64_t i = 0; i < n; i++) {
+        a[i] = init;
+    }
+    return a;
+}
+
+/* Where compiled code goes when a subscript, field access or division is invalid. */
+void tiger_failIndex(int64_t i, int64_t length) {
+    fflush(stdout);
+    fprintf(stderr, "index %" PRId64 " out of bounds (length %" PRId64 ")\n", i, length);
+    exit(1);
+}
+
+void tiger_failNil(void) {
+    fail("nil record dereference");
+}
+
+void tiger_failDivision(void) {
+    fail("division by zero");
+}
+
+/* The address of a[i], for the C backend, once i is checked to be in bounds. */
+void *tiger_checkIndex(void *a, int64_t i) {
+    int64_t *words = a;
+    if ((uint64_t)i >= (uint64_t)words[-1]) {
+        tiger_failIndex(i, words[-1]);
+    }
+    return words + i;
+}
+
+/* r, for the C backend, once it is checked not to be nil. */
+void *tiger_checkNil(void *r) {
+    if (r == NULL) {
+        tiger_failNil();
+    }
+    return r;
+}
+
+int64_t tiger_stringEqual(struct string *a, struct string *b) {
+    return a->length == b->length && memcmp(a->chars, b->chars, a->length) == 0;
+}
+
+int64_t tiger_stringCompare(struct string *a, struct string *b) {
+    int64_t n = a->length < b->length ? a->length : b->length;
+    int c = memcmp(a->chars, b->chars, n);
+    if (c != 0) {
+        return c < 0 ? -1 : 1;
+    }
+    return (a->length > b->length) - (a->length < b->length);
+}
+
+void tiger_print(struct string *s) {
+    fwrite(s->chars, 1, s->length, stdout);
+}
+
+void tiger_printi(int64_t i) {
+    printf("%" PRId64, i);
+}
+
+void tiger_flush(void) {
+    fflush(stdout);
+}
+
+struct string *tiger_getchar(void) {
+    int c = getchar();
+    unsigned char ch = c;
+    return new_string(&ch, c == EOF ? 0 : 1);
+}
+
+int64_t tiger_ord(struct string *s) {
+    return s->length == 0 ? -1 : s->chars[0];
+}
+
+struct string *tiger_chr(int64_t i) {
+    if (i < 0 || i > 255) {
+        fail("chr: out of range");
+    }
+    unsigned char ch = i;
+    return new_string(&ch, 1);
+}
+
+int64_t tiger_size(struct string *s) {
+    return s->length;
+}
+
+struct string *tiger_substring(struct string *s, int64_t first, int64_t n) {
+    if (first < 0 || first > s->length || n < 0 || n > s->length - first) {
+        fail("substring: out of range");
+    }
+    return new_string(s->chars + first, n);
+}
+
+struct string *tiger_concat(struct string *a, struct string *b) {
+    struct string *s = alloc(sizeof(struct string) + a->length + b->length);
+    s->length = a->length + b->length;
+    memcpy(s->chars, a->chars, a->length);
+    memcpy(s->chars + a->length, b->chars, b->length);
+    return s;
+}
+
+/* a / b, wrapping as the interpreter does: INT64_MIN / -1 is INT64_MIN. */
+int64_t tiger_divide(int64_t a, int64_t b) {
+    if (b == 0) {
+        tiger_failDivision();
+    }
+    return b == -1 ? (int64_t)(0 - (uint64_t)a) : a / b;
+}
+
+int64_t tiger_not(int64_t i) {
+    return i == 0;
+}
+
+int64_t tiger_tigermain(void);
+
+/* The exit status is the result of the program, which is 0 unless it is an int. */
+int main(void) {
+    int64_t result = tiger_tigermain();
+    fflush(stdout);
+    return (int)(result & 0xff);
+}
@@ -0,0 +1,429 @@
+  ;; The runtime of Tiger programs compiled to WebAssembly: allocation, string operations
+  ;; and the standard library, on top of three host functions. Every value is an i64, and
+  ;; records, arrays and strings are addresses in memory, strings being a 64-bit length
+  ;; followed by the bytes, as in runtime.c. Addresses 8 to 40 are a buffer for printi and
+  ;; the messages below end before 256, where the strings of the program begin.
+  (data (i32.const 40) "\0d\00\00\00\00\00\00\00out of memory")
+  (data (i32.const 64) "\0e\00\00\00\00\00\00\00stack overflow")
+  (data (i32.const 88) "\11\00\00\00\00\00\00\00chr: out of range")
+  (data (i32.const 120) "\17\00\00\00\00\00\00\00substring: out of range")
+  (data (i32.const 152) "\16\00\00\00\00\00\00\00array of negative size")
+  (data (i32.const 184) "\01\00\00\00\00\00\00\00\0a")
+
+  ;; Prints the message at address s and exits with status 1.
+  (func $fail (param $s i64)
+    call $tiger_flush
+    local.get $s
+    call $tiger_print
+    i64.const 184
+    call $tiger_print
+    i32.const 1
+    call $host_exit
+    unreachable)
+
+  ;; Takes size bytes, rounded up to words, from the heap, which grows as needed.
+  (func $alloc (param $size i64) (result i64)
+    (local $p i64)
+    global.get $hp
+    local.set $p
+    global.get $hp
+    local.get $size
+    i64.const 7
+    i64.add
+    i64.const -8
+    i64.and
+    i64.add
+    global.set $hp
+    block $done
+      loop $grow
+        global.get $hp
+        memory.size
+        i64.extend_i32_u
+        i64.const 16
+        i64.shl
+        i64.le_u
+        br_if $done
+        i32.const 1
+        memory.grow
+        i32.const -1
+        i32.eq
+        if
+          i64.const 40
+          call $fail
+        end
+        br $grow
+      end
+    end
+    local.get $p)
+
+  ;; Pushes an environment of size bytes on the stack; functions pop theirs by restoring $sp.
+  (func $enter (param $size i64) (result i64)
+    (local $p i64)
+    global.get $sp
+    local.set $p
+    global.get $sp
+    local.get $size
+    i64.add
+    global.set $sp
+    global.get $sp
+    global.get $stack_end
+    i64.gt_u
+    if
+      i64.const 64
+      call $fail
+    end
+    local.get $p)
+
+  (func $new_string (param $n i64) (result i64)
+    (local $s i64)
+    local.get $n
+    i64.const 8
+    i64.add
+    call $alloc
+    local.tee $s
+    i32.wrap_i64
+    local.get $n
+    i64.store
+    local.get $s)
+
+  ;; Copies n bytes from src to dst.
+  (func $copy (param $dst i64) (param $src i64) (param $n i64)
+    block $done
+      loop $next
+        local.get $n
+        i64.eqz
+        br_if $done
+        local.get $dst
+        i32.wrap_i64
+        local.get $src
+        i32.wrap_i64
+        i32.load8_u
+        i32.store8
+        local.get $dst
+        i64.const 1
+        i64.add
+        local.set $dst
+        local.get $src
+        i64.const 1
+        i64.add
+        local.set $src
+        local.get $n
+        i64.const 1
+        i64.sub
+        local.set $n
+        br $next
+      end
+    end)
+
+  (func $tiger_allocRecord (param $size i64) (result i64)
+    local.get $size
+    call $alloc)
+
+  (func $tiger_initArray (param $n i64) (param $init i64) (result i64)
+    (local $a i64)
+    (local $i i64)
+    local.get $n
+    i64.const 0
+    i64.lt_s
+    if
+      i64.const 152
+      call $fail
+    end
+    local.get $n
+    i64.const 3
+    i64.shl
+    call $alloc
+    local.set $a
+    block $done
+      loop $next
+        local.get $i
+        local.get $n
+        i64.ge_s
+        br_if $done
+        local.get $a
+        local.get $i
+        i64.const 3
+        i64.shl
+        i64.add
+        i32.wrap_i64
+        local.get $init
+        i64.store
+        local.get $i
+        i64.const 1
+        i64.add
+        local.set $i
+        br $next
+      end
+    end
+    local.get $a)
+
+  ;; Negative, zero or positive as a is before, equal to or after b.
+  (func $tiger_stringCompare (param $a i64) (param $b i64) (result i64)
+    (local $la i64)
+    (local $lb i64)
+    (local $i i64)
+    (local $ca i64)
+    (local $cb i64)
+    local.get $a
+    i32.wrap_i64
+    i64.load
+    local.set $la
+    local.get $b
+    i32.wrap_i64
+    i64.load
+    local.set $lb
+    block $done
+      loop $next
+        local.get $i
+        local.get $la
+        i64.ge_s
+        br_if $done
+        local.get $i
+        local.get $lb
+        i64.ge_s
+        br_if $done
+        local.get $a
+        local.get $i
+        i64.add
+        i32.wrap_i64
+        i64.load8_u offset=8
+        local.set $ca
+        local.get $b
+        local.get $i
+        i64.add
+        i32.wrap_i64
+        i64.load8_u offset=8
+        local.set $cb
+        local.get $ca
+        local.get $cb
+        i64.ne
+        if
+          local.get $ca
+          local.get $cb
+          i64.lt_u
+          if
+            i64.const -1
+            return
+          end
+          i64.const 1
+          return
+        end
+        local.get $i
+        i64.const 1
+        i64.add
+        local.set $i
+        br $next
+      end
+    end
+    local.get $la
+    local.get $lb
+    i64.gt_s
+    i64.extend_i32_u
+    local.get $la
+    local.get $lb
+    i64.lt_s
+    i64.extend_i32_u
+    i64.sub)
+
+  (func $tiger_stringEqual (param $a i64) (param $b i64) (result i64)
+    local.get $a
+    local.get $b
+    call $tiger_stringCompare
+    i64.eqz
+    i64.extend_i32_u)
+
+  (func $tiger_print (param $s i64)
+    local.get $s
+    i32.wrap_i64
+    i32.const 8
+    i32.add
+    local.get $s
+    i32.wrap_i64
+    i64.load
+    i32.wrap_i64
+    call $host_print)
+
+  ;; Writes the digits backwards from the end of the buffer, negating non-negative numbers
+  ;; rather than negative ones so that the smallest int works too.
+  (func $tiger_printi (param $i i64)
+    (local $n i64)
+    (local $p i32)
+    i32.const 40
+    local.set $p
+    local.get $i
+    i64.const 0
+    local.get $i
+    i64.sub
+    local.get $i
+    i64.const 0
+    i64.lt_s
+    select
+    local.set $n
+    loop $digit
+      local.get $p
+      i32.const 1
+      i32.sub
+      local.tee $p
+      i64.const 48
+      local.get $n
+      i64.const 10
+      i64.rem_s
+      i64.sub
+      i64.store8
+      local.get $n
+      i64.const 10
+      i64.div_s
+      local.tee $n
+      i64.const 0
+      i64.ne
+      br_if $digit
+    end
+    local.get $i
+    i64.const 0
+    i64.lt_s
+    if
+      local.get $p
+      i32.const 1
+      i32.sub
+      local.tee $p
+      i32.const 45
+      i32.store8
+    end
+    local.get $p
+    i32.const 40
+    local.get $p
+    i32.sub
+    call $host_print)
+
+  ;; The host writes what it is given at once, so there is nothing to flush.
+  (func $tiger_flush)
+
+  (func $tiger_getchar (result i64)
+    (local $c i32)
+    (local $s i64)
+    call $host_getchar
+    local.tee $c
+    i32.const 0
+    i32.lt_s
+    if
+      i64.const 0
+      call $new_string
+      return
+    end
+    i64.const 1
+    call $new_string
+    local.tee $s
+    i32.wrap_i64
+    local.get $c
+    i32.store8 offset=8
+    local.get $s)
+
+  (func $tiger_ord (param $s i64) (result i64)
+    local.get $s
+    i32.wrap_i64
+    i64.load
+    i64.eqz
+    if
+      i64.const -1
+      return
+    end
+    local.get $s
+    i32.wrap_i64
+    i64.load8_u offset=8)
+
+  (func $tiger_chr (param $i i64) (result i64)
+    (local $s i64)
+    local.get $i
+    i64.const 255
+    i64.gt_u
+    if
+      i64.const 88
+      call $fail
+    end
+    i64.const 1
+    call $new_string
+    local.tee $s
+    i32.wrap_i64
+    local.get $i
+    i64.store8 offset=8
+    local.get $s)
+
+  (func $tiger_size (param $s i64) (result i64)
+    local.get $s
+    i32.wrap_i64
+    i64.load)
+
+  (func $tiger_substring (param $s i64) (param $first i64) (param $n i64) (result i64)
+    (local $t i64)
+    local.get $first
+    i64.const 0
+    i64.lt_s
+    local.get $n
+    i64.const 0
+    i64.lt_s
+    i32.or
+    local.get $first
+    local.get $n
+    i64.add
+    local.get $s
+    i32.wrap_i64
+    i64.load
+    i64.gt_s
+    i32.or
+    if
+      i64.const 120
+      call $fail
+    end
+    local.get $n
+    call $new_string
+    local.tee $t
+    i64.const 8
+    i64.add
+    local.get $s
+    i64.const 8
+    i64.add
+    local.get $first
+    i64.add
+    local.get $n
+    call $copy
+    local.get $t)
+
+  (func $tiger_concat (param $a i64) (param $b i64) (result i64)
+    (local $la i64)
+    (local $s i64)
+    local.get $a
+    i32.wrap_i64
+    i64.load
+    local.set $la
+    local.get $la
+    local.get $b
+    i32.wrap_i64
+    i64.load
+    i64.add
+    call $new_string
+    local.tee $s
+    i64.const 8
+    i64.add
+    local.get $a
+    i64.const 8
+    i64.add
+    local.get $la
+    call $copy
+    local.get $s
+    i64.const 8
+    i64.add
+    local.get $la
+    i64.add
+    local.get $b
+    i64.const 8
+    i64.add
+    local.get $b
+    i32.wrap_i64
+    i64.load
+    call $copy
+    local.get $s)
+
+  (func $tiger_not (param $i i64) (result i64)
+    local.get $i
+    i64.eqz
+    i64.extend_i32_u)
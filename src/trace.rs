@@ -0,0 +1,267 @@
+use crate::ast::{Expr, LineIndex, Span, Value};
+use crate::interpret::Scope;
+use std::io::Write;
+
+/*
+ * Hooks called by the interpreter while it evaluates a program.
+ * Every method has an empty default, so an implementation only overrides what it needs.
+ * Expression events are emitted for located nodes (Expr::Spanned) only.
+ */
+pub trait Tracer {
+    fn enter_expr(&mut self, _span: Span, _expr: &Expr, _scope: &Scope) {}
+    /* result is None if evaluation was cut short by break or a runtime error */
+    fn exit_expr(&mut self, _span: Span, _result: Option<&Value>) {}
+    fn call(&mut self, _name: &str, _args: &[Value]) {}
+    fn ret(&mut self, _name: &str, _result: Option<&Value>) {}
+    /* both initialisation (var, parameters, for index) and assignment */
+    fn write_var(&mut self, _name: &str, _val: &Value) {}
+    /* called before each iteration of the innermost for/while loop, counting from 0 */
+    fn loop_iter(&mut self, _iteration: usize) {}
+    /* once true, the interpreter stops with an error at the next expression */
+    fn aborted(&self) -> bool {
+        false
+    }
+}
+
+pub struct NoTracer;
+
+impl Tracer for NoTracer {}
+
+const SNIPPET_LEN: usize = 40;
+
+fn snippet(src: &str, span: Span) -> String {
+    let text = src.get(span.lo..span.hi).unwrap_or("");
+    let line = text.lines().next().unwrap_or("");
+    let mut short: String = line.chars().take(SNIPPET_LEN).collect();
+    if short.len() < text.len() {
+        short.push_str(" ...");
+    }
+    short
+}
+
+fn show_args(args: &[Value]) -> String {
+    args.iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/*
+ * Human-readable trace, one event per line, indented by call depth:
+ *   3:5 fib (x - 1)
+ *   call fib(4)
+ *     ...
+ *   return fib = 3
+ */
+pub struct LineTracer<'a, W: Write> {
+    out: W,
+    src: &'a str,
+    lines: LineIndex,
+    depth: usize,
+}
+
+impl<'a, W: Write> LineTracer<'a, W> {
+    pub fn new(out: W, src: &'a str) -> Self {
+        LineTracer {
+            out,
+            src,
+            lines: LineIndex::new(src),
+            depth: 0,
+        }
+    }
+    fn emit(&mut self, msg: &str) {
+        let _ = writeln!(self.out, "{:width$}{}", "", msg, width = 2 * self.depth);
+    }
+}
+
+impl<'a, W: Write> Tracer for LineTracer<'a, W> {
+    fn enter_expr(&mut self, span: Span, _expr: &Expr, _scope: &Scope) {
+        let (line, col) = self.lines.line_col(span.lo);
+        let msg = format!("{}:{} {}", line, col, snippet(self.src, span));
+        self.emit(&msg);
+    }
+    fn exit_expr(&mut self, span: Span, result: Option<&Value>) {
+        let (line, col) = self.lines.line_col(span.lo);
+        let msg = match result {
+            Some(Value::VUnit) => return,
+            Some(v) => format!("{}:{} => {}", line, col, v),
+            None => format!("{}:{} => (unwound)", line, col),
+        };
+        self.emit(&msg);
+    }
+    fn call(&mut self, name: &str, args: &[Value]) {
+        self.emit(&format!("call {}({})", name, show_args(args)));
+        self.depth += 1;
+    }
+    fn ret(&mut self, name: &str, result: Option<&Value>) {
+        self.depth = self.depth.saturating_sub(1);
+        let msg = match result {
+            Some(Value::VUnit) => format!("return {}", name),
+            Some(v) => format!("return {} = {}", name, v),
+            None => format!("return {} (unwound)", name),
+        };
+        self.emit(&msg);
+    }
+    fn write_var(&mut self, name: &str, val: &Value) {
+        self.emit(&format!("{} := {}", name, val));
+    }
+    fn loop_iter(&mut self, iteration: usize) {
+        self.emit(&format!("iteration {}", iteration));
+    }
+}
+
+pub fn json_str(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len() + 2);
+    ret.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => ret.push_str("\\\""),
+            '\\' => ret.push_str("\\\\"),
+            '\n' => ret.push_str("\\n"),
+            '\r' => ret.push_str("\\r"),
+            '\t' => ret.push_str("\\t"),
+            c if (c as u32) < 0x20 => ret.push_str(&format!("\\u{:04x}", c as u32)),
+            c => ret.push(c),
+        }
+    }
+    ret.push('"');
+    ret
+}
+
+fn json_value(val: &Value) -> String {
+    match *val {
+        Value::VNum(i) => i.to_string(),
+        Value::VStr(ref s) => json_str(s),
+        Value::VNil | Value::VUnit => "null".to_string(),
+        Value::VRecord(_) | Value::VArray(_) => json_str(&val.to_string()),
+    }
+}
+
+fn json_result(result: Option<&Value>) -> String {
+    match result {
+        Some(v) => json_value(v),
+        None => "null".to_string(),
+    }
+}
+
+/*
+ * Machine-readable trace: one JSON object per line, each with an "event" field
+ * ("enter", "exit", "call", "return", "write" or "loop") and the call depth.
+ */
+pub struct JsonTracer<W: Write> {
+    out: W,
+    lines: LineIndex,
+    depth: usize,
+}
+
+impl<W: Write> JsonTracer<W> {
+    pub fn new(out: W, src: &str) -> Self {
+        JsonTracer {
+            out,
+            lines: LineIndex::new(src),
+            depth: 0,
+        }
+    }
+    fn span_fields(&self, span: Span) -> String {
+        let (line, col) = self.lines.line_col(span.lo);
+        format!(
+            "\"line\":{},\"col\":{},\"lo\":{},\"hi\":{}",
+            line, col, span.lo, span.hi
+        )
+    }
+    fn emit(&mut self, event: &str, fields: &str) {
+        let _ = writeln!(
+            self.out,
+            "{{\"event\":\"{}\",\"depth\":{},{}}}",
+            event, self.depth, fields
+        );
+    }
+}
+
+impl<W: Write> Tracer for JsonTracer<W> {
+    fn enter_expr(&mut self, span: Span, _expr: &Expr, _scope: &Scope) {
+        let fields = self.span_fields(span);
+        self.emit("enter", &fields);
+    }
+    fn exit_expr(&mut self, span: Span, result: Option<&Value>) {
+        let fields = format!(
+            "{},\"unwound\":{},\"value\":{}",
+            self.span_fields(span),
+            result.is_none(),
+            json_result(result)
+        );
+        self.emit("exit", &fields);
+    }
+    fn call(&mut self, name: &str, args: &[Value]) {
+        let args = args.iter().map(json_value).collect::<Vec<_>>().join(",");
+        self.emit(
+            "call",
+            &format!("\"name\":{},\"args\":[{}]", json_str(name), args),
+        );
+        self.depth += 1;
+    }
+    fn ret(&mut self, name: &str, result: Option<&Value>) {
+        self.depth = self.depth.saturating_sub(1);
+        let fields = format!(
+            "\"name\":{},\"unwound\":{},\"value\":{}",
+            json_str(name),
+            result.is_none(),
+            json_result(result)
+        );
+        self.emit("return", &fields);
+    }
+    fn write_var(&mut self, name: &str, val: &Value) {
+        let fields = format!("\"name\":{},\"value\":{}", json_str(name), json_value(val));
+        self.emit("write", &fields);
+    }
+    fn loop_iter(&mut self, iteration: usize) {
+        self.emit("loop", &format!("\"iteration\":{}", iteration));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{interpret, parse};
+    fn trace_lines(src: &str) -> Vec<String> {
+        let ast = parse::parse_located(src);
+        let mut out = Vec::new();
+        interpret::f(&ast, &mut LineTracer::new(&mut out, src));
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|l| l.to_string())
+            .collect()
+    }
+    #[test]
+    fn line_tracer_test() {
+        let lines = trace_lines("let function f(a: int) = a + 1 in\nf(2) end");
+        assert_eq!(lines[0], "1:1 let function f(a: int) = a + 1 in ...");
+        assert!(lines.contains(&"2:1 f(2)".to_string()));
+        assert!(lines.contains(&"call f(2)".to_string()));
+        assert!(lines.contains(&"  a := 2".to_string()));
+        assert!(lines.contains(&"  1:26 a + 1".to_string()));
+        assert!(lines.contains(&"return f = 3".to_string()));
+        assert_eq!(lines.last().unwrap(), "1:1 => 3");
+    }
+    #[test]
+    fn loop_trace_test() {
+        let lines = trace_lines("for i := 1 to 2 do i");
+        assert!(lines.contains(&"iteration 0".to_string()));
+        assert!(lines.contains(&"i := 2".to_string()));
+        assert!(!lines.contains(&"iteration 2".to_string()));
+    }
+    #[test]
+    fn json_tracer_test() {
+        let src = "let var s := \"hi\" in s end";
+        let ast = parse::parse_located(src);
+        let mut out = Vec::new();
+        interpret::f(&ast, &mut JsonTracer::new(&mut out, src));
+        let out = String::from_utf8(out).unwrap();
+        assert!(out
+            .lines()
+            .all(|l| l.starts_with("{\"event\":") && l.ends_with('}')));
+        assert!(out.contains("{\"event\":\"write\",\"depth\":0,\"name\":\"s\",\"value\":\"hi\"}"));
+        assert_eq!(json_str("a\"\n"), "\"a\\\"\\n\"");
+    }
+}
@@ -0,0 +1,722 @@
+use crate::ast::{ty_of_ast, Op, Ty, TyDef, TypeTable, TypedDec, TypedExpr};
+use crate::escape::Escapes;
+use crate::frame::{Access, Fragment, Frame};
+use crate::library;
+use crate::resolve::BindingId;
+use crate::tree::{binop, jump, mem, mov, seq, BinOp, Exp, Gen, Label, Printer, RelOp, Stm};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+
+/*
+ * Lowering of typed trees to the Tree IR, one fragment per function plus one per string
+ * literal. The program itself becomes the function tigermain.
+ * Records and arrays are pointers to words allocated by the runtime (allocRecord and
+ * initArray), the length of an array in the word before it; strings are compared by the
+ * runtime as well (stringEqual and stringCompare). Field accesses, subscripts and divisions
+ * are checked, and call a runtime function that exits when they are invalid.
+ */
+
+pub const MAIN: &str = "tigermain";
+/* Functions of the runtime that compiled code calls by itself, by any backend. */
+pub const RUNTIME: &[&str] = &[
+    "allocRecord",
+    "initArray",
+    "stringEqual",
+    "stringCompare",
+    "divide",
+    "failIndex",
+    "failNil",
+    "failDivision",
+    "checkIndex",
+    "checkNil",
+];
+
+type Cond = Box<dyn FnOnce(Label, Label) -> Stm>;
+
+/* A translated expression: one with a value, one without, or a condition to jump on. */
+enum Tr {
+    Ex(Exp),
+    Nx(Stm),
+    Cx(Cond),
+}
+
+/* A function and the one it is nested in; its frame is set once its formals are known. */
+struct Level<F> {
+    frame: Option<F>,
+    parent: Option<usize>,
+}
+
+#[derive(Clone)]
+enum Entry {
+    Var(usize, Access), // level, access
+    Fun(usize, Label),  // level of the function itself
+}
+
+type Env = HashMap<BindingId, Entry>;
+
+struct Ctx<'a, F> {
+    types: &'a TypeTable,
+    escapes: &'a Escapes,
+    levels: Vec<Level<F>>,
+    labels: HashSet<String>, // function labels taken
+    breaks: Vec<Label>,      // where break jumps in each enclosing loop
+    fragments: Vec<Fragment<F>>,
+    gen: &'a mut Gen,
+}
+
+fn int(i: i64) -> Exp {
+    Exp::Const(i)
+}
+
+impl<F: Frame> Ctx<'_, F> {
+    fn frame(&self, level: usize) -> &F {
+        self.levels[level].frame.as_ref().unwrap()
+    }
+
+    fn alloc_local(&mut self, level: usize, escape: bool) -> Access {
+        let frame = self.levels[level].frame.as_mut().unwrap();
+        frame.alloc_local(escape, self.gen)
+    }
+
+    fn fun_label(&mut self, name: &str) -> Label {
+        let taken = |l: &str, labels: &HashSet<String>| {
+            labels.contains(l) || (l.starts_with('L') && l[1..].bytes().all(|b| b.is_ascii_digit()))
+        };
+        let mut label = name.to_string();
+        let mut k = 1;
+        while taken(&label, &self.labels) {
+            label = format!("{}_{}", name, k);
+            k += 1;
+        }
+        self.labels.insert(label.clone());
+        Label(label)
+    }
+
+    /* The frame pointer of level to, as seen from level from, which it encloses. */
+    fn static_link(&mut self, mut from: usize, to: usize) -> Exp {
+        let mut fp = Exp::Temp(F::fp());
+        while from != to {
+            let link = self.frame(from).formals()[0];
+            fp = F::exp(link, fp);
+            from = self.levels[from]
+                .parent
+                .expect("static link above the main program");
+        }
+        fp
+    }
+
+    fn un_ex(&mut self, tr: Tr) -> Exp {
+        match tr {
+            Tr::Ex(e) => e,
+            Tr::Nx(s) => Exp::Eseq(Box::new(s), Box::new(int(0))),
+            Tr::Cx(cond) => {
+                let r = Exp::Temp(self.gen.temp());
+                let (t, f) = (self.gen.label(), self.gen.label());
+                let stm = seq(vec![
+                    mov(r.clone(), int(1)),
+                    cond(t.clone(), f.clone()),
+                    Stm::Label(f),
+                    mov(r.clone(), int(0)),
+                    Stm::Label(t),
+                ]);
+                Exp::Eseq(Box::new(stm), Box::new(r))
+            }
+        }
+    }
+
+    fn un_nx(&mut self, tr: Tr) -> Stm {
+        match tr {
+            Tr::Ex(e) => Stm::Exp(Box::new(e)),
+            Tr::Nx(s) => s,
+            Tr::Cx(cond) => {
+                let l = self.gen.label();
+                seq(vec![cond(l.clone(), l.clone()), Stm::Label(l)])
+            }
+        }
+    }
+
+    fn un_cx(&mut self, tr: Tr) -> Cond {
+        match tr {
+            Tr::Ex(Exp::Const(0)) => Box::new(|_, f| jump(f)),
+            Tr::Ex(Exp::Const(_)) => Box::new(|t, _| jump(t)),
+            Tr::Ex(e) => {
+                Box::new(|t, f| Stm::Cjump(RelOp::Ne, Box::new(e), Box::new(int(0)), t, f))
+            }
+            Tr::Nx(_) => panic!("internal error: a statement used as a condition"),
+            Tr::Cx(cond) => cond,
+        }
+    }
+
+    fn if_tr(&mut self, c: Tr, ty: Ty, e1: Tr, e2: Tr) -> Tr {
+        let cond = self.un_cx(c);
+        let (t, f, join) = (self.gen.label(), self.gen.label(), self.gen.label());
+        if ty == Ty::Unit {
+            let (s1, s2) = (self.un_nx(e1), self.un_nx(e2));
+            return Tr::Nx(seq(vec![
+                cond(t.clone(), f.clone()),
+                Stm::Label(t),
+                s1,
+                jump(join.clone()),
+                Stm::Label(f),
+                s2,
+                Stm::Label(join),
+            ]));
+        }
+        let r = Exp::Temp(self.gen.temp());
+        let (v1, v2) = (self.un_ex(e1), self.un_ex(e2));
+        let stm = seq(vec![
+            cond(t.clone(), f.clone()),
+            Stm::Label(t),
+            mov(r.clone(), v1),
+            jump(join.clone()),
+            Stm::Label(f),
+            mov(r.clone(), v2),
+            Stm::Label(join),
+        ]);
+        Tr::Ex(Exp::Eseq(Box::new(stm), Box::new(r)))
+    }
+
+    /* Calls fail, which does not return, unless a op b. */
+    fn guard(&mut self, op: RelOp, a: Exp, b: Exp, fail: Exp) -> Stm {
+        let (ok, bad) = (self.gen.label(), self.gen.label());
+        seq(vec![
+            Stm::Cjump(op, Box::new(a), Box::new(b), ok.clone(), bad.clone()),
+            Stm::Label(bad),
+            Stm::Exp(Box::new(fail)),
+            Stm::Label(ok),
+        ])
+    }
+
+    /*
+     * e1 / e2, failing if e2 is 0. x86 faults on the smallest int divided by -1, so that
+     * division is a negation, which wraps as the interpreter does.
+     */
+    fn divide(&mut self, e1: Exp, e2: Exp) -> Exp {
+        if let Exp::Const(d) = e2 {
+            if d != 0 && d != -1 {
+                return binop(BinOp::Div, e1, e2);
+            }
+        }
+        let (a, b, r) = (
+            Exp::Temp(self.gen.temp()),
+            Exp::Temp(self.gen.temp()),
+            Exp::Temp(self.gen.temp()),
+        );
+        let check = self.guard(
+            RelOp::Ne,
+            b.clone(),
+            int(0),
+            F::external_call("failDivision", vec![]),
+        );
+        let (neg, div, done) = (self.gen.label(), self.gen.label(), self.gen.label());
+        let stm = seq(vec![
+            mov(a.clone(), e1),
+            mov(b.clone(), e2),
+            check,
+            Stm::Cjump(
+                RelOp::Eq,
+                Box::new(b.clone()),
+                Box::new(int(-1)),
+                neg.clone(),
+                div.clone(),
+            ),
+            Stm::Label(neg),
+            mov(r.clone(), binop(BinOp::Minus, int(0), a.clone())),
+            jump(done.clone()),
+            Stm::Label(div),
+            mov(r.clone(), binop(BinOp::Div, a, b)),
+            Stm::Label(done),
+        ]);
+        Exp::Eseq(Box::new(stm), Box::new(r))
+    }
+
+    fn field_offset(&self, record: Ty, name: &str) -> i64 {
+        match record {
+            Ty::Record(i) => match self.types[i] {
+                TyDef::Record(_, ref fields) => {
+                    let k = fields.iter().position(|f| f.0 == name).unwrap();
+                    k as i64 * F::WORD_SIZE
+                }
+                TyDef::Array(..) => unreachable!(),
+            },
+            _ => panic!("internal error: field of a non-record"),
+        }
+    }
+
+    fn compare(&mut self, op: Op, ty: Ty, e1: Exp, e2: Exp) -> Tr {
+        let relop = match op {
+            Op::Eq => RelOp::Eq,
+            Op::Ne => RelOp::Ne,
+            Op::Lt => RelOp::Lt,
+            Op::Gt => RelOp::Gt,
+            Op::Le => RelOp::Le,
+            Op::Ge => RelOp::Ge,
+            _ => unreachable!(),
+        };
+        let (relop, e1, e2) = match (ty, relop) {
+            // stringEqual is 1 for equal strings, stringCompare negative, zero or positive
+            (Ty::Str, RelOp::Eq) => (
+                RelOp::Ne,
+                F::external_call("stringEqual", vec![e1, e2]),
+                int(0),
+            ),
+            (Ty::Str, RelOp::Ne) => (
+                RelOp::Eq,
+                F::external_call("stringEqual", vec![e1, e2]),
+                int(0),
+            ),
+            (Ty::Str, _) => (
+                relop,
+                F::external_call("stringCompare", vec![e1, e2]),
+                int(0),
+            ),
+            _ => (relop, e1, e2),
+        };
+        Tr::Cx(Box::new(move |t, f| {
+            Stm::Cjump(relop, Box::new(e1), Box::new(e2), t, f)
+        }))
+    }
+
+    fn expr(&mut self, tast: &TypedExpr, env: &Env, level: usize) -> Tr {
+        match *tast {
+            TypedExpr::Num(i) => Tr::Ex(int(i)),
+            TypedExpr::Str(ref s) => {
+                let label = self.gen.label();
+                self.fragments.push(Fragment::Str(label.clone(), s.clone()));
+                Tr::Ex(Exp::Name(label))
+            }
+            TypedExpr::Nil => Tr::Ex(int(0)),
+            TypedExpr::Var(ref name, id, _) => match env.get(&id) {
+                Some(&Entry::Var(var_level, access)) => {
+                    let fp = self.static_link(level, var_level);
+                    Tr::Ex(F::exp(access, fp))
+                }
+                _ => panic!("internal error: unbound variable {}", name),
+            },
+            TypedExpr::Field(ref r, ref name, _) => {
+                let offset = self.field_offset(ty_of_ast(r), name);
+                let r = self.expr(r, env, level);
+                let r = self.un_ex(r);
+                let t = Exp::Temp(self.gen.temp());
+                let fail = F::external_call("failNil", vec![]);
+                let check = self.guard(RelOp::Ne, t.clone(), int(0), fail);
+                let at = mem(binop(BinOp::Plus, t.clone(), int(offset)));
+                Tr::Ex(Exp::Eseq(
+                    Box::new(seq(vec![mov(t, r), check])),
+                    Box::new(at),
+                ))
+            }
+            TypedExpr::Index(ref a, ref i, _) => {
+                let a = self.expr(a, env, level);
+                let a = self.un_ex(a);
+                let i = self.expr(i, env, level);
+                let i = self.un_ex(i);
+                let (ta, ti) = (Exp::Temp(self.gen.temp()), Exp::Temp(self.gen.temp()));
+                // unsigned, so that a negative index is out of bounds too
+                let length = mem(binop(BinOp::Minus, ta.clone(), int(F::WORD_SIZE)));
+                let fail = F::external_call("failIndex", vec![ti.clone(), length.clone()]);
+                let check = self.guard(RelOp::Ult, ti.clone(), length, fail);
+                let offset = binop(BinOp::Mul, ti.clone(), int(F::WORD_SIZE));
+                let at = mem(binop(BinOp::Plus, ta.clone(), offset));
+                let stm = seq(vec![mov(ta, a), mov(ti, i), check]);
+                Tr::Ex(Exp::Eseq(Box::new(stm), Box::new(at)))
+            }
+            TypedExpr::Neg(ref e) => {
+                let e = self.expr(e, env, level);
+                Tr::Ex(binop(BinOp::Minus, int(0), self.un_ex(e)))
+            }
+            TypedExpr::OpNode(Op::And, _, ref e1, ref e2) => {
+                // e1 & e2 is if e1 then e2 else 0
+                let c = self.expr(e1, env, level);
+                let e2 = self.expr(e2, env, level);
+                self.if_tr(c, Ty::Int, e2, Tr::Ex(int(0)))
+            }
+            TypedExpr::OpNode(Op::Or, _, ref e1, ref e2) => {
+                // e1 | e2 is e1 if it is not 0, else e2
+                let t = Exp::Temp(self.gen.temp());
+                let e1 = self.expr(e1, env, level);
+                let e1 = self.un_ex(e1);
+                let e2 = self.expr(e2, env, level);
+                let choice = self.if_tr(Tr::Ex(t.clone()), Ty::Int, Tr::Ex(t.clone()), e2);
+                let choice = self.un_ex(choice);
+                Tr::Ex(Exp::Eseq(Box::new(mov(t, e1)), Box::new(choice)))
+            }
+            TypedExpr::OpNode(op, ty, ref e1, ref e2) => {
+                let e1 = self.expr(e1, env, level);
+                let e1 = self.un_ex(e1);
+                let e2 = self.expr(e2, env, level);
+                let e2 = self.un_ex(e2);
+                let binop_of = match op {
+                    Op::Add => BinOp::Plus,
+                    Op::Sub => BinOp::Minus,
+                    Op::Mul => BinOp::Mul,
+                    Op::Div => return Tr::Ex(self.divide(e1, e2)),
+                    _ => return self.compare(op, ty, e1, e2),
+                };
+                Tr::Ex(binop(binop_of, e1, e2))
+            }
+            TypedExpr::IfNode(ref c, ty, ref e1, ref e2) => {
+                let c = self.expr(c, env, level);
+                let e1 = self.expr(e1, env, level);
+                let e2 = self.expr(e2, env, level);
+                self.if_tr(c, ty, e1, e2)
+            }
+            TypedExpr::Assign(ref lval, ref e) => {
+                let lval = self.expr(lval, env, level);
+                let lval = self.un_ex(lval);
+                let e = self.expr(e, env, level);
+                Tr::Nx(mov(lval, self.un_ex(e)))
+            }
+            TypedExpr::Seq(ref es, ty) => {
+                let mut trs: Vec<Tr> = es.iter().map(|e| self.expr(e, env, level)).collect();
+                let last = match trs.pop() {
+                    Some(last) => last,
+                    None => return Tr::Ex(int(0)),
+                };
+                let mut stms: Vec<Stm> = trs.into_iter().map(|tr| self.un_nx(tr)).collect();
+                if ty == Ty::Unit {
+                    stms.push(self.un_nx(last));
+                    return Tr::Nx(seq(stms));
+                }
+                let last = self.un_ex(last);
+                if stms.is_empty() {
+                    return Tr::Ex(last);
+                }
+                Tr::Ex(Exp::Eseq(Box::new(seq(stms)), Box::new(last)))
+            }
+            TypedExpr::Let(ref decs, ref body, ty) => {
+                let mut env = env.clone();
+                let mut stms = Vec::new();
+                let mut i = 0;
+                while i < decs.len() {
+                    match decs[i] {
+                        TypedDec::Var(_, id, _, ref e) => {
+                            let init = self.expr(e, &env, level);
+                            let init = self.un_ex(init);
+                            let access = self.alloc_local(level, self.escapes.escapes(id));
+                            stms.push(mov(F::exp(access, Exp::Temp(F::fp())), init));
+                            env.insert(id, Entry::Var(level, access));
+                            i += 1;
+                        }
+                        TypedDec::Fun(_) => {
+                            let n = decs[i..]
+                                .iter()
+                                .take_while(|dec| matches!(dec, TypedDec::Fun(_)))
+                                .count();
+                            self.fun_group(&decs[i..i + n], &mut env, level);
+                            i += n;
+                        }
+                    }
+                }
+                let body = self.expr(body, &env, level);
+                if stms.is_empty() {
+                    return body;
+                }
+                if ty == Ty::Unit {
+                    stms.push(self.un_nx(body));
+                    return Tr::Nx(seq(stms));
+                }
+                let body = self.un_ex(body);
+                Tr::Ex(Exp::Eseq(Box::new(seq(stms)), Box::new(body)))
+            }
+            TypedExpr::For(_, id, ref lo, ref hi, ref body) => {
+                let lo = self.expr(lo, env, level);
+                let lo = self.un_ex(lo);
+                let hi = self.expr(hi, env, level);
+                let hi = self.un_ex(hi);
+                let access = self.alloc_local(level, self.escapes.escapes(id));
+                let mut env = env.clone();
+                env.insert(id, Entry::Var(level, access));
+                let i = F::exp(access, Exp::Temp(F::fp()));
+                let limit = Exp::Temp(self.gen.temp());
+                let (start, next, done) = (self.gen.label(), self.gen.label(), self.gen.label());
+                self.breaks.push(done.clone());
+                let body = self.expr(body, &env, level);
+                let body = self.un_nx(body);
+                self.breaks.pop();
+                // compares before incrementing, so that hi may be the largest int
+                Tr::Nx(seq(vec![
+                    mov(i.clone(), lo),
+                    mov(limit.clone(), hi),
+                    Stm::Cjump(
+                        RelOp::Le,
+                        Box::new(i.clone()),
+                        Box::new(limit.clone()),
+                        start.clone(),
+                        done.clone(),
+                    ),
+                    Stm::Label(start.clone()),
+                    body,
+                    Stm::Cjump(
+                        RelOp::Lt,
+                        Box::new(i.clone()),
+                        Box::new(limit),
+                        next.clone(),
+                        done.clone(),
+                    ),
+                    Stm::Label(next),
+                    mov(i.clone(), binop(BinOp::Plus, i, int(1))),
+                    jump(start),
+                    Stm::Label(done),
+                ]))
+            }
+            TypedExpr::While(ref c, ref body) => {
+                let (test, start, done) = (self.gen.label(), self.gen.label(), self.gen.label());
+                let c = self.expr(c, env, level);
+                let cond = self.un_cx(c);
+                self.breaks.push(done.clone());
+                let body = self.expr(body, env, level);
+                let body = self.un_nx(body);
+                self.breaks.pop();
+                Tr::Nx(seq(vec![
+                    Stm::Label(test.clone()),
+                    cond(start.clone(), done.clone()),
+                    Stm::Label(start),
+                    body,
+                    jump(test),
+                    Stm::Label(done),
+                ]))
+            }
+            TypedExpr::FunApp(ref name, id, _, ret, ref args) => {
+                let mut vals = Vec::with_capacity(args.len() + 1);
+                for arg in args {
+                    let arg = self.expr(arg, env, level);
+                    vals.push(self.un_ex(arg));
+                }
+                let call = match env.get(&id) {
+                    Some(&Entry::Fun(fun_level, ref label)) => {
+                        let parent = self.levels[fun_level].parent.unwrap();
+                        vals.insert(0, self.static_link(level, parent));
+                        Exp::Call(Box::new(Exp::Name(label.clone())), vals)
+                    }
+                    _ => F::external_call(name, vals),
+                };
+                if ret == Ty::Unit {
+                    Tr::Nx(Stm::Exp(Box::new(call)))
+                } else {
+                    Tr::Ex(call)
+                }
+            }
+            TypedExpr::NewStruct(_, ref fields) => {
+                let r = Exp::Temp(self.gen.temp());
+                let size = int(fields.len() as i64 * F::WORD_SIZE);
+                let mut stms = vec![mov(r.clone(), F::external_call("allocRecord", vec![size]))];
+                for (k, (_, e)) in fields.iter().enumerate() {
+                    let e = self.expr(e, env, level);
+                    let at = binop(BinOp::Plus, r.clone(), int(k as i64 * F::WORD_SIZE));
+                    stms.push(mov(mem(at), self.un_ex(e)));
+                }
+                Tr::Ex(Exp::Eseq(Box::new(seq(stms)), Box::new(r)))
+            }
+            TypedExpr::NewArray(_, ref n, ref init) => {
+                let n = self.expr(n, env, level);
+                let n = self.un_ex(n);
+                let init = self.expr(init, env, level);
+                let init = self.un_ex(init);
+                Tr::Ex(F::external_call("initArray", vec![n, init]))
+            }
+            TypedExpr::Break => Tr::Nx(jump(self.breaks.last().unwrap().clone())),
+        }
+    }
+
+    /* Declares every function of a group first, so that members can call each other. */
+    fn fun_group(&mut self, decs: &[TypedDec], env: &mut Env, level: usize) {
+        let mut levels = Vec::with_capacity(decs.len());
+        for dec in decs {
+            if let TypedDec::Fun((ref name, id, ..)) = *dec {
+                let label = self.fun_label(name);
+                self.levels.push(Level {
+                    frame: None,
+                    parent: Some(level),
+                });
+                let fun_level = self.levels.len() - 1;
+                env.insert(id, Entry::Fun(fun_level, label.clone()));
+                levels.push((fun_level, label));
+            }
+        }
+        for (dec, (fun_level, label)) in decs.iter().zip(levels) {
+            if let TypedDec::Fun((_, _, ref params, ret, ref body)) = *dec {
+                // the static link comes first and always escapes
+                let mut formals = vec![true];
+                for &(_, id, _) in params {
+                    formals.push(self.escapes.escapes(id));
+                }
+                let frame = F::new(label, &formals, self.gen);
+                let mut body_env = env.clone();
+                for (&(_, id, _), &access) in params.iter().zip(&frame.formals()[1..]) {
+                    body_env.insert(id, Entry::Var(fun_level, access));
+                }
+                self.levels[fun_level].frame = Some(frame);
+                let breaks = std::mem::take(&mut self.breaks);
+                let body = self.expr(body, &body_env, fun_level);
+                self.breaks = breaks;
+                self.proc(fun_level, ret, body);
+            }
+        }
+    }
+
+    /* Turns the body of the function at level into a fragment, its result moved to rv. */
+    fn proc(&mut self, level: usize, ret: Ty, body: Tr) {
+        let body = if ret == Ty::Unit {
+            self.un_nx(body)
+        } else {
+            mov(Exp::Temp(F::rv()), self.un_ex(body))
+        };
+        let frame = self.levels[level].frame.take().unwrap();
+        let body = frame.proc_entry_exit1(body);
+        self.fragments.push(Fragment::Proc { body, frame });
+    }
+}
+
+/*
+ * Translates tast, typed with the given types. escapes come from the resolution of the
+ * located tree tast was typed from and say which variables and parameters have to live in frames.
+ */
+pub fn f<F: Frame>(
+    tast: &TypedExpr,
+    types: &TypeTable,
+    escapes: &Escapes,
+    gen: &mut Gen,
+) -> Vec<Fragment<F>> {
+    let mut labels: HashSet<String> = RUNTIME
+        .iter()
+        .chain(library::NAMES)
+        .map(|s| s.to_string())
+        .collect();
+    labels.insert(MAIN.to_string());
+    labels.insert("main".to_string());
+    let main = F::new(Label(MAIN.to_string()), &[], gen);
+    let mut ctx = Ctx {
+        types,
+        escapes,
+        levels: vec![Level {
+            frame: Some(main),
+            parent: None,
+        }],
+        labels,
+        breaks: Vec::new(),
+        fragments: Vec::new(),
+        gen,
+    };
+    let body = ctx.expr(tast, &Env::new(), 0);
+    ctx.proc(0, ty_of_ast(tast), body);
+    ctx.fragments
+}
+
+/* Writes the fragments, with temporaries that stand for registers named after them. */
+pub fn write<F: Frame, W: Write>(mut out: W, fragments: &[Fragment<F>]) -> io::Result<()> {
+    let printer = Printer {
+        temp_name: &F::temp_name,
+    };
+    for fragment in fragments {
+        match *fragment {
+            Fragment::Proc {
+                ref body,
+                ref frame,
+            } => writeln!(
+                out,
+                "PROC {} (frame {} bytes)\n{}\n",
+                frame.name(),
+                frame.frame_size(),
+                printer.stm(body)
+            )?,
+            Fragment::Str(ref label, ref s) => writeln!(out, "STRING {} {:?}\n", label, s)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::X64Frame;
+    use crate::{escape, resolve, typing};
+
+    fn translate(src: &str) -> Vec<Fragment<X64Frame>> {
+        let (ast, types, tast) = typing::check(src, &[]).unwrap();
+        let (res, _) = resolve::f(&ast, &[]);
+        f(&tast, &types, &escape::f(&res), &mut Gen::new())
+    }
+
+    fn show(fragments: &[Fragment<X64Frame>]) -> String {
+        let mut out = Vec::new();
+        write(&mut out, fragments).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn main_test() {
+        assert_eq!(
+            show(&translate("1 + 2")),
+            "PROC tigermain (frame 0 bytes)
+MOVE(
+ TEMP rax,
+ BINOP(Plus,
+  CONST 1,
+  CONST 2))
+
+"
+        );
+    }
+
+    #[test]
+    fn static_link_test() {
+        let src = "let function f(x: int, y: int): int =
+  let function g(): int = x + 1 in g() + y end
+in f(1, 2) end";
+        let fragments = translate(src);
+        let names: Vec<String> = fragments
+            .iter()
+            .map(|frag| match *frag {
+                Fragment::Proc { ref frame, .. } => frame.name().to_string(),
+                Fragment::Str(ref l, _) => l.to_string(),
+            })
+            .collect();
+        assert_eq!(names, vec!["g", "f", "tigermain"]);
+        let out = show(&fragments);
+        // g reaches x, which escapes, through its static link; y stays in a temporary
+        assert!(out.contains(
+            "   MEM(
+    BINOP(Plus,
+     MEM(
+      BINOP(Plus,
+       TEMP rbp,
+       CONST -8)),
+     CONST -16)),"
+        ));
+        assert!(out.contains("MOVE(\n    TEMP t100,\n    TEMP rdx)"));
+        assert!(out.contains("PROC f (frame 16 bytes)"));
+        // f is called with the frame of the main program as its static link
+        assert!(out.contains(
+            "CALL(
+  NAME f,
+  TEMP rbp,"
+        ));
+    }
+
+    #[test]
+    fn fragments_test() {
+        let fragments = translate(
+            "let type p = {a: int, s: string} var r := p {a = 1, s = \"x\"}
+                 function a() = ()
+                 var b := 0
+                 function a() = ()
+             in r.s = \"y\" end",
+        );
+        let out = show(&fragments);
+        assert!(out.contains("STRING L0 \"x\""));
+        assert!(out.contains("NAME allocRecord"));
+        assert!(out.contains("NAME stringEqual"));
+        // the second a, in a group of its own, gets a label of its own
+        assert!(out.contains("PROC a (frame 8 bytes)"));
+        assert!(out.contains("PROC a_1 (frame 8 bytes)"));
+        // every sample translates, declarations lining up with the resolver's
+        for src in [
+            include_str!("../sample/fib.tiger"),
+            include_str!("../sample/for-break.tiger"),
+            include_str!("../sample/list.tiger"),
+        ] {
+            translate(src);
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use std::{fmt, ops};
+
+/*
+ * The Tree intermediate representation of Appel's "Modern Compiler Implementation":
+ * expressions that compute a value and statements that do not, over an unbounded
+ * number of temporaries (abstract registers) and symbolic labels.
+ */
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, PartialOrd, Ord)]
+pub struct Temp(pub usize);
+
+#[derive(PartialEq, Eq, Hash, Clone, Debug, PartialOrd, Ord)]
+pub struct Label(pub String);
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum BinOp {
+    Plus,
+    Minus,
+    Mul,
+    Div,
+    And,
+    Or,
+    Lshift,
+    Rshift,
+    Arshift,
+    Xor,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum RelOp {
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Ult,
+    Ule,
+    Ugt,
+    Uge,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum Exp {
+    Const(i64),
+    Name(Label),
+    Temp(Temp),
+    Binop(BinOp, Box<Exp>, Box<Exp>),
+    Mem(Box<Exp>),            // the word at the address
+    Call(Box<Exp>, Vec<Exp>), // function, arguments
+    Eseq(Box<Stm>, Box<Exp>), // the statement, then the value of the expression
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum Stm {
+    Move(Box<Exp>, Box<Exp>),   // destination (Temp or Mem), source
+    Exp(Box<Exp>),              // evaluates and discards
+    Jump(Box<Exp>, Vec<Label>), // target, every label it may evaluate to
+    Cjump(RelOp, Box<Exp>, Box<Exp>, Label, Label), // to the first label if the relation holds, else to the second
+    Seq(Box<Stm>, Box<Stm>),
+    Label(Label),
+}
+
+/* The relation that holds exactly when this one does not. */
+impl ops::Not for RelOp {
+    type Output = RelOp;
+    fn not(self) -> RelOp {
+        match self {
+            RelOp::Eq => RelOp::Ne,
+            RelOp::Ne => RelOp::Eq,
+            RelOp::Lt => RelOp::Ge,
+            RelOp::Gt => RelOp::Le,
+            RelOp::Le => RelOp::Gt,
+            RelOp::Ge => RelOp::Lt,
+            RelOp::Ult => RelOp::Uge,
+            RelOp::Ule => RelOp::Ugt,
+            RelOp::Ugt => RelOp::Ule,
+            RelOp::Uge => RelOp::Ult,
+        }
+    }
+}
+
+/* Sources of fresh temporaries and labels, numbered from where precolored registers end. */
+#[derive(Clone, Debug)]
+pub struct Gen {
+    temps: usize,
+    labels: usize,
+}
+
+pub const FIRST_TEMP: usize = 100;
+
+impl Default for Gen {
+    fn default() -> Self {
+        Gen {
+            temps: FIRST_TEMP,
+            labels: 0,
+        }
+    }
+}
+
+impl Gen {
+    pub fn new() -> Self {
+        Gen::default()
+    }
+    pub fn temp(&mut self) -> Temp {
+        self.temps += 1;
+        Temp(self.temps - 1)
+    }
+    pub fn label(&mut self) -> Label {
+        self.labels += 1;
+        Label(format!("L{}", self.labels - 1))
+    }
+}
+
+/* Builds the sequence of stms, which must not be empty. */
+pub fn seq(stms: Vec<Stm>) -> Stm {
+    stms.into_iter()
+        .rev()
+        .reduce(|rest, stm| Stm::Seq(Box::new(stm), Box::new(rest)))
+        .expect("empty sequence")
+}
+
+pub fn binop(op: BinOp, e1: Exp, e2: Exp) -> Exp {
+    Exp::Binop(op, Box::new(e1), Box::new(e2))
+}
+
+pub fn mem(e: Exp) -> Exp {
+    Exp::Mem(Box::new(e))
+}
+
+pub fn mov(dst: Exp, src: Exp) -> Stm {
+    Stm::Move(Box::new(dst), Box::new(src))
+}
+
+pub fn jump(label: Label) -> Stm {
+    Stm::Jump(Box::new(Exp::Name(label.clone())), vec![label])
+}
+
+impl fmt::Display for Temp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "t{}", self.0)
+    }
+}
+
+impl fmt::Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/*
+ * Prints trees in the indented style of Appel's printtree, one node per line,
+ * naming temporaries with temp_name so that precolored registers can show as such.
+ */
+pub struct Printer<'a> {
+    pub temp_name: &'a dyn Fn(Temp) -> String,
+}
+
+impl Printer<'_> {
+    pub fn stm(&self, stm: &Stm) -> String {
+        let mut out = String::new();
+        self.write_stm(&mut out, stm, 0);
+        out
+    }
+
+    fn write_stm(&self, out: &mut String, stm: &Stm, d: usize) {
+        let indent = " ".repeat(d);
+        match *stm {
+            Stm::Seq(ref s1, ref s2) => {
+                *out += &format!("{}SEQ(\n", indent);
+                self.write_stm(out, s1, d + 1);
+                *out += ",\n";
+                self.write_stm(out, s2, d + 1);
+                *out += ")";
+            }
+            Stm::Label(ref l) => *out += &format!("{}LABEL {}", indent, l),
+            Stm::Jump(ref e, _) => {
+                *out += &format!("{}JUMP(\n", indent);
+                self.write_exp(out, e, d + 1);
+                *out += ")";
+            }
+            Stm::Cjump(op, ref a, ref b, ref t, ref f) => {
+                *out += &format!("{}CJUMP({:?},\n", indent, op);
+                self.write_exp(out, a, d + 1);
+                *out += ",\n";
+                self.write_exp(out, b, d + 1);
+                *out += &format!(",\n{} {}, {})", indent, t, f);
+            }
+            Stm::Move(ref a, ref b) => {
+                *out += &format!("{}MOVE(\n", indent);
+                self.write_exp(out, a, d + 1);
+                *out += ",\n";
+                self.write_exp(out, b, d + 1);
+                *out += ")";
+            }
+            Stm::Exp(ref e) => {
+                *out += &format!("{}EXP(\n", indent);
+                self.write_exp(out, e, d + 1);
+                *out += ")";
+            }
+        }
+    }
+
+    fn write_exp(&self, out: &mut String, e: &Exp, d: usize) {
+        let indent = " ".repeat(d);
+        match *e {
+            Exp::Binop(op, ref a, ref b) => {
+                *out += &format!("{}BINOP({:?},\n", indent, op);
+                self.write_exp(out, a, d + 1);
+                *out += ",\n";
+                self.write_exp(out, b, d + 1);
+                *out += ")";
+            }
+            Exp::Mem(ref e) => {
+                *out += &format!("{}MEM(\n", indent);
+                self.write_exp(out, e, d + 1);
+                *out += ")";
+            }
+            Exp::Temp(t) => *out += &format!("{}TEMP {}", indent, (self.temp_name)(t)),
+            Exp::Eseq(ref s, ref e) => {
+                *out += &format!("{}ESEQ(\n", indent);
+                self.write_stm(out, s, d + 1);
+                *out += ",\n";
+                self.write_exp(out, e, d + 1);
+                *out += ")";
+            }
+            Exp::Name(ref l) => *out += &format!("{}NAME {}", indent, l),
+            Exp::Const(i) => *out += &format!("{}CONST {}", indent, i),
+            Exp::Call(ref f, ref args) => {
+                *out += &format!("{}CALL(\n", indent);
+                self.write_exp(out, f, d + 1);
+                for arg in args {
+                    *out += ",\n";
+                    self.write_exp(out, arg, d + 1);
+                }
+                *out += ")";
+            }
+        }
+    }
+}
+
+impl fmt::Display for Stm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let printer = Printer {
+            temp_name: &|t| t.to_string(),
+        };
+        write!(f, "{}", printer.stm(self))
+    }
+}
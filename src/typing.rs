@@ -1,119 +1,1695 @@
-use ast::{AST, Type, FunDec, TypedAST, TypedFunDec, Op, ty_of_ast};
-use std::collections::HashMap;
+use crate::ast::{
+    ty_of_ast, Dec, Expr, LValue, Op, Span, Ty, TyDef, Type, TypeTable, TypedDec, TypedExpr,
+};
+use crate::diagnostics::{Code, Diagnostic};
+use crate::engine::HostFun;
+use crate::parse;
+use crate::resolve::{self, BindingId, Site};
+use std::collections::{HashMap, HashSet};
 
-fn expect_type(fundecs: &[FunDec], ast: &AST, env: &mut HashMap<String, Type>, expected_type: &Type, msg: &str)
-               -> TypedAST {
-    let tast = f_sub(fundecs, ast, env);
-    if ty_of_ast(&tast) != *expected_type {
-        panic!(format!("type error in expression {:?}: expected: {:?}, but got: {:?}\n{}", ast, expected_type, ty_of_ast(&tast), msg));
+#[derive(Clone, Debug)]
+enum Entry {
+    Var(Ty, BindingId),
+    LoopVar(BindingId), // the index of an enclosing for, an int that must not be assigned to
+    Fun(Vec<Ty>, Ty, BindingId),
+}
+
+#[derive(Clone, Debug)]
+struct Env {
+    vars: HashMap<String, Entry>, // variables and functions share one namespace
+    types: HashMap<String, Ty>,
+    in_loop: bool, // whether break is allowed: inside a loop body, but not a function declared there
+}
+
+/* A check on a type that was not known yet when it was needed. */
+struct Deferred {
+    ty: Ty,
+    ok: fn(Ty) -> bool,
+    code: Code,
+    span: Option<Span>,
+    message: Box<dyn Fn(&str) -> String>, // given the type, once known
+}
+
+struct Ctx {
+    types: TypeTable,
+    span: Option<Span>, // innermost located expression being checked
+    diagnostics: Vec<Diagnostic>,
+    vars: Vec<(String, Option<Ty>)>, // for each Ty::Var, its function and what it stands for
+    deferred: Vec<Deferred>,
+    sites: HashMap<Site, BindingId>, // from the resolution of the tree checked
+}
+
+impl Ctx {
+    fn err<T>(&self, code: Code, message: String) -> Result<T, Diagnostic> {
+        Err(Diagnostic::error(code, self.span, message))
+    }
+    /* The BindingId that resolve gave the declaration at site. */
+    fn declare(&self, site: Site) -> BindingId {
+        self.sites[&site]
+    }
+    fn show(&self, ty: Ty) -> String {
+        self.norm(ty).show(&self.types)
+    }
+    /* ty, with the type variables known by now replaced; an open one is its class's oldest. */
+    fn norm(&self, mut ty: Ty) -> Ty {
+        while let Ty::Var(i) = ty {
+            match self.vars[i].1 {
+                Some(known) => ty = known,
+                None => break,
+            }
+        }
+        ty
+    }
+    fn ty_of(&self, tast: &TypedExpr) -> Ty {
+        self.norm(ty_of_ast(tast))
+    }
+    /* A type variable for the result of function name. */
+    fn fresh(&mut self, name: &str) -> Ty {
+        self.vars.push((name.to_string(), None));
+        Ty::Var(self.vars.len() - 1)
+    }
+    /* Makes the open variable i stand for ty, which is normalized. */
+    fn bind(&mut self, i: usize, ty: Ty) {
+        match ty {
+            Ty::Var(j) if j == i => {}
+            Ty::Var(j) if j > i => self.vars[j].1 = Some(Ty::Var(i)),
+            _ => self.vars[i].1 = Some(ty),
+        }
+    }
+    /* Checks ok on ty once ty is known, reporting message at the current span if it fails. */
+    fn defer(
+        &mut self,
+        ty: Ty,
+        ok: fn(Ty) -> bool,
+        code: Code,
+        message: impl Fn(&str) -> String + 'static,
+    ) {
+        self.deferred.push(Deferred {
+            ty,
+            ok,
+            code,
+            span: self.span,
+            message: Box::new(message),
+        });
+    }
+    /* Runs the deferred checks whose types are known by now. */
+    fn check_deferred(&mut self) {
+        for d in std::mem::take(&mut self.deferred) {
+            let ty = self.norm(d.ty);
+            if let Ty::Var(_) = ty {
+                self.deferred.push(d);
+            } else if !(d.ok)(ty) {
+                let message = (d.message)(&self.show(ty));
+                self.diagnostics
+                    .push(Diagnostic::error(d.code, d.span, message));
+            }
+        }
+    }
+    /* nil where a value of the open type Var(i) goes, which has to be a record type then. */
+    fn nil_for(&mut self, i: usize) {
+        self.defer(
+            Ty::Var(i),
+            |ty| matches!(ty, Ty::Record(_) | Ty::Nil | Ty::Unknown),
+            Code::Mismatch,
+            |ty| format!("nil cannot stand for a value of type {}", ty),
+        );
+    }
+    /* The type of an expression whose value is one of ty1 or ty2: nil joins with any record type. */
+    fn join(&mut self, ty1: Ty, ty2: Ty) -> Option<Ty> {
+        let (ty1, ty2) = (self.norm(ty1), self.norm(ty2));
+        match (ty1, ty2) {
+            _ if ty1 == ty2 => Some(ty1),
+            (Ty::Unknown, ty) | (ty, Ty::Unknown) => Some(ty),
+            (Ty::Var(i), Ty::Nil) | (Ty::Nil, Ty::Var(i)) => {
+                self.nil_for(i);
+                Some(ty1)
+            }
+            (Ty::Var(i), ty) | (ty, Ty::Var(i)) => {
+                self.bind(i, ty);
+                Some(self.norm(ty))
+            }
+            (Ty::Nil, Ty::Record(i)) | (Ty::Record(i), Ty::Nil) => Some(Ty::Record(i)),
+            _ => None,
+        }
+    }
+    /* Whether a value of type actual may be stored where expected is wanted. */
+    fn assignable(&mut self, expected: Ty, actual: Ty) -> bool {
+        let (expected, actual) = (self.norm(expected), self.norm(actual));
+        match (expected, actual) {
+            _ if expected == actual => true,
+            (Ty::Unknown, _) | (_, Ty::Unknown) => true,
+            (Ty::Var(i), Ty::Nil) => {
+                self.nil_for(i);
+                true
+            }
+            (Ty::Var(i), ty) | (ty, Ty::Var(i)) => {
+                self.bind(i, ty);
+                true
+            }
+            (Ty::Record(_), Ty::Nil) => true,
+            _ => false,
+        }
+    }
+    /* Replaces the type variables in tast by what they stand for, as far as known. */
+    fn settle(&self, tast: &mut TypedExpr) {
+        match *tast {
+            TypedExpr::Num(_) | TypedExpr::Str(_) | TypedExpr::Nil | TypedExpr::Break => {}
+            TypedExpr::Var(_, _, ref mut ty) => *ty = self.norm(*ty),
+            TypedExpr::Field(ref mut e, _, ref mut ty) => {
+                self.settle(e);
+                *ty = self.norm(*ty);
+            }
+            TypedExpr::Index(ref mut a, ref mut i, ref mut ty) => {
+                self.settle(a);
+                self.settle(i);
+                *ty = self.norm(*ty);
+            }
+            TypedExpr::Neg(ref mut e) => self.settle(e),
+            TypedExpr::OpNode(_, ref mut ty, ref mut e1, ref mut e2) => {
+                *ty = self.norm(*ty);
+                self.settle(e1);
+                self.settle(e2);
+            }
+            TypedExpr::IfNode(ref mut c, ref mut ty, ref mut e1, ref mut e2) => {
+                *ty = self.norm(*ty);
+                self.settle(c);
+                self.settle(e1);
+                self.settle(e2);
+            }
+            TypedExpr::Assign(ref mut l, ref mut e) | TypedExpr::While(ref mut l, ref mut e) => {
+                self.settle(l);
+                self.settle(e);
+            }
+            TypedExpr::Seq(ref mut es, ref mut ty) => {
+                es.iter_mut().for_each(|e| self.settle(e));
+                *ty = self.norm(*ty);
+            }
+            TypedExpr::Let(ref mut decs, ref mut body, ref mut ty) => {
+                decs.iter_mut().for_each(|dec| self.settle_dec(dec));
+                self.settle(body);
+                *ty = self.norm(*ty);
+            }
+            TypedExpr::For(_, _, ref mut lo, ref mut hi, ref mut body) => {
+                self.settle(lo);
+                self.settle(hi);
+                self.settle(body);
+            }
+            TypedExpr::FunApp(_, _, _, ref mut ret, ref mut args) => {
+                *ret = self.norm(*ret);
+                args.iter_mut().for_each(|e| self.settle(e));
+            }
+            TypedExpr::NewStruct(_, ref mut fields) => {
+                fields.iter_mut().for_each(|f| self.settle(&mut f.1));
+            }
+            TypedExpr::NewArray(_, ref mut n, ref mut init) => {
+                self.settle(n);
+                self.settle(init);
+            }
+        }
+    }
+    fn settle_dec(&self, dec: &mut TypedDec) {
+        match *dec {
+            TypedDec::Var(_, _, ref mut ty, ref mut e) => {
+                *ty = self.norm(*ty);
+                self.settle(e);
+            }
+            TypedDec::Fun((_, _, _, ref mut ret, ref mut body)) => {
+                *ret = self.norm(*ret);
+                self.settle(body);
+            }
+        }
+    }
+    /* Labels each located expression in es with its type. */
+    fn label_types(&self, mut d: Diagnostic, es: &[(&Expr, Ty)]) -> Diagnostic {
+        for &(e, ty) in es {
+            if let Some(span) = span_of(e) {
+                d = d.with_label(span, format!("this is {}", self.show(ty)));
+            }
+        }
+        d
+    }
+    /* Records an error that does not keep checking from going on. */
+    fn report(&mut self, code: Code, message: String) {
+        self.diagnostics
+            .push(Diagnostic::error(code, self.span, message));
+    }
+    /* Unknown, after reporting it, if name is not declared. */
+    fn lookup_type(&mut self, env: &Env, name: &str) -> Ty {
+        match env.types.get(name) {
+            Some(&ty) => ty,
+            None => {
+                self.report(Code::UndefinedType, format!("undefined type {}", name));
+                Ty::Unknown
+            }
+        }
     }
-    tast
 }
 
-fn f_sub(fundecs: &[FunDec], ast: &AST, env: &mut HashMap<String, Type>)
-         -> TypedAST {
-    match *ast {
-        AST::Num(n) => TypedAST::Num(n),
-        AST::Str(ref str) => TypedAST::Str(str.clone()),
-        AST::Var(ref x) => {
-            let ty = env.get(x).expect("variable not found").clone();
-            TypedAST::Var(x.clone(), ty)
+fn span_of(e: &Expr) -> Option<Span> {
+    match *e {
+        Expr::Spanned(span, _) => Some(span),
+        _ => None,
+    }
+}
+
+/*
+ * Records the error of r, if any, and stands in an expression of unknown type,
+ * which is compatible with everything so that one mistake is reported once.
+ */
+fn recover(ctx: &mut Ctx, r: Result<TypedExpr, Diagnostic>) -> TypedExpr {
+    r.unwrap_or_else(|d| {
+        ctx.diagnostics.push(d);
+        TypedExpr::Seq(Vec::new(), Ty::Unknown)
+    })
+}
+
+fn expect_type(
+    ctx: &mut Ctx,
+    ast: &Expr,
+    env: &Env,
+    expected: Ty,
+    msg: &str,
+) -> Result<TypedExpr, Diagnostic> {
+    let tast = f_sub(ctx, ast, env)?;
+    let actual = ctx.ty_of(&tast);
+    if !ctx.assignable(expected, actual) {
+        if let Expr::Spanned(span, _) = *ast {
+            ctx.span = Some(span);
+        }
+        let code = if actual == Ty::Unit {
+            Code::NoValue
+        } else {
+            Code::Mismatch
+        };
+        return ctx.err(
+            code,
+            format!(
+                "{}: expected {}, but got {}",
+                msg,
+                ctx.show(expected),
+                ctx.show(actual)
+            ),
+        );
+    }
+    Ok(tast)
+}
+
+fn expect_no_value(
+    ctx: &mut Ctx,
+    ast: &Expr,
+    env: &Env,
+    msg: &str,
+) -> Result<TypedExpr, Diagnostic> {
+    expect_type(ctx, ast, env, Ty::Unit, msg)
+}
+
+/* A field or element of a value whose type is the open result type Var(i). */
+fn pending<T>(ctx: &Ctx, i: usize) -> Result<T, Diagnostic> {
+    let name = &ctx.vars[i].0;
+    ctx.err(
+        Code::PendingResult,
+        format!("the result type of {} is not known yet here", name),
+    )
+    .map_err(|d| d.with_note(format!("annotate the result type of {}", name)))
+}
+
+fn lval(ctx: &mut Ctx, l: &LValue, env: &Env) -> Result<TypedExpr, Diagnostic> {
+    match *l {
+        LValue::Id(ref x) => match env.vars.get(x) {
+            Some(&Entry::Var(ty, id)) => Ok(TypedExpr::Var(x.clone(), id, ctx.norm(ty))),
+            Some(&Entry::LoopVar(id)) => Ok(TypedExpr::Var(x.clone(), id, Ty::Int)),
+            Some(&Entry::Fun(..)) => ctx.err(
+                Code::WrongKind,
+                format!("{} is a function, not a variable", x),
+            ),
+            None => ctx.err(Code::UndefinedVariable, format!("undefined variable {}", x)),
         },
-        AST::OpNode(op, ref e1, ref e2) => {
-            let ta1 = f_sub(fundecs, e1, env);
-            let ta2 = f_sub(fundecs, e2, env);
-            match (op, ty_of_ast(&ta1), ty_of_ast(&ta2)) {
-                (_, Type::Int, Type::Int) =>
-                    TypedAST::OpNode(op, Type::Int, Box::new(ta1), Box::new(ta2)),
-                (Op::Add, Type::Str, Type::Str) =>
-                    TypedAST::OpNode(op, Type::Str, Box::new(ta1), Box::new(ta2)),
-                _ => panic!("typing of {:?} failed", op),
+        LValue::Mem(ref l, ref name) => {
+            let base = lval(ctx, l, env)?;
+            let field_ty = match ctx.ty_of(&base) {
+                Ty::Record(i) => match ctx.types[i] {
+                    TyDef::Record(_, ref fields) => {
+                        fields.iter().find(|f| f.0 == *name).map(|f| f.1)
+                    }
+                    TyDef::Array(..) => None,
+                },
+                Ty::Unknown => Some(Ty::Unknown),
+                Ty::Var(i) => return pending(ctx, i),
+                ty => {
+                    return ctx.err(
+                        Code::NotRecord,
+                        format!("{} is not a record type", ctx.show(ty)),
+                    )
+                }
+            };
+            match field_ty {
+                Some(ty) => Ok(TypedExpr::Field(Box::new(base), name.clone(), ty)),
+                None => {
+                    let record = ctx.show(ty_of_ast(&base));
+                    ctx.err(
+                        Code::NoSuchField,
+                        format!("record type {} has no field {}", record, name),
+                    )
+                }
             }
-        },
-        AST::IfNode(ref cond, ref e_true, ref e_false) => {
-            let tcond = expect_type(fundecs, cond, env, &Type::Int,
-                                    "Condition must be of type int.");
-            
-            let t_true = f_sub(fundecs, e_true, env);
-            let t_false = expect_type(fundecs, e_false, env, &ty_of_ast(&t_true),
-                                      "The types of true part and false part in a condition must be the same.");
-            TypedAST::IfNode(Box::new(tcond), ty_of_ast(&t_true).clone(), Box::new(t_true), Box::new(t_false))
-        },
-        AST::LetEx(ref x, ref e1, ref e2) => {
-            let ast1 = f_sub(fundecs, e1, env);
-            let ty1 = ty_of_ast(&ast1);
-            let old = env.insert(x.clone(), ty1.clone());
-            let ast2 = f_sub(fundecs, e2, env);
-            env.remove(x).unwrap();
-            if let Some(o) = old {
-                env.insert(x.clone(), o);
-            }
-            TypedAST::LetEx(x.clone(), ty1, Box::new(ast1), Box::new(ast2))
-        },
-        AST::FunApp(ref f, ref es) => {
-            let sign = get_signature(fundecs, &f);
-            let n = es.len(); // # arguments
-            let m = sign.0.len(); // # parameters
-            if n != m {
-                panic!(format!("The number of arguments of {} is wrong. (expected: {}, got: {})", f.clone(), m, n));
-            }
-            let typed_es = (0 .. n).map(|i| expect_type(fundecs, &es[i], env, &sign.0[i], "Argument type differs.")).collect::<Vec<_>>();
-            TypedAST::FunApp(f.clone(), sign.0, sign.1, typed_es)
-        },
+        }
+        LValue::Idx(ref l, ref e) => {
+            let base = lval(ctx, l, env)?;
+            let elem = match ctx.ty_of(&base) {
+                Ty::Array(i) => match ctx.types[i] {
+                    TyDef::Array(_, elem) => elem,
+                    TyDef::Record(..) => unreachable!(),
+                },
+                Ty::Unknown => Ty::Unknown,
+                Ty::Var(i) => return pending(ctx, i),
+                ty => {
+                    return ctx.err(
+                        Code::NotArray,
+                        format!("{} is not an array type", ctx.show(ty)),
+                    )
+                }
+            };
+            let idx = expect_type(ctx, e, env, Ty::Int, "array index")?;
+            Ok(TypedExpr::Index(Box::new(base), Box::new(idx), elem))
+        }
+    }
+}
+
+fn op_node(
+    ctx: &mut Ctx,
+    op: Op,
+    e1: &Expr,
+    e2: &Expr,
+    env: &Env,
+) -> Result<TypedExpr, Diagnostic> {
+    let operand_ty = match op {
+        Op::Add | Op::Sub | Op::Mul | Op::Div | Op::And | Op::Or => Ty::Int,
+        Op::Lt | Op::Gt | Op::Le | Op::Ge | Op::Eq | Op::Ne => {
+            let t1 = f_sub(ctx, e1, env)?;
+            let t2 = f_sub(ctx, e2, env)?;
+            let (ty1, ty2) = (ctx.ty_of(&t1), ctx.ty_of(&t2));
+            let ordered = matches!(op, Op::Lt | Op::Gt | Op::Le | Op::Ge);
+            let ty = if ty1 == Ty::Nil || ty1 == Ty::Unknown {
+                ty2
+            } else {
+                ty1
+            };
+            let ok = if ordered {
+                matches!(ty, Ty::Int | Ty::Str | Ty::Unknown | Ty::Var(_))
+                    && ctx.assignable(ty, ty1)
+                    && ctx.assignable(ty, ty2)
+            } else {
+                // nil = nil is rejected: there is no record type to compare at
+                !matches!(ty, Ty::Unit | Ty::Nil) && ctx.join(ty1, ty2).is_some()
+            };
+            let ty = ctx.norm(ty);
+            if let (true, Ty::Var(_)) = (ok, ty) {
+                let (ok, message): (fn(Ty) -> bool, _) = if ordered {
+                    (
+                        |ty| matches!(ty, Ty::Int | Ty::Str | Ty::Unknown),
+                        "ordered",
+                    )
+                } else {
+                    (|ty| ty != Ty::Unit, "compared")
+                };
+                ctx.defer(ty, ok, Code::OperandTypes, move |ty| {
+                    format!("operands of {:?} cannot be {} as {}", op, message, ty)
+                });
+            }
+            if !ok {
+                let d = Diagnostic::error(
+                    Code::OperandTypes,
+                    ctx.span,
+                    format!(
+                        "operator {:?} cannot be applied to {} and {}",
+                        op,
+                        ctx.show(ty1),
+                        ctx.show(ty2)
+                    ),
+                );
+                return Err(ctx.label_types(d, &[(e1, ty1), (e2, ty2)]));
+            }
+            return Ok(TypedExpr::OpNode(op, ty, Box::new(t1), Box::new(t2)));
+        }
+    };
+    let msg = format!("operand of {:?}", op);
+    let t1 = expect_type(ctx, e1, env, operand_ty, &msg)?;
+    let t2 = expect_type(ctx, e2, env, operand_ty, &msg)?;
+    Ok(TypedExpr::OpNode(
+        op,
+        operand_ty,
+        Box::new(t1),
+        Box::new(t2),
+    ))
+}
+
+fn if_node(
+    ctx: &mut Ctx,
+    c: &Expr,
+    e1: &Expr,
+    e2: &Expr,
+    env: &Env,
+) -> Result<TypedExpr, Diagnostic> {
+    let tc = expect_type(ctx, c, env, Ty::Int, "condition of if")?;
+    let t_true = f_sub(ctx, e1, env)?;
+    let ty1 = ctx.ty_of(&t_true);
+    if *e2 == Expr::Nil {
+        // The parser fills in a missing else with a nil without a span, unlike `else nil`.
+        return match ctx.assignable(Ty::Unit, ty1) {
+            true => Ok(TypedExpr::IfNode(
+                Box::new(tc),
+                Ty::Unit,
+                Box::new(t_true),
+                Box::new(TypedExpr::Seq(Vec::new(), Ty::Unit)),
+            )),
+            false => {
+                let d = Diagnostic::error(
+                    Code::IfWithoutElse,
+                    ctx.span,
+                    format!(
+                        "if-then without else must produce no value, but this one has type {}",
+                        ctx.show(ty1)
+                    ),
+                )
+                .with_note("add an else part, or make the then part produce no value".to_string());
+                Err(ctx.label_types(d, &[(e1, ty1)]))
+            }
+        };
+    }
+    let t_false = f_sub(ctx, e2, env)?;
+    let ty2 = ctx.ty_of(&t_false);
+    let ty = match ctx.join(ty1, ty2) {
+        Some(ty) => ty,
+        None => {
+            let d = Diagnostic::error(
+                Code::IfBranches,
+                ctx.span,
+                format!(
+                    "the then part and the else part of if must have the same type, but they are {} and {}",
+                    ctx.show(ty1),
+                    ctx.show(ty2)
+                ),
+            );
+            return Err(ctx.label_types(d, &[(e1, ty1), (e2, ty2)]));
+        }
+    };
+    Ok(TypedExpr::IfNode(
+        Box::new(tc),
+        ty,
+        Box::new(t_true),
+        Box::new(t_false),
+    ))
+}
+
+/*
+ * Declares a maximal run of consecutive type declarations, which may refer to each other.
+ * Records and arrays are entered first so that fields and elements can name any type
+ * of the group; aliases are then followed until they reach one of those or an outer type.
+ */
+fn type_group(ctx: &mut Ctx, decs: &[Dec], env: &mut Env) {
+    let mut decls: HashMap<&str, &Type> = HashMap::new();
+    let mut group = Vec::with_capacity(decs.len());
+    for dec in decs {
+        if let Dec::Type(ref name, ref ty, span) = *dec {
+            if decls.contains_key(name.as_str()) {
+                ctx.report(
+                    Code::DuplicateDeclaration,
+                    format!("type {} is declared twice in the same group", name),
+                );
+                continue;
+            }
+            decls.insert(name, ty);
+            group.push((name, ty, span));
+        }
+    }
+    for &(name, ty, _) in &group {
+        let ty = match *ty {
+            Type::Field(_) => {
+                ctx.types.push(TyDef::Record(name.clone(), Vec::new()));
+                Ty::Record(ctx.types.len() - 1)
+            }
+            Type::Array(_) => {
+                ctx.types.push(TyDef::Array(name.clone(), Ty::Unknown));
+                Ty::Array(ctx.types.len() - 1)
+            }
+            Type::Id(_) => continue,
+        };
+        env.types.insert(name.clone(), ty);
+    }
+    // each cycle is reported once, at the first of its declarations, starting from it
+    let index = |n: &str| group.iter().position(|g| g.0 == n).unwrap();
+    let mut in_cycles = HashSet::new();
+    for &(name, ty, _) in &group {
+        if let Type::Id(ref target) = *ty {
+            let mut chain = vec![name.as_str()];
+            let mut cur = target.as_str();
+            let mut ty = None;
+            while let Some(Type::Id(next)) = decls.get(cur) {
+                if let Some(pos) = chain.iter().position(|&n| n == cur) {
+                    let mut cycle = chain[pos..].to_vec();
+                    if in_cycles.insert(cur) {
+                        in_cycles.extend(cycle.iter().copied());
+                        let first = (0..cycle.len()).min_by_key(|&k| index(cycle[k])).unwrap();
+                        cycle.rotate_left(first);
+                        let message = format!(
+                            "type declarations {} -> {} form a cycle that does not pass through a record or array type",
+                            cycle.join(" -> "),
+                            cycle[0]
+                        );
+                        let span = group[index(cycle[0])].2.or(ctx.span);
+                        ctx.diagnostics
+                            .push(Diagnostic::error(Code::AliasCycle, span, message));
+                    }
+                    ty = Some(Ty::Unknown);
+                    break;
+                }
+                chain.push(cur);
+                cur = next;
+            }
+            let ty = ty.unwrap_or_else(|| ctx.lookup_type(env, cur));
+            env.types.insert(name.clone(), ty);
+        }
+    }
+    for &(name, ty, _) in &group {
+        let (i, def) = match (ty, env.types[name]) {
+            (Type::Field(fields), Ty::Record(i)) => {
+                let mut resolved = Vec::with_capacity(fields.len());
+                for (field, ty) in fields {
+                    if resolved.iter().any(|f: &(String, Ty)| f.0 == *field) {
+                        ctx.report(
+                            Code::DuplicateDeclaration,
+                            format!("field {} appears twice in record type {}", field, name),
+                        );
+                        continue;
+                    }
+                    resolved.push((field.clone(), ctx.lookup_type(env, ty)));
+                }
+                (i, TyDef::Record(name.clone(), resolved))
+            }
+            (Type::Array(elem), Ty::Array(i)) => {
+                (i, TyDef::Array(name.clone(), ctx.lookup_type(env, elem)))
+            }
+            _ => continue,
+        };
+        ctx.types[i] = def;
+    }
+}
+
+/*
+ * Checks a maximal run of consecutive function declarations, which may call each other.
+ * A function without a result annotation gets the type of its body. Bodies are checked
+ * once each, a strongly connected component of the call graph at a time, callees first;
+ * within a component, the result of a function not checked yet is a type variable that
+ * its uses and then its body determine. A function whose result stays open (it only
+ * ever returns its own result) is a procedure, as are functions whose body produces no value.
+ */
+fn fun_group(ctx: &mut Ctx, decs: &[Dec], env: &mut Env) -> Vec<TypedDec> {
+    let first_var = ctx.vars.len();
+    let mut headers = Vec::with_capacity(decs.len());
+    for (i, dec) in decs.iter().enumerate() {
+        if let Dec::Fun(ref name, ref params, ref opt_ret, _) = *dec {
+            if decs[..i]
+                .iter()
+                .any(|d| matches!(*d, Dec::Fun(ref n, ..) if n == name))
+            {
+                ctx.report(
+                    Code::DuplicateDeclaration,
+                    format!("function {} is declared twice in the same group", name),
+                );
+            }
+            let mut param_tys = Vec::with_capacity(params.len());
+            for (_, ty) in params {
+                param_tys.push(ctx.lookup_type(env, ty));
+            }
+            let ret = match *opt_ret {
+                Some(ref ty) => ctx.lookup_type(env, ty),
+                None => ctx.fresh(name),
+            };
+            let id = ctx.declare(Site::Dec(dec));
+            env.vars
+                .insert(name.clone(), Entry::Fun(param_tys.clone(), ret, id));
+            headers.push((param_tys, ret, id));
+        }
+    }
+    let mut tdecs: Vec<Option<TypedDec>> = vec![None; decs.len()];
+    for component in components(decs) {
+        for &i in &component {
+            tdecs[i] = Some(fun_body(ctx, &decs[i], &headers[i], env));
+        }
+        for &i in &component {
+            if let Ty::Var(v) = ctx.norm(headers[i].1) {
+                if v >= first_var {
+                    ctx.bind(v, Ty::Unit);
+                }
+            }
+        }
+        ctx.check_deferred();
+        for &i in &component {
+            let tdec = tdecs[i].as_mut().unwrap();
+            ctx.settle_dec(tdec);
+            if let TypedDec::Fun((ref name, id, _, ret, _)) = *tdec {
+                env.vars
+                    .insert(name.clone(), Entry::Fun(headers[i].0.clone(), ret, id));
+            }
+        }
     }
+    tdecs.into_iter().map(Option::unwrap).collect()
 }
 
-fn get_signature(fundecs: &[FunDec], name: &str) -> (Vec<Type>, Type) {
-    let fundec = fundecs.iter().filter(|ref f| f.0 == name).next().expect(&format!("function {} was not found", name));
-    (fundec.1.iter().map(|param| param.1.clone()).collect(), fundec.2.clone())
+fn fun_body(
+    ctx: &mut Ctx,
+    dec: &Dec,
+    (param_tys, ret, id): &(Vec<Ty>, Ty, BindingId),
+    env: &Env,
+) -> TypedDec {
+    let Dec::Fun(ref name, ref params, ref opt_ret, ref body) = *dec else {
+        unreachable!()
+    };
+    let mut body_env = env.clone();
+    body_env.in_loop = false;
+    let mut tparams = Vec::with_capacity(params.len());
+    for (k, ((param, _), &ty)) in params.iter().zip(param_tys).enumerate() {
+        let id = ctx.declare(Site::Param(dec, k));
+        body_env.vars.insert(param.clone(), Entry::Var(ty, id));
+        tparams.push((param.clone(), id, ty));
+    }
+    let tbody = match *opt_ret {
+        Some(_) => expect_type(ctx, body, &body_env, *ret, &format!("result of {}", name)),
+        None => f_sub(ctx, body, &body_env).and_then(|tbody| {
+            let ty = ctx.ty_of(&tbody);
+            if ctx.assignable(*ret, ty) {
+                return Ok(tbody);
+            }
+            Err(Diagnostic::error(
+                Code::Mismatch,
+                span_of(body).or(ctx.span),
+                format!(
+                    "result of {}: its calls need {}, but its body has type {}",
+                    name,
+                    ctx.show(*ret),
+                    ctx.show(ty)
+                ),
+            ))
+        }),
+    };
+    let tbody = recover(ctx, tbody);
+    TypedDec::Fun((name.clone(), *id, tparams, *ret, tbody))
 }
 
-pub fn f(fundecs: &[FunDec], ast: &AST) -> (Vec<TypedFunDec>, TypedAST) {
-    let tast = f_sub(fundecs, ast, &mut HashMap::new());
-    let mut tfundecs: Vec<TypedFunDec> = Vec::new();
-    for i in 0 .. fundecs.len() {
-        let (fd_name, fd_arg, fd_retty, fd_body) = fundecs[i].clone();
-        let mut env = HashMap::new();
-        for v_ty in &fd_arg {
-            let (ref v, ref ty) = *v_ty;
-            env.insert(v.clone(), ty.clone());
+/*
+ * The strongly connected components of the graph of calls between the functions of decs,
+ * callees before callers, each in declaration order (Tarjan's algorithm).
+ * A call is any use of the name, even where it is shadowed, which only merges components.
+ */
+fn components(decs: &[Dec]) -> Vec<Vec<usize>> {
+    let names: Vec<&str> = decs
+        .iter()
+        .map(|dec| match *dec {
+            Dec::Fun(ref name, ..) => name.as_str(),
+            _ => unreachable!(),
+        })
+        .collect();
+    let edges: Vec<Vec<usize>> = decs
+        .iter()
+        .map(|dec| {
+            let mut called = HashSet::new();
+            if let Dec::Fun(_, _, _, ref body) = *dec {
+                calls(body, &mut called);
+            }
+            (0..decs.len())
+                .filter(|&j| called.contains(names[j]))
+                .collect()
+        })
+        .collect();
+    struct Tarjan<'a> {
+        edges: &'a [Vec<usize>],
+        next: usize,
+        index: Vec<Option<usize>>,
+        low: Vec<usize>,
+        stack: Vec<usize>,
+        on_stack: Vec<bool>,
+        components: Vec<Vec<usize>>,
+    }
+    fn visit(t: &mut Tarjan, v: usize) {
+        t.index[v] = Some(t.next);
+        t.low[v] = t.next;
+        t.next += 1;
+        t.stack.push(v);
+        t.on_stack[v] = true;
+        for &w in &t.edges[v] {
+            match t.index[w] {
+                None => {
+                    visit(t, w);
+                    t.low[v] = t.low[v].min(t.low[w]);
+                }
+                Some(i) if t.on_stack[w] => t.low[v] = t.low[v].min(i),
+                Some(_) => {}
+            }
+        }
+        if Some(t.low[v]) == t.index[v] {
+            let mut component = t
+                .stack
+                .split_off(t.stack.iter().position(|&w| w == v).unwrap());
+            for &w in &component {
+                t.on_stack[w] = false;
+            }
+            component.sort();
+            t.components.push(component);
+        }
+    }
+    let mut t = Tarjan {
+        edges: &edges,
+        next: 0,
+        index: vec![None; decs.len()],
+        low: vec![0; decs.len()],
+        stack: Vec::new(),
+        on_stack: vec![false; decs.len()],
+        components: Vec::new(),
+    };
+    for v in 0..decs.len() {
+        if t.index[v].is_none() {
+            visit(&mut t, v);
+        }
+    }
+    t.components
+}
+
+/* Adds the name of every function e calls to called. */
+fn calls<'a>(e: &'a Expr, called: &mut HashSet<&'a str>) {
+    fn lvalue<'a>(l: &'a LValue, called: &mut HashSet<&'a str>) {
+        match *l {
+            LValue::Id(_) => {}
+            LValue::Mem(ref l, _) => lvalue(l, called),
+            LValue::Idx(ref l, ref e) => {
+                lvalue(l, called);
+                calls(e, called);
+            }
+        }
+    }
+    match *e {
+        Expr::Num(_) | Expr::Str(_) | Expr::Nil | Expr::Break => {}
+        Expr::LVal(ref l) => lvalue(l, called),
+        Expr::Neg(ref e) | Expr::Spanned(_, ref e) => calls(e, called),
+        Expr::OpNode(_, ref e1, ref e2) | Expr::Do(ref e1, ref e2) => {
+            calls(e1, called);
+            calls(e2, called);
+        }
+        Expr::IfNode(ref c, ref e1, ref e2) | Expr::For(_, ref c, ref e1, ref e2) => {
+            calls(c, called);
+            calls(e1, called);
+            calls(e2, called);
+        }
+        Expr::NewArray(_, ref n, ref init) => {
+            calls(n, called);
+            calls(init, called);
+        }
+        Expr::LAsgn(ref l, ref e) => {
+            lvalue(l, called);
+            calls(e, called);
+        }
+        Expr::Seq(ref es) => es.iter().for_each(|e| calls(e, called)),
+        Expr::NewStruct(_, ref fields) => fields.iter().for_each(|f| calls(&f.1, called)),
+        Expr::FunApp(ref f, ref es) => {
+            called.insert(f);
+            es.iter().for_each(|e| calls(e, called));
+        }
+        Expr::Let(ref decs, ref body) => {
+            for dec in decs {
+                if let Dec::Var(_, _, ref e, _) | Dec::Fun(_, _, _, ref e) = *dec {
+                    calls(e, called);
+                }
+            }
+            calls(body, called);
+        }
+    }
+}
+
+fn var_dec(
+    ctx: &mut Ctx,
+    name: &str,
+    opt_ty: &Option<String>,
+    e: &Expr,
+    env: &Env,
+) -> Result<(TypedExpr, Ty), Diagnostic> {
+    if let Some(ref ty) = *opt_ty {
+        let ty = ctx.lookup_type(env, ty);
+        let te = expect_type(ctx, e, env, ty, &format!("initial value of {}", name))?;
+        return Ok((te, ty));
+    }
+    let te = f_sub(ctx, e, env)?;
+    let ty = ctx.ty_of(&te);
+    let span = span_of(e).or(ctx.span);
+    if let Ty::Var(_) = ty {
+        let (saved, name1, name2) = (ctx.span, name.to_string(), name.to_string());
+        ctx.span = span;
+        ctx.defer(
+            ty,
+            |ty| ty != Ty::Unit,
+            Code::NoValue,
+            move |_| format!("initial value of {} produces no value", name1),
+        );
+        ctx.defer(
+            ty,
+            |ty| ty != Ty::Nil,
+            Code::UntypedNil,
+            move |_| {
+                format!(
+                    "{} is initialized with nil, so it needs a record type annotation",
+                    name2
+                )
+            },
+        );
+        ctx.span = saved;
+    }
+    if ty == Ty::Unit {
+        return Err(Diagnostic::error(
+            Code::NoValue,
+            span,
+            format!("initial value of {} produces no value", name),
+        ));
+    }
+    if ty == Ty::Nil {
+        return Err(Diagnostic::error(
+            Code::UntypedNil,
+            span,
+            format!(
+                "{} is initialized with nil, so it needs a record type annotation",
+                name
+            ),
+        )
+        .with_note(format!(
+            "write `var {}: T := nil` with T a record type",
+            name
+        )));
+    }
+    Ok((te, ty))
+}
+
+fn let_decs(ctx: &mut Ctx, decs: &[Dec], env: &mut Env) -> Vec<TypedDec> {
+    let mut tdecs = Vec::new();
+    let mut i = 0;
+    while i < decs.len() {
+        match decs[i] {
+            Dec::Fun(..) => {
+                let mut j = i;
+                while j < decs.len() && matches!(decs[j], Dec::Fun(..)) {
+                    j += 1;
+                }
+                tdecs.extend(fun_group(ctx, &decs[i..j], env));
+                i = j;
+                continue;
+            }
+            Dec::Type(..) => {
+                let mut j = i;
+                while j < decs.len() && matches!(decs[j], Dec::Type(..)) {
+                    j += 1;
+                }
+                type_group(ctx, &decs[i..j], env);
+                i = j;
+                continue;
+            }
+            Dec::Var(ref name, ref opt_ty, ref e, _) => {
+                let (te, ty) = match var_dec(ctx, name, opt_ty, e, env) {
+                    Ok(dec) => dec,
+                    Err(d) => (recover(ctx, Err(d)), Ty::Unknown),
+                };
+                let id = ctx.declare(Site::Dec(&decs[i]));
+                env.vars.insert(name.clone(), Entry::Var(ty, id));
+                tdecs.push(TypedDec::Var(name.clone(), id, ty, te));
+            }
+        }
+        i += 1;
+    }
+    tdecs
+}
+
+fn f_sub(ctx: &mut Ctx, ast: &Expr, env: &Env) -> Result<TypedExpr, Diagnostic> {
+    match *ast {
+        Expr::Num(i) => Ok(TypedExpr::Num(i)),
+        Expr::Str(ref s) => Ok(TypedExpr::Str(s.clone())),
+        Expr::Nil => Ok(TypedExpr::Nil),
+        Expr::LVal(ref l) => lval(ctx, l, env),
+        Expr::Neg(ref e) => {
+            let te = expect_type(ctx, e, env, Ty::Int, "operand of unary minus")?;
+            Ok(TypedExpr::Neg(Box::new(te)))
+        }
+        Expr::OpNode(op, ref e1, ref e2) => op_node(ctx, op, e1, e2, env),
+        Expr::IfNode(ref c, ref e1, ref e2) => if_node(ctx, c, e1, e2, env),
+        Expr::LAsgn(ref l, ref e) => {
+            if let LValue::Id(ref x) = *l {
+                if let Some(Entry::LoopVar(_)) = env.vars.get(x) {
+                    return ctx.err(
+                        Code::AssignToIndex,
+                        format!("cannot assign to {}, the index of a for loop", x),
+                    );
+                }
+            }
+            let tl = lval(ctx, l, env)?;
+            let te = expect_type(ctx, e, env, ctx.ty_of(&tl), "assigned value")?;
+            Ok(TypedExpr::Assign(Box::new(tl), Box::new(te)))
+        }
+        Expr::Seq(ref es) => {
+            let mut tes = Vec::with_capacity(es.len());
+            for e in es {
+                tes.push(f_sub(ctx, e, env)?);
+            }
+            let ty = tes.last().map_or(Ty::Unit, |e| ctx.ty_of(e));
+            Ok(TypedExpr::Seq(tes, ty))
+        }
+        Expr::Let(ref decs, ref body) => {
+            let mut cp_env = env.clone();
+            let tdecs = let_decs(ctx, decs, &mut cp_env);
+            let tbody = f_sub(ctx, body, &cp_env)?;
+            let ty = ctx.ty_of(&tbody);
+            Ok(TypedExpr::Let(tdecs, Box::new(tbody), ty))
+        }
+        Expr::For(ref var, ref st, ref en, ref body) => {
+            let tst = expect_type(ctx, st, env, Ty::Int, "lower bound of for")?;
+            let ten = expect_type(ctx, en, env, Ty::Int, "upper bound of for")?;
+            let id = ctx.declare(Site::For(ast));
+            let mut cp_env = env.clone();
+            cp_env.vars.insert(var.clone(), Entry::LoopVar(id));
+            cp_env.in_loop = true;
+            let tbody = expect_no_value(ctx, body, &cp_env, "body of for")?;
+            Ok(TypedExpr::For(
+                var.clone(),
+                id,
+                Box::new(tst),
+                Box::new(ten),
+                Box::new(tbody),
+            ))
+        }
+        Expr::Do(ref c, ref body) => {
+            let tc = expect_type(ctx, c, env, Ty::Int, "condition of while")?;
+            let body_env = Env {
+                in_loop: true,
+                ..env.clone()
+            };
+            let tbody = expect_no_value(ctx, body, &body_env, "body of while")?;
+            Ok(TypedExpr::While(Box::new(tc), Box::new(tbody)))
+        }
+        Expr::FunApp(ref f, ref es) => {
+            let (params, ret, id) = match env.vars.get(f) {
+                Some(Entry::Fun(params, ret, id)) => (params.clone(), *ret, *id),
+                Some(Entry::Var(..) | Entry::LoopVar(_)) => {
+                    return ctx.err(
+                        Code::WrongKind,
+                        format!("{} is a variable, not a function", f),
+                    )
+                }
+                None => {
+                    return ctx.err(Code::UndefinedFunction, format!("undefined function {}", f))
+                }
+            };
+            if params.len() != es.len() {
+                return ctx.err(
+                    Code::ArgumentCount,
+                    format!(
+                        "{} takes {} argument(s), but {} were given",
+                        f,
+                        params.len(),
+                        es.len()
+                    ),
+                );
+            }
+            let mut targs = Vec::with_capacity(es.len());
+            for (i, (e, &ty)) in es.iter().zip(&params).enumerate() {
+                targs.push(expect_type(
+                    ctx,
+                    e,
+                    env,
+                    ty,
+                    &format!("argument {} of {}", i + 1, f),
+                )?);
+            }
+            Ok(TypedExpr::FunApp(
+                f.clone(),
+                id,
+                params,
+                ctx.norm(ret),
+                targs,
+            ))
+        }
+        Expr::NewStruct(ref tyname, ref fields) => {
+            let ty = ctx.lookup_type(env, tyname);
+            let expected = match ty {
+                Ty::Record(i) => match ctx.types[i] {
+                    TyDef::Record(_, ref fields) => fields.clone(),
+                    TyDef::Array(..) => unreachable!(),
+                },
+                Ty::Unknown => fields.iter().map(|f| (f.0.clone(), Ty::Unknown)).collect(),
+                _ => return ctx.err(Code::NotRecord, format!("{} is not a record type", tyname)),
+            };
+            let names: Vec<&String> = fields.iter().map(|f| &f.0).collect();
+            let expected_names: Vec<&String> = expected.iter().map(|f| &f.0).collect();
+            if names != expected_names {
+                return ctx.err(
+                    Code::RecordFields,
+                    format!(
+                        "record {} has fields {{{}}}, but {{{}}} were given",
+                        tyname,
+                        join_names(&expected_names),
+                        join_names(&names)
+                    ),
+                );
+            }
+            let mut tfields = Vec::with_capacity(fields.len());
+            for ((name, e), &(_, fty)) in fields.iter().zip(&expected) {
+                tfields.push((
+                    name.clone(),
+                    expect_type(ctx, e, env, fty, &format!("field {}", name))?,
+                ));
+            }
+            Ok(TypedExpr::NewStruct(ty, tfields))
+        }
+        Expr::NewArray(ref tyname, ref n, ref init) => {
+            let ty = ctx.lookup_type(env, tyname);
+            let elem = match ty {
+                Ty::Array(i) => match ctx.types[i] {
+                    TyDef::Array(_, elem) => elem,
+                    TyDef::Record(..) => unreachable!(),
+                },
+                Ty::Unknown => Ty::Unknown,
+                _ => return ctx.err(Code::NotArray, format!("{} is not an array type", tyname)),
+            };
+            let tn = expect_type(ctx, n, env, Ty::Int, "array size")?;
+            let tinit = expect_type(ctx, init, env, elem, "initial value of array")?;
+            Ok(TypedExpr::NewArray(ty, Box::new(tn), Box::new(tinit)))
+        }
+        Expr::Break if env.in_loop => Ok(TypedExpr::Break),
+        Expr::Break => ctx.err(
+            Code::BreakOutsideLoop,
+            "break outside of a loop".to_string(),
+        ),
+        Expr::Spanned(span, ref e) => {
+            let saved = ctx.span;
+            ctx.span = Some(span);
+            let result = f_sub(ctx, e, env);
+            let result = recover(ctx, result);
+            ctx.span = saved;
+            Ok(result)
+        }
+    }
+}
+
+fn join_names(names: &[&String]) -> String {
+    names
+        .iter()
+        .map(|s| s.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn builtin_type(name: &str) -> Option<Ty> {
+    match name {
+        "int" => Some(Ty::Int),
+        "string" => Some(Ty::Str),
+        _ => None,
+    }
+}
+
+pub fn f(ast: &Expr) -> Result<(TypeTable, TypedExpr), Vec<Diagnostic>> {
+    f_with_host(ast, &[])
+}
+
+/* Like f, with the signatures of host functions in scope. */
+pub fn f_with_host(
+    ast: &Expr,
+    host: &[(String, HostFun)],
+) -> Result<(TypeTable, TypedExpr), Vec<Diagnostic>> {
+    let host_names: Vec<&str> = host.iter().map(|h| h.0.as_str()).collect();
+    let (res, _) = resolve::f(ast, &host_names);
+    f_resolved(ast, host, res)
+}
+
+/* Like f_with_host, given the resolution of ast, whose BindingIds typed trees carry. */
+fn f_resolved(
+    ast: &Expr,
+    host: &[(String, HostFun)],
+    res: resolve::Resolution,
+) -> Result<(TypeTable, TypedExpr), Vec<Diagnostic>> {
+    let mut ctx = Ctx {
+        types: Vec::new(),
+        span: None,
+        diagnostics: Vec::new(),
+        vars: Vec::new(),
+        deferred: Vec::new(),
+        sites: res.sites,
+    };
+    let mut env = Env {
+        vars: HashMap::new(),
+        types: HashMap::new(),
+        in_loop: false,
+    };
+    for name in ["int", "string"] {
+        env.types
+            .insert(name.to_string(), builtin_type(name).unwrap());
+    }
+    for (k, (name, fun)) in host.iter().enumerate() {
+        let mut params = Vec::with_capacity(fun.params.len());
+        for ty in &fun.params {
+            params.push(ctx.lookup_type(&env, ty));
+        }
+        let ret = match fun.ret {
+            Some(ref ty) => ctx.lookup_type(&env, ty),
+            None => Ty::Unit,
+        };
+        let id = ctx.declare(Site::Host(k));
+        env.vars.insert(name.clone(), Entry::Fun(params, ret, id));
+    }
+    let tast = f_sub(&mut ctx, ast, &env);
+    let mut tast = recover(&mut ctx, tast);
+    ctx.check_deferred();
+    ctx.settle(&mut tast);
+    if ctx.diagnostics.is_empty() {
+        Ok((ctx.types, tast))
+    } else {
+        Err(ctx.diagnostics)
+    }
+}
+
+/*
+ * Parses, resolves and checks src, collecting every problem found; the located tree is
+ * returned too. Unbound names are reported by the resolver, with suggestions.
+ */
+pub fn check(
+    src: &str,
+    host: &[(String, HostFun)],
+) -> Result<(Expr, TypeTable, TypedExpr), Vec<Diagnostic>> {
+    let ast = parse::parse_diagnostic(src).map_err(|d| vec![d])?;
+    let host_names: Vec<&str> = host.iter().map(|h| h.0.as_str()).collect();
+    let (res, mut diags) = resolve::f(&ast, &host_names);
+    match f_resolved(&ast, host, res) {
+        Ok((types, tast)) if diags.is_empty() => Ok((ast, types, tast)),
+        Ok(_) => Err(diags),
+        Err(type_diags) => {
+            diags.extend(type_diags.into_iter().filter(|d| {
+                !matches!(
+                    d.code,
+                    Code::UndefinedVariable | Code::UndefinedFunction | Code::UndefinedType
+                )
+            }));
+            diags.sort_by_key(|d| d.span.map(|s| s.lo));
+            Err(diags)
         }
-        let ty_body = expect_type(fundecs, &fd_body, &mut env, &fd_retty,
-                                  "return type differs");
-        tfundecs.push((fd_name, fd_arg, fd_retty, ty_body))
     }
-    (tfundecs, tast)
 }
 
 #[cfg(test)]
 mod tests {
-    use parse;
-    use typing;
-    use ast;
-    use ast::{AST, Op, Type, TypedAST};
-    fn typing(ast: &AST) -> TypedAST {
-        typing::f(&Vec::new(), ast).1
+    use super::*;
+    use crate::{library, parse};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    fn typing(src: &str) -> Ty {
+        let (_, tast) = f(&parse::parse_located(src)).unwrap_or_else(|e| panic!("{:?}", e));
+        ty_of_ast(&tast)
+    }
+    /* The first error reported for src. */
+    fn type_error(src: &str) -> String {
+        f(&parse::parse_located(src)).unwrap_err()[0]
+            .message
+            .clone()
     }
     #[test]
     fn operations_test() {
-        let ast1 = AST::OpNode(Op::Sub, Box::new(AST::Num(7)), Box::new(AST::Num(4)));
-        assert_eq!(ast::ty_of_ast(&typing(&ast1)), Type::Int);
-        let ast2 = AST::OpNode(Op::Div, Box::new(AST::Num(20)), Box::new(AST::Num(4)));
-        assert_eq!(ast::ty_of_ast(&typing(&ast2)), Type::Int);
+        assert_eq!(typing("7 - 4"), Ty::Int);
+        assert_eq!(typing("20 / 4"), Ty::Int);
+        assert_eq!(typing("\"a\" < \"b\""), Ty::Int);
+        assert_eq!(
+            type_error("1 + \"a\""),
+            "operand of Add: expected int, but got string"
+        );
+        assert_eq!(
+            type_error("1 < \"a\""),
+            "operator Lt cannot be applied to int and string"
+        );
     }
     #[test]
     fn letex_test() {
-        let ast1 = parse::parse("let x = 4 in x + x");
-        assert_eq!(ast::ty_of_ast(&typing(&ast1.1)), Type::Int);
-        let ast2 = parse::parse("let x = 4 in let x = 3 in x + x");
-        assert_eq!(ast::ty_of_ast(&typing(&ast2.1)), Type::Int);
-        let ast3 = parse::parse("let x = 4 in (let x = 3 in x) + x");
-        assert_eq!(ast::ty_of_ast(&typing(&ast3.1)), Type::Int);
+        assert_eq!(typing("let var x := 4 in x + x end"), Ty::Int);
+        assert_eq!(
+            typing("let var x := 4 in let var x := \"s\" in x end end"),
+            Ty::Str
+        );
+        assert_eq!(
+            typing("let var x := 4 in (let var x := 3 in x end) + x end"),
+            Ty::Int
+        );
+        assert_eq!(typing("let var x := 4 in x := 3 end"), Ty::Unit);
+        assert_eq!(
+            type_error("let var x: string := 4 in x end"),
+            "initial value of x: expected string, but got int"
+        );
+        assert_eq!(
+            type_error("let var x := 1 in y end"),
+            "undefined variable y"
+        );
     }
     #[test]
     fn fundec_test() {
-        let (fundec1, ast1) = parse::parse("def id(x: int): int {x} id(5)");
-        let (_ty_fundec1, ty_ast1) = typing::f(&fundec1, &ast1);
-        assert_eq!(ast::ty_of_ast(&ty_ast1), Type::Int);
+        assert_eq!(
+            typing("let function id(x: int): int = x in id(5) end"),
+            Ty::Int
+        );
+        // mutually recursive functions in one group
+        assert_eq!(
+            typing("let function even(n: int): int = if n = 0 then 1 else odd(n - 1)
+                        function odd(n: int): int = if n = 0 then 0 else even(n - 1) in even(4) end"),
+            Ty::Int
+        );
+        // without an annotation, the result type is that of the body
+        assert_eq!(
+            typing("let function fib(x: int) = if x <= 1 then x else fib(x - 1) + fib(x - 2) in fib(5) end"),
+            Ty::Int
+        );
+        assert_eq!(
+            type_error("let function id(x: int): int = x in id(\"a\") end"),
+            "argument 1 of id: expected int, but got string"
+        );
+        assert_eq!(
+            type_error("let function id(x: int): int = x in id(1, 2) end"),
+            "id takes 1 argument(s), but 2 were given"
+        );
+        assert_eq!(
+            type_error("let function g(): string = 3 in g() end"),
+            "result of g: expected string, but got int"
+        );
+    }
+    #[test]
+    fn procedure_test() {
+        // the result type of a recursive group is inferred across calls
+        assert_eq!(
+            typing(
+                "let function f(n: int) = if n = 0 then g() else f(n - 1)
+                        function g() = 42 in f(3) end"
+            ),
+            Ty::Int
+        );
+        assert_eq!(
+            typing("let var x := 0 function p() = x := 1 in p() end"),
+            Ty::Unit
+        );
+        // a function that only returns its own result never produces a value
+        assert_eq!(typing("let function f() = f() in f() end"), Ty::Unit);
+        assert_eq!(
+            type_error("let var x := 0 function p() = x := 1 in x := p() end"),
+            "assigned value: expected int, but got unit"
+        );
+        let src = "let var x := 0 function p() = x := 1 in 1 + p() end";
+        let err = &f(&parse::parse_located(src)).unwrap_err()[0];
+        assert_eq!(err.code, Code::NoValue);
+        assert_eq!(
+            type_error("let function p() = () var y := p() in y end"),
+            "initial value of y produces no value"
+        );
+    }
+    #[test]
+    fn inference_test() {
+        // f is checked before g, whose result it returns
+        assert_eq!(
+            typing(
+                "let function f(n: int) = g(n)
+                        function g(n: int) = if n = 0 then \"done\" else f(n - 1) in f(3) end"
+            ),
+            Ty::Str
+        );
+        // a use fixes the result type before the body does
+        assert_eq!(
+            type_error(
+                "let function f(x: int) = if x then \"s\" else (let var y := f(x - 1) + 1 in \"t\" end)
+                 in f(1) end"
+            ),
+            "result of f: its calls need int, but its body has type string"
+        );
+        assert_eq!(
+            type_error(
+                "let function p(x: int) = if x then p(x - 1) in let var y := p(1) in y end end"
+            ),
+            "initial value of y produces no value"
+        );
+        let src = "let type r = {x: int}
+                       function f(n: int) = let var v := f(n - 1) in v.x end in f(1) end";
+        let err = &f(&parse::parse_located(src)).unwrap_err()[0];
+        assert_eq!(err.code, Code::PendingResult);
+        assert_eq!(err.message, "the result type of f is not known yet here");
+        // every body is checked once, however deeply recursive functions nest
+        // (built directly, as the parser backtracks on deep nesting)
+        let n = || Expr::LVal(LValue::Id("n".to_string()));
+        let mut ast = Expr::Num(0);
+        for k in 0..20 {
+            let name = format!("f{}", k);
+            let call = |arg| Expr::FunApp(name.clone(), vec![arg]);
+            let recur = call(Expr::OpNode(Op::Sub, Box::new(n()), Box::new(Expr::Num(1))));
+            let body = Expr::IfNode(
+                Box::new(Expr::OpNode(Op::Eq, Box::new(n()), Box::new(Expr::Num(0)))),
+                Box::new(Expr::Num(0)),
+                Box::new(Expr::OpNode(Op::Add, Box::new(recur), Box::new(ast))),
+            );
+            let params = vec![("n".to_string(), "int".to_string())];
+            let dec = Dec::Fun(name.clone(), params, None, body);
+            ast = Expr::Let(vec![dec], Box::new(Expr::Seq(vec![call(Expr::Num(1))])));
+        }
+        let (_, tast) = f(&ast).unwrap_or_else(|e| panic!("{:?}", e));
+        assert_eq!(ty_of_ast(&tast), Ty::Int);
+    }
+    #[test]
+    fn binding_test() {
+        /* Every name of tast with its BindingId and, for a declaration, its kind. */
+        fn names(tast: &TypedExpr, out: &mut Vec<(String, BindingId, Option<resolve::Kind>)>) {
+            use resolve::Kind;
+            match *tast {
+                TypedExpr::Var(ref x, id, _) => out.push((x.clone(), id, None)),
+                TypedExpr::FunApp(ref x, id, _, _, ref args) => {
+                    out.push((x.clone(), id, None));
+                    args.iter().for_each(|e| names(e, out));
+                }
+                TypedExpr::For(ref x, id, ref lo, ref hi, ref body) => {
+                    names(lo, out);
+                    names(hi, out);
+                    out.push((x.clone(), id, Some(Kind::LoopIndex)));
+                    names(body, out);
+                }
+                TypedExpr::Let(ref decs, ref body, _) => {
+                    for dec in decs {
+                        match *dec {
+                            TypedDec::Var(ref x, id, _, ref e) => {
+                                names(e, out);
+                                out.push((x.clone(), id, Some(Kind::Var)));
+                            }
+                            TypedDec::Fun((ref x, id, ref params, _, ref e)) => {
+                                out.push((x.clone(), id, Some(Kind::Fun)));
+                                for &(ref p, id, _) in params {
+                                    out.push((p.clone(), id, Some(Kind::Param)));
+                                }
+                                names(e, out);
+                            }
+                        }
+                    }
+                    names(body, out);
+                }
+                TypedExpr::Field(ref e, ..) | TypedExpr::Neg(ref e) => names(e, out),
+                TypedExpr::Index(ref e1, ref e2, _)
+                | TypedExpr::OpNode(_, _, ref e1, ref e2)
+                | TypedExpr::Assign(ref e1, ref e2)
+                | TypedExpr::While(ref e1, ref e2)
+                | TypedExpr::NewArray(_, ref e1, ref e2) => {
+                    names(e1, out);
+                    names(e2, out);
+                }
+                TypedExpr::IfNode(ref c, _, ref e1, ref e2) => {
+                    names(c, out);
+                    names(e1, out);
+                    names(e2, out);
+                }
+                TypedExpr::Seq(ref es, _) => es.iter().for_each(|e| names(e, out)),
+                TypedExpr::NewStruct(_, ref fields) => fields.iter().for_each(|f| names(&f.1, out)),
+                TypedExpr::Num(_) | TypedExpr::Str(_) | TypedExpr::Nil | TypedExpr::Break => {}
+            }
+        }
+        // f is checked after g, whose body declares names of its own
+        let src = "let type r = {x: int}
+  var a := 1
+  function f(n: int): int = (g(n); let var b := n in b end)
+  function g(m: int) = let type t = {y: int} var c := t {y = m} in for i := 1 to c.y do a := a + i end
+  type s = array of r
+in f(a); let var a := s [1] of nil in print(\"x\"); size(\"\") + a[0].x end end";
+        let host = library::functions(
+            Rc::new(RefCell::new(Vec::new())),
+            Rc::new(RefCell::new(&b""[..])),
+        );
+        // the names of tast, in the order resolve declares and uses them, checked against it
+        let ids = |src: &str| {
+            let (ast, _, tast) = check(src, &host).unwrap_or_else(|e| panic!("{:?}", e));
+            let (res, _) = resolve::f(&ast, library::NAMES);
+            let mut found = Vec::new();
+            names(&tast, &mut found);
+            for &(ref name, id, kind) in &found {
+                let b = &res.bindings[id];
+                assert_eq!(b.name, *name);
+                if let Some(kind) = kind {
+                    assert_eq!(b.kind, kind, "{}", name);
+                }
+            }
+            found
+        };
+        assert_eq!(ids(src).len(), 23);
+        let mut paths: Vec<_> = std::fs::read_dir("sample")
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "tiger"))
+            .collect();
+        paths.sort();
+        assert!(!paths.is_empty());
+        for path in paths {
+            ids(&std::fs::read_to_string(path).unwrap());
+        }
+    }
+    #[test]
+    fn record_array_test() {
+        assert_eq!(
+            typing("let type p = {x: int, s: string} var a := p {x = 1, s = \"\"} in a.s end"),
+            Ty::Str
+        );
+        assert_eq!(
+            typing("let type p = {x: int} var a: p := nil in a := nil; a end"),
+            Ty::Record(0)
+        );
+        assert_eq!(
+            typing("let type a = array of int var x := a [3] of 0 in x[1] end"),
+            Ty::Int
+        );
+        assert_eq!(
+            type_error("let type p = {x: int} var a := p {y = 1} in 0 end"),
+            "record p has fields {x}, but {y} were given"
+        );
+        assert_eq!(
+            type_error("let type p = {x: int} var a := p {x = 1} in a.y end"),
+            "record type p has no field y"
+        );
+        assert_eq!(
+            type_error("let var x := 1 in x[0] end"),
+            "int is not an array type"
+        );
+        // each record declaration is a distinct type, even with the same fields
+        assert_eq!(
+            type_error("let type p = {x: int} type q = {x: int} var a: p := q {x = 1} in 0 end"),
+            "initial value of a: expected p, but got q"
+        );
+        assert_eq!(
+            typing("let type p = {x: int} type q = p var a: p := q {x = 1} in a end"),
+            Ty::Record(0)
+        );
+    }
+    #[test]
+    fn type_dec_test() {
+        assert_eq!(
+            typing("let type myint = int var x: myint := 3 in x end"),
+            Ty::Int
+        );
+        // aliases may come before the type they name
+        assert_eq!(
+            typing("let type b = a type a = string var x: b := \"s\" in x end"),
+            Ty::Str
+        );
+        // a recursive record
+        assert_eq!(
+            typing("let type list = {hd: int, tl: list} var l := list {hd = 1, tl = list {hd = 2, tl = nil}} in l.tl.tl end"),
+            Ty::Record(0)
+        );
+        // records and arrays referring to each other through an alias
+        assert_eq!(
+            typing("let type tree = {key: int, children: forest} type forest = array of node type node = tree
+                       var t := tree {key = 1, children = forest [0] of nil} in t.children end"),
+            Ty::Array(1)
+        );
+        assert_eq!(
+            type_error("let type a = b type b = a in 0 end"),
+            "type declarations a -> b -> a form a cycle that does not pass through a record or array type"
+        );
+        assert_eq!(
+            type_error("let type a = a in 0 end"),
+            "type declarations a -> a form a cycle that does not pass through a record or array type"
+        );
+        assert_eq!(
+            type_error("let type a = int type a = string in 0 end"),
+            "type a is declared twice in the same group"
+        );
+        // a cycle is reported once, at its first declaration, whichever alias reaches it
+        let src = "let type c = b type a = b type b = a type d = {x: int} in 0 end";
+        let diags = check(src, &[]).unwrap_err();
+        assert_eq!(diags.len(), 1);
+        assert_eq!(
+            diags[0].message,
+            "type declarations a -> b -> a form a cycle that does not pass through a record or array type"
+        );
+        assert_eq!(diags[0].span.map(|s| &src[s.lo..s.hi]), Some("a"));
+        let diags = check("let type x = y type y = z type z = x in 0 end", &[]).unwrap_err();
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].span.map(|s| s.lo), Some(9));
+        // a var declaration ends the group
+        assert_eq!(
+            type_error("let type p = {next: q} var x := 0 type q = {x: int} in 0 end"),
+            "undefined type q"
+        );
+        assert_eq!(
+            typing("let type t = int var x := 0 type t = string var y: t := \"s\" in y end"),
+            Ty::Str
+        );
+    }
+    #[test]
+    fn nil_test() {
+        const REC: &str = "type rectype = {name: string, id: int} var b: rectype := nil";
+        // test46: comparing a record variable with nil
+        assert_eq!(
+            typing(&format!("let {} in b = nil; b <> nil end", REC)),
+            Ty::Int
+        );
+        assert_eq!(typing(&format!("let {} in nil = b end", REC)), Ty::Int);
+        // test45: nil needs a record type from context
+        assert_eq!(
+            type_error("let type rectype = {name: string} var a := nil in a end"),
+            "a is initialized with nil, so it needs a record type annotation"
+        );
+        assert_eq!(
+            type_error("let var a: int := nil in a end"),
+            "initial value of a: expected int, but got nil"
+        );
+        assert_eq!(
+            type_error("let var s := \"s\" in s := nil end"),
+            "assigned value: expected string, but got nil"
+        );
+        assert_eq!(
+            type_error("let var i := 0 in i = nil end"),
+            "operator Eq cannot be applied to int and nil"
+        );
+        assert_eq!(
+            type_error("nil = nil"),
+            "operator Eq cannot be applied to nil and nil"
+        );
+        // nil passed for and returned as a record
+        assert_eq!(
+            typing(&format!(
+                "let {} function f(r: rectype): rectype = nil in f(nil) end",
+                REC
+            )),
+            Ty::Record(0)
+        );
+        assert_eq!(
+            type_error(&format!(
+                "let {} in b := rectype {{name = nil, id = 0}} end",
+                REC
+            )),
+            "field name: expected string, but got nil"
+        );
+        // if branches: nil joins with the record type of the other side
+        assert_eq!(
+            typing(&format!("let {} in if 1 then nil else b end", REC)),
+            Ty::Record(0)
+        );
+        assert_eq!(
+            typing(&format!("let {} in if 1 then b else nil end", REC)),
+            Ty::Record(0)
+        );
+        // without an else there is no nil to join with
+        let src = format!("let {} in if 1 then b end", REC);
+        let err = &f(&parse::parse_located(&src)).unwrap_err()[0];
+        assert_eq!(err.code, Code::IfWithoutElse);
+        assert_eq!(
+            err.message,
+            "if-then without else must produce no value, but this one has type rectype"
+        );
+        assert_eq!(
+            type_error("if 1 then nil"),
+            "if-then without else must produce no value, but this one has type nil"
+        );
+        assert_eq!(
+            type_error("if 1 then nil else 3"),
+            "the then part and the else part of if must have the same type, but they are nil and int"
+        );
+        // test43-style: a record of another type does not join
+        assert_eq!(
+            type_error("let type a = {x: int} type b = {x: int} var va: a := nil var vb: b := nil in if 1 then va else vb end"),
+            "the then part and the else part of if must have the same type, but they are a and b"
+        );
+    }
+    #[test]
+    fn loop_rules_test() {
+        assert_eq!(
+            type_error("for i := 1 to 3 do i := 5"),
+            "cannot assign to i, the index of a for loop"
+        );
+        // a variable declared in the body may shadow the index
+        assert_eq!(
+            typing("for i := 1 to 3 do let var i := 0 in i := 5 end"),
+            Ty::Unit
+        );
+        assert_eq!(
+            typing("let var x := 1 in while x do (x := 0; break) end"),
+            Ty::Unit
+        );
+        assert_eq!(type_error("break"), "break outside of a loop");
+        assert_eq!(
+            type_error("let var x := 0 in (if x then break); x end"),
+            "break outside of a loop"
+        );
+        // a function declared inside a loop cannot break out of it
+        assert_eq!(
+            type_error("for i := 1 to 3 do let function f() = break in f() end"),
+            "break outside of a loop"
+        );
+        let src = "let var x := 0 in\n  for i := 1 to 3 do (x := x + i; i := 0)\nend";
+        let err = &f(&parse::parse_located(src)).unwrap_err()[0];
+        assert_eq!(err.span, Some(Span::new(52, 58)));
+    }
+    #[test]
+    fn multiple_errors_test() {
+        let src = "let
+  type p = {x: int}
+  var a: q := 0
+  var b := nil
+in
+  a := \"s\";
+  a.y;
+  b + 1;
+  undefined(1)
+end";
+        let diags = check(src, &[]).unwrap_err();
+        let codes: Vec<&str> = diags.iter().map(|d| d.code.id()).collect();
+        // a has an unknown type and b none at all: no further errors about them
+        assert_eq!(codes, vec!["E0004", "E0016", "E0003"]);
+        assert_eq!(diags[1].notes.len(), 1);
+        let diags = check("if 1 then 2 else \"s\"", &[]).unwrap_err();
+        assert_eq!(diags[0].code, Code::IfBranches);
+        assert_eq!(diags[0].labels[1].message, "this is string");
+        assert_eq!(check("1 +", &[]).unwrap_err()[0].code, Code::Syntax);
+    }
+    #[test]
+    fn control_test() {
+        assert_eq!(typing("if 1 then 2 else 3"), Ty::Int);
+        assert_eq!(typing("let var x := 0 in if 1 then x := 2 end"), Ty::Unit);
+        assert_eq!(
+            typing("let var x := 0 in while x < 3 do x := x + 1 end"),
+            Ty::Unit
+        );
+        assert_eq!(typing("for i := 1 to 3 do (if i = 2 then break)"), Ty::Unit);
+        assert_eq!(
+            type_error("for i := 1 to 3 do i"),
+            "body of for: expected unit, but got int"
+        );
+        assert_eq!(
+            type_error("if 1 then 2"),
+            "if-then without else must produce no value, but this one has type int"
+        );
+        assert_eq!(
+            type_error("if 1 then 2 else \"3\""),
+            "the then part and the else part of if must have the same type, but they are int and string"
+        );
+    }
+    #[test]
+    fn error_span_test() {
+        let src = "let var x := 1 in\n  x + \"a\" end";
+        let err = &f(&parse::parse_located(src)).unwrap_err()[0];
+        assert_eq!(err.span, Some(Span::new(24, 27)));
     }
 }
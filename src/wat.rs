@@ -0,0 +1,743 @@
+use crate::ast::{ty_of_ast, Op, Ty, TyDef, TypeTable, TypedDec, TypedExpr};
+use crate::escape::Escapes;
+use crate::library;
+use crate::resolve::BindingId;
+use crate::translate::{self, MAIN};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+
+/*
+ * The WebAssembly backend: typed trees become a module in the text format, whose
+ * structured control flow Tiger's maps onto directly. Every value is an i64; records,
+ * arrays and strings live in linear memory, allocated by a bump allocator in the runtime
+ * (runtime.wat), which needs only print, getchar and exit from the host. Variables that
+ * escape live in environments on a stack in memory, linked by static links as in translate;
+ * the others are locals.
+ *
+ * Memory holds the runtime's buffer and messages, then the program's strings from
+ * DATA_START, then the stack (STACK_SIZE bytes) and the heap.
+ */
+
+pub const RUNTIME: &str = include_str!("runtime.wat");
+const DATA_START: i64 = 256;
+const STACK_SIZE: i64 = 1 << 20;
+const PAGE_SIZE: i64 = 1 << 16;
+
+/* A function being generated, the one it is nested in, and what it declares. */
+struct Level {
+    name: String,
+    parent: Option<usize>,
+    env_size: i64, // bytes in its environment, the static link included
+    uses_env: bool,
+    locals: Vec<String>,
+}
+
+#[derive(Clone)]
+enum Loc {
+    Local(String),
+    Env(i64), // offset in the environment
+}
+
+#[derive(Clone)]
+enum Entry {
+    Var(usize, Loc), // level
+    Fun(usize, String),
+}
+
+type Env = HashMap<BindingId, Entry>;
+
+/* Instructions of a function body, indented by depth. */
+#[derive(Default)]
+struct Body {
+    code: String,
+    depth: usize,
+}
+
+impl Body {
+    fn line(&mut self, s: &str) {
+        for _ in 0..self.depth {
+            self.code += "  ";
+        }
+        self.code += s;
+        self.code.push('\n');
+    }
+
+    fn open(&mut self, s: &str) {
+        self.line(s);
+        self.depth += 1;
+    }
+
+    fn close(&mut self) {
+        self.depth -= 1;
+        self.line("end");
+    }
+}
+
+/* bytes as a string of the text format. */
+fn wat_string(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() + 2);
+    out.push('"');
+    for &b in bytes {
+        match b {
+            b'"' | b'\\' => {
+                out.push('\\');
+                out.push(b as char);
+            }
+            0x20..=0x7e => out.push(b as char),
+            _ => out += &format!("\\{:02x}", b),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn align(n: i64) -> i64 {
+    (n + 7) & !7
+}
+
+struct Ctx<'a> {
+    types: &'a TypeTable,
+    escapes: &'a Escapes,
+    levels: Vec<Level>,
+    labels: HashSet<String>, // function names taken
+    names: usize,            // locals and labels made so far
+    breaks: Vec<String>,     // the block that break leaves in each enclosing loop
+    strings: HashMap<String, i64>,
+    data: Vec<u8>, // from DATA_START
+    funcs: Vec<String>,
+}
+
+impl Ctx<'_> {
+    fn fun_name(&mut self, name: &str) -> String {
+        let mut label = name.to_string();
+        let mut k = 1;
+        while self.labels.contains(&label) {
+            label = format!("{}_{}", name, k);
+            k += 1;
+        }
+        self.labels.insert(label.clone());
+        format!("$tiger_{}", label)
+    }
+
+    fn fresh(&mut self, name: &str) -> String {
+        self.names += 1;
+        format!("${}_{}", name, self.names)
+    }
+
+    fn local(&mut self, level: usize, name: &str) -> String {
+        let local = self.fresh(name);
+        self.levels[level].locals.push(local.clone());
+        local
+    }
+
+    /* A slot in the environment of level, for a variable that escapes. */
+    fn slot(&mut self, level: usize) -> i64 {
+        let offset = self.levels[level].env_size;
+        self.levels[level].env_size += 8;
+        offset
+    }
+
+    fn string(&mut self, s: &str) -> i64 {
+        if let Some(&at) = self.strings.get(s) {
+            return at;
+        }
+        let at = DATA_START + self.data.len() as i64;
+        self.data.extend((s.len() as i64).to_le_bytes());
+        self.data.extend(s.as_bytes());
+        self.data.resize(align(self.data.len() as i64) as usize, 0);
+        self.strings.insert(s.to_string(), at);
+        at
+    }
+
+    fn field_offset(&self, record: Ty, name: &str) -> i64 {
+        match record {
+            Ty::Record(i) => match self.types[i] {
+                TyDef::Record(_, ref fields) => {
+                    8 * fields.iter().position(|f| f.0 == name).unwrap() as i64
+                }
+                TyDef::Array(..) => unreachable!(),
+            },
+            _ => panic!("internal error: field of a non-record"),
+        }
+    }
+
+    /* Pushes the address of the environment of level to, as seen from level from, which it encloses. */
+    fn env(&mut self, mut from: usize, to: usize, body: &mut Body) {
+        self.levels[to].uses_env = true;
+        if from == to {
+            body.line("local.get $env");
+            return;
+        }
+        // the static link is a parameter, so only the environments above are needed
+        body.line("local.get $link");
+        from = self.levels[from].parent.unwrap();
+        while from != to {
+            self.levels[from].uses_env = true;
+            body.line("i32.wrap_i64");
+            body.line("i64.load");
+            from = self.levels[from]
+                .parent
+                .expect("environment above the main program");
+        }
+    }
+
+    fn sub(&mut self, e: &TypedExpr, env: &Env, level: usize, depth: usize) -> Body {
+        let mut body = Body {
+            code: String::new(),
+            depth,
+        };
+        self.expr(e, env, level, &mut body);
+        body
+    }
+
+    /* Generates e for its value, 0 if it has none. */
+    fn value(&mut self, e: &TypedExpr, env: &Env, level: usize, body: &mut Body) {
+        self.expr(e, env, level, body);
+        if ty_of_ast(e) == Ty::Unit {
+            body.line("i64.const 0");
+        }
+    }
+
+    /* Generates e for its effects only. */
+    fn effect(&mut self, e: &TypedExpr, env: &Env, level: usize, body: &mut Body) {
+        self.expr(e, env, level, body);
+        if ty_of_ast(e) != Ty::Unit {
+            body.line("drop");
+        }
+    }
+
+    /* Generates e as an expression of type ty, which may be Unit where e has a value. */
+    fn as_ty(&mut self, e: &TypedExpr, ty: Ty, env: &Env, level: usize, body: &mut Body) {
+        if ty == Ty::Unit {
+            self.effect(e, env, level, body);
+        } else {
+            self.value(e, env, level, body);
+        }
+    }
+
+    /* Generates e as a condition, an i32 that is 0 if it does not hold. */
+    fn cond(&mut self, e: &TypedExpr, env: &Env, level: usize, body: &mut Body) {
+        let (op, ty, e1, e2) = match *e {
+            TypedExpr::OpNode(op, ty, ref e1, ref e2)
+                if matches!(op, Op::Eq | Op::Ne | Op::Lt | Op::Gt | Op::Le | Op::Ge) =>
+            {
+                (op, ty, e1, e2)
+            }
+            _ => {
+                self.value(e, env, level, body);
+                body.line("i64.const 0");
+                body.line("i64.ne");
+                return;
+            }
+        };
+        self.value(e1, env, level, body);
+        self.value(e2, env, level, body);
+        let op = match op {
+            Op::Eq => "eq",
+            Op::Ne => "ne",
+            Op::Lt => "lt_s",
+            Op::Gt => "gt_s",
+            Op::Le => "le_s",
+            Op::Ge => "ge_s",
+            _ => unreachable!(),
+        };
+        match (ty, op) {
+            // stringEqual is 1 for equal strings, stringCompare negative, zero or positive
+            (Ty::Str, "eq") => {
+                body.line("call $tiger_stringEqual");
+                body.line("i32.wrap_i64");
+            }
+            (Ty::Str, "ne") => {
+                body.line("call $tiger_stringEqual");
+                body.line("i64.eqz");
+            }
+            (Ty::Str, _) => {
+                body.line("call $tiger_stringCompare");
+                body.line("i64.const 0");
+                body.line(&format!("i64.{}", op));
+            }
+            _ => body.line(&format!("i64.{}", op)),
+        }
+    }
+
+    /* Pushes the address of element i of array a, after the elements come. */
+    fn element(&mut self, a: &TypedExpr, i: &TypedExpr, env: &Env, level: usize, body: &mut Body) {
+        self.value(a, env, level, body);
+        self.value(i, env, level, body);
+        body.line("i64.const 3");
+        body.line("i64.shl");
+        body.line("i64.add");
+        body.line("i32.wrap_i64");
+    }
+
+    /* Generates e, which leaves a value on the stack unless its type is Unit. */
+    fn expr(&mut self, e: &TypedExpr, env: &Env, level: usize, body: &mut Body) {
+        match *e {
+            TypedExpr::Num(i) => body.line(&format!("i64.const {}", i)),
+            TypedExpr::Str(ref s) => {
+                let at = self.string(s);
+                body.line(&format!("i64.const {}", at));
+            }
+            TypedExpr::Nil => body.line("i64.const 0"),
+            TypedExpr::Var(ref name, id, _) => match env.get(&id) {
+                Some(Entry::Var(_, Loc::Local(local))) => {
+                    body.line(&format!("local.get {}", local));
+                }
+                Some(&Entry::Var(var_level, Loc::Env(offset))) => {
+                    self.env(level, var_level, body);
+                    body.line("i32.wrap_i64");
+                    body.line(&format!("i64.load offset={}", offset));
+                }
+                _ => panic!("internal error: unbound variable {}", name),
+            },
+            TypedExpr::Field(ref r, ref name, _) => {
+                let offset = self.field_offset(ty_of_ast(r), name);
+                self.value(r, env, level, body);
+                body.line("i32.wrap_i64");
+                body.line(&format!("i64.load offset={}", offset));
+            }
+            TypedExpr::Index(ref a, ref i, _) => {
+                self.element(a, i, env, level, body);
+                body.line("i64.load");
+            }
+            TypedExpr::Neg(ref e) => {
+                body.line("i64.const 0");
+                self.value(e, env, level, body);
+                body.line("i64.sub");
+            }
+            TypedExpr::OpNode(Op::And, _, ref e1, ref e2) => {
+                // e1 & e2 is if e1 then e2 else 0
+                self.cond(e1, env, level, body);
+                body.open("if (result i64)");
+                self.value(e2, env, level, body);
+                body.depth -= 1;
+                body.line("else");
+                body.depth += 1;
+                body.line("i64.const 0");
+                body.close();
+            }
+            TypedExpr::OpNode(Op::Or, _, ref e1, ref e2) => {
+                // e1 | e2 is e1 if it is not 0, else e2
+                let t = self.local(level, "t");
+                self.value(e1, env, level, body);
+                body.line(&format!("local.tee {}", t));
+                body.line("i64.const 0");
+                body.line("i64.ne");
+                body.open("if (result i64)");
+                body.line(&format!("local.get {}", t));
+                body.depth -= 1;
+                body.line("else");
+                body.depth += 1;
+                self.value(e2, env, level, body);
+                body.close();
+            }
+            TypedExpr::OpNode(op, _, ref e1, ref e2) => {
+                let instr = match op {
+                    Op::Add => "i64.add",
+                    Op::Sub => "i64.sub",
+                    Op::Mul => "i64.mul",
+                    Op::Div => "i64.div_s",
+                    _ => {
+                        self.cond(e, env, level, body);
+                        body.line("i64.extend_i32_u");
+                        return;
+                    }
+                };
+                self.value(e1, env, level, body);
+                self.value(e2, env, level, body);
+                body.line(instr);
+            }
+            TypedExpr::IfNode(ref c, ty, ref e1, ref e2) => {
+                self.cond(c, env, level, body);
+                let mut b1 = Body {
+                    code: String::new(),
+                    depth: body.depth + 1,
+                };
+                self.as_ty(e1, ty, env, level, &mut b1);
+                let mut b2 = Body {
+                    code: String::new(),
+                    depth: body.depth + 1,
+                };
+                self.as_ty(e2, ty, env, level, &mut b2);
+                body.line(if ty == Ty::Unit {
+                    "if"
+                } else {
+                    "if (result i64)"
+                });
+                body.code += &b1.code;
+                if !b2.code.is_empty() {
+                    body.line("else");
+                    body.code += &b2.code;
+                }
+                body.line("end");
+            }
+            TypedExpr::Assign(ref lval, ref e) => match **lval {
+                TypedExpr::Var(ref name, id, _) => match env.get(&id) {
+                    Some(Entry::Var(_, Loc::Local(local))) => {
+                        let local = local.clone();
+                        self.value(e, env, level, body);
+                        body.line(&format!("local.set {}", local));
+                    }
+                    Some(&Entry::Var(var_level, Loc::Env(offset))) => {
+                        self.env(level, var_level, body);
+                        body.line("i32.wrap_i64");
+                        self.value(e, env, level, body);
+                        body.line(&format!("i64.store offset={}", offset));
+                    }
+                    _ => panic!("internal error: unbound variable {}", name),
+                },
+                TypedExpr::Field(ref r, ref name, _) => {
+                    let offset = self.field_offset(ty_of_ast(r), name);
+                    self.value(r, env, level, body);
+                    body.line("i32.wrap_i64");
+                    self.value(e, env, level, body);
+                    body.line(&format!("i64.store offset={}", offset));
+                }
+                TypedExpr::Index(ref a, ref i, _) => {
+                    self.element(a, i, env, level, body);
+                    self.value(e, env, level, body);
+                    body.line("i64.store");
+                }
+                _ => panic!("internal error: assignment to a non-lvalue"),
+            },
+            TypedExpr::Seq(ref es, ty) => match es.split_last() {
+                Some((last, es)) => {
+                    for e in es {
+                        self.effect(e, env, level, body);
+                    }
+                    self.as_ty(last, ty, env, level, body);
+                }
+                None if ty != Ty::Unit => body.line("i64.const 0"),
+                None => {}
+            },
+            TypedExpr::Let(ref decs, ref e, ty) => {
+                let mut env = env.clone();
+                let mut i = 0;
+                while i < decs.len() {
+                    match decs[i] {
+                        TypedDec::Var(ref name, id, _, ref init) => {
+                            let mut init_body = self.sub(init, &env, level, body.depth);
+                            if ty_of_ast(init) == Ty::Unit {
+                                init_body.line("i64.const 0");
+                            }
+                            let loc = if self.escapes.escapes(id) {
+                                let offset = self.slot(level);
+                                self.env(level, level, body);
+                                body.line("i32.wrap_i64");
+                                body.code += &init_body.code;
+                                body.line(&format!("i64.store offset={}", offset));
+                                Loc::Env(offset)
+                            } else {
+                                let local = self.local(level, name);
+                                body.code += &init_body.code;
+                                body.line(&format!("local.set {}", local));
+                                Loc::Local(local)
+                            };
+                            env.insert(id, Entry::Var(level, loc));
+                            i += 1;
+                        }
+                        TypedDec::Fun(_) => {
+                            let n = decs[i..]
+                                .iter()
+                                .take_while(|dec| matches!(dec, TypedDec::Fun(_)))
+                                .count();
+                            self.fun_group(&decs[i..i + n], &mut env, level);
+                            i += n;
+                        }
+                    }
+                }
+                self.as_ty(e, ty, &env, level, body);
+            }
+            TypedExpr::For(ref var, id, ref lo, ref hi, ref e) => {
+                let lo = self.sub(lo, env, level, body.depth);
+                let limit = self.local(level, "limit");
+                let (get, set, loc) = if self.escapes.escapes(id) {
+                    let offset = self.slot(level);
+                    let mut get = Body::default();
+                    self.env(level, level, &mut get);
+                    get.line("i32.wrap_i64");
+                    let addr = get.code.clone();
+                    get.line(&format!("i64.load offset={}", offset));
+                    let set = (addr, format!("i64.store offset={}", offset));
+                    (get.code, set, Loc::Env(offset))
+                } else {
+                    let local = self.local(level, var);
+                    let get = format!("local.get {}\n", local);
+                    let set = (String::new(), format!("local.set {}", local));
+                    (get, set, Loc::Local(local))
+                };
+                let lines = |body: &mut Body, code: &str| {
+                    for line in code.lines() {
+                        body.line(line);
+                    }
+                };
+                lines(body, &set.0);
+                body.code += &lo.code;
+                body.line(&set.1);
+                self.value(hi, env, level, body);
+                body.line(&format!("local.set {}", limit));
+                let (done, next) = (self.fresh("done"), self.fresh("next"));
+                body.open(&format!("block {}", done));
+                // compares before incrementing, so that hi may be the largest int
+                lines(body, &get);
+                body.line(&format!("local.get {}", limit));
+                body.line("i64.gt_s");
+                body.line(&format!("br_if {}", done));
+                body.open(&format!("loop {}", next));
+                let mut env = env.clone();
+                env.insert(id, Entry::Var(level, loc));
+                self.breaks.push(done.clone());
+                self.effect(e, &env, level, body);
+                self.breaks.pop();
+                lines(body, &get);
+                body.line(&format!("local.get {}", limit));
+                body.line("i64.ge_s");
+                body.line(&format!("br_if {}", done));
+                lines(body, &set.0);
+                lines(body, &get);
+                body.line("i64.const 1");
+                body.line("i64.add");
+                body.line(&set.1);
+                body.line(&format!("br {}", next));
+                body.close();
+                body.close();
+            }
+            TypedExpr::While(ref c, ref e) => {
+                let (done, next) = (self.fresh("done"), self.fresh("next"));
+                body.open(&format!("block {}", done));
+                body.open(&format!("loop {}", next));
+                self.cond(c, env, level, body);
+                body.line("i32.eqz");
+                body.line(&format!("br_if {}", done));
+                self.breaks.push(done.clone());
+                self.effect(e, env, level, body);
+                self.breaks.pop();
+                body.line(&format!("br {}", next));
+                body.close();
+                body.close();
+            }
+            TypedExpr::FunApp(ref name, id, _, _, ref args) => {
+                let fun = match env.get(&id) {
+                    Some(&Entry::Fun(fun_level, ref fun)) => {
+                        let fun = fun.clone();
+                        let parent = self.levels[fun_level].parent.unwrap();
+                        self.env(level, parent, body);
+                        fun
+                    }
+                    _ => format!("$tiger_{}", name),
+                };
+                for arg in args {
+                    self.value(arg, env, level, body);
+                }
+                body.line(&format!("call {}", fun));
+            }
+            TypedExpr::NewStruct(_, ref fields) => {
+                let r = self.local(level, "r");
+                // at least a word, so that every record has an address of its own
+                let size = 8 * fields.len().max(1);
+                body.line(&format!("i64.const {}", size));
+                body.line("call $tiger_allocRecord");
+                body.line(&format!("local.set {}", r));
+                for (k, (_, e)) in fields.iter().enumerate() {
+                    body.line(&format!("local.get {}", r));
+                    body.line("i32.wrap_i64");
+                    self.value(e, env, level, body);
+                    body.line(&format!("i64.store offset={}", 8 * k));
+                }
+                body.line(&format!("local.get {}", r));
+            }
+            TypedExpr::NewArray(_, ref n, ref init) => {
+                self.value(n, env, level, body);
+                self.value(init, env, level, body);
+                body.line("call $tiger_initArray");
+            }
+            TypedExpr::Break => body.line(&format!("br {}", self.breaks.last().unwrap())),
+        }
+    }
+
+    /* Declares every function of a group first, so that members can call each other. */
+    fn fun_group(&mut self, decs: &[TypedDec], env: &mut Env, level: usize) {
+        let mut levels = Vec::with_capacity(decs.len());
+        for dec in decs {
+            if let TypedDec::Fun((ref name, id, ..)) = *dec {
+                let fun = self.fun_name(name);
+                self.levels.push(Level {
+                    name: fun.clone(),
+                    parent: Some(level),
+                    env_size: 8,
+                    uses_env: false,
+                    locals: Vec::new(),
+                });
+                let fun_level = self.levels.len() - 1;
+                env.insert(id, Entry::Fun(fun_level, fun));
+                levels.push(fun_level);
+            }
+        }
+        for (dec, fun_level) in decs.iter().zip(levels) {
+            if let TypedDec::Fun((_, _, ref params, ret, ref e)) = *dec {
+                let mut body = Body {
+                    code: String::new(),
+                    depth: 2,
+                };
+                let mut formals = vec!["(param $link i64)".to_string()];
+                let mut body_env = env.clone();
+                for &(ref param, id, _) in params {
+                    let local = self.fresh(param);
+                    formals.push(format!("(param {} i64)", local));
+                    let loc = if self.escapes.escapes(id) {
+                        let offset = self.slot(fun_level);
+                        self.env(fun_level, fun_level, &mut body);
+                        body.line("i32.wrap_i64");
+                        body.line(&format!("local.get {}", local));
+                        body.line(&format!("i64.store offset={}", offset));
+                        Loc::Env(offset)
+                    } else {
+                        Loc::Local(local)
+                    };
+                    body_env.insert(id, Entry::Var(fun_level, loc));
+                }
+                if ret != Ty::Unit {
+                    formals.push("(result i64)".to_string());
+                }
+                let breaks = std::mem::take(&mut self.breaks);
+                self.as_ty(e, ret, &body_env, fun_level, &mut body);
+                self.breaks = breaks;
+                self.function(fun_level, &formals.join(" "), body);
+            }
+        }
+    }
+
+    /* Adds the function at level, which pushes its environment on entry and pops it on exit. */
+    fn function(&mut self, level: usize, signature: &str, body: Body) {
+        let lv = &self.levels[level];
+        let mut text = format!("  (func {} {}\n", lv.name, signature);
+        let mut locals = lv.locals.clone();
+        if lv.uses_env {
+            locals.insert(0, "$env".to_string());
+        }
+        for local in &locals {
+            text += &format!("    (local {} i64)\n", local);
+        }
+        if lv.uses_env {
+            text += &format!(
+                "    i64.const {}\n    call $enter\n    local.set $env\n",
+                lv.env_size
+            );
+            if lv.parent.is_some() {
+                text +=
+                    "    local.get $env\n    i32.wrap_i64\n    local.get $link\n    i64.store\n";
+            }
+        }
+        text += &body.code;
+        if lv.uses_env {
+            text += "    local.get $env\n    global.set $sp\n";
+        }
+        text.pop();
+        text += ")\n";
+        self.funcs.push(text);
+    }
+
+    fn write<W: Write>(&self, mut out: W) -> io::Result<()> {
+        let stack = DATA_START + self.data.len() as i64;
+        let heap = stack + STACK_SIZE;
+        writeln!(out, "(module")?;
+        writeln!(
+            out,
+            "  (import \"tiger\" \"print\" (func $host_print (param i32 i32)))"
+        )?;
+        writeln!(
+            out,
+            "  (import \"tiger\" \"getchar\" (func $host_getchar (result i32)))"
+        )?;
+        writeln!(
+            out,
+            "  (import \"tiger\" \"exit\" (func $host_exit (param i32)))"
+        )?;
+        writeln!(
+            out,
+            "  (memory (export \"memory\") {})",
+            (heap + PAGE_SIZE - 1) / PAGE_SIZE
+        )?;
+        writeln!(out, "  (global $sp (mut i64) (i64.const {}))", stack)?;
+        writeln!(out, "  (global $stack_end i64 (i64.const {}))", heap)?;
+        writeln!(out, "  (global $hp (mut i64) (i64.const {}))", heap)?;
+        writeln!(out)?;
+        out.write_all(RUNTIME.as_bytes())?;
+        for func in &self.funcs {
+            writeln!(out, "\n{}", func.trim_end())?;
+        }
+        if !self.data.is_empty() {
+            writeln!(
+                out,
+                "\n  (data (i32.const {}) {})",
+                DATA_START,
+                wat_string(&self.data)
+            )?;
+        }
+        // the exit status is the result of the program, which is 0 unless it is an int
+        writeln!(out, "\n  (func (export \"_start\")")?;
+        writeln!(out, "    call $tiger_{}", MAIN)?;
+        writeln!(out, "    i64.const 255\n    i64.and\n    i32.wrap_i64")?;
+        writeln!(out, "    call $host_exit))")
+    }
+}
+
+/*
+ * Writes tast, typed with the given types, as a WebAssembly module in the text format.
+ * escapes come from the resolution of the located tree tast was typed from, as for translate. The
+ * module exports its memory and _start, which runs the program and calls exit with its
+ * status.
+ */
+pub fn write<W: Write>(
+    out: W,
+    tast: &TypedExpr,
+    types: &TypeTable,
+    escapes: &Escapes,
+) -> io::Result<()> {
+    let mut labels: HashSet<String> = translate::RUNTIME
+        .iter()
+        .chain(library::NAMES)
+        .map(|s| s.to_string())
+        .collect();
+    labels.insert(MAIN.to_string());
+    let mut ctx = Ctx {
+        types,
+        escapes,
+        levels: vec![Level {
+            name: format!("$tiger_{}", MAIN),
+            parent: None,
+            env_size: 8,
+            uses_env: false,
+            locals: Vec::new(),
+        }],
+        labels,
+        names: 0,
+        breaks: Vec::new(),
+        strings: HashMap::new(),
+        data: Vec::new(),
+        funcs: Vec::new(),
+    };
+    let mut body = Body {
+        code: String::new(),
+        depth: 2,
+    };
+    if ty_of_ast(tast) == Ty::Int {
+        ctx.expr(tast, &Env::new(), 0, &mut body);
+    } else {
+        ctx.effect(tast, &Env::new(), 0, &mut body);
+        body.line("i64.const 0");
+    }
+    ctx.function(0, "(result i64)", body);
+    ctx.write(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_test() {
+        assert_eq!(wat_string(b"a\"\n\0"), "\"a\\\"\\0a\\00\"");
+        assert_eq!(align(9), 16);
+    }
+}
@@ -0,0 +1,455 @@
+use calc::ast::Value;
+use calc::{eval, library, typing, wat};
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::rc::Rc;
+use std::{env, fs, process};
+
+/* Every sample, in order. */
+fn samples() -> Vec<PathBuf> {
+    let mut paths: Vec<_> = fs::read_dir("sample")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "tiger"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty());
+    paths
+}
+
+/*
+ * Programs on the edges of int arithmetic, where C and LLVM leave the behaviour undefined
+ * but the interpreter wraps or fails.
+ */
+const ARITHMETIC: &[(&str, &str)] = &[
+    (
+        "wrap",
+        "let var m := -9223372036854775807 - 1 in
+  printi(9223372036854775807 + 1); print(chr(10));
+  printi(m - 1); print(chr(10));
+  printi(m * -1); print(chr(10));
+  printi(-m); print(chr(10));
+  m / -1 = m
+end",
+    ),
+    ("divide-by-zero", "let var z := 0 in printi(1); 5 / z end"),
+    (
+        "substring-overflow",
+        "size(substring(\"abc\", 1, 9223372036854775807))",
+    ),
+];
+
+/*
+ * Programs that the interpreter stops with a runtime error, which compiled code must check,
+ * as it must a division by zero.
+ */
+const ERRORS: &[(&str, &str)] = &[
+    (
+        "index-out-of-bounds",
+        "let type a = array of int var x := a[2] of 0 in x[2] end",
+    ),
+    (
+        "negative-index",
+        "let type a = array of int var x := a[2] of 0 in printi(x[1]); x[-1] := 1; 0 end",
+    ),
+    (
+        "nil-field",
+        "let type r = {x: int} var v: r := nil in v.x end",
+    ),
+    (
+        "nil-field-assign",
+        "let type r = {x: int} var v: r := nil in v.x := 1; 0 end",
+    ),
+];
+
+/* Writes programs into dir, giving their paths. */
+fn programs(dir: &Path, programs: &[(&str, &str)]) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    for &(name, src) in programs {
+        let path = dir.join(format!("{}.tiger", name));
+        fs::write(&path, src).unwrap();
+        paths.push(path);
+    }
+    paths
+}
+
+/*
+ * Checks that run, which runs what path was compiled to, prints what the interpreter
+ * prints, and exits with its result (modulo 256) if that is an int, or with 1 if the
+ * interpreter fails.
+ */
+fn check(path: &Path, run: &mut Command) {
+    let run = run.output().unwrap();
+    let (expected, out) = interpret_file(path);
+    assert_eq!(
+        String::from_utf8_lossy(&run.stdout),
+        String::from_utf8_lossy(&out),
+        "{}",
+        path.display()
+    );
+    let status = match expected {
+        Ok(Value::VNum(n)) => n & 0xff,
+        Ok(_) => 0,
+        Err(_) => 1,
+    };
+    assert_eq!(
+        run.status.code(),
+        Some(status as i32),
+        "{}: {}",
+        path.display(),
+        String::from_utf8_lossy(&run.stderr)
+    );
+}
+
+/* Compiles path into dir with `rust-tigress compile`. */
+fn build_native(path: &Path, dir: &Path) -> PathBuf {
+    let exe = dir.join(path.file_stem().unwrap());
+    let compiled = Command::new(env!("CARGO_BIN_EXE_rust-tigress"))
+        .arg("compile")
+        .arg(path)
+        .arg("-o")
+        .arg(&exe)
+        .output()
+        .unwrap();
+    assert!(
+        compiled.status.success(),
+        "{}: {:?}",
+        path.display(),
+        compiled
+    );
+    exe
+}
+
+#[test]
+fn samples_test() {
+    let dir = env::temp_dir().join(format!("rust-tigress-samples-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    for path in samples() {
+        check(&path, &mut Command::new(build_native(&path, &dir)));
+    }
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn errors_test() {
+    let dir = env::temp_dir().join(format!("rust-tigress-errors-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    for path in programs(&dir, ERRORS)
+        .into_iter()
+        .chain(programs(&dir, ARITHMETIC))
+    {
+        check(&path, &mut Command::new(build_native(&path, &dir)));
+    }
+    fs::remove_dir_all(&dir).ok();
+}
+
+/* Compiles the C that `--emit=c` gives for path into dir with $CC or cc, as C99. */
+fn build_c(path: &Path, dir: &Path) -> PathBuf {
+    let cc = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let emitted = Command::new(env!("CARGO_BIN_EXE_rust-tigress"))
+        .arg("--emit=c")
+        .arg(path)
+        .output()
+        .unwrap();
+    assert!(emitted.status.success(), "{}", path.display());
+    let (c, exe) = (
+        dir.join(path.with_extension("c").file_name().unwrap()),
+        dir.join(path.file_stem().unwrap()),
+    );
+    fs::write(&c, &emitted.stdout).unwrap();
+    let compiled = Command::new(&cc)
+        .args(["-std=c99", "-pedantic-errors", "-o"])
+        .arg(&exe)
+        .arg(&c)
+        .output()
+        .unwrap();
+    assert!(
+        compiled.status.success(),
+        "{}: {}",
+        path.display(),
+        String::from_utf8_lossy(&compiled.stderr)
+    );
+    exe
+}
+
+#[test]
+fn c_samples_test() {
+    let dir = env::temp_dir().join(format!("rust-tigress-c-samples-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    for path in samples() {
+        check(&path, &mut Command::new(build_c(&path, &dir)));
+    }
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn c_errors_test() {
+    let dir = env::temp_dir().join(format!("rust-tigress-c-errors-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    for path in programs(&dir, ARITHMETIC)
+        .into_iter()
+        .chain(programs(&dir, ERRORS))
+    {
+        check(&path, &mut Command::new(build_c(&path, &dir)));
+    }
+    fs::remove_dir_all(&dir).ok();
+}
+
+/*
+ * Compares what `--emit=<what>` gives for every sample, without the text of shared, which
+ * it must contain, with tests/<what>/<sample>.<ext>. UPDATE_GOLDEN=1 rewrites the files instead.
+ */
+fn golden(what: &str, ext: &str, shared: &str) {
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+    let dir = Path::new("tests").join(what);
+    fs::create_dir_all(&dir).unwrap();
+    for path in samples() {
+        let emitted = Command::new(env!("CARGO_BIN_EXE_rust-tigress"))
+            .arg(format!("--emit={}", what))
+            .arg(&path)
+            .output()
+            .unwrap();
+        assert!(emitted.status.success(), "{}", path.display());
+        let emitted = String::from_utf8_lossy(&emitted.stdout);
+        assert!(emitted.contains(shared), "{}", path.display());
+        let emitted = emitted.replacen(shared, "", 1);
+        let golden = dir.join(path.with_extension(ext).file_name().unwrap());
+        if update {
+            fs::write(&golden, &emitted).unwrap();
+            continue;
+        }
+        let expected = fs::read_to_string(&golden).unwrap_or_else(|_| {
+            panic!("{} is missing; run with UPDATE_GOLDEN=1", golden.display())
+        });
+        assert_eq!(emitted, expected, "{}", path.display());
+    }
+}
+
+#[test]
+fn wat_samples_test() {
+    golden("wat", "wat", wat::RUNTIME);
+}
+
+#[test]
+fn llvm_samples_test() {
+    golden("llvm", "ll", "");
+}
+
+/*
+ * The major version of LLVM that llc reports, if llc is installed. Modules use opaque
+ * pointers, which llc only reads from LLVM 15 on unless given -opaque-pointers.
+ */
+fn llc_version() -> Option<u32> {
+    let version = Command::new("llc").arg("--version").output().ok()?;
+    let version = String::from_utf8_lossy(&version.stdout);
+    let (_, rest) = version.split_once("LLVM version ")?;
+    rest.split('.').next()?.parse().ok()
+}
+
+/*
+ * Compiles the IR that `--emit=llvm` gives for path into dir with llc of the given version,
+ * and links it with runtime.c by $CC or cc.
+ */
+fn build_llvm(path: &Path, dir: &Path, version: u32) -> PathBuf {
+    let cc = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let runtime = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/runtime.c");
+    let emitted = Command::new(env!("CARGO_BIN_EXE_rust-tigress"))
+        .arg("--emit=llvm")
+        .arg(path)
+        .output()
+        .unwrap();
+    assert!(emitted.status.success(), "{}", path.display());
+    let (ll, object, exe) = (
+        dir.join(path.with_extension("ll").file_name().unwrap()),
+        dir.join(path.with_extension("o").file_name().unwrap()),
+        dir.join(path.file_stem().unwrap()),
+    );
+    fs::write(&ll, &emitted.stdout).unwrap();
+    let mut llc = Command::new("llc");
+    if version < 15 {
+        llc.arg("-opaque-pointers");
+    }
+    let compiled = llc
+        .args(["-filetype=obj", "-relocation-model=pic", "-o"])
+        .arg(&object)
+        .arg(&ll)
+        .output()
+        .unwrap();
+    assert!(
+        compiled.status.success(),
+        "{}: {}",
+        path.display(),
+        String::from_utf8_lossy(&compiled.stderr)
+    );
+    let linked = Command::new(&cc)
+        .arg("-o")
+        .arg(&exe)
+        .arg(&object)
+        .arg(&runtime)
+        .output()
+        .unwrap();
+    assert!(
+        linked.status.success(),
+        "{}: {}",
+        path.display(),
+        String::from_utf8_lossy(&linked.stderr)
+    );
+    exe
+}
+
+/* Runs every sample, and the programs on the edges, compiled by llc. Skipped unless llc is installed. */
+#[test]
+fn llvm_run_test() {
+    let Some(version) = llc_version() else {
+        eprintln!("llc is not installed; skipping");
+        return;
+    };
+    let dir = env::temp_dir().join(format!("rust-tigress-llvm-samples-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let edges = programs(&dir, ARITHMETIC)
+        .into_iter()
+        .chain(programs(&dir, ERRORS));
+    for path in samples().into_iter().chain(edges) {
+        check(&path, &mut Command::new(build_llvm(&path, &dir, version)));
+    }
+    fs::remove_dir_all(&dir).ok();
+}
+
+/* Whether program can be run, as a tool that may not be installed. */
+fn installed(program: &str) -> bool {
+    Command::new(program).arg("--version").output().is_ok()
+}
+
+/*
+ * The host functions of the WebAssembly backend, written over WASI so that wasmtime can run
+ * modules. The runtime leaves addresses 0 to 8 free, for an iovec: print writes the count
+ * of bytes written over it once it is used, and getchar reads into the printi buffer after it.
+ */
+const WASI_HOST: &str = r#"  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+  (func $host_print (param $p i32) (param $n i32)
+    i32.const 0
+    local.get $p
+    i32.store
+    i32.const 4
+    local.get $n
+    i32.store
+    i32.const 1
+    i32.const 0
+    i32.const 1
+    i32.const 0
+    call $fd_write
+    drop)
+  (func $host_getchar (result i32)
+    i32.const 0
+    i32.const 8
+    i32.store
+    i32.const 4
+    i32.const 1
+    i32.store
+    i32.const 0
+    i32.const 0
+    i32.const 1
+    i32.const 12
+    call $fd_read
+    drop
+    i32.const 12
+    i32.load
+    if (result i32)
+      i32.const 8
+      i32.load8_u
+    else
+      i32.const -1
+    end)
+  (func $host_exit (param $status i32)
+    local.get $status
+    call $proc_exit)
+"#;
+
+/*
+ * Assembles the module that `--emit=wat` gives for every sample with wat2wasm and runs it
+ * with wasmtime, its imports of the tiger host functions replaced by WASI_HOST. Skipped
+ * unless both are installed.
+ */
+#[test]
+fn wat_run_test() {
+    if !installed("wat2wasm") || !installed("wasmtime") {
+        eprintln!("wat2wasm or wasmtime is not installed; skipping");
+        return;
+    }
+    let dir = env::temp_dir().join(format!("rust-tigress-wat-samples-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    for path in samples() {
+        let emitted = Command::new(env!("CARGO_BIN_EXE_rust-tigress"))
+            .arg("--emit=wat")
+            .arg(&path)
+            .output()
+            .unwrap();
+        assert!(emitted.status.success(), "{}", path.display());
+        let module = String::from_utf8_lossy(&emitted.stdout);
+        let imports: Vec<&str> = module
+            .lines()
+            .filter(|line| line.starts_with("  (import \"tiger\""))
+            .collect();
+        assert_eq!(imports.len(), 3, "{}", path.display());
+        let mut module = module.replacen(imports[0], WASI_HOST.trim_end(), 1);
+        for import in &imports[1..] {
+            module = module.replacen(&format!("{}\n", import), "", 1);
+        }
+        let (text, binary) = (
+            dir.join(path.with_extension("wat").file_name().unwrap()),
+            dir.join(path.with_extension("wasm").file_name().unwrap()),
+        );
+        fs::write(&text, module).unwrap();
+        let assembled = Command::new("wat2wasm")
+            .arg(&text)
+            .arg("-o")
+            .arg(&binary)
+            .output()
+            .unwrap();
+        assert!(
+            assembled.status.success(),
+            "{}: {}",
+            path.display(),
+            String::from_utf8_lossy(&assembled.stderr)
+        );
+        check(&path, Command::new("wasmtime").arg("run").arg(&binary));
+    }
+    fs::remove_dir_all(&dir).ok();
+}
+
+/* The interpreter reports runtime errors as diagnostics, located when it runs the source tree. */
+#[test]
+fn runtime_error_test() {
+    let dir = env::temp_dir().join(format!("rust-tigress-runtime-errors-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    for path in programs(&dir, ERRORS) {
+        for args in [&[][..], &["--no-typecheck"][..]] {
+            let run = Command::new(env!("CARGO_BIN_EXE_rust-tigress"))
+                .args(args)
+                .arg(&path)
+                .output()
+                .unwrap();
+            let stderr = String::from_utf8_lossy(&run.stderr);
+            assert_eq!(run.status.code(), Some(1), "{}: {}", path.display(), stderr);
+            assert!(stderr.starts_with("error[E0022]: "), "{}", stderr);
+            assert!(!stderr.contains("panicked"), "{}", stderr);
+            if !args.is_empty() {
+                assert!(stderr.contains(" --> "), "{}", stderr);
+            }
+        }
+    }
+    fs::remove_dir_all(&dir).ok();
+}
+
+fn interpret_file(path: &Path) -> (Result<Value, String>, Vec<u8>) {
+    let src = fs::read_to_string(path).unwrap();
+    let out = Rc::new(RefCell::new(Vec::new()));
+    let host = library::functions(out.clone(), Rc::new(RefCell::new(&b""[..])));
+    let (_, _, tast) = typing::check(&src, &host).unwrap();
+    let result = eval::run(&tast, &host);
+    let out = out.borrow().clone();
+    (result, out)
+}
@@ -0,0 +1,27 @@
+; Generated by rust-tigress; compile with clang together with runtime.c.
+
+
+declare ptr @tiger_allocRecord(i64)
+declare ptr @tiger_initArray(i64, i64)
+declare i64 @tiger_stringEqual(ptr, ptr)
+declare i64 @tiger_stringCompare(ptr, ptr)
+declare void @tiger_print(ptr)
+declare void @tiger_printi(i64)
+declare void @tiger_flush()
+declare ptr @tiger_getchar()
+declare i64 @tiger_ord(ptr)
+declare ptr @tiger_chr(i64)
+declare i64 @tiger_size(ptr)
+declare ptr @tiger_substring(ptr, i64, i64)
+declare ptr @tiger_concat(ptr, ptr)
+declare i64 @tiger_not(i64)
+declare void @tiger_failIndex(i64, i64)
+declare void @tiger_failNil()
+declare void @tiger_failDivision()
+
+define i64 @tiger_tigermain() {
+entry:
+  %t1 = add i64 123000000, 456000
+  %t2 = add i64 %t1, 789
+  ret i64 %t2
+}
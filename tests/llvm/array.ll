@@ -0,0 +1,28 @@
+; Generated by rust-tigress; compile with clang together with runtime.c.
+
+
+declare ptr @tiger_allocRecord(i64)
+declare ptr @tiger_initArray(i64, i64)
+declare i64 @tiger_stringEqual(ptr, ptr)
+declare i64 @tiger_stringCompare(ptr, ptr)
+declare void @tiger_print(ptr)
+declare void @tiger_printi(i64)
+declare void @tiger_flush()
+declare ptr @tiger_getchar()
+declare i64 @tiger_ord(ptr)
+declare ptr @tiger_chr(i64)
+declare i64 @tiger_size(ptr)
+declare ptr @tiger_substring(ptr, i64, i64)
+declare ptr @tiger_concat(ptr, ptr)
+declare i64 @tiger_not(i64)
+declare void @tiger_failIndex(i64, i64)
+declare void @tiger_failNil()
+declare void @tiger_failDivision()
+
+define i64 @tiger_tigermain() {
+entry:
+  %ary_2 = alloca ptr
+  %t1 = call ptr @tiger_initArray(i64 4, i64 0)
+  store ptr %t1, ptr %ary_2
+  ret i64 124
+}
@@ -0,0 +1,36 @@
+; Generated by rust-tigress; compile with clang together with runtime.c.
+
+
+declare ptr @tiger_allocRecord(i64)
+declare ptr @tiger_initArray(i64, i64)
+declare i64 @tiger_stringEqual(ptr, ptr)
+declare i64 @tiger_stringCompare(ptr, ptr)
+declare void @tiger_print(ptr)
+declare void @tiger_printi(i64)
+declare void @tiger_flush()
+declare ptr @tiger_getchar()
+declare i64 @tiger_ord(ptr)
+declare ptr @tiger_chr(i64)
+declare i64 @tiger_size(ptr)
+declare ptr @tiger_substring(ptr, i64, i64)
+declare ptr @tiger_concat(ptr, ptr)
+declare i64 @tiger_not(i64)
+declare void @tiger_failIndex(i64, i64)
+declare void @tiger_failNil()
+declare void @tiger_failDivision()
+
+define i64 @tiger_tigermain() {
+entry:
+  %x_1 = alloca i64
+  store i64 2, ptr %x_1
+  %t2 = load i64, ptr %x_1
+  %t3 = icmp ne i64 %t2, 0
+  br i1 %t3, label %then4, label %else5
+then4:
+  br label %join6
+else5:
+  br label %join6
+join6:
+  %t7 = phi i64 [0, %then4], [1, %else5]
+  ret i64 %t7
+}
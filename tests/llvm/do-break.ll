@@ -0,0 +1,56 @@
+; Generated by rust-tigress; compile with clang together with runtime.c.
+
+
+declare ptr @tiger_allocRecord(i64)
+declare ptr @tiger_initArray(i64, i64)
+declare i64 @tiger_stringEqual(ptr, ptr)
+declare i64 @tiger_stringCompare(ptr, ptr)
+declare void @tiger_print(ptr)
+declare void @tiger_printi(i64)
+declare void @tiger_flush()
+declare ptr @tiger_getchar()
+declare i64 @tiger_ord(ptr)
+declare ptr @tiger_chr(i64)
+declare i64 @tiger_size(ptr)
+declare ptr @tiger_substring(ptr, i64, i64)
+declare ptr @tiger_concat(ptr, ptr)
+declare i64 @tiger_not(i64)
+declare void @tiger_failIndex(i64, i64)
+declare void @tiger_failNil()
+declare void @tiger_failDivision()
+
+define i64 @tiger_tigermain() {
+entry:
+  %x_1 = alloca i64
+  %i_2 = alloca i64
+  store i64 0, ptr %x_1
+  store i64 0, ptr %i_2
+  br label %while3
+while3:
+  %t6 = load i64, ptr %i_2
+  %t7 = icmp sle i64 %t6, 10
+  br i1 %t7, label %body4, label %done5
+body4:
+  %t8 = load i64, ptr %x_1
+  %t9 = load i64, ptr %i_2
+  %t10 = add i64 %t8, %t9
+  store i64 %t10, ptr %x_1
+  %t11 = load i64, ptr %i_2
+  %t12 = icmp sge i64 %t11, 5
+  br i1 %t12, label %then13, label %else14
+then13:
+  br label %done5
+dead16:
+  br label %join15
+else14:
+  br label %join15
+join15:
+  %t17 = load i64, ptr %i_2
+  %t18 = add i64 %t17, 1
+  store i64 %t18, ptr %i_2
+  br label %while3
+done5:
+  %t19 = load i64, ptr %x_1
+  %t20 = sub i64 %t19, 15
+  ret i64 %t20
+}
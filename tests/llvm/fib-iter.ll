@@ -0,0 +1,64 @@
+; Generated by rust-tigress; compile with clang together with runtime.c.
+
+%tiger_tigermain.frame = type { ptr }
+
+declare ptr @tiger_allocRecord(i64)
+declare ptr @tiger_initArray(i64, i64)
+declare i64 @tiger_stringEqual(ptr, ptr)
+declare i64 @tiger_stringCompare(ptr, ptr)
+declare void @tiger_print(ptr)
+declare void @tiger_printi(i64)
+declare void @tiger_flush()
+declare ptr @tiger_getchar()
+declare i64 @tiger_ord(ptr)
+declare ptr @tiger_chr(i64)
+declare i64 @tiger_size(ptr)
+declare ptr @tiger_substring(ptr, i64, i64)
+declare ptr @tiger_concat(ptr, ptr)
+declare i64 @tiger_not(i64)
+declare void @tiger_failIndex(i64, i64)
+declare void @tiger_failNil()
+declare void @tiger_failDivision()
+
+define internal i64 @tiger_fib(ptr %link, i64 %x_1) {
+entry:
+  %x_1.addr = alloca i64
+  %a_2 = alloca i64
+  %b_3 = alloca i64
+  %i_5 = alloca i64
+  %c_13 = alloca i64
+  store i64 %x_1, ptr %x_1.addr
+  store i64 1, ptr %a_2
+  store i64 0, ptr %b_3
+  %t4 = load i64, ptr %x_1.addr
+  store i64 1, ptr %i_5
+  %t9 = icmp sle i64 1, %t4
+  br i1 %t9, label %for6, label %done8
+for6:
+  %t10 = load i64, ptr %a_2
+  %t11 = load i64, ptr %b_3
+  %t12 = add i64 %t10, %t11
+  store i64 %t12, ptr %c_13
+  %t14 = load i64, ptr %b_3
+  store i64 %t14, ptr %a_2
+  %t15 = load i64, ptr %c_13
+  store i64 %t15, ptr %b_3
+  %t16 = load i64, ptr %i_5
+  %t17 = icmp sge i64 %t16, %t4
+  br i1 %t17, label %done8, label %next7
+next7:
+  %t18 = add i64 %t16, 1
+  store i64 %t18, ptr %i_5
+  br label %for6
+done8:
+  %t19 = load i64, ptr %a_2
+  ret i64 %t19
+}
+
+define i64 @tiger_tigermain() {
+entry:
+  %frame = alloca %tiger_tigermain.frame
+  store ptr null, ptr %frame
+  %t20 = call i64 @tiger_fib(ptr %frame, i64 20)
+  ret i64 %t20
+}
@@ -0,0 +1,53 @@
+; Generated by rust-tigress; compile with clang together with runtime.c.
+
+%tiger_tigermain.frame = type { ptr }
+
+declare ptr @tiger_allocRecord(i64)
+declare ptr @tiger_initArray(i64, i64)
+declare i64 @tiger_stringEqual(ptr, ptr)
+declare i64 @tiger_stringCompare(ptr, ptr)
+declare void @tiger_print(ptr)
+declare void @tiger_printi(i64)
+declare void @tiger_flush()
+declare ptr @tiger_getchar()
+declare i64 @tiger_ord(ptr)
+declare ptr @tiger_chr(i64)
+declare i64 @tiger_size(ptr)
+declare ptr @tiger_substring(ptr, i64, i64)
+declare ptr @tiger_concat(ptr, ptr)
+declare i64 @tiger_not(i64)
+declare void @tiger_failIndex(i64, i64)
+declare void @tiger_failNil()
+declare void @tiger_failDivision()
+
+define internal i64 @tiger_fib(ptr %link, i64 %x_1) {
+entry:
+  %x_1.addr = alloca i64
+  store i64 %x_1, ptr %x_1.addr
+  %t2 = load i64, ptr %x_1.addr
+  %t3 = icmp sle i64 %t2, 1
+  br i1 %t3, label %then4, label %else5
+then4:
+  %t7 = load i64, ptr %x_1.addr
+  br label %join6
+else5:
+  %t8 = load i64, ptr %x_1.addr
+  %t9 = sub i64 %t8, 1
+  %t10 = call i64 @tiger_fib(ptr %link, i64 %t9)
+  %t11 = load i64, ptr %x_1.addr
+  %t12 = sub i64 %t11, 2
+  %t13 = call i64 @tiger_fib(ptr %link, i64 %t12)
+  %t14 = add i64 %t10, %t13
+  br label %join6
+join6:
+  %t15 = phi i64 [%t7, %then4], [%t14, %else5]
+  ret i64 %t15
+}
+
+define i64 @tiger_tigermain() {
+entry:
+  %frame = alloca %tiger_tigermain.frame
+  store ptr null, ptr %frame
+  %t16 = call i64 @tiger_fib(ptr %frame, i64 5)
+  ret i64 %t16
+}
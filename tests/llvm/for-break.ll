@@ -0,0 +1,56 @@
+; Generated by rust-tigress; compile with clang together with runtime.c.
+
+
+declare ptr @tiger_allocRecord(i64)
+declare ptr @tiger_initArray(i64, i64)
+declare i64 @tiger_stringEqual(ptr, ptr)
+declare i64 @tiger_stringCompare(ptr, ptr)
+declare void @tiger_print(ptr)
+declare void @tiger_printi(i64)
+declare void @tiger_flush()
+declare ptr @tiger_getchar()
+declare i64 @tiger_ord(ptr)
+declare ptr @tiger_chr(i64)
+declare i64 @tiger_size(ptr)
+declare ptr @tiger_substring(ptr, i64, i64)
+declare ptr @tiger_concat(ptr, ptr)
+declare i64 @tiger_not(i64)
+declare void @tiger_failIndex(i64, i64)
+declare void @tiger_failNil()
+declare void @tiger_failDivision()
+
+define i64 @tiger_tigermain() {
+entry:
+  %x_1 = alloca i64
+  %i_2 = alloca i64
+  store i64 0, ptr %x_1
+  store i64 0, ptr %i_2
+  %t6 = icmp sle i64 0, 10
+  br i1 %t6, label %for3, label %done5
+for3:
+  %t7 = load i64, ptr %x_1
+  %t8 = load i64, ptr %i_2
+  %t9 = add i64 %t7, %t8
+  store i64 %t9, ptr %x_1
+  %t10 = load i64, ptr %i_2
+  %t11 = icmp sge i64 %t10, 5
+  br i1 %t11, label %then12, label %else13
+then12:
+  br label %done5
+dead15:
+  br label %join14
+else13:
+  br label %join14
+join14:
+  %t16 = load i64, ptr %i_2
+  %t17 = icmp sge i64 %t16, 10
+  br i1 %t17, label %done5, label %next4
+next4:
+  %t18 = add i64 %t16, 1
+  store i64 %t18, ptr %i_2
+  br label %for3
+done5:
+  %t19 = load i64, ptr %x_1
+  %t20 = sub i64 %t19, 15
+  ret i64 %t20
+}
@@ -0,0 +1,84 @@
+; Generated by rust-tigress; compile with clang together with runtime.c.
+
+%list_0 = type { i64, ptr }
+%tiger_tigermain.frame = type { ptr }
+
+declare ptr @tiger_allocRecord(i64)
+declare ptr @tiger_initArray(i64, i64)
+declare i64 @tiger_stringEqual(ptr, ptr)
+declare i64 @tiger_stringCompare(ptr, ptr)
+declare void @tiger_print(ptr)
+declare void @tiger_printi(i64)
+declare void @tiger_flush()
+declare ptr @tiger_getchar()
+declare i64 @tiger_ord(ptr)
+declare ptr @tiger_chr(i64)
+declare i64 @tiger_size(ptr)
+declare ptr @tiger_substring(ptr, i64, i64)
+declare ptr @tiger_concat(ptr, ptr)
+declare i64 @tiger_not(i64)
+declare void @tiger_failIndex(i64, i64)
+declare void @tiger_failNil()
+declare void @tiger_failDivision()
+
+define internal i64 @tiger_sum(ptr %link, ptr %l_1) {
+entry:
+  %l_1.addr = alloca ptr
+  store ptr %l_1, ptr %l_1.addr
+  %t2 = load ptr, ptr %l_1.addr
+  %t3 = icmp eq ptr %t2, null
+  br i1 %t3, label %then4, label %else5
+then4:
+  br label %join6
+else5:
+  %t7 = load ptr, ptr %l_1.addr
+  %t8 = icmp ne ptr %t7, null
+  br i1 %t8, label %ok9, label %fail10
+fail10:
+  call void @tiger_failNil()
+  unreachable
+ok9:
+  %t11 = getelementptr %list_0, ptr %t7, i32 0, i32 0
+  %t12 = load i64, ptr %t11
+  %t13 = load ptr, ptr %l_1.addr
+  %t14 = icmp ne ptr %t13, null
+  br i1 %t14, label %ok15, label %fail16
+fail16:
+  call void @tiger_failNil()
+  unreachable
+ok15:
+  %t17 = getelementptr %list_0, ptr %t13, i32 0, i32 1
+  %t18 = load ptr, ptr %t17
+  %t19 = call i64 @tiger_sum(ptr %link, ptr %t18)
+  %t20 = add i64 %t12, %t19
+  br label %join6
+join6:
+  %t21 = phi i64 [0, %then4], [%t20, %ok15]
+  ret i64 %t21
+}
+
+define i64 @tiger_tigermain() {
+entry:
+  %frame = alloca %tiger_tigermain.frame
+  store ptr null, ptr %frame
+  %l_31 = alloca ptr
+  %t22 = call ptr @tiger_allocRecord(i64 16)
+  %t23 = getelementptr %list_0, ptr %t22, i32 0, i32 0
+  store i64 1, ptr %t23
+  %t24 = call ptr @tiger_allocRecord(i64 16)
+  %t25 = getelementptr %list_0, ptr %t24, i32 0, i32 0
+  store i64 2, ptr %t25
+  %t26 = call ptr @tiger_allocRecord(i64 16)
+  %t27 = getelementptr %list_0, ptr %t26, i32 0, i32 0
+  store i64 3, ptr %t27
+  %t28 = getelementptr %list_0, ptr %t26, i32 0, i32 1
+  store ptr null, ptr %t28
+  %t29 = getelementptr %list_0, ptr %t24, i32 0, i32 1
+  store ptr %t26, ptr %t29
+  %t30 = getelementptr %list_0, ptr %t22, i32 0, i32 1
+  store ptr %t24, ptr %t30
+  store ptr %t22, ptr %l_31
+  %t32 = load ptr, ptr %l_31
+  %t33 = call i64 @tiger_sum(ptr %frame, ptr %t32)
+  ret i64 %t33
+}
@@ -0,0 +1,329 @@
+; Generated by rust-tigress; compile with clang together with runtime.c.
+
+%tiger_tigermain.frame = type { ptr }
+
+declare ptr @tiger_allocRecord(i64)
+declare ptr @tiger_initArray(i64, i64)
+declare i64 @tiger_stringEqual(ptr, ptr)
+declare i64 @tiger_stringCompare(ptr, ptr)
+declare void @tiger_print(ptr)
+declare void @tiger_printi(i64)
+declare void @tiger_flush()
+declare ptr @tiger_getchar()
+declare i64 @tiger_ord(ptr)
+declare ptr @tiger_chr(i64)
+declare i64 @tiger_size(ptr)
+declare ptr @tiger_substring(ptr, i64, i64)
+declare ptr @tiger_concat(ptr, ptr)
+declare i64 @tiger_not(i64)
+declare void @tiger_failIndex(i64, i64)
+declare void @tiger_failNil()
+declare void @tiger_failDivision()
+
+define internal i64 @tiger_id(ptr %link, i64 %x_1) {
+entry:
+  %x_1.addr = alloca i64
+  store i64 %x_1, ptr %x_1.addr
+  %t2 = load i64, ptr %x_1.addr
+  ret i64 %t2
+}
+
+define internal i64 @tiger_mix(ptr %link, i64 %a_3, i64 %b_4, i64 %c_5, i64 %d_6, i64 %e_7, i64 %f_8, i64 %g_9, i64 %h_10) {
+entry:
+  %a_3.addr = alloca i64
+  %b_4.addr = alloca i64
+  %c_5.addr = alloca i64
+  %d_6.addr = alloca i64
+  %e_7.addr = alloca i64
+  %f_8.addr = alloca i64
+  %g_9.addr = alloca i64
+  %h_10.addr = alloca i64
+  %s_11 = alloca i64
+  %t_12 = alloca i64
+  %i_13 = alloca i64
+  store i64 %a_3, ptr %a_3.addr
+  store i64 %b_4, ptr %b_4.addr
+  store i64 %c_5, ptr %c_5.addr
+  store i64 %d_6, ptr %d_6.addr
+  store i64 %e_7, ptr %e_7.addr
+  store i64 %f_8, ptr %f_8.addr
+  store i64 %g_9, ptr %g_9.addr
+  store i64 %h_10, ptr %h_10.addr
+  store i64 0, ptr %s_11
+  store i64 1, ptr %t_12
+  store i64 1, ptr %i_13
+  %t17 = icmp sle i64 1, 10
+  br i1 %t17, label %for14, label %done16
+for14:
+  %t18 = load i64, ptr %s_11
+  %t19 = load i64, ptr %a_3.addr
+  %t20 = load i64, ptr %i_13
+  %t21 = mul i64 %t19, %t20
+  %t22 = add i64 %t18, %t21
+  %t23 = load i64, ptr %b_4.addr
+  %t24 = add i64 %t22, %t23
+  %t25 = load i64, ptr %c_5.addr
+  %t26 = sub i64 %t24, %t25
+  %t27 = load i64, ptr %d_6.addr
+  %t28 = load i64, ptr %e_7.addr
+  %t29 = mul i64 %t27, %t28
+  %t30 = add i64 %t26, %t29
+  %t31 = load i64, ptr %f_8.addr
+  %t32 = sub i64 %t30, %t31
+  %t33 = load i64, ptr %g_9.addr
+  %t34 = load i64, ptr %h_10.addr
+  %t35 = mul i64 %t33, %t34
+  %t36 = add i64 %t32, %t35
+  %t37 = load i64, ptr %i_13
+  %t38 = call i64 @tiger_id(ptr %link, i64 %t37)
+  %t39 = add i64 %t36, %t38
+  store i64 %t39, ptr %s_11
+  %t40 = load i64, ptr %t_12
+  %t41 = load i64, ptr %s_11
+  %t42 = sdiv i64 %t41, 7
+  %t43 = add i64 %t40, %t42
+  %t44 = load i64, ptr %a_3.addr
+  %t45 = add i64 %t43, %t44
+  %t46 = load i64, ptr %b_4.addr
+  %t47 = add i64 %t45, %t46
+  %t48 = load i64, ptr %c_5.addr
+  %t49 = add i64 %t47, %t48
+  %t50 = load i64, ptr %d_6.addr
+  %t51 = add i64 %t49, %t50
+  %t52 = load i64, ptr %e_7.addr
+  %t53 = add i64 %t51, %t52
+  %t54 = load i64, ptr %f_8.addr
+  %t55 = add i64 %t53, %t54
+  %t56 = load i64, ptr %g_9.addr
+  %t57 = add i64 %t55, %t56
+  %t58 = load i64, ptr %h_10.addr
+  %t59 = add i64 %t57, %t58
+  store i64 %t59, ptr %t_12
+  %t60 = load i64, ptr %i_13
+  %t61 = icmp sge i64 %t60, 10
+  br i1 %t61, label %done16, label %next15
+next15:
+  %t62 = add i64 %t60, 1
+  store i64 %t62, ptr %i_13
+  br label %for14
+done16:
+  %t63 = load i64, ptr %s_11
+  %t64 = load i64, ptr %t_12
+  %t65 = add i64 %t63, %t64
+  ret i64 %t65
+}
+
+define i64 @tiger_tigermain() {
+entry:
+  %frame = alloca %tiger_tigermain.frame
+  store ptr null, ptr %frame
+  %v0_67 = alloca i64
+  %v1_69 = alloca i64
+  %v2_71 = alloca i64
+  %v3_73 = alloca i64
+  %v4_75 = alloca i64
+  %v5_77 = alloca i64
+  %v6_79 = alloca i64
+  %v7_81 = alloca i64
+  %v8_83 = alloca i64
+  %v9_85 = alloca i64
+  %v10_87 = alloca i64
+  %v11_89 = alloca i64
+  %v12_91 = alloca i64
+  %v13_93 = alloca i64
+  %v14_95 = alloca i64
+  %v15_97 = alloca i64
+  %v16_99 = alloca i64
+  %v17_101 = alloca i64
+  %v18_103 = alloca i64
+  %v19_105 = alloca i64
+  %mixed_125 = alloca i64
+  %t66 = call i64 @tiger_id(ptr %frame, i64 0)
+  store i64 %t66, ptr %v0_67
+  %t68 = call i64 @tiger_id(ptr %frame, i64 1)
+  store i64 %t68, ptr %v1_69
+  %t70 = call i64 @tiger_id(ptr %frame, i64 2)
+  store i64 %t70, ptr %v2_71
+  %t72 = call i64 @tiger_id(ptr %frame, i64 3)
+  store i64 %t72, ptr %v3_73
+  %t74 = call i64 @tiger_id(ptr %frame, i64 4)
+  store i64 %t74, ptr %v4_75
+  %t76 = call i64 @tiger_id(ptr %frame, i64 5)
+  store i64 %t76, ptr %v5_77
+  %t78 = call i64 @tiger_id(ptr %frame, i64 6)
+  store i64 %t78, ptr %v6_79
+  %t80 = call i64 @tiger_id(ptr %frame, i64 7)
+  store i64 %t80, ptr %v7_81
+  %t82 = call i64 @tiger_id(ptr %frame, i64 8)
+  store i64 %t82, ptr %v8_83
+  %t84 = call i64 @tiger_id(ptr %frame, i64 9)
+  store i64 %t84, ptr %v9_85
+  %t86 = call i64 @tiger_id(ptr %frame, i64 10)
+  store i64 %t86, ptr %v10_87
+  %t88 = call i64 @tiger_id(ptr %frame, i64 11)
+  store i64 %t88, ptr %v11_89
+  %t90 = call i64 @tiger_id(ptr %frame, i64 12)
+  store i64 %t90, ptr %v12_91
+  %t92 = call i64 @tiger_id(ptr %frame, i64 13)
+  store i64 %t92, ptr %v13_93
+  %t94 = call i64 @tiger_id(ptr %frame, i64 14)
+  store i64 %t94, ptr %v14_95
+  %t96 = call i64 @tiger_id(ptr %frame, i64 15)
+  store i64 %t96, ptr %v15_97
+  %t98 = call i64 @tiger_id(ptr %frame, i64 16)
+  store i64 %t98, ptr %v16_99
+  %t100 = call i64 @tiger_id(ptr %frame, i64 17)
+  store i64 %t100, ptr %v17_101
+  %t102 = call i64 @tiger_id(ptr %frame, i64 18)
+  store i64 %t102, ptr %v18_103
+  %t104 = call i64 @tiger_id(ptr %frame, i64 19)
+  store i64 %t104, ptr %v19_105
+  %t106 = load i64, ptr %v0_67
+  %t107 = load i64, ptr %v1_69
+  %t108 = load i64, ptr %v2_71
+  %t109 = load i64, ptr %v3_73
+  %t110 = load i64, ptr %v4_75
+  %t111 = load i64, ptr %v5_77
+  %t112 = load i64, ptr %v6_79
+  %t113 = load i64, ptr %v7_81
+  %t114 = call i64 @tiger_mix(ptr %frame, i64 %t106, i64 %t107, i64 %t108, i64 %t109, i64 %t110, i64 %t111, i64 %t112, i64 %t113)
+  %t115 = load i64, ptr %v19_105
+  %t116 = load i64, ptr %v18_103
+  %t117 = load i64, ptr %v17_101
+  %t118 = load i64, ptr %v16_99
+  %t119 = load i64, ptr %v15_97
+  %t120 = load i64, ptr %v14_95
+  %t121 = load i64, ptr %v13_93
+  %t122 = load i64, ptr %v12_91
+  %t123 = call i64 @tiger_mix(ptr %frame, i64 %t115, i64 %t116, i64 %t117, i64 %t118, i64 %t119, i64 %t120, i64 %t121, i64 %t122)
+  %t124 = add i64 %t114, %t123
+  store i64 %t124, ptr %mixed_125
+  %t126 = load i64, ptr %mixed_125
+  call void @tiger_printi(i64 %t126)
+  %t127 = call ptr @tiger_chr(i64 10)
+  call void @tiger_print(ptr %t127)
+  %t128 = load i64, ptr %v0_67
+  %t129 = load i64, ptr %v1_69
+  %t130 = call i64 @tiger_id(ptr %frame, i64 1)
+  %t131 = mul i64 %t129, %t130
+  %t132 = add i64 %t128, %t131
+  %t133 = load i64, ptr %v2_71
+  %t134 = call i64 @tiger_id(ptr %frame, i64 2)
+  %t135 = mul i64 %t133, %t134
+  %t136 = add i64 %t132, %t135
+  %t137 = load i64, ptr %v3_73
+  %t138 = call i64 @tiger_id(ptr %frame, i64 3)
+  %t139 = mul i64 %t137, %t138
+  %t140 = add i64 %t136, %t139
+  %t141 = load i64, ptr %v4_75
+  %t142 = call i64 @tiger_id(ptr %frame, i64 4)
+  %t143 = mul i64 %t141, %t142
+  %t144 = add i64 %t140, %t143
+  %t145 = load i64, ptr %v5_77
+  %t146 = call i64 @tiger_id(ptr %frame, i64 5)
+  %t147 = mul i64 %t145, %t146
+  %t148 = add i64 %t144, %t147
+  %t149 = load i64, ptr %v6_79
+  %t150 = call i64 @tiger_id(ptr %frame, i64 6)
+  %t151 = mul i64 %t149, %t150
+  %t152 = add i64 %t148, %t151
+  %t153 = load i64, ptr %v7_81
+  %t154 = call i64 @tiger_id(ptr %frame, i64 7)
+  %t155 = mul i64 %t153, %t154
+  %t156 = add i64 %t152, %t155
+  %t157 = load i64, ptr %v8_83
+  %t158 = call i64 @tiger_id(ptr %frame, i64 8)
+  %t159 = mul i64 %t157, %t158
+  %t160 = add i64 %t156, %t159
+  %t161 = load i64, ptr %v9_85
+  %t162 = call i64 @tiger_id(ptr %frame, i64 9)
+  %t163 = mul i64 %t161, %t162
+  %t164 = add i64 %t160, %t163
+  %t165 = load i64, ptr %v10_87
+  %t166 = call i64 @tiger_id(ptr %frame, i64 10)
+  %t167 = mul i64 %t165, %t166
+  %t168 = add i64 %t164, %t167
+  %t169 = load i64, ptr %v11_89
+  %t170 = call i64 @tiger_id(ptr %frame, i64 11)
+  %t171 = mul i64 %t169, %t170
+  %t172 = add i64 %t168, %t171
+  %t173 = load i64, ptr %v12_91
+  %t174 = call i64 @tiger_id(ptr %frame, i64 12)
+  %t175 = mul i64 %t173, %t174
+  %t176 = add i64 %t172, %t175
+  %t177 = load i64, ptr %v13_93
+  %t178 = call i64 @tiger_id(ptr %frame, i64 13)
+  %t179 = mul i64 %t177, %t178
+  %t180 = add i64 %t176, %t179
+  %t181 = load i64, ptr %v14_95
+  %t182 = call i64 @tiger_id(ptr %frame, i64 14)
+  %t183 = mul i64 %t181, %t182
+  %t184 = add i64 %t180, %t183
+  %t185 = load i64, ptr %v15_97
+  %t186 = call i64 @tiger_id(ptr %frame, i64 15)
+  %t187 = mul i64 %t185, %t186
+  %t188 = add i64 %t184, %t187
+  %t189 = load i64, ptr %v16_99
+  %t190 = call i64 @tiger_id(ptr %frame, i64 16)
+  %t191 = mul i64 %t189, %t190
+  %t192 = add i64 %t188, %t191
+  %t193 = load i64, ptr %v17_101
+  %t194 = call i64 @tiger_id(ptr %frame, i64 17)
+  %t195 = mul i64 %t193, %t194
+  %t196 = add i64 %t192, %t195
+  %t197 = load i64, ptr %v18_103
+  %t198 = call i64 @tiger_id(ptr %frame, i64 18)
+  %t199 = mul i64 %t197, %t198
+  %t200 = add i64 %t196, %t199
+  %t201 = load i64, ptr %v19_105
+  %t202 = call i64 @tiger_id(ptr %frame, i64 19)
+  %t203 = mul i64 %t201, %t202
+  %t204 = add i64 %t200, %t203
+  call void @tiger_printi(i64 %t204)
+  %t205 = call ptr @tiger_chr(i64 10)
+  call void @tiger_print(ptr %t205)
+  %t206 = load i64, ptr %mixed_125
+  %t207 = load i64, ptr %v19_105
+  %t208 = load i64, ptr %v18_103
+  %t209 = add i64 %t207, %t208
+  %t210 = load i64, ptr %v17_101
+  %t211 = add i64 %t209, %t210
+  %t212 = load i64, ptr %v16_99
+  %t213 = add i64 %t211, %t212
+  %t214 = load i64, ptr %v15_97
+  %t215 = add i64 %t213, %t214
+  %t216 = load i64, ptr %v14_95
+  %t217 = add i64 %t215, %t216
+  %t218 = load i64, ptr %v13_93
+  %t219 = add i64 %t217, %t218
+  %t220 = load i64, ptr %v12_91
+  %t221 = add i64 %t219, %t220
+  %t222 = load i64, ptr %v11_89
+  %t223 = add i64 %t221, %t222
+  %t224 = load i64, ptr %v10_87
+  %t225 = add i64 %t223, %t224
+  %t226 = load i64, ptr %v9_85
+  %t227 = add i64 %t225, %t226
+  %t228 = load i64, ptr %v8_83
+  %t229 = add i64 %t227, %t228
+  %t230 = load i64, ptr %v7_81
+  %t231 = add i64 %t229, %t230
+  %t232 = load i64, ptr %v6_79
+  %t233 = add i64 %t231, %t232
+  %t234 = load i64, ptr %v5_77
+  %t235 = add i64 %t233, %t234
+  %t236 = load i64, ptr %v4_75
+  %t237 = add i64 %t235, %t236
+  %t238 = load i64, ptr %v3_73
+  %t239 = add i64 %t237, %t238
+  %t240 = load i64, ptr %v2_71
+  %t241 = add i64 %t239, %t240
+  %t242 = load i64, ptr %v1_69
+  %t243 = add i64 %t241, %t242
+  %t244 = load i64, ptr %v0_67
+  %t245 = add i64 %t243, %t244
+  %t246 = call i64 @tiger_id(ptr %frame, i64 3)
+  %t247 = mul i64 %t245, %t246
+  %t248 = sub i64 %t206, %t247
+  ret i64 %t248
+}
@@ -0,0 +1,352 @@
+; Generated by rust-tigress; compile with clang together with runtime.c.
+
+%tiger_tigermain.frame = type { ptr, i64, ptr, ptr, ptr, ptr, i64 }
+
+@str0 = private unnamed_addr constant { i64, [2 x i8] } { i64 2, [2 x i8] c" O" }, align 8
+@str1 = private unnamed_addr constant { i64, [2 x i8] } { i64 2, [2 x i8] c" ." }, align 8
+
+declare ptr @tiger_allocRecord(i64)
+declare ptr @tiger_initArray(i64, i64)
+declare i64 @tiger_stringEqual(ptr, ptr)
+declare i64 @tiger_stringCompare(ptr, ptr)
+declare void @tiger_print(ptr)
+declare void @tiger_printi(i64)
+declare void @tiger_flush()
+declare ptr @tiger_getchar()
+declare i64 @tiger_ord(ptr)
+declare ptr @tiger_chr(i64)
+declare i64 @tiger_size(ptr)
+declare ptr @tiger_substring(ptr, i64, i64)
+declare ptr @tiger_concat(ptr, ptr)
+declare i64 @tiger_not(i64)
+declare void @tiger_failIndex(i64, i64)
+declare void @tiger_failNil()
+declare void @tiger_failDivision()
+
+define internal void @tiger_printboard(ptr %link) {
+entry:
+  %i_34 = alloca i64
+  %j_42 = alloca i64
+  %t27 = getelementptr %tiger_tigermain.frame, ptr %link, i32 0, i32 6
+  %t28 = getelementptr %tiger_tigermain.frame, ptr %link, i32 0, i32 6
+  %t29 = load i64, ptr %t28
+  %t30 = add i64 %t29, 1
+  store i64 %t30, ptr %t27
+  %t31 = getelementptr %tiger_tigermain.frame, ptr %link, i32 0, i32 1
+  %t32 = load i64, ptr %t31
+  %t33 = sub i64 %t32, 1
+  store i64 0, ptr %i_34
+  %t38 = icmp sle i64 0, %t33
+  br i1 %t38, label %for35, label %done37
+for35:
+  %t39 = getelementptr %tiger_tigermain.frame, ptr %link, i32 0, i32 1
+  %t40 = load i64, ptr %t39
+  %t41 = sub i64 %t40, 1
+  store i64 0, ptr %j_42
+  %t46 = icmp sle i64 0, %t41
+  br i1 %t46, label %for43, label %done45
+for43:
+  %t47 = getelementptr %tiger_tigermain.frame, ptr %link, i32 0, i32 3
+  %t48 = load ptr, ptr %t47
+  %t49 = load i64, ptr %i_34
+  %t50 = getelementptr i64, ptr %t48, i64 -1
+  %t51 = load i64, ptr %t50
+  %t52 = icmp ult i64 %t49, %t51
+  br i1 %t52, label %ok53, label %fail54
+fail54:
+  call void @tiger_failIndex(i64 %t49, i64 %t51)
+  unreachable
+ok53:
+  %t55 = getelementptr i64, ptr %t48, i64 %t49
+  %t56 = load i64, ptr %t55
+  %t57 = load i64, ptr %j_42
+  %t58 = icmp eq i64 %t56, %t57
+  br i1 %t58, label %then59, label %else60
+then59:
+  br label %join61
+else60:
+  br label %join61
+join61:
+  %t62 = phi ptr [@str0, %then59], [@str1, %else60]
+  call void @tiger_print(ptr %t62)
+  %t63 = load i64, ptr %j_42
+  %t64 = icmp sge i64 %t63, %t41
+  br i1 %t64, label %done45, label %next44
+next44:
+  %t65 = add i64 %t63, 1
+  store i64 %t65, ptr %j_42
+  br label %for43
+done45:
+  %t66 = call ptr @tiger_chr(i64 10)
+  call void @tiger_print(ptr %t66)
+  %t67 = load i64, ptr %i_34
+  %t68 = icmp sge i64 %t67, %t33
+  br i1 %t68, label %done37, label %next36
+next36:
+  %t69 = add i64 %t67, 1
+  store i64 %t69, ptr %i_34
+  br label %for35
+done37:
+  %t70 = call ptr @tiger_chr(i64 10)
+  call void @tiger_print(ptr %t70)
+  ret void
+}
+
+define internal void @tiger_try(ptr %link, i64 %c_71) {
+entry:
+  %c_71.addr = alloca i64
+  %r_82 = alloca i64
+  store i64 %c_71, ptr %c_71.addr
+  %t72 = load i64, ptr %c_71.addr
+  %t73 = getelementptr %tiger_tigermain.frame, ptr %link, i32 0, i32 1
+  %t74 = load i64, ptr %t73
+  %t75 = icmp eq i64 %t72, %t74
+  br i1 %t75, label %then76, label %else77
+then76:
+  call void @tiger_printboard(ptr %link)
+  br label %join78
+else77:
+  %t79 = getelementptr %tiger_tigermain.frame, ptr %link, i32 0, i32 1
+  %t80 = load i64, ptr %t79
+  %t81 = sub i64 %t80, 1
+  store i64 0, ptr %r_82
+  %t86 = icmp sle i64 0, %t81
+  br i1 %t86, label %for83, label %done85
+for83:
+  %t87 = getelementptr %tiger_tigermain.frame, ptr %link, i32 0, i32 2
+  %t88 = load ptr, ptr %t87
+  %t89 = load i64, ptr %r_82
+  %t90 = getelementptr i64, ptr %t88, i64 -1
+  %t91 = load i64, ptr %t90
+  %t92 = icmp ult i64 %t89, %t91
+  br i1 %t92, label %ok93, label %fail94
+fail94:
+  call void @tiger_failIndex(i64 %t89, i64 %t91)
+  unreachable
+ok93:
+  %t95 = getelementptr i64, ptr %t88, i64 %t89
+  %t96 = load i64, ptr %t95
+  %t97 = icmp eq i64 %t96, 0
+  %t98 = zext i1 %t97 to i64
+  %t99 = icmp ne i64 %t98, 0
+  br i1 %t99, label %rhs100, label %join101
+rhs100:
+  %t102 = getelementptr %tiger_tigermain.frame, ptr %link, i32 0, i32 4
+  %t103 = load ptr, ptr %t102
+  %t104 = load i64, ptr %r_82
+  %t105 = load i64, ptr %c_71.addr
+  %t106 = add i64 %t104, %t105
+  %t107 = getelementptr i64, ptr %t103, i64 -1
+  %t108 = load i64, ptr %t107
+  %t109 = icmp ult i64 %t106, %t108
+  br i1 %t109, label %ok110, label %fail111
+fail111:
+  call void @tiger_failIndex(i64 %t106, i64 %t108)
+  unreachable
+ok110:
+  %t112 = getelementptr i64, ptr %t103, i64 %t106
+  %t113 = load i64, ptr %t112
+  %t114 = icmp eq i64 %t113, 0
+  %t115 = zext i1 %t114 to i64
+  br label %join101
+join101:
+  %t116 = phi i64 [0, %ok93], [%t115, %ok110]
+  %t117 = icmp ne i64 %t116, 0
+  br i1 %t117, label %rhs118, label %join119
+rhs118:
+  %t120 = getelementptr %tiger_tigermain.frame, ptr %link, i32 0, i32 5
+  %t121 = load ptr, ptr %t120
+  %t122 = load i64, ptr %r_82
+  %t123 = getelementptr %tiger_tigermain.frame, ptr %link, i32 0, i32 1
+  %t124 = load i64, ptr %t123
+  %t125 = add i64 %t122, %t124
+  %t126 = sub i64 %t125, 1
+  %t127 = load i64, ptr %c_71.addr
+  %t128 = sub i64 %t126, %t127
+  %t129 = getelementptr i64, ptr %t121, i64 -1
+  %t130 = load i64, ptr %t129
+  %t131 = icmp ult i64 %t128, %t130
+  br i1 %t131, label %ok132, label %fail133
+fail133:
+  call void @tiger_failIndex(i64 %t128, i64 %t130)
+  unreachable
+ok132:
+  %t134 = getelementptr i64, ptr %t121, i64 %t128
+  %t135 = load i64, ptr %t134
+  %t136 = icmp eq i64 %t135, 0
+  %t137 = zext i1 %t136 to i64
+  br label %join119
+join119:
+  %t138 = phi i64 [0, %join101], [%t137, %ok132]
+  %t139 = icmp ne i64 %t138, 0
+  br i1 %t139, label %then140, label %else141
+then140:
+  %t143 = getelementptr %tiger_tigermain.frame, ptr %link, i32 0, i32 2
+  %t144 = load ptr, ptr %t143
+  %t145 = load i64, ptr %r_82
+  %t146 = getelementptr i64, ptr %t144, i64 -1
+  %t147 = load i64, ptr %t146
+  %t148 = icmp ult i64 %t145, %t147
+  br i1 %t148, label %ok149, label %fail150
+fail150:
+  call void @tiger_failIndex(i64 %t145, i64 %t147)
+  unreachable
+ok149:
+  %t151 = getelementptr i64, ptr %t144, i64 %t145
+  store i64 1, ptr %t151
+  %t152 = getelementptr %tiger_tigermain.frame, ptr %link, i32 0, i32 4
+  %t153 = load ptr, ptr %t152
+  %t154 = load i64, ptr %r_82
+  %t155 = load i64, ptr %c_71.addr
+  %t156 = add i64 %t154, %t155
+  %t157 = getelementptr i64, ptr %t153, i64 -1
+  %t158 = load i64, ptr %t157
+  %t159 = icmp ult i64 %t156, %t158
+  br i1 %t159, label %ok160, label %fail161
+fail161:
+  call void @tiger_failIndex(i64 %t156, i64 %t158)
+  unreachable
+ok160:
+  %t162 = getelementptr i64, ptr %t153, i64 %t156
+  store i64 1, ptr %t162
+  %t163 = getelementptr %tiger_tigermain.frame, ptr %link, i32 0, i32 5
+  %t164 = load ptr, ptr %t163
+  %t165 = load i64, ptr %r_82
+  %t166 = getelementptr %tiger_tigermain.frame, ptr %link, i32 0, i32 1
+  %t167 = load i64, ptr %t166
+  %t168 = add i64 %t165, %t167
+  %t169 = sub i64 %t168, 1
+  %t170 = load i64, ptr %c_71.addr
+  %t171 = sub i64 %t169, %t170
+  %t172 = getelementptr i64, ptr %t164, i64 -1
+  %t173 = load i64, ptr %t172
+  %t174 = icmp ult i64 %t171, %t173
+  br i1 %t174, label %ok175, label %fail176
+fail176:
+  call void @tiger_failIndex(i64 %t171, i64 %t173)
+  unreachable
+ok175:
+  %t177 = getelementptr i64, ptr %t164, i64 %t171
+  store i64 1, ptr %t177
+  %t178 = getelementptr %tiger_tigermain.frame, ptr %link, i32 0, i32 3
+  %t179 = load ptr, ptr %t178
+  %t180 = load i64, ptr %c_71.addr
+  %t181 = getelementptr i64, ptr %t179, i64 -1
+  %t182 = load i64, ptr %t181
+  %t183 = icmp ult i64 %t180, %t182
+  br i1 %t183, label %ok184, label %fail185
+fail185:
+  call void @tiger_failIndex(i64 %t180, i64 %t182)
+  unreachable
+ok184:
+  %t186 = getelementptr i64, ptr %t179, i64 %t180
+  %t187 = load i64, ptr %r_82
+  store i64 %t187, ptr %t186
+  %t188 = load i64, ptr %c_71.addr
+  %t189 = add i64 %t188, 1
+  call void @tiger_try(ptr %link, i64 %t189)
+  %t190 = getelementptr %tiger_tigermain.frame, ptr %link, i32 0, i32 2
+  %t191 = load ptr, ptr %t190
+  %t192 = load i64, ptr %r_82
+  %t193 = getelementptr i64, ptr %t191, i64 -1
+  %t194 = load i64, ptr %t193
+  %t195 = icmp ult i64 %t192, %t194
+  br i1 %t195, label %ok196, label %fail197
+fail197:
+  call void @tiger_failIndex(i64 %t192, i64 %t194)
+  unreachable
+ok196:
+  %t198 = getelementptr i64, ptr %t191, i64 %t192
+  store i64 0, ptr %t198
+  %t199 = getelementptr %tiger_tigermain.frame, ptr %link, i32 0, i32 4
+  %t200 = load ptr, ptr %t199
+  %t201 = load i64, ptr %r_82
+  %t202 = load i64, ptr %c_71.addr
+  %t203 = add i64 %t201, %t202
+  %t204 = getelementptr i64, ptr %t200, i64 -1
+  %t205 = load i64, ptr %t204
+  %t206 = icmp ult i64 %t203, %t205
+  br i1 %t206, label %ok207, label %fail208
+fail208:
+  call void @tiger_failIndex(i64 %t203, i64 %t205)
+  unreachable
+ok207:
+  %t209 = getelementptr i64, ptr %t200, i64 %t203
+  store i64 0, ptr %t209
+  %t210 = getelementptr %tiger_tigermain.frame, ptr %link, i32 0, i32 5
+  %t211 = load ptr, ptr %t210
+  %t212 = load i64, ptr %r_82
+  %t213 = getelementptr %tiger_tigermain.frame, ptr %link, i32 0, i32 1
+  %t214 = load i64, ptr %t213
+  %t215 = add i64 %t212, %t214
+  %t216 = sub i64 %t215, 1
+  %t217 = load i64, ptr %c_71.addr
+  %t218 = sub i64 %t216, %t217
+  %t219 = getelementptr i64, ptr %t211, i64 -1
+  %t220 = load i64, ptr %t219
+  %t221 = icmp ult i64 %t218, %t220
+  br i1 %t221, label %ok222, label %fail223
+fail223:
+  call void @tiger_failIndex(i64 %t218, i64 %t220)
+  unreachable
+ok222:
+  %t224 = getelementptr i64, ptr %t211, i64 %t218
+  store i64 0, ptr %t224
+  br label %join142
+else141:
+  br label %join142
+join142:
+  %t225 = load i64, ptr %r_82
+  %t226 = icmp sge i64 %t225, %t81
+  br i1 %t226, label %done85, label %next84
+next84:
+  %t227 = add i64 %t225, 1
+  store i64 %t227, ptr %r_82
+  br label %for83
+done85:
+  br label %join78
+join78:
+  ret void
+}
+
+define i64 @tiger_tigermain() {
+entry:
+  %frame = alloca %tiger_tigermain.frame
+  store ptr null, ptr %frame
+  %t1 = getelementptr %tiger_tigermain.frame, ptr %frame, i32 0, i32 1
+  store i64 6, ptr %t1
+  %t2 = getelementptr %tiger_tigermain.frame, ptr %frame, i32 0, i32 1
+  %t3 = load i64, ptr %t2
+  %t4 = call ptr @tiger_initArray(i64 %t3, i64 0)
+  %t5 = getelementptr %tiger_tigermain.frame, ptr %frame, i32 0, i32 2
+  store ptr %t4, ptr %t5
+  %t6 = getelementptr %tiger_tigermain.frame, ptr %frame, i32 0, i32 1
+  %t7 = load i64, ptr %t6
+  %t8 = call ptr @tiger_initArray(i64 %t7, i64 0)
+  %t9 = getelementptr %tiger_tigermain.frame, ptr %frame, i32 0, i32 3
+  store ptr %t8, ptr %t9
+  %t10 = getelementptr %tiger_tigermain.frame, ptr %frame, i32 0, i32 1
+  %t11 = load i64, ptr %t10
+  %t12 = getelementptr %tiger_tigermain.frame, ptr %frame, i32 0, i32 1
+  %t13 = load i64, ptr %t12
+  %t14 = add i64 %t11, %t13
+  %t15 = sub i64 %t14, 1
+  %t16 = call ptr @tiger_initArray(i64 %t15, i64 0)
+  %t17 = getelementptr %tiger_tigermain.frame, ptr %frame, i32 0, i32 4
+  store ptr %t16, ptr %t17
+  %t18 = getelementptr %tiger_tigermain.frame, ptr %frame, i32 0, i32 1
+  %t19 = load i64, ptr %t18
+  %t20 = getelementptr %tiger_tigermain.frame, ptr %frame, i32 0, i32 1
+  %t21 = load i64, ptr %t20
+  %t22 = add i64 %t19, %t21
+  %t23 = sub i64 %t22, 1
+  %t24 = call ptr @tiger_initArray(i64 %t23, i64 0)
+  %t25 = getelementptr %tiger_tigermain.frame, ptr %frame, i32 0, i32 5
+  store ptr %t24, ptr %t25
+  %t26 = getelementptr %tiger_tigermain.frame, ptr %frame, i32 0, i32 6
+  store i64 0, ptr %t26
+  call void @tiger_try(ptr %frame, i64 0)
+  %t228 = getelementptr %tiger_tigermain.frame, ptr %frame, i32 0, i32 6
+  %t229 = load i64, ptr %t228
+  ret i64 %t229
+}
@@ -0,0 +1,147 @@
+; Generated by rust-tigress; compile with clang together with runtime.c.
+
+%tiger_tigermain.frame = type { ptr, ptr }
+
+@str0 = private unnamed_addr constant { i64, [2 x i8] } { i64 2, [2 x i8] c"ti" }, align 8
+@str1 = private unnamed_addr constant { i64, [3 x i8] } { i64 3, [3 x i8] c"ger" }, align 8
+@str2 = private unnamed_addr constant { i64, [1 x i8] } { i64 1, [1 x i8] c"0" }, align 8
+@str3 = private unnamed_addr constant { i64, [1 x i8] } { i64 1, [1 x i8] c"0" }, align 8
+@str4 = private unnamed_addr constant { i64, [0 x i8] } { i64 0, [0 x i8] c"" }, align 8
+@str5 = private unnamed_addr constant { i64, [5 x i8] } { i64 5, [5 x i8] c"tiger" }, align 8
+@str6 = private unnamed_addr constant { i64, [9 x i8] } { i64 9, [9 x i8] c"different" }, align 8
+@str7 = private unnamed_addr constant { i64, [6 x i8] } { i64 6, [6 x i8] c" equal" }, align 8
+
+declare ptr @tiger_allocRecord(i64)
+declare ptr @tiger_initArray(i64, i64)
+declare i64 @tiger_stringEqual(ptr, ptr)
+declare i64 @tiger_stringCompare(ptr, ptr)
+declare void @tiger_print(ptr)
+declare void @tiger_printi(i64)
+declare void @tiger_flush()
+declare ptr @tiger_getchar()
+declare i64 @tiger_ord(ptr)
+declare ptr @tiger_chr(i64)
+declare i64 @tiger_size(ptr)
+declare ptr @tiger_substring(ptr, i64, i64)
+declare ptr @tiger_concat(ptr, ptr)
+declare i64 @tiger_not(i64)
+declare void @tiger_failIndex(i64, i64)
+declare void @tiger_failNil()
+declare void @tiger_failDivision()
+
+define internal ptr @tiger_pick(ptr %link, i64 %a_3, i64 %b_4, i64 %c_5, i64 %d_6, i64 %e_7, i64 %f_8, i64 %first_9, i64 %n_10) {
+entry:
+  %a_3.addr = alloca i64
+  %b_4.addr = alloca i64
+  %c_5.addr = alloca i64
+  %d_6.addr = alloca i64
+  %e_7.addr = alloca i64
+  %f_8.addr = alloca i64
+  %first_9.addr = alloca i64
+  %n_10.addr = alloca i64
+  store i64 %a_3, ptr %a_3.addr
+  store i64 %b_4, ptr %b_4.addr
+  store i64 %c_5, ptr %c_5.addr
+  store i64 %d_6, ptr %d_6.addr
+  store i64 %e_7, ptr %e_7.addr
+  store i64 %f_8, ptr %f_8.addr
+  store i64 %first_9, ptr %first_9.addr
+  store i64 %n_10, ptr %n_10.addr
+  %t11 = getelementptr %tiger_tigermain.frame, ptr %link, i32 0, i32 1
+  %t12 = load ptr, ptr %t11
+  %t13 = load i64, ptr %first_9.addr
+  %t14 = load i64, ptr %a_3.addr
+  %t15 = add i64 %t13, %t14
+  %t16 = load i64, ptr %b_4.addr
+  %t17 = add i64 %t15, %t16
+  %t18 = load i64, ptr %c_5.addr
+  %t19 = add i64 %t17, %t18
+  %t20 = load i64, ptr %d_6.addr
+  %t21 = add i64 %t19, %t20
+  %t22 = load i64, ptr %e_7.addr
+  %t23 = add i64 %t21, %t22
+  %t24 = load i64, ptr %f_8.addr
+  %t25 = add i64 %t23, %t24
+  %t26 = sub i64 %t25, 21
+  %t27 = load i64, ptr %n_10.addr
+  %t28 = call ptr @tiger_substring(ptr %t12, i64 %t26, i64 %t27)
+  ret ptr %t28
+}
+
+define internal ptr @tiger_digits(ptr %link, i64 %i_29) {
+entry:
+  %i_29.addr = alloca i64
+  store i64 %i_29, ptr %i_29.addr
+  %t30 = load i64, ptr %i_29.addr
+  %t31 = icmp slt i64 %t30, 10
+  br i1 %t31, label %then32, label %else33
+then32:
+  %t35 = call i64 @tiger_ord(ptr @str2)
+  %t36 = load i64, ptr %i_29.addr
+  %t37 = add i64 %t35, %t36
+  %t38 = call ptr @tiger_chr(i64 %t37)
+  br label %join34
+else33:
+  %t39 = load i64, ptr %i_29.addr
+  %t40 = sdiv i64 %t39, 10
+  %t41 = call ptr @tiger_digits(ptr %link, i64 %t40)
+  %t42 = call i64 @tiger_ord(ptr @str3)
+  %t43 = load i64, ptr %i_29.addr
+  %t44 = add i64 %t42, %t43
+  %t45 = load i64, ptr %i_29.addr
+  %t46 = sdiv i64 %t45, 10
+  %t47 = mul i64 %t46, 10
+  %t48 = sub i64 %t44, %t47
+  %t49 = call ptr @tiger_chr(i64 %t48)
+  %t50 = call ptr @tiger_concat(ptr %t41, ptr %t49)
+  br label %join34
+join34:
+  %t51 = phi ptr [%t38, %then32], [%t50, %else33]
+  ret ptr %t51
+}
+
+define i64 @tiger_tigermain() {
+entry:
+  %frame = alloca %tiger_tigermain.frame
+  store ptr null, ptr %frame
+  %t1 = call ptr @tiger_concat(ptr @str0, ptr @str1)
+  %t2 = getelementptr %tiger_tigermain.frame, ptr %frame, i32 0, i32 1
+  store ptr %t1, ptr %t2
+  %t52 = call ptr @tiger_pick(ptr %frame, i64 1, i64 2, i64 3, i64 4, i64 5, i64 6, i64 1, i64 3)
+  call void @tiger_print(ptr %t52)
+  %t53 = call ptr @tiger_chr(i64 10)
+  call void @tiger_print(ptr %t53)
+  %t54 = call ptr @tiger_digits(ptr %frame, i64 2024)
+  call void @tiger_print(ptr %t54)
+  %t55 = call ptr @tiger_chr(i64 10)
+  call void @tiger_print(ptr %t55)
+  %t56 = getelementptr %tiger_tigermain.frame, ptr %frame, i32 0, i32 1
+  %t57 = load ptr, ptr %t56
+  %t58 = call i64 @tiger_size(ptr %t57)
+  call void @tiger_printi(i64 %t58)
+  %t59 = call i64 @tiger_ord(ptr @str4)
+  call void @tiger_printi(i64 %t59)
+  %t60 = getelementptr %tiger_tigermain.frame, ptr %frame, i32 0, i32 1
+  %t61 = load ptr, ptr %t60
+  %t62 = call i64 @tiger_size(ptr %t61)
+  %t63 = call i64 @tiger_not(i64 %t62)
+  call void @tiger_printi(i64 %t63)
+  %t64 = getelementptr %tiger_tigermain.frame, ptr %frame, i32 0, i32 1
+  %t65 = load ptr, ptr %t64
+  %t67 = call i64 @tiger_stringEqual(ptr %t65, ptr @str5)
+  %t66 = icmp eq i64 %t67, 0
+  br i1 %t66, label %then68, label %else69
+then68:
+  call void @tiger_print(ptr @str6)
+  br label %join70
+else69:
+  call void @tiger_print(ptr @str7)
+  br label %join70
+join70:
+  %t71 = call ptr @tiger_chr(i64 10)
+  call void @tiger_print(ptr %t71)
+  call void @tiger_flush()
+  %t72 = getelementptr %tiger_tigermain.frame, ptr %frame, i32 0, i32 1
+  %t73 = load ptr, ptr %t72
+  ret i64 0
+}
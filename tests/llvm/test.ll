@@ -0,0 +1,57 @@
+; Generated by rust-tigress; compile with clang together with runtime.c.
+
+%tiger_tigermain.frame = type { ptr }
+
+declare ptr @tiger_allocRecord(i64)
+declare ptr @tiger_initArray(i64, i64)
+declare i64 @tiger_stringEqual(ptr, ptr)
+declare i64 @tiger_stringCompare(ptr, ptr)
+declare void @tiger_print(ptr)
+declare void @tiger_printi(i64)
+declare void @tiger_flush()
+declare ptr @tiger_getchar()
+declare i64 @tiger_ord(ptr)
+declare ptr @tiger_chr(i64)
+declare i64 @tiger_size(ptr)
+declare ptr @tiger_substring(ptr, i64, i64)
+declare ptr @tiger_concat(ptr, ptr)
+declare i64 @tiger_not(i64)
+declare void @tiger_failIndex(i64, i64)
+declare void @tiger_failNil()
+declare void @tiger_failDivision()
+
+define internal i64 @tiger_f(ptr %link, i64 %a_7, i64 %b_8) {
+entry:
+  %a_7.addr = alloca i64
+  %b_8.addr = alloca i64
+  store i64 %a_7, ptr %a_7.addr
+  store i64 %b_8, ptr %b_8.addr
+  %t9 = load i64, ptr %a_7.addr
+  %t10 = load i64, ptr %b_8.addr
+  %t11 = add i64 %t9, %t10
+  %t12 = add i64 %t11, 9
+  ret i64 %t12
+}
+
+define i64 @tiger_tigermain() {
+entry:
+  %frame = alloca %tiger_tigermain.frame
+  store ptr null, ptr %frame
+  %x_1 = alloca i64
+  %y_2 = alloca i64
+  %z_6 = alloca i64
+  store i64 4, ptr %x_1
+  store i64 5, ptr %y_2
+  %t3 = load i64, ptr %x_1
+  %t4 = load i64, ptr %y_2
+  %t5 = add i64 %t3, %t4
+  store i64 %t5, ptr %z_6
+  %t13 = load i64, ptr %x_1
+  %t14 = load i64, ptr %y_2
+  %t15 = call i64 @tiger_f(ptr %frame, i64 %t13, i64 %t14)
+  %t16 = load i64, ptr %y_2
+  %t17 = load i64, ptr %x_1
+  %t18 = call i64 @tiger_f(ptr %frame, i64 %t16, i64 %t17)
+  %t19 = add i64 %t15, %t18
+  ret i64 %t19
+}
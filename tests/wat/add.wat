@@ -0,0 +1,23 @@
+(module
+  (import "tiger" "print" (func $host_print (param i32 i32)))
+  (import "tiger" "getchar" (func $host_getchar (result i32)))
+  (import "tiger" "exit" (func $host_exit (param i32)))
+  (memory (export "memory") 17)
+  (global $sp (mut i64) (i64.const 256))
+  (global $stack_end i64 (i64.const 1048832))
+  (global $hp (mut i64) (i64.const 1048832))
+
+
+  (func $tiger_tigermain (result i64)
+    i64.const 123000000
+    i64.const 456000
+    i64.add
+    i64.const 789
+    i64.add)
+
+  (func (export "_start")
+    call $tiger_tigermain
+    i64.const 255
+    i64.and
+    i32.wrap_i64
+    call $host_exit))
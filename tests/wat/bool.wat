@@ -0,0 +1,29 @@
+(module
+  (import "tiger" "print" (func $host_print (param i32 i32)))
+  (import "tiger" "getchar" (func $host_getchar (result i32)))
+  (import "tiger" "exit" (func $host_exit (param i32)))
+  (memory (export "memory") 17)
+  (global $sp (mut i64) (i64.const 256))
+  (global $stack_end i64 (i64.const 1048832))
+  (global $hp (mut i64) (i64.const 1048832))
+
+
+  (func $tiger_tigermain (result i64)
+    (local $x_1 i64)
+    i64.const 2
+    local.set $x_1
+    local.get $x_1
+    i64.const 0
+    i64.ne
+    if (result i64)
+      i64.const 0
+    else
+      i64.const 1
+    end)
+
+  (func (export "_start")
+    call $tiger_tigermain
+    i64.const 255
+    i64.and
+    i32.wrap_i64
+    call $host_exit))
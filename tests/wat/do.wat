@@ -0,0 +1,45 @@
+(module
+  (import "tiger" "print" (func $host_print (param i32 i32)))
+  (import "tiger" "getchar" (func $host_getchar (result i32)))
+  (import "tiger" "exit" (func $host_exit (param i32)))
+  (memory (export "memory") 17)
+  (global $sp (mut i64) (i64.const 256))
+  (global $stack_end i64 (i64.const 1048832))
+  (global $hp (mut i64) (i64.const 1048832))
+
+
+  (func $tiger_tigermain (result i64)
+    (local $x_1 i64)
+    (local $i_2 i64)
+    i64.const 0
+    local.set $x_1
+    i64.const 0
+    local.set $i_2
+    block $done_3
+      loop $next_4
+        local.get $i_2
+        i64.const 10
+        i64.le_s
+        i32.eqz
+        br_if $done_3
+        local.get $x_1
+        local.get $i_2
+        i64.add
+        local.set $x_1
+        local.get $i_2
+        i64.const 1
+        i64.add
+        local.set $i_2
+        br $next_4
+      end
+    end
+    local.get $x_1
+    i64.const 55
+    i64.sub)
+
+  (func (export "_start")
+    call $tiger_tigermain
+    i64.const 255
+    i64.and
+    i32.wrap_i64
+    call $host_exit))
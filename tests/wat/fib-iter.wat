@@ -0,0 +1,68 @@
+(module
+  (import "tiger" "print" (func $host_print (param i32 i32)))
+  (import "tiger" "getchar" (func $host_getchar (result i32)))
+  (import "tiger" "exit" (func $host_exit (param i32)))
+  (memory (export "memory") 17)
+  (global $sp (mut i64) (i64.const 256))
+  (global $stack_end i64 (i64.const 1048832))
+  (global $hp (mut i64) (i64.const 1048832))
+
+
+  (func $tiger_fib (param $link i64) (param $x_1 i64) (result i64)
+    (local $a_2 i64)
+    (local $b_3 i64)
+    (local $limit_4 i64)
+    (local $i_5 i64)
+    (local $c_8 i64)
+    i64.const 1
+    local.set $a_2
+    i64.const 0
+    local.set $b_3
+    i64.const 1
+    local.set $i_5
+    local.get $x_1
+    local.set $limit_4
+    block $done_6
+      local.get $i_5
+      local.get $limit_4
+      i64.gt_s
+      br_if $done_6
+      loop $next_7
+        local.get $a_2
+        local.get $b_3
+        i64.add
+        local.set $c_8
+        local.get $b_3
+        local.set $a_2
+        local.get $c_8
+        local.set $b_3
+        local.get $i_5
+        local.get $limit_4
+        i64.ge_s
+        br_if $done_6
+        local.get $i_5
+        i64.const 1
+        i64.add
+        local.set $i_5
+        br $next_7
+      end
+    end
+    local.get $a_2)
+
+  (func $tiger_tigermain (result i64)
+    (local $env i64)
+    i64.const 8
+    call $enter
+    local.set $env
+    local.get $env
+    i64.const 20
+    call $tiger_fib
+    local.get $env
+    global.set $sp)
+
+  (func (export "_start")
+    call $tiger_tigermain
+    i64.const 255
+    i64.and
+    i32.wrap_i64
+    call $host_exit))
@@ -0,0 +1,47 @@
+(module
+  (import "tiger" "print" (func $host_print (param i32 i32)))
+  (import "tiger" "getchar" (func $host_getchar (result i32)))
+  (import "tiger" "exit" (func $host_exit (param i32)))
+  (memory (export "memory") 17)
+  (global $sp (mut i64) (i64.const 256))
+  (global $stack_end i64 (i64.const 1048832))
+  (global $hp (mut i64) (i64.const 1048832))
+
+
+  (func $tiger_fib (param $link i64) (param $x_1 i64) (result i64)
+    local.get $x_1
+    i64.const 1
+    i64.le_s
+    if (result i64)
+      local.get $x_1
+    else
+      local.get $link
+      local.get $x_1
+      i64.const 1
+      i64.sub
+      call $tiger_fib
+      local.get $link
+      local.get $x_1
+      i64.const 2
+      i64.sub
+      call $tiger_fib
+      i64.add
+    end)
+
+  (func $tiger_tigermain (result i64)
+    (local $env i64)
+    i64.const 8
+    call $enter
+    local.set $env
+    local.get $env
+    i64.const 5
+    call $tiger_fib
+    local.get $env
+    global.set $sp)
+
+  (func (export "_start")
+    call $tiger_tigermain
+    i64.const 255
+    i64.and
+    i32.wrap_i64
+    call $host_exit))
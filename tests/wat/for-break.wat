@@ -0,0 +1,57 @@
+(module
+  (import "tiger" "print" (func $host_print (param i32 i32)))
+  (import "tiger" "getchar" (func $host_getchar (result i32)))
+  (import "tiger" "exit" (func $host_exit (param i32)))
+  (memory (export "memory") 17)
+  (global $sp (mut i64) (i64.const 256))
+  (global $stack_end i64 (i64.const 1048832))
+  (global $hp (mut i64) (i64.const 1048832))
+
+
+  (func $tiger_tigermain (result i64)
+    (local $x_1 i64)
+    (local $limit_2 i64)
+    (local $i_3 i64)
+    i64.const 0
+    local.set $x_1
+    i64.const 0
+    local.set $i_3
+    i64.const 10
+    local.set $limit_2
+    block $done_4
+      local.get $i_3
+      local.get $limit_2
+      i64.gt_s
+      br_if $done_4
+      loop $next_5
+        local.get $x_1
+        local.get $i_3
+        i64.add
+        local.set $x_1
+        local.get $i_3
+        i64.const 5
+        i64.ge_s
+        if
+          br $done_4
+        end
+        local.get $i_3
+        local.get $limit_2
+        i64.ge_s
+        br_if $done_4
+        local.get $i_3
+        i64.const 1
+        i64.add
+        local.set $i_3
+        br $next_5
+      end
+    end
+    local.get $x_1
+    i64.const 15
+    i64.sub)
+
+  (func (export "_start")
+    call $tiger_tigermain
+    i64.const 255
+    i64.and
+    i32.wrap_i64
+    call $host_exit))
@@ -0,0 +1,84 @@
+(module
+  (import "tiger" "print" (func $host_print (param i32 i32)))
+  (import "tiger" "getchar" (func $host_getchar (result i32)))
+  (import "tiger" "exit" (func $host_exit (param i32)))
+  (memory (export "memory") 17)
+  (global $sp (mut i64) (i64.const 256))
+  (global $stack_end i64 (i64.const 1048832))
+  (global $hp (mut i64) (i64.const 1048832))
+
+
+  (func $tiger_sum (param $link i64) (param $l_1 i64) (result i64)
+    local.get $l_1
+    i64.const 0
+    i64.eq
+    if (result i64)
+      i64.const 0
+    else
+      local.get $l_1
+      i32.wrap_i64
+      i64.load offset=0
+      local.get $link
+      local.get $l_1
+      i32.wrap_i64
+      i64.load offset=8
+      call $tiger_sum
+      i64.add
+    end)
+
+  (func $tiger_tigermain (result i64)
+    (local $env i64)
+    (local $r_2 i64)
+    (local $r_3 i64)
+    (local $r_4 i64)
+    (local $l_5 i64)
+    i64.const 8
+    call $enter
+    local.set $env
+    i64.const 16
+    call $tiger_allocRecord
+    local.set $r_2
+    local.get $r_2
+    i32.wrap_i64
+    i64.const 1
+    i64.store offset=0
+    local.get $r_2
+    i32.wrap_i64
+    i64.const 16
+    call $tiger_allocRecord
+    local.set $r_3
+    local.get $r_3
+    i32.wrap_i64
+    i64.const 2
+    i64.store offset=0
+    local.get $r_3
+    i32.wrap_i64
+    i64.const 16
+    call $tiger_allocRecord
+    local.set $r_4
+    local.get $r_4
+    i32.wrap_i64
+    i64.const 3
+    i64.store offset=0
+    local.get $r_4
+    i32.wrap_i64
+    i64.const 0
+    i64.store offset=8
+    local.get $r_4
+    i64.store offset=8
+    local.get $r_3
+    i64.store offset=8
+    local.get $r_2
+    local.set $l_5
+    local.get $env
+    local.get $l_5
+    call $tiger_sum
+    local.get $env
+    global.set $sp)
+
+  (func (export "_start")
+    call $tiger_tigermain
+    i64.const 255
+    i64.and
+    i32.wrap_i64
+    call $host_exit))
@@ -0,0 +1,399 @@
+(module
+  (import "tiger" "print" (func $host_print (param i32 i32)))
+  (import "tiger" "getchar" (func $host_getchar (result i32)))
+  (import "tiger" "exit" (func $host_exit (param i32)))
+  (memory (export "memory") 17)
+  (global $sp (mut i64) (i64.const 256))
+  (global $stack_end i64 (i64.const 1048832))
+  (global $hp (mut i64) (i64.const 1048832))
+
+
+  (func $tiger_id (param $link i64) (param $x_1 i64) (result i64)
+    local.get $x_1)
+
+  (func $tiger_mix (param $link i64) (param $a_2 i64) (param $b_3 i64) (param $c_4 i64) (param $d_5 i64) (param $e_6 i64) (param $f_7 i64) (param $g_8 i64) (param $h_9 i64) (result i64)
+    (local $s_10 i64)
+    (local $t_11 i64)
+    (local $limit_12 i64)
+    (local $i_13 i64)
+    i64.const 0
+    local.set $s_10
+    i64.const 1
+    local.set $t_11
+    i64.const 1
+    local.set $i_13
+    i64.const 10
+    local.set $limit_12
+    block $done_14
+      local.get $i_13
+      local.get $limit_12
+      i64.gt_s
+      br_if $done_14
+      loop $next_15
+        local.get $s_10
+        local.get $a_2
+        local.get $i_13
+        i64.mul
+        i64.add
+        local.get $b_3
+        i64.add
+        local.get $c_4
+        i64.sub
+        local.get $d_5
+        local.get $e_6
+        i64.mul
+        i64.add
+        local.get $f_7
+        i64.sub
+        local.get $g_8
+        local.get $h_9
+        i64.mul
+        i64.add
+        local.get $link
+        local.get $i_13
+        call $tiger_id
+        i64.add
+        local.set $s_10
+        local.get $t_11
+        local.get $s_10
+        i64.const 7
+        i64.div_s
+        i64.add
+        local.get $a_2
+        i64.add
+        local.get $b_3
+        i64.add
+        local.get $c_4
+        i64.add
+        local.get $d_5
+        i64.add
+        local.get $e_6
+        i64.add
+        local.get $f_7
+        i64.add
+        local.get $g_8
+        i64.add
+        local.get $h_9
+        i64.add
+        local.set $t_11
+        local.get $i_13
+        local.get $limit_12
+        i64.ge_s
+        br_if $done_14
+        local.get $i_13
+        i64.const 1
+        i64.add
+        local.set $i_13
+        br $next_15
+      end
+    end
+    local.get $s_10
+    local.get $t_11
+    i64.add)
+
+  (func $tiger_tigermain (result i64)
+    (local $env i64)
+    (local $v0_16 i64)
+    (local $v1_17 i64)
+    (local $v2_18 i64)
+    (local $v3_19 i64)
+    (local $v4_20 i64)
+    (local $v5_21 i64)
+    (local $v6_22 i64)
+    (local $v7_23 i64)
+    (local $v8_24 i64)
+    (local $v9_25 i64)
+    (local $v10_26 i64)
+    (local $v11_27 i64)
+    (local $v12_28 i64)
+    (local $v13_29 i64)
+    (local $v14_30 i64)
+    (local $v15_31 i64)
+    (local $v16_32 i64)
+    (local $v17_33 i64)
+    (local $v18_34 i64)
+    (local $v19_35 i64)
+    (local $mixed_36 i64)
+    i64.const 8
+    call $enter
+    local.set $env
+    local.get $env
+    i64.const 0
+    call $tiger_id
+    local.set $v0_16
+    local.get $env
+    i64.const 1
+    call $tiger_id
+    local.set $v1_17
+    local.get $env
+    i64.const 2
+    call $tiger_id
+    local.set $v2_18
+    local.get $env
+    i64.const 3
+    call $tiger_id
+    local.set $v3_19
+    local.get $env
+    i64.const 4
+    call $tiger_id
+    local.set $v4_20
+    local.get $env
+    i64.const 5
+    call $tiger_id
+    local.set $v5_21
+    local.get $env
+    i64.const 6
+    call $tiger_id
+    local.set $v6_22
+    local.get $env
+    i64.const 7
+    call $tiger_id
+    local.set $v7_23
+    local.get $env
+    i64.const 8
+    call $tiger_id
+    local.set $v8_24
+    local.get $env
+    i64.const 9
+    call $tiger_id
+    local.set $v9_25
+    local.get $env
+    i64.const 10
+    call $tiger_id
+    local.set $v10_26
+    local.get $env
+    i64.const 11
+    call $tiger_id
+    local.set $v11_27
+    local.get $env
+    i64.const 12
+    call $tiger_id
+    local.set $v12_28
+    local.get $env
+    i64.const 13
+    call $tiger_id
+    local.set $v13_29
+    local.get $env
+    i64.const 14
+    call $tiger_id
+    local.set $v14_30
+    local.get $env
+    i64.const 15
+    call $tiger_id
+    local.set $v15_31
+    local.get $env
+    i64.const 16
+    call $tiger_id
+    local.set $v16_32
+    local.get $env
+    i64.const 17
+    call $tiger_id
+    local.set $v17_33
+    local.get $env
+    i64.const 18
+    call $tiger_id
+    local.set $v18_34
+    local.get $env
+    i64.const 19
+    call $tiger_id
+    local.set $v19_35
+    local.get $env
+    local.get $v0_16
+    local.get $v1_17
+    local.get $v2_18
+    local.get $v3_19
+    local.get $v4_20
+    local.get $v5_21
+    local.get $v6_22
+    local.get $v7_23
+    call $tiger_mix
+    local.get $env
+    local.get $v19_35
+    local.get $v18_34
+    local.get $v17_33
+    local.get $v16_32
+    local.get $v15_31
+    local.get $v14_30
+    local.get $v13_29
+    local.get $v12_28
+    call $tiger_mix
+    i64.add
+    local.set $mixed_36
+    local.get $mixed_36
+    call $tiger_printi
+    i64.const 10
+    call $tiger_chr
+    call $tiger_print
+    local.get $v0_16
+    local.get $v1_17
+    local.get $env
+    i64.const 1
+    call $tiger_id
+    i64.mul
+    i64.add
+    local.get $v2_18
+    local.get $env
+    i64.const 2
+    call $tiger_id
+    i64.mul
+    i64.add
+    local.get $v3_19
+    local.get $env
+    i64.const 3
+    call $tiger_id
+    i64.mul
+    i64.add
+    local.get $v4_20
+    local.get $env
+    i64.const 4
+    call $tiger_id
+    i64.mul
+    i64.add
+    local.get $v5_21
+    local.get $env
+    i64.const 5
+    call $tiger_id
+    i64.mul
+    i64.add
+    local.get $v6_22
+    local.get $env
+    i64.const 6
+    call $tiger_id
+    i64.mul
+    i64.add
+    local.get $v7_23
+    local.get $env
+    i64.const 7
+    call $tiger_id
+    i64.mul
+    i64.add
+    local.get $v8_24
+    local.get $env
+    i64.const 8
+    call $tiger_id
+    i64.mul
+    i64.add
+    local.get $v9_25
+    local.get $env
+    i64.const 9
+    call $tiger_id
+    i64.mul
+    i64.add
+    local.get $v10_26
+    local.get $env
+    i64.const 10
+    call $tiger_id
+    i64.mul
+    i64.add
+    local.get $v11_27
+    local.get $env
+    i64.const 11
+    call $tiger_id
+    i64.mul
+    i64.add
+    local.get $v12_28
+    local.get $env
+    i64.const 12
+    call $tiger_id
+    i64.mul
+    i64.add
+    local.get $v13_29
+    local.get $env
+    i64.const 13
+    call $tiger_id
+    i64.mul
+    i64.add
+    local.get $v14_30
+    local.get $env
+    i64.const 14
+    call $tiger_id
+    i64.mul
+    i64.add
+    local.get $v15_31
+    local.get $env
+    i64.const 15
+    call $tiger_id
+    i64.mul
+    i64.add
+    local.get $v16_32
+    local.get $env
+    i64.const 16
+    call $tiger_id
+    i64.mul
+    i64.add
+    local.get $v17_33
+    local.get $env
+    i64.const 17
+    call $tiger_id
+    i64.mul
+    i64.add
+    local.get $v18_34
+    local.get $env
+    i64.const 18
+    call $tiger_id
+    i64.mul
+    i64.add
+    local.get $v19_35
+    local.get $env
+    i64.const 19
+    call $tiger_id
+    i64.mul
+    i64.add
+    call $tiger_printi
+    i64.const 10
+    call $tiger_chr
+    call $tiger_print
+    local.get $mixed_36
+    local.get $v19_35
+    local.get $v18_34
+    i64.add
+    local.get $v17_33
+    i64.add
+    local.get $v16_32
+    i64.add
+    local.get $v15_31
+    i64.add
+    local.get $v14_30
+    i64.add
+    local.get $v13_29
+    i64.add
+    local.get $v12_28
+    i64.add
+    local.get $v11_27
+    i64.add
+    local.get $v10_26
+    i64.add
+    local.get $v9_25
+    i64.add
+    local.get $v8_24
+    i64.add
+    local.get $v7_23
+    i64.add
+    local.get $v6_22
+    i64.add
+    local.get $v5_21
+    i64.add
+    local.get $v4_20
+    i64.add
+    local.get $v3_19
+    i64.add
+    local.get $v2_18
+    i64.add
+    local.get $v1_17
+    i64.add
+    local.get $v0_16
+    i64.add
+    local.get $env
+    i64.const 3
+    call $tiger_id
+    i64.mul
+    i64.sub
+    local.get $env
+    global.set $sp)
+
+  (func (export "_start")
+    call $tiger_tigermain
+    i64.const 255
+    i64.and
+    i32.wrap_i64
+    call $host_exit))
@@ -0,0 +1,364 @@
+(module
+  (import "tiger" "print" (func $host_print (param i32 i32)))
+  (import "tiger" "getchar" (func $host_getchar (result i32)))
+  (import "tiger" "exit" (func $host_exit (param i32)))
+  (memory (export "memory") 17)
+  (global $sp (mut i64) (i64.const 288))
+  (global $stack_end i64 (i64.const 1048864))
+  (global $hp (mut i64) (i64.const 1048864))
+
+
+  (func $tiger_printboard (param $link i64)
+    (local $limit_1 i64)
+    (local $i_2 i64)
+    (local $limit_5 i64)
+    (local $j_6 i64)
+    local.get $link
+    i32.wrap_i64
+    local.get $link
+    i32.wrap_i64
+    i64.load offset=48
+    i64.const 1
+    i64.add
+    i64.store offset=48
+    i64.const 0
+    local.set $i_2
+    local.get $link
+    i32.wrap_i64
+    i64.load offset=8
+    i64.const 1
+    i64.sub
+    local.set $limit_1
+    block $done_3
+      local.get $i_2
+      local.get $limit_1
+      i64.gt_s
+      br_if $done_3
+      loop $next_4
+        i64.const 0
+        local.set $j_6
+        local.get $link
+        i32.wrap_i64
+        i64.load offset=8
+        i64.const 1
+        i64.sub
+        local.set $limit_5
+        block $done_7
+          local.get $j_6
+          local.get $limit_5
+          i64.gt_s
+          br_if $done_7
+          loop $next_8
+            local.get $link
+            i32.wrap_i64
+            i64.load offset=24
+            local.get $i_2
+            i64.const 3
+            i64.shl
+            i64.add
+            i32.wrap_i64
+            i64.load
+            local.get $j_6
+            i64.eq
+            if (result i64)
+              i64.const 256
+            else
+              i64.const 272
+            end
+            call $tiger_print
+            local.get $j_6
+            local.get $limit_5
+            i64.ge_s
+            br_if $done_7
+            local.get $j_6
+            i64.const 1
+            i64.add
+            local.set $j_6
+            br $next_8
+          end
+        end
+        i64.const 10
+        call $tiger_chr
+        call $tiger_print
+        local.get $i_2
+        local.get $limit_1
+        i64.ge_s
+        br_if $done_3
+        local.get $i_2
+        i64.const 1
+        i64.add
+        local.set $i_2
+        br $next_4
+      end
+    end
+    i64.const 10
+    call $tiger_chr
+    call $tiger_print)
+
+  (func $tiger_try (param $link i64) (param $c_9 i64)
+    (local $limit_10 i64)
+    (local $r_11 i64)
+    local.get $c_9
+    local.get $link
+    i32.wrap_i64
+    i64.load offset=8
+    i64.eq
+    if
+      local.get $link
+      call $tiger_printboard
+    else
+      i64.const 0
+      local.set $r_11
+      local.get $link
+      i32.wrap_i64
+      i64.load offset=8
+      i64.const 1
+      i64.sub
+      local.set $limit_10
+      block $done_12
+        local.get $r_11
+        local.get $limit_10
+        i64.gt_s
+        br_if $done_12
+        loop $next_13
+          local.get $link
+          i32.wrap_i64
+          i64.load offset=16
+          local.get $r_11
+          i64.const 3
+          i64.shl
+          i64.add
+          i32.wrap_i64
+          i64.load
+          i64.const 0
+          i64.eq
+          if (result i64)
+            local.get $link
+            i32.wrap_i64
+            i64.load offset=32
+            local.get $r_11
+            local.get $c_9
+            i64.add
+            i64.const 3
+            i64.shl
+            i64.add
+            i32.wrap_i64
+            i64.load
+            i64.const 0
+            i64.eq
+            i64.extend_i32_u
+          else
+            i64.const 0
+          end
+          i64.const 0
+          i64.ne
+          if (result i64)
+            local.get $link
+            i32.wrap_i64
+            i64.load offset=40
+            local.get $r_11
+            local.get $link
+            i32.wrap_i64
+            i64.load offset=8
+            i64.add
+            i64.const 1
+            i64.sub
+            local.get $c_9
+            i64.sub
+            i64.const 3
+            i64.shl
+            i64.add
+            i32.wrap_i64
+            i64.load
+            i64.const 0
+            i64.eq
+            i64.extend_i32_u
+          else
+            i64.const 0
+          end
+          i64.const 0
+          i64.ne
+          if
+            local.get $link
+            i32.wrap_i64
+            i64.load offset=16
+            local.get $r_11
+            i64.const 3
+            i64.shl
+            i64.add
+            i32.wrap_i64
+            i64.const 1
+            i64.store
+            local.get $link
+            i32.wrap_i64
+            i64.load offset=32
+            local.get $r_11
+            local.get $c_9
+            i64.add
+            i64.const 3
+            i64.shl
+            i64.add
+            i32.wrap_i64
+            i64.const 1
+            i64.store
+            local.get $link
+            i32.wrap_i64
+            i64.load offset=40
+            local.get $r_11
+            local.get $link
+            i32.wrap_i64
+            i64.load offset=8
+            i64.add
+            i64.const 1
+            i64.sub
+            local.get $c_9
+            i64.sub
+            i64.const 3
+            i64.shl
+            i64.add
+            i32.wrap_i64
+            i64.const 1
+            i64.store
+            local.get $link
+            i32.wrap_i64
+            i64.load offset=24
+            local.get $c_9
+            i64.const 3
+            i64.shl
+            i64.add
+            i32.wrap_i64
+            local.get $r_11
+            i64.store
+            local.get $link
+            local.get $c_9
+            i64.const 1
+            i64.add
+            call $tiger_try
+            local.get $link
+            i32.wrap_i64
+            i64.load offset=16
+            local.get $r_11
+            i64.const 3
+            i64.shl
+            i64.add
+            i32.wrap_i64
+            i64.const 0
+            i64.store
+            local.get $link
+            i32.wrap_i64
+            i64.load offset=32
+            local.get $r_11
+            local.get $c_9
+            i64.add
+            i64.const 3
+            i64.shl
+            i64.add
+            i32.wrap_i64
+            i64.const 0
+            i64.store
+            local.get $link
+            i32.wrap_i64
+            i64.load offset=40
+            local.get $r_11
+            local.get $link
+            i32.wrap_i64
+            i64.load offset=8
+            i64.add
+            i64.const 1
+            i64.sub
+            local.get $c_9
+            i64.sub
+            i64.const 3
+            i64.shl
+            i64.add
+            i32.wrap_i64
+            i64.const 0
+            i64.store
+          end
+          local.get $r_11
+          local.get $limit_10
+          i64.ge_s
+          br_if $done_12
+          local.get $r_11
+          i64.const 1
+          i64.add
+          local.set $r_11
+          br $next_13
+        end
+      end
+    end)
+
+  (func $tiger_tigermain (result i64)
+    (local $env i64)
+    i64.const 56
+    call $enter
+    local.set $env
+    local.get $env
+    i32.wrap_i64
+    i64.const 6
+    i64.store offset=8
+    local.get $env
+    i32.wrap_i64
+    local.get $env
+    i32.wrap_i64
+    i64.load offset=8
+    i64.const 0
+    call $tiger_initArray
+    i64.store offset=16
+    local.get $env
+    i32.wrap_i64
+    local.get $env
+    i32.wrap_i64
+    i64.load offset=8
+    i64.const 0
+    call $tiger_initArray
+    i64.store offset=24
+    local.get $env
+    i32.wrap_i64
+    local.get $env
+    i32.wrap_i64
+    i64.load offset=8
+    local.get $env
+    i32.wrap_i64
+    i64.load offset=8
+    i64.add
+    i64.const 1
+    i64.sub
+    i64.const 0
+    call $tiger_initArray
+    i64.store offset=32
+    local.get $env
+    i32.wrap_i64
+    local.get $env
+    i32.wrap_i64
+    i64.load offset=8
+    local.get $env
+    i32.wrap_i64
+    i64.load offset=8
+    i64.add
+    i64.const 1
+    i64.sub
+    i64.const 0
+    call $tiger_initArray
+    i64.store offset=40
+    local.get $env
+    i32.wrap_i64
+    i64.const 0
+    i64.store offset=48
+    local.get $env
+    i64.const 0
+    call $tiger_try
+    local.get $env
+    i32.wrap_i64
+    i64.load offset=48
+    local.get $env
+    global.set $sp)
+
+  (data (i32.const 256) "\02\00\00\00\00\00\00\00 O\00\00\00\00\00\00\02\00\00\00\00\00\00\00 .\00\00\00\00\00\00")
+
+  (func (export "_start")
+    call $tiger_tigermain
+    i64.const 255
+    i64.and
+    i32.wrap_i64
+    call $host_exit))
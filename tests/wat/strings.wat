@@ -0,0 +1,141 @@
+(module
+  (import "tiger" "print" (func $host_print (param i32 i32)))
+  (import "tiger" "getchar" (func $host_getchar (result i32)))
+  (import "tiger" "exit" (func $host_exit (param i32)))
+  (memory (export "memory") 17)
+  (global $sp (mut i64) (i64.const 368))
+  (global $stack_end i64 (i64.const 1048944))
+  (global $hp (mut i64) (i64.const 1048944))
+
+
+  (func $tiger_pick (param $link i64) (param $a_1 i64) (param $b_2 i64) (param $c_3 i64) (param $d_4 i64) (param $e_5 i64) (param $f_6 i64) (param $first_7 i64) (param $n_8 i64) (result i64)
+    local.get $link
+    i32.wrap_i64
+    i64.load offset=8
+    local.get $first_7
+    local.get $a_1
+    i64.add
+    local.get $b_2
+    i64.add
+    local.get $c_3
+    i64.add
+    local.get $d_4
+    i64.add
+    local.get $e_5
+    i64.add
+    local.get $f_6
+    i64.add
+    i64.const 21
+    i64.sub
+    local.get $n_8
+    call $tiger_substring)
+
+  (func $tiger_digits (param $link i64) (param $i_9 i64) (result i64)
+    local.get $i_9
+    i64.const 10
+    i64.lt_s
+    if (result i64)
+      i64.const 288
+      call $tiger_ord
+      local.get $i_9
+      i64.add
+      call $tiger_chr
+    else
+      local.get $link
+      local.get $i_9
+      i64.const 10
+      i64.div_s
+      call $tiger_digits
+      i64.const 288
+      call $tiger_ord
+      local.get $i_9
+      i64.add
+      local.get $i_9
+      i64.const 10
+      i64.div_s
+      i64.const 10
+      i64.mul
+      i64.sub
+      call $tiger_chr
+      call $tiger_concat
+    end)
+
+  (func $tiger_tigermain (result i64)
+    (local $env i64)
+    i64.const 16
+    call $enter
+    local.set $env
+    local.get $env
+    i32.wrap_i64
+    i64.const 256
+    i64.const 272
+    call $tiger_concat
+    i64.store offset=8
+    local.get $env
+    i64.const 1
+    i64.const 2
+    i64.const 3
+    i64.const 4
+    i64.const 5
+    i64.const 6
+    i64.const 1
+    i64.const 3
+    call $tiger_pick
+    call $tiger_print
+    i64.const 10
+    call $tiger_chr
+    call $tiger_print
+    local.get $env
+    i64.const 2024
+    call $tiger_digits
+    call $tiger_print
+    i64.const 10
+    call $tiger_chr
+    call $tiger_print
+    local.get $env
+    i32.wrap_i64
+    i64.load offset=8
+    call $tiger_size
+    call $tiger_printi
+    i64.const 304
+    call $tiger_ord
+    call $tiger_printi
+    local.get $env
+    i32.wrap_i64
+    i64.load offset=8
+    call $tiger_size
+    call $tiger_not
+    call $tiger_printi
+    local.get $env
+    i32.wrap_i64
+    i64.load offset=8
+    i64.const 312
+    call $tiger_stringEqual
+    i64.eqz
+    if
+      i64.const 328
+      call $tiger_print
+    else
+      i64.const 352
+      call $tiger_print
+    end
+    i64.const 10
+    call $tiger_chr
+    call $tiger_print
+    call $tiger_flush
+    local.get $env
+    i32.wrap_i64
+    i64.load offset=8
+    drop
+    i64.const 0
+    local.get $env
+    global.set $sp)
+
+  (data (i32.const 256) "\02\00\00\00\00\00\00\00ti\00\00\00\00\00\00\03\00\00\00\00\00\00\00ger\00\00\00\00\00\01\00\00\00\00\00\00\000\00\00\00\00\00\00\00\00\00\00\00\00\00\00\00\05\00\00\00\00\00\00\00tiger\00\00\00\09\00\00\00\00\00\00\00different\00\00\00\00\00\00\00\06\00\00\00\00\00\00\00 equal\00\00")
+
+  (func (export "_start")
+    call $tiger_tigermain
+    i64.const 255
+    i64.and
+    i32.wrap_i64
+    call $host_exit))
@@ -0,0 +1,51 @@
+(module
+  (import "tiger" "print" (func $host_print (param i32 i32)))
+  (import "tiger" "getchar" (func $host_getchar (result i32)))
+  (import "tiger" "exit" (func $host_exit (param i32)))
+  (memory (export "memory") 17)
+  (global $sp (mut i64) (i64.const 256))
+  (global $stack_end i64 (i64.const 1048832))
+  (global $hp (mut i64) (i64.const 1048832))
+
+
+  (func $tiger_f (param $link i64) (param $a_4 i64) (param $b_5 i64) (result i64)
+    local.get $a_4
+    local.get $b_5
+    i64.add
+    i64.const 9
+    i64.add)
+
+  (func $tiger_tigermain (result i64)
+    (local $env i64)
+    (local $x_1 i64)
+    (local $y_2 i64)
+    (local $z_3 i64)
+    i64.const 8
+    call $enter
+    local.set $env
+    i64.const 4
+    local.set $x_1
+    i64.const 5
+    local.set $y_2
+    local.get $x_1
+    local.get $y_2
+    i64.add
+    local.set $z_3
+    local.get $env
+    local.get $x_1
+    local.get $y_2
+    call $tiger_f
+    local.get $env
+    local.get $y_2
+    local.get $x_1
+    call $tiger_f
+    i64.add
+    local.get $env
+    global.set $sp)
+
+  (func (export "_start")
+    call $tiger_tigermain
+    i64.const 255
+    i64.and
+    i32.wrap_i64
+    call $host_exit))